@@ -51,11 +51,13 @@ impl LoongArchScause {
 pub fn tlb_init(tlbrentry: usize) {
     // // setup PWCTL
     unsafe {
+    // $r21 保存着每核数据区的地址，不能用作临时寄存器
     asm!(
-        "li.d     $r21,  0x4d52c",     // (9 << 15) | (21 << 10) | (9 << 5) | 12
-        "csrwr    $r21,  0x1c",        // LOONGARCH_CSR_PWCTL0
-        "li.d     $r21,  0x25e",       // (9 << 6)  | 30
-        "csrwr    $r21,  0x1d",         //LOONGARCH_CSR_PWCTL1
+        "li.d     {tmp},  0x4d52c",     // (9 << 15) | (21 << 10) | (9 << 5) | 12
+        "csrwr    {tmp},  0x1c",        // LOONGARCH_CSR_PWCTL0
+        "li.d     {tmp},  0x25e",       // (9 << 6)  | 30
+        "csrwr    {tmp},  0x1d",         //LOONGARCH_CSR_PWCTL1
+        tmp = out(reg) _,
         )
     }
     pub const PS_4K: usize = 0x0c;
@@ -94,7 +96,21 @@ impl TrapArch for LoongArch64Trap {
         ecfg::set_vs(0);
         let mut lie = ecfg::read().lie();
         lie.remove(ecfg::LineBasedInterrupt::TIMER); 
+        // 核间中断和外部中断线 HWI0-HWI7 始终打开，由 CRMD.IE 统一控制；
+        // 外设中断经 PCH-PIC/EIOINTC 汇聚到这些线上，由 handle_irq 分发
+        lie.insert(ecfg::LineBasedInterrupt::IPI);
+        lie.insert(
+            ecfg::LineBasedInterrupt::HWI0
+                | ecfg::LineBasedInterrupt::HWI1
+                | ecfg::LineBasedInterrupt::HWI2
+                | ecfg::LineBasedInterrupt::HWI3
+                | ecfg::LineBasedInterrupt::HWI4
+                | ecfg::LineBasedInterrupt::HWI5
+                | ecfg::LineBasedInterrupt::HWI6
+                | ecfg::LineBasedInterrupt::HWI7,
+        );
         ecfg::set_lie(lie);
+        crate::sbi::init_ipi();
         crmd::set_ie(false);
         loongArch64::register::eentry::set_eentry(trap_vector_base as usize); 
     }
@@ -37,12 +37,16 @@ impl TrapArch for RiscV64Trap {
     fn enable_irqs() {
         unsafe {
             sie::set_stimer();
+            sie::set_sext();
+            sie::set_ssoft();
         }
     }
     
     fn disable_irqs() {
         unsafe {
             sie::clear_stimer();
+            sie::clear_sext();
+            sie::clear_ssoft();
         }
     }
     
@@ -8,6 +8,37 @@ pub const          MAX_SYMLINK_DEPTH :usize =100;
 pub const MAX_SHM_SIZE : usize = 1024 * 1024 * 1024; // 1 GiB
 ///
 pub const MNT_TABLE_MAX_ENTRIES: usize = 16;
+/// 块缓冲缓存的默认容量（4 KiB 块数），16 MiB
+pub const BLOCK_CACHE_BLOCKS: usize = 4096;
+/// 顺序读时最大预读块数
+pub const BLOCK_READAHEAD_MAX: usize = 16;
+/// 脏块定时写回的间隔（毫秒）
+pub const BLOCK_WRITEBACK_INTERVAL_MS: usize = 5000;
+/// 空闲物理页低于该值时开始换出（页数），4 MiB
+pub const SWAP_LOW_WATERMARK: usize = 1024;
+/// 换出直到空闲物理页回到该值（页数），8 MiB
+pub const SWAP_HIGH_WATERMARK: usize = 2048;
+/// 缺页时内存不足一次换出的页数
+pub const SWAP_CLUSTER: usize = 32;
+/// 为页表等内核必需分配保留的物理页数，普通分配不能动用，1 MiB
+pub const OOM_RESERVE_FRAMES: usize = 256;
+/// OOM 时等待被杀进程释放内存的最多调度轮数
+pub const OOM_WAIT_ROUNDS: usize = 64;
+/// 伙伴系统的最大阶，最大的块为 2^18 页（1 GiB）
+pub const FRAME_MAX_ORDER: usize = 18;
+/// 每 CPU 页缓存一次从伙伴系统取出或归还的页数
+pub const FRAME_PCP_BATCH: usize = 32;
+/// 每 CPU 页缓存超过该页数时归还一批给伙伴系统
+pub const FRAME_PCP_HIGH: usize = 128;
+/// 透明大页的页数，2 MiB 大页由 512 个 4K 页组成
+pub const HUGE_PAGE_PAGES: usize = 512;
+/// 透明大页默认关闭，相当于 Linux 的 madvise 模式：只有用 madvise(MADV_HUGEPAGE)
+/// 标记过的匿名私有映射和堆才使用大页，避免小内存机器上首次访问就占用 2 MiB
+pub const THP_DEFAULT_ENABLED: bool = false;
+/// 支持的最大 CPU 数，编号不小于该值的核启动后不参与调度
+pub const MAX_CPUS: usize = 8;
+/// 负载均衡的周期（时钟中断次数）
+pub const LOAD_BALANCE_TICKS: usize = 4;
 ///File descriptor set size
 pub const FD_SETSIZE:usize = 1024;
 //  MAX_KERNEL_RW_BUFFER_SIZE
@@ -88,3 +119,7 @@ pub const PATH_MAX: usize = 4096;
 // 定义一个内核中转缓冲区的合理大小
 pub const SENDFILE_KERNEL_BUFFER_SIZE: usize = 4*PAGE_SIZE;
 pub const TOTALMEM: usize = 1 * 1024 * 1024 * 1024; // 1 GiB
+/// tmpfs 未指定 size= 时的容量上限，数据存放在内核堆中
+pub const TMPFS_DEFAULT_SIZE: usize = 32 * 1024 * 1024;
+/// tmpfs 未指定 nr_inodes= 时的 inode 数量上限
+pub const TMPFS_DEFAULT_INODES: usize = 8192;
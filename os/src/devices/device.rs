@@ -1,5 +1,6 @@
-use alloc::{sync::Arc, vec::Vec};
+use alloc::{boxed::Box, sync::Arc, vec::Vec};
 use alloc::vec;
+use crate::utils::error::ASyncRet;
 use super::{INT_DEVICE, MAIN_UART};
 
 pub enum DeviceType {
@@ -77,6 +78,22 @@ pub trait RtcDriver: Driver {
 pub trait BlkDriver: Driver {
     fn read_blocks(&self, block_id: usize, buf: &mut [u8]);
     fn write_blocks(&self, block_id: usize, buf: &[u8]);
+    /// 异步读：等待设备期间挂起当前任务，默认退化为同步读
+    fn read_blocks_async<'a>(&'a self, block_id: usize, buf: &'a mut [u8]) -> ASyncRet<'a, ()> {
+        Box::pin(async move {
+            self.read_blocks(block_id, buf);
+            Ok(())
+        })
+    }
+    /// 异步写，默认退化为同步写
+    fn write_blocks_async<'a>(&'a self, block_id: usize, buf: &'a [u8]) -> ASyncRet<'a, ()> {
+        Box::pin(async move {
+            self.write_blocks(block_id, buf);
+            Ok(())
+        })
+    }
+    /// 收取已完成的请求并唤醒等待者，供调度循环在中断未送达时兜底
+    fn poll_completions(&self) {}
     fn capacity(&self) -> usize {
         0
     }
@@ -85,11 +102,14 @@ pub trait BlkDriver: Driver {
 #[derive(Debug)]
 pub enum NetError {
     NoData,
+    /// 发送队列已满或设备出错
+    SendFailed,
 }
 
 pub trait NetDriver: Driver {
     fn recv(&self, buf: &mut [u8]) -> Result<usize, NetError>;
     fn send(&self, buf: &[u8]) -> Result<(), NetError>;
+    fn mac_address(&self) -> [u8; 6];
 }
 
 pub trait IntDriver: Driver {
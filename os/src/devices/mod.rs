@@ -40,6 +40,13 @@ pub fn get_blk_devices() -> Vec<Arc<dyn BlkDriver>> {
     ALL_DEVICES.lock().blk.clone()
 }
 
+/// 收取各块设备已完成的请求，见 `BlkDriver::poll_completions`
+pub fn poll_blk_completions() {
+    for dev in get_blk_devices() {
+        dev.poll_completions();
+    }
+}
+
 #[inline]
 pub fn get_int_device() -> Arc<dyn IntDriver> {
     INT_DEVICE.try_get().expect("can't find int device").clone()
@@ -60,6 +67,16 @@ pub fn get_net_device(id: usize) -> Arc<dyn NetDriver> {
         .clone()
 }
 
+#[inline]
+pub fn get_net_devices() -> Vec<Arc<dyn NetDriver>> {
+    ALL_DEVICES.lock().net.clone()
+}
+
+#[inline]
+pub fn has_int_device() -> bool {
+    INT_DEVICE.try_get().is_some()
+}
+
 /// prepare_drivers
 /// This function will init drivers
 #[inline]
@@ -106,6 +123,21 @@ pub fn register_device_irqs(driver: Arc<dyn Driver>) {
     });
 }
 
+/// 处理外部中断：从中断控制器取出 IRQ，交给注册该 IRQ 的驱动
+pub fn handle_irq() {
+    let Some(int_device) = INT_DEVICE.try_get() else {
+        return;
+    };
+    while let Some(irq) = int_device.claim() {
+        let driver = IRQ_MANAGER.lock().get(&irq).cloned();
+        match driver {
+            Some(driver) if driver.try_handle_interrupt(irq) => {}
+            _ => warn!("Unhandled IRQ: {}", irq),
+        }
+        int_device.complete(irq);
+    }
+}
+
 pub fn node_to_interrupts(node: &Node) -> Vec<u32> {
     node.interrupts()
         .map(|x| x.flatten().collect())
@@ -0,0 +1,615 @@
+//! 块设备缓冲缓存
+//!
+//! 位于 lwext4 与 `BlkDriver` 之间，以 `CACHE_BLOCK_SIZE` 为单位缓存扇区：
+//! - LRU 淘汰，容量可在运行时通过 /proc/lingos/block_cache 调整；
+//! - 写入只修改缓存并记为脏块，在 sync、定时写回或淘汰时合并连续脏块写回设备；
+//! - 顺序读取时按窗口预读后续块，窗口随连续命中翻倍增长。
+//!
+//! lwext4 的回调是同步的。文件读写的系统调用先在 [`defer_misses`] 中执行：
+//! 未命中的块只登记下来并让 lwext4 的本次读取失败，调用者 `.await` 异步读入后重试；
+//! 其他路径上未命中的读同步等待设备，但读设备时不持有缓存的锁。预读和 sync/定时
+//! 写回推迟到 trap 返回路径上通过 `run_deferred_io` 异步提交，等待设备期间
+//! 当前任务挂起，其他任务照常运行。
+
+use alloc::collections::{BTreeMap, BTreeSet, VecDeque};
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use spin::{Lazy, Mutex, MutexGuard};
+
+use crate::config::{BLOCK_CACHE_BLOCKS, BLOCK_READAHEAD_MAX, BLOCK_WRITEBACK_INTERVAL_MS, MAX_CPUS};
+use crate::devices::get_blk_device;
+use crate::smp::cpu_id;
+use crate::timer::get_time_ms;
+use crate::utils::error::{GeneralRet, TemplateRet};
+
+/// 扇区大小
+pub const SECTOR_SIZE: usize = 0x200;
+/// 缓存块大小
+pub const CACHE_BLOCK_SIZE: usize = 0x1000;
+const SECTORS_PER_BLOCK: usize = CACHE_BLOCK_SIZE / SECTOR_SIZE;
+/// 待执行预读窗口的上限
+const PENDING_READAHEAD_MAX: usize = 16;
+
+/// (设备号, 缓存块号)
+type BlockKey = (usize, usize);
+
+struct CacheEntry {
+    data: Vec<u8>,
+    dirty: bool,
+    /// 在 `lru` 中的时间戳
+    stamp: u64,
+    /// 最近一次写入时的时间戳，异步写回完成后据此判断期间是否又被写过
+    version: u64,
+}
+
+/// 每个设备的顺序读检测状态
+#[derive(Default)]
+struct ReadaheadState {
+    /// 上一次读取结束处的缓存块号
+    next_block: usize,
+    /// 当前预读窗口（块数）
+    window: usize,
+}
+
+#[derive(Default, Clone, Copy)]
+struct CacheStats {
+    hits: usize,
+    misses: usize,
+    readahead: usize,
+    writebacks: usize,
+}
+
+struct BlockCache {
+    capacity: usize,
+    entries: BTreeMap<BlockKey, CacheEntry>,
+    /// 时间戳 -> 块，最小的最久未使用
+    lru: BTreeMap<u64, BlockKey>,
+    dirty: BTreeSet<BlockKey>,
+    tick: u64,
+    readahead: BTreeMap<usize, ReadaheadState>,
+    /// 待异步预读的 (设备号, 起始块, 结束块)
+    pending_readahead: VecDeque<(usize, usize, usize)>,
+    /// 正在异步预读、尚未进入缓存的块
+    reading: BTreeSet<BlockKey>,
+    /// 正在异步写回的块
+    writing: BTreeSet<BlockKey>,
+    /// 淘汰脏块的次数。不持锁读设备期间若有脏块被写回淘汰，
+    /// 读到的内容可能比刚写回的旧，不能放入缓存
+    evict_epoch: u64,
+    last_writeback_ms: usize,
+    stats: CacheStats,
+}
+
+static BLOCK_CACHE: Lazy<Mutex<BlockCache>> = Lazy::new(|| Mutex::new(BlockCache::new(BLOCK_CACHE_BLOCKS)));
+
+impl BlockCache {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: BTreeMap::new(),
+            lru: BTreeMap::new(),
+            dirty: BTreeSet::new(),
+            tick: 0,
+            readahead: BTreeMap::new(),
+            pending_readahead: VecDeque::new(),
+            reading: BTreeSet::new(),
+            writing: BTreeSet::new(),
+            evict_epoch: 0,
+            last_writeback_ms: 0,
+            stats: CacheStats::default(),
+        }
+    }
+
+    /// 设备上的缓存块数
+    fn device_blocks(dev: usize) -> usize {
+        get_blk_device(dev)
+            .expect("can't find block device")
+            .capacity()
+            / CACHE_BLOCK_SIZE
+    }
+
+    fn touch(&mut self, key: BlockKey) {
+        self.tick += 1;
+        let tick = self.tick;
+        if let Some(entry) = self.entries.get_mut(&key) {
+            self.lru.remove(&entry.stamp);
+            entry.stamp = tick;
+            self.lru.insert(tick, key);
+        }
+    }
+
+    fn insert(&mut self, key: BlockKey, data: Vec<u8>) {
+        self.tick += 1;
+        self.lru.insert(self.tick, key);
+        let old = self.entries.insert(key, CacheEntry { data, dirty: false, stamp: self.tick, version: 0 });
+        if let Some(old) = old {
+            self.lru.remove(&old.stamp);
+        }
+    }
+
+    /// 放入从设备读到的从 `first` 开始的块，已缓存的块不覆盖，返回放入的块数
+    fn insert_read(&mut self, dev: usize, first: usize, buf: &[u8], epoch: u64) -> usize {
+        if epoch != self.evict_epoch {
+            return 0;
+        }
+        let mut inserted = 0;
+        for (i, chunk) in buf.chunks(CACHE_BLOCK_SIZE).enumerate() {
+            let key = (dev, first + i);
+            if !self.entries.contains_key(&key) {
+                self.insert(key, chunk.to_vec());
+                inserted += 1;
+            }
+        }
+        inserted
+    }
+
+    /// [first, last) 中第一个未缓存的块
+    fn first_missing(&self, dev: usize, first: usize, last: usize) -> Option<usize> {
+        (first..last).find(|&blk| !self.entries.contains_key(&(dev, blk)))
+    }
+
+    /// 从 `blk` 开始、不超过 `last` 的连续未缓存块的结束位置
+    fn missing_run(&self, dev: usize, blk: usize, last: usize) -> usize {
+        let mut end = blk + 1;
+        while end < last && !self.entries.contains_key(&(dev, end)) {
+            end += 1;
+        }
+        end
+    }
+
+    /// 记录对 [first, last) 的读取：统计命中，检测顺序读并登记预读窗口
+    fn note_read(&mut self, dev: usize, first: usize, last: usize) {
+        let hits = (first..last).filter(|&blk| self.entries.contains_key(&(dev, blk))).count();
+        self.stats.hits += hits;
+        let state = self.readahead.entry(dev).or_default();
+        if state.next_block == first && first != 0 {
+            state.window = (state.window * 2).clamp(1, BLOCK_READAHEAD_MAX);
+        } else {
+            state.window = 0;
+        }
+        state.next_block = last;
+        let window = state.window;
+        if window > 0 {
+            let limit = (last + window).min(Self::device_blocks(dev));
+            // 预读在返回用户态前才执行，队列过长时丢弃最旧的窗口
+            if last < limit {
+                if self.pending_readahead.len() >= PENDING_READAHEAD_MAX {
+                    self.pending_readahead.pop_front();
+                }
+                self.pending_readahead.push_back((dev, last, limit));
+            }
+        }
+    }
+
+    /// 把扇区范围换算为缓存块范围
+    fn block_range(sector: usize, len: usize) -> (usize, usize) {
+        let first = sector / SECTORS_PER_BLOCK;
+        let last = (sector * SECTOR_SIZE + len + CACHE_BLOCK_SIZE - 1) / CACHE_BLOCK_SIZE;
+        (first, last)
+    }
+
+    /// 从缓存中复制出数据，[sector, sector + buf.len()) 涉及的块必须都已缓存
+    fn copy_out(&mut self, dev: usize, sector: usize, buf: &mut [u8]) {
+        let (first, last) = Self::block_range(sector, buf.len());
+        let start = sector * SECTOR_SIZE;
+        let end = start + buf.len();
+        for blk in first..last {
+            self.touch((dev, blk));
+            let (from, to) = Self::overlap(blk, start, end);
+            let entry = &self.entries[&(dev, blk)];
+            let blk_start = blk * CACHE_BLOCK_SIZE;
+            buf[from - start..to - start].copy_from_slice(&entry.data[from - blk_start..to - blk_start]);
+        }
+        self.shrink();
+    }
+
+    /// 写入的首尾块中只被部分覆盖、尚未缓存的块，需要先读入原有内容
+    fn partial_missing(&self, dev: usize, sector: usize, len: usize) -> Option<usize> {
+        let (first, last) = Self::block_range(sector, len);
+        let start = sector * SECTOR_SIZE;
+        let end = start + len;
+        [first, last - 1].into_iter().find(|&blk| {
+            let (from, to) = Self::overlap(blk, start, end);
+            to - from < CACHE_BLOCK_SIZE && !self.entries.contains_key(&(dev, blk))
+        })
+    }
+
+    /// 写入缓存，部分覆盖的块必须已经缓存
+    fn write(&mut self, dev: usize, sector: usize, buf: &[u8]) {
+        let (first, last) = Self::block_range(sector, buf.len());
+        let start = sector * SECTOR_SIZE;
+        let end = start + buf.len();
+        for blk in first..last {
+            let (from, to) = Self::overlap(blk, start, end);
+            let blk_start = blk * CACHE_BLOCK_SIZE;
+            let key = (dev, blk);
+            if !self.entries.contains_key(&key) {
+                // 整块覆盖，无需先读
+                self.insert(key, vec![0u8; CACHE_BLOCK_SIZE]);
+            }
+            self.touch(key);
+            let entry = self.entries.get_mut(&key).unwrap();
+            entry.data[from - blk_start..to - blk_start].copy_from_slice(&buf[from - start..to - start]);
+            entry.dirty = true;
+            entry.version = self.tick;
+            self.dirty.insert(key);
+        }
+        self.shrink();
+    }
+
+    /// 块 `blk` 与字节范围 [start, end) 的交集
+    fn overlap(blk: usize, start: usize, end: usize) -> (usize, usize) {
+        let blk_start = blk * CACHE_BLOCK_SIZE;
+        (start.max(blk_start), end.min(blk_start + CACHE_BLOCK_SIZE))
+    }
+
+    /// 淘汰最久未使用的块直到不超过容量，脏块先写回，正在异步写回的块跳过
+    fn shrink(&mut self) {
+        let mut budget = self.lru.len();
+        while self.entries.len() > self.capacity && budget > 0 {
+            budget -= 1;
+            let Some((_, key)) = self.lru.first_key_value().map(|(s, k)| (*s, *k)) else { break };
+            if self.writing.contains(&key) {
+                self.touch(key);
+                continue;
+            }
+            self.lru.pop_first();
+            if self.dirty.contains(&key) {
+                self.writeback_run(key);
+                self.evict_epoch += 1;
+            }
+            self.entries.remove(&key);
+        }
+    }
+
+    /// 可由同步路径写回的脏块
+    fn sync_dirty(&self, key: BlockKey) -> bool {
+        self.dirty.contains(&key) && !self.writing.contains(&key)
+    }
+
+    /// 写回包含 `key` 的连续脏块段
+    fn writeback_run(&mut self, key: BlockKey) {
+        let (dev, blk) = key;
+        let mut first = blk;
+        while first > 0 && self.sync_dirty((dev, first - 1)) {
+            first -= 1;
+        }
+        let mut last = blk + 1;
+        while self.sync_dirty((dev, last)) {
+            last += 1;
+        }
+        self.write_out(dev, first, last);
+    }
+
+    /// 把 [first, last) 这一段连续脏块一次写回设备
+    fn write_out(&mut self, dev: usize, first: usize, last: usize) {
+        let mut buf = Vec::with_capacity((last - first) * CACHE_BLOCK_SIZE);
+        for blk in first..last {
+            let entry = self.entries.get_mut(&(dev, blk)).unwrap();
+            buf.extend_from_slice(&entry.data);
+            entry.dirty = false;
+            self.dirty.remove(&(dev, blk));
+        }
+        get_blk_device(dev)
+            .expect("can't find block device")
+            .write_blocks(first * SECTORS_PER_BLOCK, &buf);
+        self.stats.writebacks += last - first;
+    }
+
+    /// 写回 `dev`（None 表示所有设备）中不在异步写回的脏块
+    fn flush(&mut self, dev: Option<usize>) {
+        loop {
+            let key = self
+                .dirty
+                .iter()
+                .find(|k| dev.map_or(true, |d| k.0 == d) && !self.writing.contains(k))
+                .copied();
+            match key {
+                Some(key) => self.writeback_run(key),
+                None => break,
+            }
+        }
+        self.last_writeback_ms = get_time_ms();
+    }
+
+    /// `dev`（None 表示所有设备）中正在异步写回的块
+    fn writing_on(&self, dev: Option<usize>) -> Vec<BlockKey> {
+        self.writing.iter().filter(|k| dev.map_or(true, |d| k.0 == d)).copied().collect()
+    }
+
+    /// [first, last) 中是否有块正在异步写回
+    fn writing_in(&self, dev: usize, first: usize, last: usize) -> bool {
+        self.writing.range((dev, first)..(dev, last)).next().is_some()
+    }
+}
+
+/// 各核在 [`defer_misses`] 中时登记的未命中
+#[derive(Default)]
+struct DeferState {
+    active: bool,
+    miss: Option<(usize, usize, usize)>,
+}
+
+static DEFERRED: [Mutex<DeferState>; MAX_CPUS] =
+    [const { Mutex::new(DeferState { active: false, miss: None }) }; MAX_CPUS];
+
+/// [`defer_misses`] 中遇到的一段未缓存的块
+pub struct Miss {
+    dev: usize,
+    first: usize,
+    last: usize,
+}
+
+impl Miss {
+    /// 异步读入这些块，等待设备期间当前任务挂起
+    pub async fn fetch(self) {
+        // 这些块正由其他任务异步读入时让出 CPU，等它完成
+        if !fill_async(self.dev, self.first, self.last, false).await {
+            crate::task::yield_now().await;
+        }
+    }
+}
+
+/// 执行 `f`，其间未命中的读不等待设备：登记第一段未命中的块并让这次读取失败。
+///
+/// `f` 失败且有登记的未命中时返回 `Err(Miss)`，调用者 `.await` [`Miss::fetch`]
+/// 后重试，所以 `f` 失败时不能留下副作用。
+pub fn defer_misses<T>(f: impl FnOnce() -> TemplateRet<T>) -> Result<TemplateRet<T>, Miss> {
+    let cpu = cpu_id();
+    DEFERRED[cpu].lock().active = true;
+    let res = f();
+    let state = core::mem::take(&mut *DEFERRED[cpu].lock());
+    match (res, state.miss) {
+        (Err(_), Some((dev, first, last))) => Err(Miss { dev, first, last }),
+        (res, _) => Ok(res),
+    }
+}
+
+/// 当前核在 `defer_misses` 中时登记未命中并返回 true
+fn defer_miss(dev: usize, first: usize, last: usize) -> bool {
+    let mut state = DEFERRED[cpu_id()].lock();
+    if !state.active {
+        return false;
+    }
+    state.miss.get_or_insert((dev, first, last));
+    true
+}
+
+/// 同步读入 [blk, last) 这一段未缓存的块，读设备时不持锁
+fn fill_sync(mut cache: MutexGuard<'static, BlockCache>, dev: usize, blk: usize, last: usize) -> MutexGuard<'static, BlockCache> {
+    cache.stats.misses += last - blk;
+    let epoch = cache.evict_epoch;
+    drop(cache);
+    let mut buf = vec![0u8; (last - blk) * CACHE_BLOCK_SIZE];
+    get_blk_device(dev)
+        .expect("can't find block device")
+        .read_blocks(blk * SECTORS_PER_BLOCK, &mut buf);
+    let mut cache = BLOCK_CACHE.lock();
+    cache.insert_read(dev, blk, &buf, epoch);
+    cache
+}
+
+/// 从 `dev` 的第 `sector` 个扇区开始读满 `buf`。
+///
+/// 在 [`defer_misses`] 中遇到未命中时返回 false，不读设备
+pub fn read(dev: usize, sector: usize, buf: &mut [u8]) -> bool {
+    let (first, last) = BlockCache::block_range(sector, buf.len());
+    let mut cache = BLOCK_CACHE.lock();
+    cache.note_read(dev, first, last);
+    while let Some(blk) = cache.first_missing(dev, first, last) {
+        let end = cache.missing_run(dev, blk, last);
+        if defer_miss(dev, blk, end) {
+            return false;
+        }
+        cache = fill_sync(cache, dev, blk, end);
+    }
+    cache.copy_out(dev, sector, buf);
+    true
+}
+
+/// 从 `dev` 的第 `sector` 个扇区开始写入 `buf`，写回推迟到 flush
+pub fn write(dev: usize, sector: usize, buf: &[u8]) {
+    let mut cache = BLOCK_CACHE.lock();
+    while let Some(blk) = cache.partial_missing(dev, sector, buf.len()) {
+        cache = fill_sync(cache, dev, blk, blk + 1);
+    }
+    cache.write(dev, sector, buf);
+}
+
+/// 写回 `dev`（None 表示所有设备）的全部脏块。
+///
+/// 开始时正在异步写回的块要等写回完成，之后仍为脏（写回期间又被写过）的再同步写回；
+/// 等待时不持锁，并主动收割设备完成的请求
+fn flush_sync(dev: Option<usize>) {
+    let mut cache = BLOCK_CACHE.lock();
+    cache.flush(dev);
+    let inflight = cache.writing_on(dev);
+    if inflight.is_empty() {
+        return;
+    }
+    while inflight.iter().any(|k| cache.writing.contains(k)) {
+        drop(cache);
+        crate::devices::poll_blk_completions();
+        core::hint::spin_loop();
+        cache = BLOCK_CACHE.lock();
+    }
+    cache.flush(dev);
+}
+
+/// 写回 `dev` 的全部脏块
+pub fn flush(dev: usize) {
+    flush_sync(Some(dev));
+}
+
+/// 写回所有设备的脏块
+pub fn flush_all() {
+    flush_sync(None);
+}
+
+/// 异步读入 [first, last) 中第一段未缓存、也没有在异步读入的连续块，没有这样的块时返回 false
+async fn fill_async(dev: usize, first: usize, last: usize, readahead: bool) -> bool {
+    let (start, end, epoch) = {
+        let mut cache = BLOCK_CACHE.lock();
+        let absent = |c: &BlockCache, blk: usize| {
+            !c.entries.contains_key(&(dev, blk)) && !c.reading.contains(&(dev, blk))
+        };
+        let Some(start) = (first..last).find(|&blk| absent(&cache, blk)) else { return false };
+        let mut end = start + 1;
+        while end < last && absent(&cache, end) {
+            end += 1;
+        }
+        for blk in start..end {
+            cache.reading.insert((dev, blk));
+        }
+        if !readahead {
+            cache.stats.misses += end - start;
+        }
+        (start, end, cache.evict_epoch)
+    };
+    let mut buf = vec![0u8; (end - start) * CACHE_BLOCK_SIZE];
+    let device = get_blk_device(dev).expect("can't find block device");
+    let res = device.read_blocks_async(start * SECTORS_PER_BLOCK, &mut buf).await;
+
+    let mut cache = BLOCK_CACHE.lock();
+    for blk in start..end {
+        cache.reading.remove(&(dev, blk));
+    }
+    // 等待期间同步路径可能已读入或写入该块，以缓存中的为准
+    if res.is_ok() {
+        let inserted = cache.insert_read(dev, start, &buf, epoch);
+        if readahead {
+            cache.stats.readahead += inserted;
+        }
+    }
+    cache.shrink();
+    true
+}
+
+/// 异步写回 [first, last) 中仍为脏的连续块
+async fn write_run_async(dev: usize, first: usize, last: usize) -> GeneralRet {
+    let mut blk = first;
+    while blk < last {
+        let (start, buf, versions) = {
+            let mut cache = BLOCK_CACHE.lock();
+            while blk < last && !cache.sync_dirty((dev, blk)) {
+                blk += 1;
+            }
+            let start = blk;
+            let mut buf = Vec::new();
+            let mut versions = Vec::new();
+            while blk < last && cache.sync_dirty((dev, blk)) {
+                let entry = &cache.entries[&(dev, blk)];
+                buf.extend_from_slice(&entry.data);
+                versions.push(entry.version);
+                cache.writing.insert((dev, blk));
+                blk += 1;
+            }
+            (start, buf, versions)
+        };
+        if versions.is_empty() {
+            break;
+        }
+        let device = get_blk_device(dev).expect("can't find block device");
+        let res = device.write_blocks_async(start * SECTORS_PER_BLOCK, &buf).await;
+
+        let mut cache = BLOCK_CACHE.lock();
+        for (i, version) in versions.into_iter().enumerate() {
+            let key = (dev, start + i);
+            cache.writing.remove(&key);
+            if res.is_err() {
+                continue;
+            }
+            // 写回期间又被写过的块保持脏状态
+            if let Some(entry) = cache.entries.get_mut(&key) {
+                if entry.version == version {
+                    entry.dirty = false;
+                    cache.dirty.remove(&key);
+                }
+            }
+        }
+        cache.stats.writebacks += blk - start;
+        res?;
+    }
+    Ok(())
+}
+
+/// 异步写回所有设备的脏块，sync/fsync 使用
+pub async fn flush_all_async() -> GeneralRet {
+    // 只写回开始时已脏或正在写回的块，避免持续写入时无法结束
+    let runs = {
+        let mut cache = BLOCK_CACHE.lock();
+        cache.last_writeback_ms = get_time_ms();
+        let pending: BTreeSet<BlockKey> = cache.dirty.union(&cache.writing).copied().collect();
+        let mut runs: Vec<(usize, usize, usize)> = Vec::new();
+        for &(dev, blk) in pending.iter() {
+            match runs.last_mut() {
+                Some(run) if run.0 == dev && run.2 == blk => run.2 += 1,
+                _ => runs.push((dev, blk, blk + 1)),
+            }
+        }
+        runs
+    };
+    for (dev, first, last) in runs {
+        // 先等其他任务对这些块的写回完成，再写回仍为脏的块
+        loop {
+            while BLOCK_CACHE.lock().writing_in(dev, first, last) {
+                crate::task::yield_now().await;
+            }
+            write_run_async(dev, first, last).await?;
+            if !BLOCK_CACHE.lock().writing_in(dev, first, last) {
+                break;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// 执行登记的预读，并在距上次写回超过 `BLOCK_WRITEBACK_INTERVAL_MS` 时写回脏块；
+/// 由 trap 返回路径调用
+pub async fn run_deferred_io() {
+    loop {
+        let job = BLOCK_CACHE.lock().pending_readahead.pop_front();
+        match job {
+            Some((dev, first, last)) => {
+                fill_async(dev, first, last, true).await;
+            }
+            None => break,
+        }
+    }
+    let due = {
+        let cache = BLOCK_CACHE.lock();
+        !cache.dirty.is_empty()
+            && get_time_ms().saturating_sub(cache.last_writeback_ms) >= BLOCK_WRITEBACK_INTERVAL_MS
+    };
+    if due {
+        if let Err(e) = flush_all_async().await {
+            warn!("[bcache] periodic writeback failed: {:?}", e);
+        }
+    }
+}
+
+/// 调整缓存容量（块数），多出的块立即淘汰
+pub fn set_capacity(blocks: usize) {
+    let mut cache = BLOCK_CACHE.lock();
+    cache.capacity = blocks.max(1);
+    cache.shrink();
+}
+
+/// /proc/lingos/block_cache 的内容
+pub fn report() -> String {
+    let cache = BLOCK_CACHE.lock();
+    let stats = cache.stats;
+    format!(
+        "block_size\t{}\ncapacity\t{}\ncached\t{}\ndirty\t{}\nhits\t{}\nmisses\t{}\nreadahead\t{}\nwritebacks\t{}\n",
+        CACHE_BLOCK_SIZE,
+        cache.capacity,
+        cache.entries.len(),
+        cache.dirty.len(),
+        stats.hits,
+        stats.misses,
+        stats.readahead,
+        stats.writebacks,
+    )
+}
@@ -0,0 +1,124 @@
+//! LoongArch QEMU virt 的中断控制器
+//!
+//! 外设中断先进入桥片上的 PCH-PIC，再以向量的形式送到 CPU 内的 EIOINTC，
+//! EIOINTC 把向量汇聚到 HWI0 中断线上。这里只使用 0 号核和 HWI0：
+//! PCH-PIC 的第 n 个输入映射为 EIOINTC 的第 n 个向量，`claim` 返回的 IRQ 号
+//! 就是 PCH-PIC 的输入号，与设备树/ACPI 里写的中断号一致。
+
+use alloc::sync::Arc;
+use core::ptr::{read_volatile, write_volatile};
+use loongArch64::iocsr::{iocsr_read_d, iocsr_read_w, iocsr_write_b, iocsr_write_d, iocsr_write_w};
+use spin::Mutex;
+
+use crate::devices::device::{DeviceType, Driver, IntDriver};
+use crate::devices::VIRT_ADDR_START;
+use crate::driver_define;
+
+/// PCH-PIC 的物理地址
+const PCH_PIC_BASE: usize = 0x1000_0000;
+/// 中断屏蔽，置位表示屏蔽
+const PCH_PIC_MASK: usize = 0x20;
+/// 置位表示以向量的形式发给 EIOINTC
+const PCH_PIC_HTMSI_EN: usize = 0x40;
+/// 置位表示边沿触发
+const PCH_PIC_EDGE: usize = 0x60;
+/// 每个输入对应的 EIOINTC 向量，每项一个字节
+const PCH_PIC_HTVEC: usize = 0x200;
+/// PCH-PIC 的输入数
+const PCH_PIC_IRQS: u32 = 64;
+
+const IOCSR_MISC_FUNC: usize = 0x420;
+const IOCSR_MISC_FUNC_EXT_IOI_EN: u64 = 1 << 48;
+/// 每 32 个向量一组映射到 CPU 中断线，每组一个字节
+const EIOINTC_IPMAP: usize = 0x14c0;
+const EIOINTC_ENABLE: usize = 0x1600;
+const EIOINTC_BOUNCE: usize = 0x1680;
+/// 本核看到的待处理向量，写 1 清除
+const EIOINTC_ISR: usize = 0x1800;
+/// 每个向量路由到的核，每项一个字节
+const EIOINTC_ROUTE: usize = 0x1c00;
+const EIOINTC_VECS: usize = 256;
+
+pub struct Eiointc {
+    /// 保证使能位的读-改-写不会交错
+    lock: Mutex<()>,
+}
+
+impl Eiointc {
+    fn pch_pic_reg(off: usize) -> *mut u32 {
+        (PCH_PIC_BASE + off | VIRT_ADDR_START) as *mut u32
+    }
+
+    fn new() -> Self {
+        // 所有向量都送到 0 号核的 HWI0
+        iocsr_write_d(IOCSR_MISC_FUNC, iocsr_read_d(IOCSR_MISC_FUNC) | IOCSR_MISC_FUNC_EXT_IOI_EN);
+        iocsr_write_d(EIOINTC_IPMAP, 0x0101_0101_0101_0101);
+        for vec in 0..EIOINTC_VECS {
+            iocsr_write_b(EIOINTC_ROUTE + vec, 1);
+        }
+        for i in 0..EIOINTC_VECS / 32 {
+            iocsr_write_w(EIOINTC_ENABLE + i * 4, 0);
+            iocsr_write_w(EIOINTC_BOUNCE + i * 4, 0);
+        }
+        unsafe {
+            write_volatile(Self::pch_pic_reg(PCH_PIC_MASK), u32::MAX);
+            write_volatile(Self::pch_pic_reg(PCH_PIC_MASK + 4), u32::MAX);
+        }
+        Self { lock: Mutex::new(()) }
+    }
+
+    /// 以电平触发方式打开 PCH-PIC 的第 `irq` 个输入
+    fn enable(&self, irq: u32) {
+        if irq >= PCH_PIC_IRQS {
+            warn!("[eiointc] irq {} out of range", irq);
+            return;
+        }
+        let _guard = self.lock.lock();
+        let (word, bit) = ((irq / 32) as usize * 4, 1u32 << (irq % 32));
+        unsafe {
+            let htvec = (PCH_PIC_BASE + PCH_PIC_HTVEC + irq as usize | VIRT_ADDR_START) as *mut u8;
+            write_volatile(htvec, irq as u8);
+            let edge = Self::pch_pic_reg(PCH_PIC_EDGE + word);
+            write_volatile(edge, read_volatile(edge) & !bit);
+            let htmsi = Self::pch_pic_reg(PCH_PIC_HTMSI_EN + word);
+            write_volatile(htmsi, read_volatile(htmsi) | bit);
+            let mask = Self::pch_pic_reg(PCH_PIC_MASK + word);
+            write_volatile(mask, read_volatile(mask) & !bit);
+        }
+        let enable = EIOINTC_ENABLE + word;
+        iocsr_write_w(enable, iocsr_read_w(enable) | bit);
+    }
+}
+
+impl Driver for Eiointc {
+    fn get_id(&self) -> &str {
+        "loongarch-eiointc"
+    }
+
+    fn get_device_wrapper(self: Arc<Self>) -> DeviceType {
+        DeviceType::INT(self.clone())
+    }
+}
+
+impl IntDriver for Eiointc {
+    fn register_irq(&self, irq: u32, _driver: Arc<dyn Driver>) {
+        self.enable(irq);
+    }
+
+    /// 取出编号最小的待处理向量并立即清除，处理期间再次到来的中断不会丢失
+    fn claim(&self) -> Option<u32> {
+        for i in 0..EIOINTC_VECS / 64 {
+            let pending = iocsr_read_d(EIOINTC_ISR + i * 8);
+            if pending != 0 {
+                let bit = pending.trailing_zeros();
+                iocsr_write_d(EIOINTC_ISR + i * 8, 1 << bit);
+                return Some(i as u32 * 64 + bit);
+            }
+        }
+        None
+    }
+
+    fn complete(&self, _irq: u32) {}
+}
+
+driver_define!({ Some(Arc::new(Eiointc::new())) });
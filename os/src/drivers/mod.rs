@@ -3,12 +3,17 @@
 
 
 mod virtio;
+pub mod bcache;
+#[cfg(target_arch = "loongarch64")]
+mod eiointc;
+#[cfg(target_arch = "loongarch64")]
+mod uart;
 use crate::devices::get_blk_device;
 use lwext4_rust::KernelDevOp;
 // pub use virtio::loongson::IRQ_HANDLERS
 use crate::{ utils::error::{SysErrNo, TemplateRet}};
 
-const BLOCK_SIZE: usize = 0x200;
+const BLOCK_SIZE: usize = bcache::SECTOR_SIZE;
 pub type Ext4Disk=Ext4DiskWrapper;
 pub struct Ext4DiskWrapper {
     block_id: usize,
@@ -45,18 +50,17 @@ impl KernelDevOp for Ext4DiskWrapper {
 
     fn write(dev: &mut Self::DevType, buf: &[u8]) -> Result<usize, i32> {
         assert!(dev.offset % BLOCK_SIZE == 0);
-        get_blk_device(dev.blk_id)
-            .expect("can't find block device")
-            .write_blocks(dev.block_id, buf);
+        bcache::write(dev.blk_id, dev.block_id, buf);
         dev.block_id += buf.len() / BLOCK_SIZE;
         Ok(buf.len())
     }
 
     fn read(dev: &mut Self::DevType, buf: &mut [u8]) -> Result<usize, i32> {
         assert!(dev.offset % BLOCK_SIZE == 0);
-        get_blk_device(dev.blk_id)
-            .expect("can't find block device")
-            .read_blocks(dev.block_id, buf);
+        if !bcache::read(dev.blk_id, dev.block_id, buf) {
+            // 未命中且调用者要求异步读入，让 lwext4 的本次操作失败
+            return Err(-1);
+        }
         dev.block_id += buf.len() / BLOCK_SIZE;
         Ok(buf.len())
     }
@@ -82,8 +86,9 @@ impl KernelDevOp for Ext4DiskWrapper {
         Ok(new_pos)
     }
 
-    fn flush(_dev: &mut Self::DevType) -> Result<usize, i32> {
-        todo!()
+    fn flush(dev: &mut Self::DevType) -> Result<usize, i32> {
+        bcache::flush(dev.blk_id);
+        Ok(0)
     }
 }
 
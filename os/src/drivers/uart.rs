@@ -0,0 +1,79 @@
+//! LoongArch QEMU virt 的 NS16550A 控制台串口
+//!
+//! 收发仍然经过 polyhal 的 `DebugConsole`，这里只打开接收中断：收到字符时
+//! 串口经 PCH-PIC 的 2 号输入发出中断，处理函数把字符全部交给终端行规程。
+
+use alloc::sync::Arc;
+use core::ptr::{read_volatile, write_volatile};
+use polyhal::debug_console::DebugConsole;
+
+use crate::devices::device::{DeviceType, Driver, UartDriver};
+use crate::devices::{register_device_irqs, VIRT_ADDR_START};
+use crate::driver_define;
+
+/// 串口寄存器的物理地址
+const UART_BASE: usize = 0x1fe0_01e0;
+/// 串口在 PCH-PIC 上的输入号
+const UART_IRQ: u32 = 2;
+/// 中断使能寄存器，bit 0 为接收数据可用中断
+const UART_IER: usize = 1;
+const UART_IER_RDI: u8 = 0x01;
+/// Modem 控制寄存器，OUT2 打开中断输出
+const UART_MCR: usize = 4;
+const UART_MCR_OUT2: u8 = 0x08;
+
+pub struct Ns16550 {
+    irqs: [u32; 1],
+}
+
+impl Ns16550 {
+    fn reg(off: usize) -> *mut u8 {
+        (UART_BASE + off | VIRT_ADDR_START) as *mut u8
+    }
+
+    fn new() -> Self {
+        unsafe {
+            let mcr = Self::reg(UART_MCR);
+            write_volatile(mcr, read_volatile(mcr) | UART_MCR_OUT2);
+            write_volatile(Self::reg(UART_IER), UART_IER_RDI);
+        }
+        Self { irqs: [UART_IRQ] }
+    }
+}
+
+impl Driver for Ns16550 {
+    fn get_id(&self) -> &str {
+        "ns16550a"
+    }
+
+    fn interrupts(&self) -> &[u32] {
+        &self.irqs
+    }
+
+    fn try_handle_interrupt(&self, _irq: u32) -> bool {
+        // 读空接收 FIFO 后中断线自动撤销
+        crate::fs::tty::receive_all();
+        true
+    }
+
+    fn get_device_wrapper(self: Arc<Self>) -> DeviceType {
+        DeviceType::UART(self.clone())
+    }
+}
+
+impl UartDriver for Ns16550 {
+    fn put(&self, c: u8) {
+        DebugConsole::putchar(c);
+    }
+
+    fn get(&self) -> Option<u8> {
+        DebugConsole::getchar()
+    }
+}
+
+driver_define!({
+    let uart = Arc::new(Ns16550::new());
+    register_device_irqs(uart.clone());
+    crate::fs::tty::set_rx_irq();
+    Some(uart)
+});
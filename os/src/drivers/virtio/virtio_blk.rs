@@ -1,26 +1,220 @@
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll, Waker};
+
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
 use alloc::sync::Arc;
 use alloc::vec::Vec;
 use spin::Mutex;
 use crate::devices::device::{BlkDriver, DeviceType, Driver};
 use crate::devices::{register_device_irqs};
-use virtio_drivers::device::blk::VirtIOBlk;
+use crate::task::{Task, TaskStatus};
+use crate::utils::error::{ASyncRet, GeneralRet, SysErrNo};
+use virtio_drivers::device::blk::{BlkReq, BlkResp, VirtIOBlk};
 use virtio_drivers::transport::Transport;
+use virtio_drivers::Error;
 
 use super::virtio_impl::HalImpl;
 
+/// 已提交给设备的请求
+///
+/// `req`、`resp` 和数据缓冲区在设备完成前都被设备使用，请求装箱以固定地址，
+/// 数据缓冲区由发起者保证在完成前有效（见 `BlkIoFuture` 的 Drop）。
+struct BlkRequest {
+    req: BlkReq,
+    resp: BlkResp,
+    buf: *mut u8,
+    len: usize,
+    write: bool,
+    /// 设备完成后的结果
+    result: Option<GeneralRet>,
+    /// 等待该请求的任务
+    waker: Option<Waker>,
+}
+
+struct BlkInner<T: Transport> {
+    dev: VirtIOBlk<HalImpl, T>,
+    /// token -> 在途或已完成待取走的请求
+    inflight: BTreeMap<u16, Box<BlkRequest>>,
+}
+
 pub struct VirtIOBlock<T: Transport> {
-    inner: Mutex<VirtIOBlk<HalImpl, T>>,
+    inner: Mutex<BlkInner<T>>,
     irqs: Vec<u32>,
 }
 
 unsafe impl<T: Transport> Sync for VirtIOBlock<T> {}
 unsafe impl<T: Transport> Send for VirtIOBlock<T> {}
 
+impl<T: Transport> BlkInner<T> {
+    /// 提交请求，队列满时返回 `Error::QueueFull`
+    fn submit(&mut self, block_id: usize, buf: *mut u8, len: usize, write: bool) -> Result<u16, Error> {
+        let mut request = Box::new(BlkRequest {
+            req: BlkReq::default(),
+            resp: BlkResp::default(),
+            buf,
+            len,
+            write,
+            result: None,
+            waker: None,
+        });
+        let r = &mut *request;
+        let token = unsafe {
+            if write {
+                let data = core::slice::from_raw_parts(buf, len);
+                self.dev.write_blocks_nb(block_id, &mut r.req, data, &mut r.resp)?
+            } else {
+                let data = core::slice::from_raw_parts_mut(buf, len);
+                self.dev.read_blocks_nb(block_id, &mut r.req, data, &mut r.resp)?
+            }
+        };
+        self.inflight.insert(token, request);
+        Ok(token)
+    }
+
+    /// 取出设备已完成的请求，记录结果并唤醒等待者
+    ///
+    /// 已用环只能按完成顺序弹出，所以任何一方都要替其他请求完成收尾
+    fn reap(&mut self) {
+        while let Some(token) = self.dev.peek_used() {
+            let Some(r) = self.inflight.get_mut(&token) else {
+                error!("[virtio-blk] completion for unknown token {}", token);
+                break;
+            };
+            let res = unsafe {
+                if r.write {
+                    let data = core::slice::from_raw_parts(r.buf, r.len);
+                    self.dev.complete_write_blocks(token, &r.req, data, &mut r.resp)
+                } else {
+                    let data = core::slice::from_raw_parts_mut(r.buf, r.len);
+                    self.dev.complete_read_blocks(token, &r.req, data, &mut r.resp)
+                }
+            };
+            r.result = Some(res.map_err(|e| {
+                warn!("[virtio-blk] request {} failed: {:?}", token, e);
+                SysErrNo::EIO
+            }));
+            if let Some(waker) = r.waker.take() {
+                waker.wake();
+            }
+        }
+    }
+
+    /// 请求完成时取走结果
+    fn take_result(&mut self, token: u16) -> Option<GeneralRet> {
+        let done = self.inflight.get(&token)?.result.is_some();
+        if done {
+            self.inflight.remove(&token).and_then(|r| r.result)
+        } else {
+            None
+        }
+    }
+}
+
+impl<T: Transport + 'static> VirtIOBlock<T> {
+    /// 同步路径：提交后自旋等待完成，等待期间不持有设备锁
+    fn request_sync(&self, block_id: usize, buf: *mut u8, len: usize, write: bool) {
+        let token = loop {
+            let mut inner = self.inner.lock();
+            match inner.submit(block_id, buf, len, write) {
+                Ok(token) => break token,
+                Err(Error::QueueFull) => inner.reap(),
+                Err(e) => panic!("can't submit request to virtio block: {:?}", e),
+            }
+            drop(inner);
+            core::hint::spin_loop();
+        };
+        loop {
+            let mut inner = self.inner.lock();
+            inner.reap();
+            if let Some(res) = inner.take_result(token) {
+                res.expect("can't access block by virtio block");
+                return;
+            }
+            drop(inner);
+            core::hint::spin_loop();
+        }
+    }
+}
+
+/// 异步块请求：提交后挂起任务，由设备中断（或调度循环中的轮询）唤醒
+struct BlkIoFuture<'a, T: Transport> {
+    dev: &'a VirtIOBlock<T>,
+    block_id: usize,
+    buf: *mut u8,
+    len: usize,
+    write: bool,
+    token: Option<u16>,
+}
+
+unsafe impl<T: Transport> Send for BlkIoFuture<'_, T> {}
+
+impl<T: Transport + 'static> Future for BlkIoFuture<'_, T> {
+    type Output = GeneralRet;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        let mut inner = this.dev.inner.lock();
+        inner.reap();
+        let token = match this.token {
+            Some(token) => token,
+            None => match inner.submit(this.block_id, this.buf, this.len, this.write) {
+                Ok(token) => {
+                    this.token = Some(token);
+                    token
+                }
+                // 队列满：让出 CPU，稍后重试
+                Err(Error::QueueFull) => {
+                    cx.waker().wake_by_ref();
+                    return Poll::Pending;
+                }
+                Err(e) => {
+                    warn!("[virtio-blk] submit failed: {:?}", e);
+                    return Poll::Ready(Err(SysErrNo::EIO));
+                }
+            },
+        };
+        if let Some(res) = inner.take_result(token) {
+            this.token = None;
+            return Poll::Ready(res);
+        }
+        inner.inflight.get_mut(&token).unwrap().waker = Some(cx.waker().clone());
+        let task = cx.waker().data() as *const Task;
+        unsafe { &*task }.set_state(TaskStatus::Blocking);
+        Poll::Pending
+    }
+}
+
+impl<T: Transport> Drop for BlkIoFuture<'_, T> {
+    fn drop(&mut self) {
+        // 设备仍在访问缓冲区，必须等它完成后才能释放
+        if let Some(token) = self.token {
+            loop {
+                let mut inner = self.dev.inner.lock();
+                inner.reap();
+                if inner.take_result(token).is_some() {
+                    break;
+                }
+                drop(inner);
+                core::hint::spin_loop();
+            }
+        }
+    }
+}
+
 impl<T: Transport + 'static> Driver for VirtIOBlock<T> {
     fn interrupts(&self) -> &[u32] {
         &self.irqs
     }
 
+    fn try_handle_interrupt(&self, _irq: u32) -> bool {
+        let mut inner = self.inner.lock();
+        let handled = inner.dev.ack_interrupt();
+        inner.reap();
+        handled
+    }
+
     fn get_id(&self) -> &str {
         "virtio-blk"
     }
@@ -32,29 +226,57 @@ impl<T: Transport + 'static> Driver for VirtIOBlock<T> {
 
 impl<T: Transport + 'static> BlkDriver for VirtIOBlock<T> {
     fn read_blocks(&self, block_id: usize, buf: &mut [u8]) {
-        self.inner
-            .lock()
-            .read_blocks(block_id, buf)
-            .expect("can't read block by virtio block");
+        self.request_sync(block_id, buf.as_mut_ptr(), buf.len(), false);
     }
 
     fn write_blocks(&self, block_id: usize, buf: &[u8]) {
-        self.inner
-            .lock()
-            .write_blocks(block_id, buf)
-            .expect("can't write block by virtio block");
+        self.request_sync(block_id, buf.as_ptr() as *mut u8, buf.len(), true);
+    }
+
+    fn read_blocks_async<'a>(&'a self, block_id: usize, buf: &'a mut [u8]) -> ASyncRet<'a, ()> {
+        Box::pin(BlkIoFuture {
+            dev: self,
+            block_id,
+            buf: buf.as_mut_ptr(),
+            len: buf.len(),
+            write: false,
+            token: None,
+        })
+    }
+
+    fn write_blocks_async<'a>(&'a self, block_id: usize, buf: &'a [u8]) -> ASyncRet<'a, ()> {
+        Box::pin(BlkIoFuture {
+            dev: self,
+            block_id,
+            buf: buf.as_ptr() as *mut u8,
+            len: buf.len(),
+            write: true,
+            token: None,
+        })
+    }
+
+    fn poll_completions(&self) {
+        // 中断可能没有送达（如内核态关中断空转时），由调度循环兜底
+        if let Some(mut inner) = self.inner.try_lock() {
+            if !inner.inflight.is_empty() {
+                inner.reap();
+            }
+        }
     }
 
     fn capacity(&self) -> usize {
-        self.inner.lock().capacity() as usize * 0x200
+        self.inner.lock().dev.capacity() as usize * 0x200
     }
 }
 
 pub fn init<T: Transport + 'static>(transport: T, irqs: Vec<u32>) -> Arc<dyn Driver> {
+    let mut dev = VirtIOBlk::<HalImpl, T>::new(transport).expect("failed to create blk driver");
+    dev.enable_interrupts();
     let blk_device = Arc::new(VirtIOBlock {
-        inner: Mutex::new(
-            VirtIOBlk::<HalImpl, T>::new(transport).expect("failed to create blk driver"),
-        ),
+        inner: Mutex::new(BlkInner {
+            dev,
+            inflight: BTreeMap::new(),
+        }),
         irqs,
     });
 
@@ -10,7 +10,6 @@ use virtio_drivers::transport::Transport;
 use spin::Mutex;
 use super::virtio_impl::HalImpl;
 
-#[allow(dead_code)]
 pub struct VirtIONet<T: Transport> {
     inner: Mutex<net::VirtIONet<HalImpl, T, 32>>,
     irqs: Vec<u32>,
@@ -21,7 +20,20 @@ unsafe impl<T: Transport> Send for VirtIONet<T> {}
 
 impl<T: Transport + 'static> Driver for VirtIONet<T> {
     fn get_id(&self) -> &str {
-        "virtio-blk"
+        "virtio-net"
+    }
+
+    fn interrupts(&self) -> &[u32] {
+        &self.irqs
+    }
+
+    fn try_handle_interrupt(&self, _irq: u32) -> bool {
+        // 中断上下文里只应答设备，收包交给协议栈在任务上下文中处理
+        let handled = self.inner.lock().ack_interrupt();
+        if handled {
+            crate::fs::net::stack::notify_rx();
+        }
+        handled
     }
 
     fn get_device_wrapper(self: Arc<Self>) -> DeviceType {
@@ -31,11 +43,11 @@ impl<T: Transport + 'static> Driver for VirtIONet<T> {
 
 impl<T: Transport + 'static> NetDriver for VirtIONet<T> {
     fn recv(&self, buf: &mut [u8]) -> Result<usize, NetError> {
-        let packet = self.inner.lock().receive().map_err(|_| NetError::NoData)?;
+        let mut inner = self.inner.lock();
+        let packet = inner.receive().map_err(|_| NetError::NoData)?;
         let rlen = cmp::min(buf.len(), packet.packet_len());
         buf[..rlen].copy_from_slice(&packet.packet()[..rlen]);
-        self.inner
-            .lock()
+        inner
             .recycle_rx_buffer(packet)
             .expect("can't receive data");
         Ok(rlen)
@@ -45,18 +57,26 @@ impl<T: Transport + 'static> NetDriver for VirtIONet<T> {
         self.inner
             .lock()
             .send(TxBuffer::from(buf))
-            .expect("can't send data");
-        Ok(())
+            .map_err(|_| NetError::SendFailed)
+    }
+
+    fn mac_address(&self) -> [u8; 6] {
+        self.inner.lock().mac_address()
     }
 }
 
 pub fn init<T: Transport + 'static>(transport: T, irqs: Vec<u32>) -> Arc<dyn Driver> {
     info!("Initailize virtio-net device, irqs: {:?}", irqs);
+    let mut inner = net::VirtIONet::<HalImpl, T, 32>::new(transport, 2048)
+        .expect("failed to create net driver");
+    if irqs.is_empty() {
+        // 没有可用的中断线，协议栈会轮询设备
+        inner.disable_interrupts();
+    } else {
+        inner.enable_interrupts();
+    }
     let net_device = Arc::new(VirtIONet {
-        inner: Mutex::new(
-            net::VirtIONet::<HalImpl, T, 32>::new(transport, 2048)
-                .expect("failed to create blk driver"),
-        ),
+        inner: Mutex::new(inner),
         irqs,
     });
     register_device_irqs(net_device.clone());
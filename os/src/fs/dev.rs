@@ -8,21 +8,15 @@
 /// - `/dev/random`: 提供随机数流的设备。
 /// - `/dev/tty`: 用于与标准输入和标准输出交互的终端设备。
 /// - `/dev/cpu_dma_latency`: 用于获取/设置CPU最大反应时间的设备。
+/// - `/dev/vdX`: 驱动层块设备，按字节偏移读写扇区。
+/// - `/dev/ttySX`: 驱动层串口。
 ///
-/// 该模块使用设备树（`DEVICES`）将设备名称映射到设备编号，
-/// 允许动态注册和查找设备。每个设备都实现了`File` trait，
-/// 使其可以在虚拟文件系统中使用。
+/// 设备节点由 devfs（`fs::devfs`）管理，本模块只提供设备文件本身。
 ///
 /// # 设备注册
 ///
-/// 设备使用`register_device`函数注册，该函数将条目添加到
-/// `DEVICES`树，将设备名称与唯一的设备编号相关联。
-/// `unregister_device`函数从树中删除设备。
-///
-/// # 设备访问
-///
-/// `open_device_file`函数用于获取给定设备路径的`Arc<dyn File>`。
-/// 这允许像打开常规文件一样打开设备并与之交互。
+/// `register_builtin_devices`把本模块实现的字符设备连同主次设备号登记到 devfs；
+/// 驱动层的块设备和串口由 devfs 按`ALL_DEVICES`自动登记为`DevBlock`、`DevUart`。
 ///
 /// # 设备实现
 ///
@@ -36,6 +30,8 @@ use crate::{
 };
 use alloc::{
     collections::BTreeMap,
+    vec,
+    vec::Vec,
     fmt::{Debug, Formatter},
     format,
     string::{String, ToString},
@@ -44,10 +40,18 @@ use alloc::{
 use async_trait::async_trait;
 use linux_raw_sys::general::xattr_args;
 use core::{cmp::min, task::Waker};
-use spin::{Lazy, Mutex, RwLock};
+use spin::{Mutex, RwLock};
 
 use alloc::boxed::Box;
-use super::{stat::StMode, File, Kstat, PollEvents, Stdin, Stdout};
+use super::{stat::StMode, File, InodeType, Kstat, PollEvents, Stdin, Stdout};
+use super::devfs::{device_stat, find_device, register_device};
+use super::tty::{tty_ioctl, tty_poll_readable};
+use crate::syscall::flags::{BLKGETSIZE, BLKGETSIZE64, BLKSSZGET, RTC_RD_TIME};
+use crate::timer::get_usertime;
+use crate::devices::device::{BlkDriver, UartDriver};
+use crate::drivers::bcache;
+use crate::task::yield_now;
+use lwext4_rust::bindings::{SEEK_CUR, SEEK_END, SEEK_SET};
 
 pub struct DevZero;
 pub struct DevNull;
@@ -60,47 +64,28 @@ pub struct DevCpuDmaLatency {
     reaction_time: RwLock<u32>, //进程最大反应时间,即CPU最大延迟,单位us
 }
 
-//设备树，通过设备名称可以查找到设备号
-pub static DEVICES: Lazy<Mutex<BTreeMap<String, usize>>> =
-    Lazy::new(|| Mutex::new(BTreeMap::new()));
-
-//从1起算，0为其他抽象文件
-static mut DEV_NO: usize = 1;
-
-pub fn register_device(abs_path: &str) {
-    unsafe {
-        DEVICES.lock().insert(abs_path.to_string(), DEV_NO);
-        info!("register device {},{}", abs_path, DEV_NO);
-        DEV_NO += 1;
+/// 这里实现的设备在 devfs 中的登记：(名字, 主设备号, 次设备号, 打开方法)
+const BUILTIN_DEVICES: [(&str, u32, u32, fn() -> Arc<dyn File>); 9] = [
+    ("null", 1, 3, || Arc::new(DevNull::new())),
+    ("zero", 1, 5, || Arc::new(DevZero::new())),
+    ("random", 1, 8, || Arc::new(DevRandom::new())),
+    ("urandom", 1, 9, || Arc::new(DevRandom::new())),
+    ("tty", 5, 0, || Arc::new(DevTty::new())),
+    ("rtc", 252, 0, || Arc::new(DevRtc::new())),
+    ("rtc0", 252, 0, || Arc::new(DevRtc::new())),
+    ("misc/rtc", 10, 135, || Arc::new(DevRtc::new())),
+    ("cpu_dma_latency", 10, 62, || Arc::new(DevCpuDmaLatency::new())),
+];
+
+/// 把本模块实现的字符设备登记到 devfs
+pub fn register_builtin_devices() {
+    for (name, major, minor, open) in BUILTIN_DEVICES {
+        register_device(name, InodeType::CharDevice, major, minor, Arc::new(move || Ok(open())));
     }
 }
 
-pub fn unregister_device(abs_path: &str) {
-    DEVICES.lock().remove(&abs_path.to_string());
-}
-
-pub fn find_device(abs_path: &str) -> bool {
-   let a= DEVICES.lock().get(abs_path).is_some();
-    // info!("find device {},{}", abs_path,a);
-    a
-}
-
 pub fn get_devno(abs_path: &str) -> usize {
-    *DEVICES.lock().get(abs_path).unwrap()
-}
-
-pub fn open_device_file(abs_path: &str) -> Result<Arc<dyn File>, SysErrNo> {
-    match abs_path {
-        "/dev/zero" => Ok(Arc::new(DevZero::new())),
-        "/dev/null" => Ok(Arc::new(DevNull::new())),
-        "/dev/rtc" | "/dev/rtc0" | "/dev/misc/rtc" => Ok(Arc::new(DevRtc::new())),
-        "/dev/random" => Ok(Arc::new(DevRandom::new())),
-        "/dev/tty" => Ok(Arc::new(DevTty::new())),
-        "/dev/cpu_dma_latency" => Ok(Arc::new(DevCpuDmaLatency::new())),
-
-       
-        _ => Err(SysErrNo::ENOENT),
-    }
+    find_device(abs_path).map_or(0, |e| e.rdev())
 }
 
 /// zero设备
@@ -116,6 +101,9 @@ impl DevZero {
 
 #[async_trait]
 impl File for DevZero {
+    fn get_path(&self) -> String {
+        self.get_path()
+    }
     fn as_any(&self) -> &dyn core::any::Any {
         self
     }
@@ -144,14 +132,7 @@ impl File for DevZero {
     }
 
     fn fstat(&self) -> Kstat {
-        let devno = get_devno("/dev/zero");
-        Kstat {
-            st_dev: devno,
-            st_mode: StMode::FCHR.bits(),
-            st_rdev: devno,
-            st_nlink: 1,
-            ..Kstat::default()
-        }
+        device_stat(&self.get_path())
     }
 
     fn poll(&self, events: PollEvents, _waker: &Waker) -> PollEvents {
@@ -182,6 +163,9 @@ impl DevNull {
 }
 #[async_trait]
 impl File for DevNull {
+    fn get_path(&self) -> String {
+        self.get_path()
+    }
     fn as_any(&self) -> &dyn core::any::Any {
         self
     }
@@ -210,14 +194,7 @@ impl File for DevNull {
     }
 
     fn fstat(&self) -> Kstat {
-        let devno = get_devno("/dev/null");
-        Kstat {
-            st_dev: devno,
-            st_mode: StMode::FCHR.bits(),
-            st_rdev: devno,
-            st_nlink: 1,
-            ..Kstat::default()
-        }
+        device_stat(&self.get_path())
     }
 
     fn poll(&self, events: PollEvents, _waker: &Waker) -> PollEvents {
@@ -271,6 +248,54 @@ impl Debug for RtcTime {
     }
 }
 
+/// `RTC_RD_TIME` 返回的 `struct rtc_time`
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct RtcTm {
+    tm_sec: i32,
+    tm_min: i32,
+    tm_hour: i32,
+    tm_mday: i32,
+    tm_mon: i32,
+    tm_year: i32,
+    tm_wday: i32,
+    tm_yday: i32,
+    tm_isdst: i32,
+}
+
+impl RtcTm {
+    /// 由 Unix 时间戳换算 UTC 日期
+    fn from_unix(secs: usize) -> Self {
+        let days = (secs / 86400) as i64;
+        let rem = (secs % 86400) as i32;
+        // 以 0000-03-01 为纪元换算公历日期
+        let z = days + 719468;
+        let era = z.div_euclid(146097);
+        let doe = z - era * 146097;
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let mday = doy - (153 * mp + 2) / 5 + 1;
+        let mon = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = yoe + era * 400 + (mon <= 2) as i64;
+        let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+        const CUM_DAYS: [i64; 12] = [0, 31, 59, 90, 120, 151, 181, 212, 243, 273, 304, 334];
+        let yday = CUM_DAYS[mon as usize - 1] + mday - 1 + (leap && mon > 2) as i64;
+        Self {
+            tm_sec: rem % 60,
+            tm_min: rem / 60 % 60,
+            tm_hour: rem / 3600,
+            tm_mday: mday as i32,
+            tm_mon: mon as i32 - 1,
+            tm_year: year as i32 - 1900,
+            // 1970-01-01 是星期四
+            tm_wday: ((days + 4) % 7) as i32,
+            tm_yday: yday as i32,
+            tm_isdst: 0,
+        }
+    }
+}
+
 /// 时钟设备
 impl DevRtc {
  
@@ -286,6 +311,9 @@ impl DevRtc {
 
 #[async_trait]
 impl File for DevRtc {
+    fn get_path(&self) -> String {
+        self.get_path()
+    }
     fn as_any(&self) -> &dyn core::any::Any {
         self
     }
@@ -318,17 +346,22 @@ impl File for DevRtc {
         Ok(user_buf.len())
     }
 
-    fn fstat(&self) -> Kstat {
-        let devno = get_devno("/dev/rtc");
-        Kstat {
-            st_dev: devno,
-            st_mode: StMode::FCHR.bits(),
-            st_rdev: devno,
-            st_nlink: 1,
-            ..Kstat::default()
+    async fn ioctl(&self, cmd: usize, arg: usize) -> SyscallRet {
+        match cmd {
+            RTC_RD_TIME => {
+                // 与 CLOCK_REALTIME 使用同一时间源
+                let tm = RtcTm::from_unix(get_usertime().tv_sec);
+                current_process().memory_set.lock().await.put_user(arg, tm).await?;
+                Ok(0)
+            }
+            _ => Err(SysErrNo::ENOTTY),
         }
     }
 
+    fn fstat(&self) -> Kstat {
+        device_stat(&self.get_path())
+    }
+
     fn poll(&self, events: PollEvents, _waker: &Waker) -> PollEvents {
         let mut revents = PollEvents::empty();
         if events.contains(PollEvents::POLLIN) {
@@ -358,6 +391,9 @@ impl DevRandom {
 
 #[async_trait]
 impl File for DevRandom {
+    fn get_path(&self) -> String {
+        self.get_path()
+    }
     fn as_any(&self) -> &dyn core::any::Any {
         self
     }
@@ -386,14 +422,7 @@ impl File for DevRandom {
     }
 
     fn fstat(&self) -> Kstat {
-        let devno = get_devno("/dev/random");
-        Kstat {
-            st_dev: devno,
-            st_mode: StMode::FCHR.bits(),
-            st_rdev: devno,
-            st_nlink: 1,
-            ..Kstat::default()
-        }
+        device_stat(&self.get_path())
     }
 
     fn poll(&self, events: PollEvents, _waker: &Waker) -> PollEvents {
@@ -433,6 +462,9 @@ impl DevTty {
 
 #[async_trait]
 impl File for DevTty {
+    fn get_path(&self) -> String {
+        self.get_path()
+    }
     fn as_any(&self) -> &dyn core::any::Any {
         self
     }
@@ -460,28 +492,22 @@ impl File for DevTty {
         Stdout.write(user_buf).await
     }
 
+    async fn ioctl(&self, cmd: usize, arg: usize) -> SyscallRet {
+        tty_ioctl(cmd, arg).await
+    }
+
     fn fstat(&self) -> Kstat {
-        let devno = get_devno("/dev/tty");
-        Kstat {
-            st_dev: devno,
-            st_mode: StMode::FCHR.bits(),
-            st_rdev: devno,
-            st_nlink: 1,
-            ..Kstat::default()
-        }
+        device_stat(&self.get_path())
     }
 
-    fn poll(&self, events: PollEvents, _waker: &Waker) -> PollEvents {
+    fn poll(&self, events: PollEvents, waker: &Waker) -> PollEvents {
         let mut revents = PollEvents::empty();
-        if events.contains(PollEvents::POLLIN) {
+        if events.contains(PollEvents::POLLIN) && tty_poll_readable(waker) {
             revents |= PollEvents::POLLIN;
         }
         if events.contains(PollEvents::POLLOUT) {
             revents |= PollEvents::POLLOUT;
         }
-        if events.contains(PollEvents::POLLERR) {
-            revents |= PollEvents::POLLERR;
-        }
         revents
     }
 
@@ -505,6 +531,9 @@ impl DevCpuDmaLatency {
 
 #[async_trait]
 impl File for DevCpuDmaLatency {
+    fn get_path(&self) -> String {
+        self.get_path()
+    }
     fn as_any(&self) -> &dyn core::any::Any {
         self
     }
@@ -557,14 +586,7 @@ impl File for DevCpuDmaLatency {
     }
 
     fn fstat(&self) -> Kstat {
-        let devno = get_devno("/dev/cpu_dma_latency");
-        Kstat {
-            st_dev: devno,
-            st_mode: StMode::FCHR.bits(),
-            st_rdev: devno,
-            st_nlink: 1,
-            ..Kstat::default()
-        }
+        device_stat(&self.get_path())
     }
 
     fn poll(&self, events: PollEvents, _waker: &Waker) -> PollEvents {
@@ -754,4 +776,208 @@ impl File for DevShm {
     fn get_path(&self)->String{
         return "/dev/shm/cyclictest9".to_string()
     }
-}
\ No newline at end of file
+}
+
+const SECTOR_SIZE: usize = 512;
+/// 块设备文件每次读写经过的内核缓冲区大小
+const DEV_BLOCK_CHUNK: usize = 16 * bcache::CACHE_BLOCK_SIZE;
+
+/// 驱动层块设备，按字节偏移读写，经过块缓存，与文件系统看到的内容一致
+pub struct DevBlock {
+    path: String,
+    /// 在 `ALL_DEVICES` 中的编号，也是块缓存中的设备号
+    id: usize,
+    dev: Arc<dyn BlkDriver>,
+    offset: Mutex<usize>,
+}
+
+impl DevBlock {
+    pub fn new(path: &str, id: usize, dev: Arc<dyn BlkDriver>) -> Self {
+        Self {
+            path: path.to_string(),
+            id,
+            dev,
+            offset: Mutex::new(0),
+        }
+    }
+}
+
+#[async_trait]
+impl File for DevBlock {
+    fn get_path(&self) -> String {
+        self.path.clone()
+    }
+    fn as_any(&self) -> &dyn core::any::Any {
+        self
+    }
+    fn readable<'a>(&'a self) -> TemplateRet<bool> {
+        Ok(true)
+    }
+
+    fn writable<'a>(&'a self) -> TemplateRet<bool> {
+        Ok(true)
+    }
+
+    async fn read<'a>(
+        &self,
+        mut user_buf: UserBuffer<'a>
+    ) -> Result<usize, SysErrNo> {
+        let mut offset = self.offset.lock();
+        let end = min(*offset + user_buf.len(), self.dev.capacity());
+        let mut done = 0;
+        // 每次最多经过 DEV_BLOCK_CHUNK 字节的内核缓冲区，不按用户给出的长度分配
+        while *offset < end {
+            let head = *offset % SECTOR_SIZE;
+            let n = min(DEV_BLOCK_CHUNK, end - *offset);
+            let mut buf = vec![0u8; head + n];
+            bcache::read(self.id, *offset / SECTOR_SIZE, &mut buf);
+            user_buf.write_at(done, &buf[head..]);
+            done += n;
+            *offset += n;
+        }
+        Ok(done)
+    }
+
+    async fn write<'a>(
+        &self,
+        user_buf: UserBuffer<'a>
+    ) -> Result<usize, SysErrNo> {
+        let mut offset = self.offset.lock();
+        let capacity = self.dev.capacity();
+        if *offset >= capacity && !user_buf.is_empty() {
+            return Err(SysErrNo::ENOSPC);
+        }
+        let len = min(user_buf.len(), capacity - *offset);
+        let mut done = 0;
+        while done < len {
+            let head = *offset % SECTOR_SIZE;
+            let n = min(DEV_BLOCK_CHUNK, len - done);
+            let mut buf = vec![0u8; head + n];
+            // 起点不在扇区边界时先读出扇区开头的原内容
+            if head > 0 {
+                bcache::read(self.id, *offset / SECTOR_SIZE, &mut buf[..head]);
+            }
+            buf[head..].copy_from_slice(&user_buf.read_from(done, n));
+            bcache::write(self.id, *offset / SECTOR_SIZE, &buf);
+            done += n;
+            *offset += n;
+        }
+        Ok(done)
+    }
+
+    async fn ioctl(&self, cmd: usize, arg: usize) -> SyscallRet {
+        let capacity = self.dev.capacity();
+        let proc = current_process();
+        let mut ms = proc.memory_set.lock().await;
+        match cmd {
+            BLKGETSIZE64 => ms.put_user(arg, capacity as u64).await?,
+            BLKGETSIZE => ms.put_user(arg, capacity / SECTOR_SIZE).await?,
+            BLKSSZGET => ms.put_user(arg, SECTOR_SIZE as i32).await?,
+            _ => return Err(SysErrNo::ENOTTY),
+        }
+        Ok(0)
+    }
+
+    fn fstat(&self) -> Kstat {
+        let capacity = self.dev.capacity();
+        Kstat {
+            st_size: capacity as isize,
+            st_blksize: SECTOR_SIZE as i32,
+            st_blocks: (capacity / SECTOR_SIZE) as isize,
+            ..device_stat(&self.path)
+        }
+    }
+
+    fn poll(&self, events: PollEvents, _waker: &Waker) -> PollEvents {
+        events & (PollEvents::POLLIN | PollEvents::POLLOUT)
+    }
+
+    fn lseek(&self, offset: isize, whence: u32) -> SyscallRet {
+        let mut cur = self.offset.lock();
+        let base = match whence {
+            SEEK_SET => 0,
+            SEEK_CUR => *cur as isize,
+            SEEK_END => self.dev.capacity() as isize,
+            _ => return Err(SysErrNo::EINVAL),
+        };
+        let new = base.checked_add(offset).filter(|o| *o >= 0).ok_or(SysErrNo::EINVAL)?;
+        *cur = new as usize;
+        Ok(new as usize)
+    }
+}
+
+/// 驱动层串口，直接收发原始字节
+pub struct DevUart {
+    path: String,
+    uart: Arc<dyn UartDriver>,
+}
+
+impl DevUart {
+    pub fn new(path: &str, uart: Arc<dyn UartDriver>) -> Self {
+        Self {
+            path: path.to_string(),
+            uart,
+        }
+    }
+}
+
+#[async_trait]
+impl File for DevUart {
+    fn get_path(&self) -> String {
+        self.path.clone()
+    }
+    fn as_any(&self) -> &dyn core::any::Any {
+        self
+    }
+    fn readable<'a>(&'a self) -> TemplateRet<bool> {
+        Ok(true)
+    }
+
+    fn writable<'a>(&'a self) -> TemplateRet<bool> {
+        Ok(true)
+    }
+
+    async fn read<'a>(
+        &self,
+        mut user_buf: UserBuffer<'a>
+    ) -> Result<usize, SysErrNo> {
+        if user_buf.is_empty() {
+            return Ok(0);
+        }
+        // 至少等到一个字节，之后只取已经到达的
+        let mut buf = Vec::new();
+        loop {
+            match self.uart.get() {
+                Some(c) => buf.push(c),
+                None if buf.is_empty() => yield_now().await,
+                None => break,
+            }
+            if buf.len() == user_buf.len() {
+                break;
+            }
+        }
+        Ok(user_buf.write(&buf))
+    }
+
+    async fn write<'a>(
+        &self,
+        user_buf: UserBuffer<'a>
+    ) -> Result<usize, SysErrNo> {
+        for c in user_buf.read(user_buf.len()) {
+            self.uart.put(c);
+        }
+        Ok(user_buf.len())
+    }
+
+    async fn ioctl(&self, cmd: usize, arg: usize) -> SyscallRet {
+        tty_ioctl(cmd, arg).await
+    }
+
+    fn fstat(&self) -> Kstat {
+        device_stat(&self.path)
+    }
+
+    fn poll(&self, events: PollEvents, _waker: &Waker) -> PollEvents {
+        events & PollEvents::POLLOUT
+    }
+}
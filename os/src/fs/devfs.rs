@@ -0,0 +1,409 @@
+//! 挂载在 /dev 上的 devfs
+//!
+//! 设备节点不落盘，由设备的提供者在运行时调用 [`register_device`] 登记名字、设备号和打开方法，
+//! 目录列表和 `st_rdev` 都从登记表生成。驱动层的块设备、串口在 [`register_driver_devices`]
+//! 中按 `ALL_DEVICES` 自动登记，新增驱动不需要再改 `fs/dev.rs`。
+
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use lwext4_rust::InodeTypes;
+use spin::{Lazy, Mutex};
+
+use crate::config::PAGE_SIZE;
+use crate::devices::ALL_DEVICES;
+use crate::fs::dev::{DevBlock, DevUart};
+use crate::fs::inode::InodeType;
+use crate::fs::mount::is_path_prefix;
+use crate::fs::stat::Kstat;
+use crate::fs::vfs::vfs_ops::{VfsNodeOps, VfsOps};
+use crate::fs::{Dirent, File, OpenFlags, Statfs};
+use crate::timer::get_time_ms;
+use crate::utils::error::{GeneralRet, SysErrNo, SyscallRet};
+
+const DEVFS_MAGIC: i64 = 0x1373;
+/// devfs 固定挂载在这里，设备文件按这个前缀换算登记名
+pub const DEV_ROOT: &str = "/dev";
+
+/// virtio-blk 的主设备号，每块盘占 16 个次设备号（分区）
+const VIRTBLK_MAJOR: u32 = 254;
+const TTYS_MAJOR: u32 = 4;
+const TTYS_MINOR_BASE: u32 = 64;
+
+/// 打开设备时调用，返回一个新的设备文件对象
+pub type DevOpen = Arc<dyn Fn() -> Result<Arc<dyn File>, SysErrNo> + Send + Sync>;
+
+/// 登记表中的一项，`open` 为空表示目录
+#[derive(Clone)]
+pub struct DevEntry {
+    pub ty: InodeType,
+    pub major: u32,
+    pub minor: u32,
+    pub perm: u32,
+    ino: usize,
+    ctime: isize,
+    open: Option<DevOpen>,
+}
+
+impl DevEntry {
+    pub fn rdev(&self) -> usize {
+        makedev(self.major, self.minor)
+    }
+}
+
+/// 设备登记表，键是相对 /dev 的路径（如 `null`、`misc/rtc`）
+static DEV_TABLE: Lazy<Mutex<BTreeMap<String, DevEntry>>> = Lazy::new(|| Mutex::new(BTreeMap::new()));
+/// 根目录占用 1 号
+static NEXT_INO: AtomicUsize = AtomicUsize::new(2);
+
+/// 和 glibc 的 `makedev` 相同的编码
+pub const fn makedev(major: u32, minor: u32) -> usize {
+    let (major, minor) = (major as usize, minor as usize);
+    (minor & 0xff) | ((major & 0xfff) << 8) | ((minor & !0xff) << 12) | ((major & !0xfff) << 32)
+}
+
+fn insert_entry(name: &str, ty: InodeType, major: u32, minor: u32, perm: u32, open: Option<DevOpen>) {
+    let name = name.trim_matches('/');
+    let mut table = DEV_TABLE.lock();
+    // 补齐中间目录
+    let mut end = 0;
+    while let Some(pos) = name[end..].find('/') {
+        end += pos;
+        let dir = &name[..end];
+        if !table.contains_key(dir) {
+            table.insert(dir.to_string(), new_entry(InodeType::Dir, 0, 0, 0o755, None));
+        }
+        end += 1;
+    }
+    let ino = table.get(name).map(|e| e.ino);
+    let mut entry = new_entry(ty, major, minor, perm, open);
+    if let Some(ino) = ino {
+        entry.ino = ino;
+    }
+    info!("[devfs] register {}/{} ({},{})", DEV_ROOT, name, major, minor);
+    table.insert(name.to_string(), entry);
+}
+
+fn new_entry(ty: InodeType, major: u32, minor: u32, perm: u32, open: Option<DevOpen>) -> DevEntry {
+    DevEntry {
+        ty,
+        major,
+        minor,
+        perm,
+        ino: NEXT_INO.fetch_add(1, Ordering::Relaxed),
+        ctime: (get_time_ms() / 1000) as isize,
+        open,
+    }
+}
+
+/// 登记一个字符设备或块设备，同名的旧节点会被替换
+pub fn register_device(name: &str, ty: InodeType, major: u32, minor: u32, open: DevOpen) {
+    insert_entry(name, ty, major, minor, 0o666, Some(open));
+}
+
+/// 登记一个空目录，用作其他文件系统的挂载点（如 /dev/shm）
+pub fn register_dir(name: &str) {
+    insert_entry(name, InodeType::Dir, 0, 0, 0o755, None);
+}
+
+/// 删除一个节点；目录连同其下的节点一起删除
+pub fn unregister_device(name: &str) {
+    let name = name.trim_matches('/');
+    let prefix = format!("{}/", name);
+    DEV_TABLE.lock().retain(|k, _| k != name && !k.starts_with(&prefix));
+}
+
+/// 按绝对路径查登记项
+pub fn find_device(abs_path: &str) -> Option<DevEntry> {
+    let name = abs_path.strip_prefix(DEV_ROOT)?.trim_matches('/');
+    DEV_TABLE.lock().get(name).cloned()
+}
+
+/// 设备文件的 fstat：类型、权限和设备号都取自登记表
+pub fn device_stat(abs_path: &str) -> Kstat {
+    let Some(entry) = find_device(abs_path) else {
+        return Kstat { st_nlink: 1, ..Kstat::default() };
+    };
+    Kstat {
+        st_ino: entry.ino,
+        st_mode: ((entry.ty as u32) << 12) | entry.perm,
+        st_nlink: 1,
+        st_rdev: entry.rdev(),
+        st_blksize: PAGE_SIZE as i32,
+        st_atime: entry.ctime,
+        st_mtime: entry.ctime,
+        st_ctime: entry.ctime,
+        ..Kstat::default()
+    }
+}
+
+/// 为驱动层已经探测到的块设备和串口登记节点：vda、vdb…，ttyS0、ttyS1…
+pub fn register_driver_devices() {
+    let (blks, uarts) = {
+        let all = ALL_DEVICES.lock();
+        (all.blk.clone(), all.uart.clone())
+    };
+    for (i, blk) in blks.into_iter().enumerate() {
+        let name = format!("vd{}", (b'a' + i as u8) as char);
+        let path = format!("{}/{}", DEV_ROOT, name);
+        register_device(
+            &name,
+            InodeType::BlockDevice,
+            VIRTBLK_MAJOR,
+            i as u32 * 16,
+            Arc::new(move || Ok(Arc::new(DevBlock::new(&path, i, blk.clone())) as Arc<dyn File>)),
+        );
+    }
+    for (i, uart) in uarts.into_iter().enumerate() {
+        let name = format!("ttyS{}", i);
+        let path = format!("{}/{}", DEV_ROOT, name);
+        register_device(
+            &name,
+            InodeType::CharDevice,
+            TTYS_MAJOR,
+            TTYS_MINOR_BASE + i as u32,
+            Arc::new(move || Ok(Arc::new(DevUart::new(&path, uart.clone())) as Arc<dyn File>)),
+        );
+    }
+}
+
+pub struct DevFs {
+    root: Arc<DevNode>,
+}
+
+impl DevFs {
+    pub fn new(mount_point: &str) -> Self {
+        Self {
+            root: Arc::new(DevNode {
+                mount_point: Arc::from(mount_point.trim_end_matches('/')),
+                name: String::new(),
+            }),
+        }
+    }
+}
+
+impl VfsOps for DevFs {
+    fn sync(&mut self) -> GeneralRet {
+        Ok(())
+    }
+
+    fn name(&self) -> String {
+        String::from("devtmpfs")
+    }
+
+    fn root_inode(&self) -> Arc<dyn VfsNodeOps> {
+        self.root.clone()
+    }
+
+    fn statfs(&self) -> Result<Statfs, i32> {
+        Ok(Statfs {
+            f_type: DEVFS_MAGIC,
+            f_bsize: PAGE_SIZE as i64,
+            f_files: DEV_TABLE.lock().len() as i64 + 1,
+            f_name_len: 255,
+            f_frsize: PAGE_SIZE as i64,
+            ..Default::default()
+        })
+    }
+}
+
+/// devfs 中的一个节点，只保存登记名，属性每次从登记表中取
+pub struct DevNode {
+    mount_point: Arc<str>,
+    name: String,
+}
+
+impl DevNode {
+    fn entry(&self) -> Option<DevEntry> {
+        if self.name.is_empty() {
+            return Some(DevEntry {
+                ty: InodeType::Dir,
+                major: 0,
+                minor: 0,
+                perm: 0o755,
+                ino: 1,
+                ctime: 0,
+                open: None,
+            });
+        }
+        DEV_TABLE.lock().get(&self.name).cloned()
+    }
+
+    fn node_type(&self) -> InodeType {
+        self.entry().map_or(InodeType::Unknown, |e| e.ty)
+    }
+
+    /// 目录下的直接子项
+    fn children(&self) -> Vec<(String, DevEntry)> {
+        let prefix = if self.name.is_empty() { String::new() } else { format!("{}/", self.name) };
+        DEV_TABLE
+            .lock()
+            .range(prefix.clone()..)
+            .take_while(|(k, _)| k.starts_with(&prefix))
+            .filter(|(k, _)| !k[prefix.len()..].contains('/'))
+            .map(|(k, e)| (k[prefix.len()..].to_string(), e.clone()))
+            .collect()
+    }
+
+    /// 若是设备节点，返回打开后的设备文件
+    pub fn open_device(&self) -> Option<Result<Arc<dyn File>, SysErrNo>> {
+        self.entry()?.open.map(|open| open())
+    }
+}
+
+impl VfsNodeOps for DevNode {
+    fn path(&self) -> String {
+        match (self.mount_point.is_empty(), self.name.is_empty()) {
+            (true, true) => String::from("/"),
+            (_, true) => self.mount_point.to_string(),
+            _ => format!("{}/{}", self.mount_point, self.name),
+        }
+    }
+
+    fn fstat(&self) -> Kstat {
+        let Some(entry) = self.entry() else {
+            return Kstat::default();
+        };
+        let is_dir = entry.ty == InodeType::Dir;
+        Kstat {
+            st_ino: entry.ino,
+            st_mode: ((entry.ty as u32) << 12) | entry.perm,
+            st_nlink: if is_dir { 2 } else { 1 },
+            st_rdev: entry.rdev(),
+            st_blksize: PAGE_SIZE as i32,
+            st_atime: entry.ctime,
+            st_mtime: entry.ctime,
+            st_ctime: entry.ctime,
+            ..Default::default()
+        }
+    }
+
+    fn size(&self) -> usize {
+        0
+    }
+
+    fn read_at(&self, _offset: u64, _buf: &mut [u8]) -> SyscallRet {
+        Err(SysErrNo::EISDIR)
+    }
+
+    fn write_at(&self, _offset: u64, _buf: &[u8]) -> Result<usize, i32> {
+        Err(SysErrNo::EISDIR as i32)
+    }
+
+    fn truncate(&self, _size: u64) -> Result<usize, i32> {
+        Err(SysErrNo::EINVAL as i32)
+    }
+
+    fn fsync(&self) -> Result<usize, i32> {
+        Ok(0)
+    }
+
+    fn sync(&self) {}
+
+    fn create(&self, _path: &str, _ty: InodeTypes) -> Result<usize, i32> {
+        Err(SysErrNo::EPERM as i32)
+    }
+
+    fn unlink(&self, _path: &str) -> SyscallRet {
+        Err(SysErrNo::EPERM)
+    }
+
+    fn rename(&self, _src_path: &str, _dst_path: &str) -> Result<usize, i32> {
+        Err(SysErrNo::EPERM as i32)
+    }
+
+    fn read_dentry(&self, off: usize, len: usize) -> Result<(Vec<u8>, isize), SysErrNo> {
+        if self.node_type() != InodeType::Dir {
+            return Err(SysErrNo::ENOTDIR);
+        }
+        let entries = [
+            (String::from("."), self.entry().map_or(1, |e| e.ino), InodeType::Dir),
+            (String::from(".."), 1, InodeType::Dir),
+        ]
+        .into_iter()
+        .chain(self.children().into_iter().map(|(name, e)| (name, e.ino, e.ty)));
+        let mut de: Vec<u8> = Vec::new();
+        let mut f_off = -1isize;
+        for (idx, (name, ino, ty)) in entries.enumerate().skip(off) {
+            let dirent = Dirent::new(name, idx as i64 + 1, ino as u64, ty as u8);
+            if de.len() + dirent.len() > len {
+                f_off = idx as isize;
+                break;
+            }
+            de.extend_from_slice(dirent.as_bytes());
+            f_off = dirent.off() as isize;
+        }
+        Ok((de, f_off))
+    }
+
+    fn as_any(&self) -> &dyn core::any::Any {
+        self
+    }
+
+    fn find(
+        &self,
+        path: &str,
+        flags: OpenFlags,
+        _loop_times: usize,
+    ) -> Result<Arc<dyn VfsNodeOps>, SysErrNo> {
+        if !is_path_prefix(&self.mount_point, path) {
+            return Err(SysErrNo::ENOENT);
+        }
+        let mut name = String::new();
+        for comp in path[self.mount_point.len()..].split('/').filter(|c| !c.is_empty() && *c != ".") {
+            let ty = DevNode { mount_point: self.mount_point.clone(), name: name.clone() }.node_type();
+            if ty != InodeType::Dir {
+                return Err(SysErrNo::ENOTDIR);
+            }
+            if !name.is_empty() {
+                name.push('/');
+            }
+            name.push_str(comp);
+        }
+        let node = DevNode { mount_point: self.mount_point.clone(), name };
+        match node.node_type() {
+            InodeType::Unknown => Err(SysErrNo::ENOENT),
+            InodeType::Dir => Ok(Arc::new(node)),
+            _ if flags.contains(OpenFlags::O_DIRECTORY) => Err(SysErrNo::ENOTDIR),
+            _ => Ok(Arc::new(node)),
+        }
+    }
+
+    fn fmode(&self) -> Result<u32, SysErrNo> {
+        Ok(self.fstat().st_mode)
+    }
+
+    fn fmode_set(&self, _mode: u32) -> SyscallRet {
+        Err(SysErrNo::EPERM)
+    }
+
+    fn set_owner(&self, _uid: u32, _gid: u32) -> SyscallRet {
+        Err(SysErrNo::EPERM)
+    }
+
+    fn set_timestamps(&self, _atime: Option<u32>, _mtime: Option<u32>, _ctime: Option<u32>) -> SyscallRet {
+        Ok(0)
+    }
+
+    fn is_dir(&self) -> bool {
+        self.node_type() == InodeType::Dir
+    }
+
+    fn is_dynamic(&self) -> bool {
+        // 登记表随时会变，不进 FD2NODE 缓存
+        true
+    }
+
+    fn link_cnt(&self) -> SyscallRet {
+        Ok(self.fstat().st_nlink as usize)
+    }
+
+    fn delay(&self) {}
+
+    fn if_delay(&self) -> bool {
+        false
+    }
+}
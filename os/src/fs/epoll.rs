@@ -0,0 +1,363 @@
+//! epoll 实现
+//!
+//! `EpollFile`保存兴趣列表，每个被监视的文件对应一个`EpollItem`。就绪检测
+//! 完全基于`File::poll(events, waker)`：每个条目有自己的`ItemWaker`，文件就绪
+//! 时唤醒它，它记下"有新事件"并唤醒正在`epoll_wait`的任务。
+//!
+//! - 水平触发：每次等待都重新 poll，只要就绪就报告
+//! - 边沿触发（EPOLLET）：报告过一次后，直到该条目的 waker 再次被唤醒或出现新的
+//!   事件位才会再报告
+//! - EPOLLONESHOT：报告一次后禁用，直到 EPOLL_CTL_MOD 重新启用
+//!
+//! 条目只弱引用文件，文件的最后一个引用关闭后条目自动失效，和 Linux 一致。
+
+use core::future::Future;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use core::task::{Context, Poll, Waker};
+
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::sync::{Arc, Weak};
+use alloc::task::Wake;
+use alloc::vec::Vec;
+use async_trait::async_trait;
+use spin::Mutex;
+
+use super::{File, Kstat, PollEvents};
+use crate::fs::stat::StMode;
+use crate::signal::signal_pending;
+use crate::task::sleeplist::{sleep_until, SleepFuture};
+use crate::timer::{current_time, TimeVal};
+use crate::utils::error::{SysErrNo, SyscallRet, TemplateRet};
+
+pub const EPOLL_CTL_ADD: usize = 1;
+pub const EPOLL_CTL_DEL: usize = 2;
+pub const EPOLL_CTL_MOD: usize = 3;
+/// epoll 实例互相嵌套的最大深度，与 Linux 的 EP_MAX_NESTS 相同
+const EP_MAX_NESTS: usize = 4;
+
+bitflags! {
+    /// epoll_event.events，低 16 位与 poll 的事件位相同
+    pub struct EpollEvents: u32 {
+        const EPOLLIN = 0x001;
+        const EPOLLPRI = 0x002;
+        const EPOLLOUT = 0x004;
+        const EPOLLERR = 0x008;
+        const EPOLLHUP = 0x010;
+        const EPOLLRDNORM = 0x040;
+        const EPOLLRDBAND = 0x080;
+        const EPOLLWRNORM = 0x100;
+        const EPOLLWRBAND = 0x200;
+        const EPOLLMSG = 0x400;
+        const EPOLLRDHUP = 0x2000;
+        const EPOLLEXCLUSIVE = 1 << 28;
+        const EPOLLWAKEUP = 1 << 29;
+        const EPOLLONESHOT = 1 << 30;
+        const EPOLLET = 1 << 31;
+    }
+}
+
+impl EpollEvents {
+    fn to_poll(self) -> PollEvents {
+        PollEvents::from_bits_truncate(self.bits() as u16)
+    }
+
+    fn from_poll(events: PollEvents) -> Self {
+        Self::from_bits_truncate(events.bits() as u32)
+    }
+}
+
+/// 用户态的 struct epoll_event（riscv64/loongarch64 上不是 packed）
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct EpollEvent {
+    pub events: u32,
+    pub data: u64,
+}
+
+/// 一个 epoll 实例的等待者和事件计数，条目的 waker 通过它通知 epoll_wait
+struct EpollNotify {
+    /// 每有条目被唤醒就加一，用于避免检查与睡眠之间丢失唤醒
+    seq: AtomicUsize,
+    waiters: Mutex<Vec<Waker>>,
+}
+
+impl EpollNotify {
+    fn notify(&self) {
+        self.seq.fetch_add(1, Ordering::AcqRel);
+        for waker in self.waiters.lock().drain(..) {
+            waker.wake();
+        }
+    }
+}
+
+/// 注册给被监视文件的 waker
+struct ItemWaker {
+    /// 上次报告之后是否又有新事件，边沿触发用
+    triggered: AtomicBool,
+    notify: Arc<EpollNotify>,
+}
+
+impl Wake for ItemWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.triggered.store(true, Ordering::Release);
+        self.notify.notify();
+    }
+}
+
+struct EpollItem {
+    file: Weak<dyn File>,
+    events: EpollEvents,
+    data: u64,
+    /// EPOLLONESHOT 已经报告过，等待 MOD 重新启用
+    disabled: bool,
+    /// 上一次 poll 到的事件
+    last: EpollEvents,
+    waker: Arc<ItemWaker>,
+}
+
+pub struct EpollFile {
+    items: Mutex<BTreeMap<usize, EpollItem>>,
+    notify: Arc<EpollNotify>,
+}
+
+impl EpollFile {
+    pub fn new() -> Self {
+        Self {
+            items: Mutex::new(BTreeMap::new()),
+            notify: Arc::new(EpollNotify {
+                seq: AtomicUsize::new(0),
+                waiters: Mutex::new(Vec::new()),
+            }),
+        }
+    }
+
+    fn new_item(&self, file: &Arc<dyn File>, event: &EpollEvent) -> EpollItem {
+        EpollItem {
+            file: Arc::downgrade(file),
+            events: EpollEvents::from_bits_truncate(event.events),
+            data: event.data,
+            disabled: false,
+            last: EpollEvents::empty(),
+            waker: Arc::new(ItemWaker {
+                triggered: AtomicBool::new(false),
+                notify: self.notify.clone(),
+            }),
+        }
+    }
+
+    /// 从 self 出发沿着被监视的 epoll 实例往下走，能否走到 target，
+    /// 嵌套超过 EP_MAX_NESTS 层也按成环处理
+    fn reaches(&self, target: *const EpollFile, depth: usize) -> bool {
+        if core::ptr::eq(self, target) || depth > EP_MAX_NESTS {
+            return true;
+        }
+        let nested: Vec<Arc<dyn File>> = self
+            .items
+            .lock()
+            .values()
+            .filter_map(|item| item.file.upgrade())
+            .collect();
+        nested.iter().any(|file| {
+            file.as_any()
+                .downcast_ref::<EpollFile>()
+                .is_some_and(|ep| ep.reaches(target, depth + 1))
+        })
+    }
+
+    /// epoll_ctl
+    pub fn ctl(
+        &self,
+        op: usize,
+        fd: usize,
+        file: Arc<dyn File>,
+        event: Option<EpollEvent>,
+    ) -> SyscallRet {
+        if op == EPOLL_CTL_ADD {
+            // 把另一个 epoll 加进来之前检查不会成环，也不会嵌套过深；
+            // 要在拿 self.items 之前做，遍历时会锁住沿途各实例的 items
+            if let Some(ep) = file.as_any().downcast_ref::<EpollFile>() {
+                if ep.reaches(self, 1) {
+                    return Err(SysErrNo::ELOOP);
+                }
+            }
+        }
+        let mut items = self.items.lock();
+        match op {
+            EPOLL_CTL_ADD => {
+                let event = event.ok_or(SysErrNo::EFAULT)?;
+                if let Some(item) = items.get(&fd) {
+                    // 同一个 fd 号上还是原来那个文件才算重复添加；
+                    // 原文件已经关闭、fd 号被复用时直接替换
+                    if item
+                        .file
+                        .upgrade()
+                        .is_some_and(|f| Arc::ptr_eq(&f, &file))
+                    {
+                        return Err(SysErrNo::EEXIST);
+                    }
+                }
+                items.insert(fd, self.new_item(&file, &event));
+            }
+            EPOLL_CTL_MOD => {
+                let event = event.ok_or(SysErrNo::EFAULT)?;
+                if !items.contains_key(&fd) {
+                    return Err(SysErrNo::ENOENT);
+                }
+                items.insert(fd, self.new_item(&file, &event));
+            }
+            EPOLL_CTL_DEL => {
+                items.remove(&fd).ok_or(SysErrNo::ENOENT)?;
+            }
+            _ => return Err(SysErrNo::EINVAL),
+        }
+        drop(items);
+        // 正在等待的任务需要看到新的兴趣列表
+        self.notify.notify();
+        Ok(0)
+    }
+
+    /// 收集当前就绪的事件，最多 max 个
+    fn collect(&self, max: usize) -> Vec<EpollEvent> {
+        let mut ready = Vec::new();
+        let mut items = self.items.lock();
+        items.retain(|_, item| item.file.strong_count() > 0);
+        for item in items.values_mut() {
+            if ready.len() >= max {
+                break;
+            }
+            if item.disabled {
+                continue;
+            }
+            let Some(file) = item.file.upgrade() else {
+                continue;
+            };
+            let waker = Waker::from(item.waker.clone());
+            // EPOLLERR 和 EPOLLHUP 总是被报告
+            let interest = item.events | EpollEvents::EPOLLERR | EpollEvents::EPOLLHUP;
+            let revents = EpollEvents::from_poll(file.poll(interest.to_poll(), &waker)) & interest;
+            let last = core::mem::replace(&mut item.last, revents);
+            if item.events.contains(EpollEvents::EPOLLET) {
+                // 边沿触发：文件唤醒过 waker，或出现了上次没有的事件，才算新边沿
+                let triggered = item.waker.triggered.swap(false, Ordering::AcqRel);
+                if !triggered && (revents - last).is_empty() {
+                    continue;
+                }
+            }
+            if revents.is_empty() {
+                continue;
+            }
+            ready.push(EpollEvent {
+                events: revents.bits(),
+                data: item.data,
+            });
+            if item.events.contains(EpollEvents::EPOLLONESHOT) {
+                item.disabled = true;
+            }
+        }
+        ready
+    }
+
+    /// epoll_wait：等到有事件、超时（deadline 为 None 表示无限等待）或被信号打断
+    pub async fn wait(&self, max: usize, deadline: Option<TimeVal>) -> Result<Vec<EpollEvent>, SysErrNo> {
+        loop {
+            let seq = self.notify.seq.load(Ordering::Acquire);
+            let ready = self.collect(max);
+            if !ready.is_empty() {
+                return Ok(ready);
+            }
+            if deadline.is_some_and(|d| current_time() >= d) {
+                return Ok(ready);
+            }
+            if signal_pending().await {
+                return Err(SysErrNo::EINTR);
+            }
+            EpollWait {
+                notify: &self.notify,
+                seq,
+                sleep: sleep_until(deadline),
+            }
+            .await;
+        }
+    }
+}
+
+/// 睡眠直到 epoll 有条目被唤醒、超时或任务被其它原因唤醒
+struct EpollWait<'a> {
+    notify: &'a EpollNotify,
+    seq: usize,
+    sleep: SleepFuture,
+}
+
+impl Future for EpollWait<'_> {
+    type Output = ();
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        {
+            let mut waiters = self.notify.waiters.lock();
+            if self.notify.seq.load(Ordering::Acquire) != self.seq {
+                return Poll::Ready(());
+            }
+            if !waiters.iter().any(|w| w.will_wake(cx.waker())) {
+                waiters.push(cx.waker().clone());
+            }
+        }
+        Pin::new(&mut self.sleep).poll(cx)
+    }
+}
+
+#[async_trait]
+impl File for EpollFile {
+    fn as_any(&self) -> &dyn core::any::Any {
+        self
+    }
+    fn get_path(&self) -> String {
+        String::from("anon_inode:[eventpoll]")
+    }
+    fn readable<'a>(&'a self) -> TemplateRet<bool> {
+        Ok(false)
+    }
+    fn writable<'a>(&'a self) -> TemplateRet<bool> {
+        Ok(false)
+    }
+    fn poll(&self, events: PollEvents, waker_to_register: &Waker) -> PollEvents {
+        // epoll fd 本身可以被 poll/epoll：有就绪条目时可读。
+        // 这里只看水平状态，不消耗边沿触发和 ONESHOT 的状态
+        let mut revents = PollEvents::empty();
+        if !events.contains(PollEvents::POLLIN) {
+            return revents;
+        }
+        let items = self.items.lock();
+        for item in items.values() {
+            if item.disabled {
+                continue;
+            }
+            if let Some(file) = item.file.upgrade() {
+                let waker = Waker::from(item.waker.clone());
+                if !file.poll(item.events.to_poll(), &waker).is_empty() {
+                    revents.insert(PollEvents::POLLIN);
+                    break;
+                }
+            }
+        }
+        drop(items);
+        if revents.is_empty() {
+            let mut waiters = self.notify.waiters.lock();
+            if !waiters.iter().any(|w| w.will_wake(waker_to_register)) {
+                waiters.push(waker_to_register.clone());
+            }
+        }
+        revents
+    }
+    fn fstat(&self) -> Kstat {
+        Kstat {
+            st_mode: StMode::FREG.bits() | 0o600,
+            st_nlink: 1,
+            ..Kstat::default()
+        }
+    }
+}
@@ -0,0 +1,177 @@
+//! eventfd：内核维护的 64 位计数器，用于事件通知
+//!
+//! write 把 8 字节的值加到计数器上，read 取走计数器；EFD_SEMAPHORE 模式下
+//! read 每次只取 1。计数器最大为 `u64::MAX - 1`，写满时 write 阻塞。
+
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::task::Waker;
+
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec::Vec;
+use async_trait::async_trait;
+use spin::Mutex;
+
+use super::stat::StMode;
+use super::{wait_poll, File, Kstat, PollEvents};
+use crate::mm::UserBuffer;
+use crate::signal::signal_pending;
+use crate::utils::error::{SysErrNo, TemplateRet};
+
+pub const EFD_SEMAPHORE: u32 = 1;
+pub const EFD_CLOEXEC: u32 = 0o2000000;
+pub const EFD_NONBLOCK: u32 = 0o4000;
+
+const EVENTFD_MAX: u64 = u64::MAX - 1;
+
+struct EventFdInner {
+    count: u64,
+    readers: Vec<Waker>,
+    writers: Vec<Waker>,
+}
+
+pub struct EventFd {
+    inner: Mutex<EventFdInner>,
+    semaphore: bool,
+    nonblock: AtomicBool,
+}
+
+fn register(list: &mut Vec<Waker>, waker: &Waker) {
+    if !list.iter().any(|w| w.will_wake(waker)) {
+        list.push(waker.clone());
+    }
+}
+
+fn wake_all(list: &mut Vec<Waker>) {
+    for waker in list.drain(..) {
+        waker.wake();
+    }
+}
+
+impl EventFd {
+    pub fn new(initval: u32, flags: u32) -> Self {
+        Self {
+            inner: Mutex::new(EventFdInner {
+                count: initval as u64,
+                readers: Vec::new(),
+                writers: Vec::new(),
+            }),
+            semaphore: flags & EFD_SEMAPHORE != 0,
+            nonblock: AtomicBool::new(flags & EFD_NONBLOCK != 0),
+        }
+    }
+
+    /// 计数器非零时取走一次读的值
+    fn try_read(&self) -> Option<u64> {
+        let mut inner = self.inner.lock();
+        if inner.count == 0 {
+            return None;
+        }
+        let value = if self.semaphore { 1 } else { inner.count };
+        inner.count -= value;
+        wake_all(&mut inner.writers);
+        Some(value)
+    }
+
+    /// 加上 value 不溢出时写入
+    fn try_write(&self, value: u64) -> bool {
+        let mut inner = self.inner.lock();
+        if EVENTFD_MAX - inner.count < value {
+            return false;
+        }
+        inner.count += value;
+        if value > 0 {
+            wake_all(&mut inner.readers);
+        }
+        true
+    }
+}
+
+#[async_trait]
+impl File for EventFd {
+    fn as_any(&self) -> &dyn core::any::Any {
+        self
+    }
+    fn get_path(&self) -> String {
+        String::from("anon_inode:[eventfd]")
+    }
+    fn readable<'a>(&'a self) -> TemplateRet<bool> {
+        Ok(true)
+    }
+    fn writable<'a>(&'a self) -> TemplateRet<bool> {
+        Ok(true)
+    }
+
+    async fn read<'a>(&self, mut buf: UserBuffer<'a>) -> Result<usize, SysErrNo> {
+        if buf.len() < 8 {
+            return Err(SysErrNo::EINVAL);
+        }
+        loop {
+            if let Some(value) = self.try_read() {
+                return Ok(buf.write(&value.to_ne_bytes()));
+            }
+            if self.nonblock.load(Ordering::Relaxed) {
+                return Err(SysErrNo::EAGAIN);
+            }
+            if signal_pending().await {
+                return Err(SysErrNo::ERESTART);
+            }
+            wait_poll(self, PollEvents::POLLIN).await;
+        }
+    }
+
+    async fn write<'a>(&self, buf: UserBuffer<'a>) -> Result<usize, SysErrNo> {
+        if buf.len() < 8 {
+            return Err(SysErrNo::EINVAL);
+        }
+        let bytes = buf.read(8);
+        let value = u64::from_ne_bytes(bytes[..8].try_into().unwrap());
+        if value == u64::MAX {
+            return Err(SysErrNo::EINVAL);
+        }
+        loop {
+            if self.try_write(value) {
+                return Ok(8);
+            }
+            if self.nonblock.load(Ordering::Relaxed) {
+                return Err(SysErrNo::EAGAIN);
+            }
+            if signal_pending().await {
+                return Err(SysErrNo::ERESTART);
+            }
+            wait_poll(self, PollEvents::POLLOUT).await;
+        }
+    }
+
+    fn poll(&self, events: PollEvents, waker_to_register: &Waker) -> PollEvents {
+        let mut inner = self.inner.lock();
+        let mut revents = PollEvents::empty();
+        if events.contains(PollEvents::POLLIN) {
+            if inner.count > 0 {
+                revents |= PollEvents::POLLIN;
+            } else {
+                register(&mut inner.readers, waker_to_register);
+            }
+        }
+        if events.contains(PollEvents::POLLOUT) {
+            if inner.count < EVENTFD_MAX {
+                revents |= PollEvents::POLLOUT;
+            } else {
+                register(&mut inner.writers, waker_to_register);
+            }
+        }
+        revents
+    }
+
+    fn set_nonblock(&self, nonblock: bool) {
+        self.nonblock.store(nonblock, Ordering::Relaxed);
+    }
+
+    fn fstat(&self) -> Kstat {
+        Kstat {
+            st_mode: StMode::FREG.bits() | 0o600,
+            st_nlink: 1,
+            ..Kstat::default()
+        }
+    }
+}
@@ -21,7 +21,7 @@ use alloc::sync::Arc;
 use alloc::vec::Vec;
 use log::*;
 use lwext4_rust::bindings::{
-     ext4_atime_set, ext4_ctime_set, ext4_mode_get, ext4_mode_set, ext4_mtime_set, ext4_owner_set, EOK, O_CREAT, O_RDONLY, O_RDWR, O_TRUNC, O_WRONLY, SEEK_SET
+     ext4_atime_set, ext4_ctime_set, ext4_mknod, ext4_mode_get, ext4_mode_set, ext4_mtime_set, ext4_owner_set, EOK, O_CREAT, O_RDONLY, O_RDWR, O_TRUNC, O_WRONLY, SEEK_SET
 };
 use lwext4_rust::file::OsDirent;
 use lwext4_rust::{Ext4BlockWrapper, Ext4File, InodeTypes};
@@ -325,6 +325,21 @@ impl VfsNodeOps for FileWrapper {
         } else {
             if types == InodeTypes::EXT4_DE_DIR {
                 file.dir_mk(fpath)
+            } else if matches!(
+                types,
+                InodeTypes::EXT4_DE_SOCK
+                    | InodeTypes::EXT4_DE_FIFO
+                    | InodeTypes::EXT4_DE_CHRDEV
+                    | InodeTypes::EXT4_DE_BLKDEV
+            ) {
+                // 特殊文件没有数据块，直接创建对应类型的 inode
+                let c_path = CString::new(fpath).expect("CString::new failed");
+                let r = unsafe { ext4_mknod(c_path.as_ptr(), types as i32, 0) };
+                if r != EOK as i32 {
+                    error!("ext4_mknod: rc = {}", r);
+                    return Err(r);
+                }
+                Ok(0)
             } else {
                 file.file_open(fpath, O_WRONLY | O_CREAT | O_TRUNC)
                     .expect("create file failed");
@@ -543,6 +558,21 @@ impl VfsNodeOps for FileWrapper {
                 // `file` 这个 RefMut 在这里就会随着作用域结束而 drop，释放借用
                 // 然后我们再递归调用 `find`
                 return self.find(&abs_path, flags, loop_times + 1);
+            } else if file.check_inode_exist(path, InodeTypes::EXT4_DE_UNKNOWN) {
+                // socket、FIFO 和设备文件
+                if flags.contains(OpenFlags::O_DIRECTORY) {
+                    return Err(SysErrNo::ENOTDIR);
+                }
+                for ty in [
+                    InodeTypes::EXT4_DE_SOCK,
+                    InodeTypes::EXT4_DE_FIFO,
+                    InodeTypes::EXT4_DE_CHRDEV,
+                    InodeTypes::EXT4_DE_BLKDEV,
+                ] {
+                    if file.check_inode_exist(path, ty.clone()) {
+                        return Ok(Arc::new(FileWrapper::new(path, ty)));
+                    }
+                }
             }
         }
     
@@ -553,6 +583,12 @@ impl VfsNodeOps for FileWrapper {
     fn is_dir(&self) -> bool {
         self.file.borrow_mut().get_type() == InodeTypes::EXT4_DE_DIR
     }
+    fn is_symlink(&self) -> bool {
+        self.file.borrow_mut().get_type() == InodeTypes::EXT4_DE_SYMLINK
+    }
+    fn page_cached(&self) -> bool {
+        self.file.borrow_mut().get_type() == InodeTypes::EXT4_DE_REG_FILE
+    }
 fn set_owner(&self, uid: u32, gid: u32) -> SyscallRet {
     let file = self.file.borrow_mut();
     let c_path = file.get_path();
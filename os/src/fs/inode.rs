@@ -9,6 +9,8 @@ use bitflags::bitflags;
 use lwext4_rust::bindings::{ SEEK_CUR, SEEK_END, SEEK_SET};
 use spin::Mutex;
 
+use super::pagecache::PageCache;
+use crate::drivers::bcache;
 use super::vfs::vfs_ops::VfsNodeOps;
 use super::File;
 use crate::fs::PollEvents;
@@ -55,6 +57,8 @@ bitflags! {
 pub struct OsInode {
     readable: bool,
     writable: bool,
+    /// 普通文件的页缓存，其他节点为 None
+    cache: Option<Arc<PageCache>>,
     pub inner: Mutex<OSInodeInner>,
 }
 
@@ -63,12 +67,20 @@ pub struct OSInodeInner {
     pub inode: Arc<dyn VfsNodeOps>,
 }
 
+impl Drop for OsInode {
+    fn drop(&mut self) {
+        // 打开的文件描述没有引用了，它持有的 flock 和 OFD 锁随之释放
+        super::lock::release_owner(super::lock::LockOwner::of_file(self));
+    }
+}
+
 impl OsInode {
   
     pub fn new(readable: bool, writable: bool, inode: Arc<dyn VfsNodeOps>) -> Self {
         OsInode {
             readable,
             writable,
+            cache: PageCache::of(&inode),
             inner:  Mutex::new(OSInodeInner { offset: 0, inode }) ,
         }
     }
@@ -78,7 +90,7 @@ impl OsInode {
         let mut buf = [0u8; 512];
         let mut out = Vec::new();
         loop {
-            let n =inner.inode.read_at(inner.offset as u64, &mut buf).unwrap();
+            let n = self.node_read_at(&inner, inner.offset, &mut buf).unwrap();
             if n == 0 { break; }
             inner.offset += n;
             out.extend_from_slice(&buf[..n]);
@@ -95,16 +107,61 @@ impl OsInode {
     }
     pub fn read_at(&self,offset:usize, buf:&mut [u8])->SyscallRet{
         let mut inner = self.inner.lock();
-        let n = inner.inode.read_at(offset as u64, buf)?;
+        let n = self.node_read_at(&inner, offset, buf)?;
         inner.offset += n;
         Ok(n)
     }
     pub fn write_at(&self,offset:usize, buf:&[u8])->SyscallRet{
         let mut inner = self.inner.lock();
-        let n = inner.inode.write_at(offset as u64, buf)?;
+        let n = self.node_write_at(&inner, offset, buf)?;
         inner.offset += n;
         Ok(n)
     }
+    /// 有页缓存时经由页缓存读，否则直接读底层节点
+    fn node_read_at(&self, inner: &OSInodeInner, offset: usize, buf: &mut [u8]) -> SyscallRet {
+        match &self.cache {
+            Some(cache) => cache.read_at(offset, buf),
+            None => inner.inode.read_at(offset as u64, buf),
+        }
+    }
+    fn node_write_at(&self, inner: &OSInodeInner, offset: usize, buf: &[u8]) -> SyscallRet {
+        match &self.cache {
+            Some(cache) => cache.write_at(offset, buf),
+            None => inner.inode.write_at(offset as u64, buf).map_err(SysErrNo::from),
+        }
+    }
+    /// 写入前先读一下首尾所在处，把 lwext4 需要的元数据块和部分覆盖的数据块
+    /// 异步读入缓存；写入本身不能失败重试，只能同步等待设备
+    async fn prefetch_for_write(&self, len: usize) {
+        if len == 0 || self.is_dir() {
+            return;
+        }
+        let offset = self.inner.lock().offset;
+        for pos in [offset, offset + len - 1] {
+            let mut byte = [0u8; 1];
+            loop {
+                let res = bcache::defer_misses(|| {
+                    let inner = self.inner.lock();
+                    inner.inode.read_at(pos as u64, &mut byte)
+                });
+                match res {
+                    Ok(_) => break,
+                    Err(miss) => miss.fetch().await,
+                }
+            }
+        }
+    }
+    pub fn page_cache(&self) -> Option<Arc<PageCache>> {
+        self.cache.clone()
+    }
+    /// 写回脏页并落盘，fsync 使用
+    pub fn sync(&self) -> GeneralRet {
+        if let Some(cache) = &self.cache {
+            cache.writeback()?;
+        }
+        self.inner.lock().inode.sync();
+        Ok(())
+    }
     pub fn read_dentry(&self, off: usize, len: usize) -> Result<(Vec<u8>, isize), SysErrNo> {
         let file = &mut self.inner.lock().inode;
         file.read_dentry(off, len)
@@ -117,7 +174,10 @@ impl OsInode {
          
     }
     pub fn truncate(&self, size: u64) -> SyscallRet {
-        self.inner.lock().inode.truncate(size).map_err(|err| SysErrNo::from(-err))
+        if let Some(cache) = &self.cache {
+            return cache.truncate(size as usize);
+        }
+        self.inner.lock().inode.truncate(size).map_err(SysErrNo::from)
     }
 }
 
@@ -220,7 +280,7 @@ impl File for OsInode {
         self
     }
     fn clear(&self) {
-        let _ = self.inner.lock().inode.truncate(0);
+        let _ = self.truncate(0);
         
     }
    fn readable(&self) -> TemplateRet<bool> {
@@ -234,26 +294,34 @@ impl File for OsInode {
         & self,                
         mut buf: UserBuffer<'a>  
     ) -> Result<usize, SysErrNo> {
-       
-            let mut inner = self.inner.lock();
-                let mut total = 0;
-            for slice in buf.buffers.iter_mut() {
-                let n = inner.inode.read_at(inner.offset as u64, slice)?;
-                if n == 0 { break; }
-                inner.offset += n;
-                total += n;
-            }
-            trace!("[read] off:{}",inner.offset);
-            Ok(total)
-        
+        let mut total = 0;
+        for slice in buf.buffers.iter_mut() {
+            // 未命中的块先异步读入缓存再重试，不在 lwext4 里同步等待设备
+            let n = loop {
+                let res = bcache::defer_misses(|| {
+                    let mut inner = self.inner.lock();
+                    let n = self.node_read_at(&inner, inner.offset, slice)?;
+                    inner.offset += n;
+                    Ok(n)
+                });
+                match res {
+                    Ok(res) => break res?,
+                    Err(miss) => miss.fetch().await,
+                }
+            };
+            if n == 0 { break; }
+            total += n;
+        }
+        trace!("[read] off:{}",self.inner.lock().offset);
+        Ok(total)
     }
 
     async fn write<'buf>(&self, buf: UserBuffer<'buf>) -> Result<usize, SysErrNo> {
-      
+        self.prefetch_for_write(buf.len()).await;
             let mut inner = self.inner.lock();
             let mut total = 0;
             for slice in buf.buffers.iter() {
-                let n = inner.inode.write_at(inner.offset as u64, *slice)?;
+                let n = self.node_write_at(&inner, inner.offset, *slice)?;
                 inner.offset += n;
                 total += n;
             }
@@ -282,7 +350,11 @@ impl File for OsInode {
             }
             inner.offset = newoff as usize;
         } else if whence == SEEK_END {
-            let newoff = inner.inode.size() as isize + offset;
+            let size = match &self.cache {
+                Some(cache) => cache.size(),
+                None => inner.inode.size(),
+            };
+            let newoff = size as isize + offset;
             if newoff < 0 {
                 
                 warn!("[OsInode::lseek]err:SEEK_END off < 0,off = {}",newoff);
@@ -0,0 +1,442 @@
+//! 建议性文件锁：flock 和 fcntl 的 POSIX/OFD 记录锁
+//!
+//! 锁按 (st_dev, st_ino) 挂在全局表上，同一个文件从不同路径、不同 fd 打开都能看到
+//! 同一组锁。三类锁的持有者不同：
+//! - flock：整个文件的共享/独占锁，属于打开的文件描述（`OsInode`），最后一个引用
+//!   关闭时释放
+//! - POSIX 记录锁（F_SETLK）：字节范围锁，属于进程，进程关闭该文件的任意一个 fd
+//!   或退出时释放
+//! - OFD 记录锁（F_OFD_SETLK）：字节范围锁，属于打开的文件描述
+//!
+//! flock 和记录锁互不影响，POSIX 锁和 OFD 锁之间会冲突。阻塞等待基于每个文件的
+//! `WaitQueue`，任何锁被释放或降级时唤醒所有等待者重新检查；POSIX 锁等待前会
+//! 沿等待关系检测死锁并返回 EDEADLK。
+
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+use spin::Mutex;
+
+use super::{File, OsInode};
+use crate::signal::{signal_pending, signal_pending_nowait};
+use crate::sync::WaitQueue;
+use crate::task::current_task;
+use crate::utils::error::{SysErrNo, SyscallRet};
+
+pub const LOCK_SH: usize = 1;
+pub const LOCK_EX: usize = 2;
+pub const LOCK_NB: usize = 4;
+pub const LOCK_UN: usize = 8;
+
+pub const F_RDLCK: i16 = 0;
+pub const F_WRLCK: i16 = 1;
+pub const F_UNLCK: i16 = 2;
+
+const SEEK_SET: i16 = 0;
+const SEEK_CUR: i16 = 1;
+const SEEK_END: i16 = 2;
+
+/// 锁的范围上界，表示一直到文件末尾（包括以后追加的部分）
+const OFFSET_MAX: u64 = u64::MAX;
+
+/// 用户态的 struct flock
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Flock {
+    pub l_type: i16,
+    pub l_whence: i16,
+    pub l_start: i64,
+    pub l_len: i64,
+    pub l_pid: i32,
+}
+
+/// 锁的持有者
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LockOwner {
+    /// POSIX 记录锁，值为 pid
+    Process(usize),
+    /// flock 和 OFD 锁，值为打开的文件描述（`OsInode`）的地址
+    File(usize),
+}
+
+impl LockOwner {
+    pub fn of_file(file: &OsInode) -> Self {
+        Self::File(file as *const OsInode as usize)
+    }
+}
+
+/// 一个字节范围锁，范围为 [start, end)
+#[derive(Clone, Copy, Debug)]
+pub struct RangeLock {
+    pub owner: LockOwner,
+    pub start: u64,
+    pub end: u64,
+    pub write: bool,
+}
+
+impl RangeLock {
+    fn overlaps(&self, other: &RangeLock) -> bool {
+        self.start < other.end && other.start < self.end
+    }
+
+    fn conflicts(&self, other: &RangeLock) -> bool {
+        self.owner != other.owner && (self.write || other.write) && self.overlaps(other)
+    }
+
+    /// 转换成 F_GETLK 返回给用户的 struct flock
+    pub fn to_flock(&self) -> Flock {
+        Flock {
+            l_type: if self.write { F_WRLCK } else { F_RDLCK },
+            l_whence: SEEK_SET,
+            l_start: self.start as i64,
+            l_len: if self.end == OFFSET_MAX { 0 } else { (self.end - self.start) as i64 },
+            l_pid: match self.owner {
+                LockOwner::Process(pid) => pid as i32,
+                LockOwner::File(_) => -1,
+            },
+        }
+    }
+}
+
+/// 文件在锁表中的键 (st_dev, st_ino)
+pub type LockKey = (usize, usize);
+
+pub fn lock_key(file: &OsInode) -> LockKey {
+    let stat = file.fstat();
+    (stat.st_dev, stat.st_ino)
+}
+
+#[derive(Clone, Copy)]
+struct FlockEntry {
+    owner: usize,
+    exclusive: bool,
+}
+
+#[derive(Default)]
+struct InodeLocks {
+    flocks: Vec<FlockEntry>,
+    ranges: Vec<RangeLock>,
+    wq: Arc<WaitQueue>,
+}
+
+impl InodeLocks {
+    fn is_empty(&self) -> bool {
+        self.flocks.is_empty() && self.ranges.is_empty()
+    }
+
+    fn flock_conflicts(&self, owner: usize, exclusive: bool) -> bool {
+        self.flocks
+            .iter()
+            .any(|f| f.owner != owner && (f.exclusive || exclusive))
+    }
+
+    fn range_conflict(&self, req: &RangeLock) -> Option<&RangeLock> {
+        self.ranges.iter().find(|l| l.conflicts(req))
+    }
+
+    /// 把 owner 在 [start, end) 内的锁去掉，跨过边界的锁被切开
+    fn unlock_range(&mut self, owner: LockOwner, start: u64, end: u64) {
+        let mut kept = Vec::with_capacity(self.ranges.len() + 1);
+        for l in self.ranges.drain(..) {
+            if l.owner != owner || l.end <= start || end <= l.start {
+                kept.push(l);
+                continue;
+            }
+            if l.start < start {
+                kept.push(RangeLock { end: start, ..l });
+            }
+            if end < l.end {
+                kept.push(RangeLock { start: end, ..l });
+            }
+        }
+        self.ranges = kept;
+    }
+
+    /// 设置记录锁（调用者已确认没有冲突），相邻的同类锁合并
+    fn apply_range(&mut self, req: RangeLock) {
+        self.unlock_range(req.owner, req.start, req.end);
+        let mut new = req;
+        self.ranges.retain(|l| {
+            let mergeable = l.owner == new.owner
+                && l.write == new.write
+                && l.start <= new.end
+                && new.start <= l.end;
+            if mergeable {
+                new.start = new.start.min(l.start);
+                new.end = new.end.max(l.end);
+            }
+            !mergeable
+        });
+        self.ranges.push(new);
+    }
+}
+
+/// 锁请求
+#[derive(Clone, Copy)]
+enum LockRequest {
+    Flock { owner: usize, exclusive: bool },
+    Range(RangeLock),
+}
+
+struct LockTable {
+    inodes: BTreeMap<LockKey, InodeLocks>,
+    /// 正在等待 POSIX 锁的线程（按 tid）及其请求，用于死锁检测；
+    /// 同一进程的多个线程可以同时等待不同的锁
+    waiting: BTreeMap<usize, (LockKey, RangeLock)>,
+}
+
+static LOCK_TABLE: Mutex<LockTable> = Mutex::new(LockTable {
+    inodes: BTreeMap::new(),
+    waiting: BTreeMap::new(),
+});
+
+impl LockTable {
+    fn conflicts(&self, key: LockKey, req: &LockRequest) -> bool {
+        let Some(locks) = self.inodes.get(&key) else {
+            return false;
+        };
+        match req {
+            LockRequest::Flock { owner, exclusive } => locks.flock_conflicts(*owner, *exclusive),
+            LockRequest::Range(req) => locks.range_conflict(req).is_some(),
+        }
+    }
+
+    /// pid 等待 req 会不会形成环：沿"被谁阻塞 -> 它又在等谁"一路找回到 pid
+    fn would_deadlock(&self, pid: usize, key: LockKey, req: &RangeLock) -> bool {
+        let blockers = |key: LockKey, req: &RangeLock| -> Vec<usize> {
+            self.inodes.get(&key).map_or(Vec::new(), |locks| {
+                locks
+                    .ranges
+                    .iter()
+                    .filter(|l| l.conflicts(req))
+                    .filter_map(|l| match l.owner {
+                        LockOwner::Process(p) => Some(p),
+                        // OFD 锁不属于某个进程，无法判断，和 Linux 一样不参与检测
+                        LockOwner::File(_) => None,
+                    })
+                    .collect()
+            })
+        };
+        let mut visited = BTreeSet::new();
+        let mut stack = blockers(key, req);
+        while let Some(owner) = stack.pop() {
+            if owner == pid {
+                return true;
+            }
+            if !visited.insert(owner) {
+                continue;
+            }
+            // 持有者进程中任何一个正在等待的线程都可能把环接上
+            for (k, r) in self.waiting.values() {
+                if r.owner == LockOwner::Process(owner) {
+                    stack.extend(blockers(*k, r));
+                }
+            }
+        }
+        false
+    }
+
+    /// 锁有变化，唤醒等待者；没有锁的文件从表中移除
+    fn changed(&mut self, key: LockKey) {
+        if let Some(locks) = self.inodes.get(&key) {
+            locks.wq.notify_all();
+            if locks.is_empty() {
+                self.inodes.remove(&key);
+            }
+        }
+    }
+}
+
+/// 没有冲突时加锁，有冲突且 wait 为 true 时阻塞到可以加锁或被信号打断
+async fn acquire(key: LockKey, req: LockRequest, wait: bool) -> SyscallRet {
+    // 等待表按线程记录，死锁检测按持有锁的进程进行
+    let waiter = match req {
+        LockRequest::Range(RangeLock { owner: LockOwner::Process(pid), .. }) => {
+            Some((current_task().get_tid(), pid))
+        }
+        _ => None,
+    };
+    loop {
+        let wq = {
+            let mut table = LOCK_TABLE.lock();
+            if !table.conflicts(key, &req) {
+                let locks = table.inodes.entry(key).or_default();
+                match req {
+                    LockRequest::Flock { owner, exclusive } => {
+                        locks.flocks.push(FlockEntry { owner, exclusive })
+                    }
+                    LockRequest::Range(req) => locks.apply_range(req),
+                }
+                if let Some((tid, _)) = waiter {
+                    table.waiting.remove(&tid);
+                }
+                // 记录锁的替换可能降级或缩小了原来的锁
+                table.changed(key);
+                return Ok(0);
+            }
+            if !wait {
+                return Err(SysErrNo::EAGAIN);
+            }
+            if let (Some((tid, pid)), LockRequest::Range(req)) = (waiter, req) {
+                if table.would_deadlock(pid, key, &req) {
+                    table.waiting.remove(&tid);
+                    return Err(SysErrNo::EDEADLK);
+                }
+                table.waiting.insert(tid, (key, req));
+            }
+            table.inodes.entry(key).or_default().wq.clone()
+        };
+        if signal_pending().await {
+            if let Some((tid, _)) = waiter {
+                LOCK_TABLE.lock().waiting.remove(&tid);
+            }
+            return Err(SysErrNo::EINTR);
+        }
+        // 文件上的锁全部释放后表项被移除，之后加锁会换一个新的等待队列；
+        // 发现队列换了就回到循环开头重新取，不能留在旧队列上等
+        let current = wq.clone();
+        wq.wait_until(move || {
+            let table = LOCK_TABLE.lock();
+            let stale = table.inodes.get(&key).map_or(true, |locks| !Arc::ptr_eq(&locks.wq, &current));
+            stale || !table.conflicts(key, &req) || signal_pending_nowait()
+        })
+        .await;
+    }
+}
+
+/// flock(2)
+pub async fn flock(file: &OsInode, op: usize) -> SyscallRet {
+    let key = lock_key(file);
+    let owner = file as *const OsInode as usize;
+    let exclusive = match op & !LOCK_NB {
+        LOCK_SH => false,
+        LOCK_EX => true,
+        LOCK_UN => {
+            let mut table = LOCK_TABLE.lock();
+            if let Some(locks) = table.inodes.get_mut(&key) {
+                locks.flocks.retain(|f| f.owner != owner);
+            }
+            table.changed(key);
+            return Ok(0);
+        }
+        _ => return Err(SysErrNo::EINVAL),
+    };
+    {
+        let mut table = LOCK_TABLE.lock();
+        if let Some(locks) = table.inodes.get_mut(&key) {
+            if let Some(pos) = locks.flocks.iter().position(|f| f.owner == owner) {
+                if locks.flocks[pos].exclusive == exclusive {
+                    return Ok(0);
+                }
+                // 和 Linux 一样，转换锁类型时先释放原来的锁，转换不是原子的
+                locks.flocks.remove(pos);
+                table.changed(key);
+            }
+        }
+    }
+    acquire(key, LockRequest::Flock { owner, exclusive }, op & LOCK_NB == 0).await
+}
+
+/// 把用户给出的 struct flock 换算成 [start, end)
+pub fn flock_range(file: &OsInode, fl: &Flock) -> Result<(u64, u64), SysErrNo> {
+    let base = match fl.l_whence {
+        SEEK_SET => 0,
+        SEEK_CUR => file.offset() as i64,
+        SEEK_END => file.fstat().st_size as i64,
+        _ => return Err(SysErrNo::EINVAL),
+    };
+    let start = base.checked_add(fl.l_start).ok_or(SysErrNo::EOVERFLOW)?;
+    let (start, end) = match fl.l_len {
+        0 => (start, None),
+        len if len > 0 => (start, Some(start.checked_add(len).ok_or(SysErrNo::EOVERFLOW)?)),
+        // 负的长度表示 [start + len, start)
+        len => (start + len, Some(start)),
+    };
+    if start < 0 {
+        return Err(SysErrNo::EINVAL);
+    }
+    Ok((start as u64, end.map_or(OFFSET_MAX, |e| e as u64)))
+}
+
+/// F_SETLK/F_SETLKW/F_OFD_SETLK/F_OFD_SETLKW
+pub async fn set_record_lock(
+    file: &OsInode,
+    owner: LockOwner,
+    fl: &Flock,
+    wait: bool,
+) -> SyscallRet {
+    let key = lock_key(file);
+    let (start, end) = flock_range(file, fl)?;
+    let write = match fl.l_type {
+        F_RDLCK => false,
+        F_WRLCK => true,
+        F_UNLCK => {
+            let mut table = LOCK_TABLE.lock();
+            if let Some(locks) = table.inodes.get_mut(&key) {
+                locks.unlock_range(owner, start, end);
+            }
+            table.changed(key);
+            return Ok(0);
+        }
+        _ => return Err(SysErrNo::EINVAL),
+    };
+    let req = RangeLock { owner, start, end, write };
+    acquire(key, LockRequest::Range(req), wait).await
+}
+
+/// F_GETLK/F_OFD_GETLK：返回第一个和请求冲突的锁
+pub fn get_record_lock(file: &OsInode, owner: LockOwner, fl: &Flock) -> Result<Option<RangeLock>, SysErrNo> {
+    let key = lock_key(file);
+    let (start, end) = flock_range(file, fl)?;
+    let write = match fl.l_type {
+        F_RDLCK => false,
+        F_WRLCK => true,
+        _ => return Err(SysErrNo::EINVAL),
+    };
+    let req = RangeLock { owner, start, end, write };
+    let table = LOCK_TABLE.lock();
+    Ok(table
+        .inodes
+        .get(&key)
+        .and_then(|locks| locks.range_conflict(&req).copied()))
+}
+
+/// 进程关闭了文件的某个 fd：释放该进程在这个文件上的所有 POSIX 锁
+pub fn release_posix_locks(file: &OsInode, pid: usize) {
+    if LOCK_TABLE.lock().inodes.is_empty() {
+        return;
+    }
+    let key = lock_key(file);
+    let mut table = LOCK_TABLE.lock();
+    if let Some(locks) = table.inodes.get_mut(&key) {
+        locks.unlock_range(LockOwner::Process(pid), 0, OFFSET_MAX);
+    }
+    table.changed(key);
+}
+
+/// 释放某个持有者的所有锁：进程退出，或打开的文件描述的最后一个引用被关闭
+pub fn release_owner(owner: LockOwner) {
+    let mut table = LOCK_TABLE.lock();
+    if table.inodes.is_empty() {
+        return;
+    }
+    if let LockOwner::Process(_) = owner {
+        table.waiting.retain(|_, (_, r)| r.owner != owner);
+    }
+    let keys: Vec<LockKey> = table
+        .inodes
+        .iter_mut()
+        .filter_map(|(key, locks)| {
+            let before = locks.flocks.len() + locks.ranges.len();
+            if let LockOwner::File(ptr) = owner {
+                locks.flocks.retain(|f| f.owner != ptr);
+            }
+            locks.ranges.retain(|l| l.owner != owner);
+            (locks.flocks.len() + locks.ranges.len() != before).then_some(*key)
+        })
+        .collect();
+    for key in keys {
+        table.changed(key);
+    }
+}
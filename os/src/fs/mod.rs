@@ -2,27 +2,37 @@
 
 pub(crate) mod inode;
 mod stdio;
+pub mod tty;
 mod dirent;
 pub mod vfs;
 mod fd;
 pub mod pipe;
+pub mod epoll;
+pub mod eventfd;
+pub mod timerfd;
+pub mod signalfd;
+pub mod lock;
+pub mod pagecache;
 mod poll;
 pub mod dev;
+pub mod devfs;
 pub mod net;
 pub mod mount;
 pub mod ext4;
+pub mod tmpfs;
+pub mod procfs;
 pub mod select;
 // pub mod shm;
-use core::{any::Any, future::Future, panic, task::{Context, Poll, Waker}};
+use core::{any::Any, future::Future, panic, sync::atomic::{AtomicUsize, Ordering}, task::{Context, Poll, Waker}};
 use alloc::vec::Vec;
 use async_trait::async_trait;
-use dev::{find_device, open_device_file, register_device};
+use devfs::DevNode;
 use crate::devices::get_blk_devices;
 
 use crate::{ drivers, fs::vfs::VfsManager, mm::UserBuffer, task::custom_noop_waker, timer::get_time_ms, utils::{ error::{ASyncRet, ASyscallRet, GeneralRet, SysErrNo, SyscallRet, TemplateRet}, string::{get_parent_path_and_filename, normalize_absolute_path}}};
 use alloc::{format, string::{String, ToString}, sync::Arc, vec};
 use hashbrown::{HashMap, HashSet};
-use inode::InodeType;
+pub use inode::InodeType;
 use lwext4_rust::{bindings::SEEK_END, InodeTypes};
 use spin::{Lazy, RwLock};
 pub use stat::Statfs;
@@ -32,7 +42,7 @@ pub use vfs::vfs_ops::VfsOps;
 pub use stat::Kstat;
 pub use inode::OsInode;
 pub use fd::{FileClass,FileDescriptor};
-pub use poll::{PollFuture};
+pub use poll::{wait_poll, PollFuture};
 use alloc::boxed::Box;
 pub use dirent::Dirent;
 pub use ext4::EXT4FS;
@@ -134,13 +144,22 @@ unimplemented!()
     /// # 返回
     /// 一个 `PollEvents` 位掩码，指示哪些请求的事件（或错误/特殊事件）当前已就绪。
  
-    fn poll(&self, events: PollEvents, waker_to_register: &Waker) -> PollEvents{
-        unimplemented!()
+    /// 不支持等待的文件总是可读写，和 Linux 的 DEFAULT_POLLMASK 一致
+    fn poll(&self, events: PollEvents, _waker_to_register: &Waker) -> PollEvents{
+        events & (PollEvents::POLLIN | PollEvents::POLLOUT)
     }
 
     fn get_path(&self)->String{
      unimplemented!();
     }
+
+    /// 同步 fd 上的 O_NONBLOCK。read/write 拿不到 fd 标志，自己记录阻塞模式的文件需要实现
+    fn set_nonblock(&self, _nonblock: bool) {}
+
+    /// 设备相关的控制命令，`arg` 一般是用户态指针；不支持的命令返回 ENOTTY
+    async fn ioctl(&self, _cmd: usize, _arg: usize) -> SyscallRet {
+        Err(SysErrNo::ENOTTY)
+    }
       
 }
 
@@ -224,12 +243,24 @@ fn as_inode_type(types: InodeTypes) -> InodeType {
         }
     }
 }
-pub fn create_file(abs_path: &str, flags: OpenFlags, mode: u32,vfs:Arc<dyn VfsNodeOps>) -> Result<FileDescriptor, SysErrNo> {
-    // 一定能找到,因为除了RootInode外都有父结点
-    let parent_dir = vfs;
+/// 在父目录所在的文件系统中创建节点，返回新节点
+fn create_node(abs_path: &str, ty: InodeType, flags: OpenFlags) -> Result<Arc<dyn VfsNodeOps>, SysErrNo> {
+    let (parent, name) = get_parent_path_and_filename(abs_path);
+    let parent_dir = VfsManager::lookup(&parent, OpenFlags::O_DIRECTORY)?;
+    // 父目录可能经过符号链接或位于其他挂载点下，以它的真实路径为准
+    let real_path = format!("{}/{}", parent_dir.path().trim_end_matches('/'), name);
+    match parent_dir.create(&name, as_ext4_de_type(ty)) {
+        // 节点可能刚被并发创建出来，不要求独占创建时直接打开它
+        Err(e) if e == SysErrNo::EEXIST as i32 && !flags.contains(OpenFlags::O_EXCL) => {}
+        r => {
+            r?;
+        }
+    }
+    parent_dir.find(&real_path, flags | OpenFlags::O_ASK_SYMLINK, 0)
+}
+pub fn create_file(abs_path: &str, flags: OpenFlags, mode: u32) -> Result<FileDescriptor, SysErrNo> {
     let (readable, writable) = flags.read_write();
-     parent_dir.create(abs_path, as_ext4_de_type(flags.node_type()))?;
-    let inode= parent_dir.find(abs_path,flags,0)?;
+    let inode = create_node(abs_path, flags.node_type(), flags)?;
     inode.fmode_set(mode)?;
     inode.set_owner(0, 0)?;
     inode.set_timestamps(None, Some((get_time_ms() / 1000) as u32), None)?;
@@ -238,6 +269,16 @@ pub fn create_file(abs_path: &str, flags: OpenFlags, mode: u32,vfs:Arc<dyn VfsNo
     Ok(FileDescriptor::new(flags, FileClass::File(Arc::new(osinode))))
 }
 
+/// 创建 socket、FIFO 等没有数据的特殊文件，路径已存在时返回 EEXIST
+pub fn create_special_file(abs_path: &str, ty: InodeType, mode: u32) -> Result<Arc<dyn VfsNodeOps>, SysErrNo> {
+    if has_inode(abs_path) || VfsManager::lookup(abs_path, OpenFlags::O_ASK_SYMLINK).is_ok() {
+        return Err(SysErrNo::EEXIST);
+    }
+    let inode = create_node(abs_path, ty, OpenFlags::O_EXCL)?;
+    inode.fmode_set(mode)?;
+    inode.set_timestamps(None, Some((get_time_ms() / 1000) as u32), None)?;
+    Ok(inode)
+}
 
 /// 判断是否是动态链接文件
 pub fn is_dynamic_link_file(path: &str) -> bool {
@@ -253,45 +294,23 @@ pub fn find_inode(mut abs_path :&str, flags:OpenFlags)->Result<Arc<dyn VfsNodeOp
      
         abs_path = map_dynamic_link_file(abs_path);
     }
-    // let (ops,path) =  VfsManager::resolve_mnt_path(abs_path);
-    //  ops. 
-    root_inode().find(&abs_path, flags, 0)
+    VfsManager::lookup(abs_path, flags)
 }
 ///open file
-pub static mut TMP_NAME:usize= 0;
 pub fn open_file(mut abs_path: &str, flags: OpenFlags, mode: u32) -> Result<FileDescriptor, SysErrNo> {
 
     log::debug!("[open] abs_path={},flags={:#?},mode:{}", abs_path,flags,mode);
     if abs_path=="/"{
         return Ok(FileDescriptor::new(flags,
             
-            FileClass::File(Arc::new(OsInode::new(true,false,root_inode())))
+            FileClass::File(Arc::new(OsInode::new(true,false,VfsManager::lookup("/", flags)?)))
         
         ));
     }
-    if abs_path=="/tmp"{
-        // 如果带了 O_TMPFILE 就直接模拟 tmpfile 行为
-        // 生成随机文件名，比如 /tmp/tmp-abc123
-        let tmp_name = unsafe { TMP_NAME };
-        unsafe { TMP_NAME += 1 };
-
-        // 拼接完整路径
-        let full_path = format!("/tmp/{}", tmp_name);
-
-        // 创建文件（O_CREAT | O_EXCL | O_RDWR）
-        let tmp_flags = OpenFlags::O_CREATE | OpenFlags::O_EXCL | OpenFlags::O_RDWR;
-        let inode = open_file(&full_path, tmp_flags, 0o600)?;
-
-        
-
-       return  Ok(inode)
+    if flags.contains(OpenFlags::O_TMPFILE) {
+        return open_tmpfile(abs_path, flags, mode);
     }
     
-    //判断是否是设备文件
-    if find_device(abs_path) {
-        let device = open_device_file(abs_path)?;
-        return Ok(FileDescriptor{flags,file:FileClass::Abs(device)});
-    }
     // 如果是动态链接文件,转换路径
     if is_dynamic_link_file(abs_path) {
      
@@ -300,12 +319,7 @@ pub fn open_file(mut abs_path: &str, flags: OpenFlags, mode: u32) -> Result<File
 
     
     let abs_path = &fix_path(abs_path);
-    // let (ops,path) =  VfsManager::resolve_mnt_path(abs_path);
     let path =abs_path;
-    let (parent,_)= get_parent_path_and_filename(&path);
-    if find_device(&parent){
-        return Err(SysErrNo::ENOTDIR)
-    }
     // info!("open_path:{:?},open_ops:{}",path,ops.name());
     // println!("open_file abs_path={},pid:{}", abs_path, current_task_may_uninit().map_or_else(|| 0, |f| f.get_pid()));
     let mut inode: Option<Arc<dyn VfsNodeOps >> = None;
@@ -315,7 +329,7 @@ pub fn open_file(mut abs_path: &str, flags: OpenFlags, mode: u32) -> Result<File
     } else {
        
         
-        let found_res =root_inode().find(&path, flags, 0);
+        let found_res = VfsManager::lookup(&path, flags);
         if found_res.clone().err() == Some(SysErrNo::ENOTDIR) {
             warn!("[open_file] Error: A component in the path is not a directory: {:?}", &path);
             return Err(SysErrNo::ENOTDIR);
@@ -325,14 +339,18 @@ pub fn open_file(mut abs_path: &str, flags: OpenFlags, mode: u32) -> Result<File
             return Err(SysErrNo::ELOOP);
         }
         if let Ok(t) = found_res {
-            if !flags.contains(OpenFlags::O_ASK_SYMLINK) {
-                //符号链接文件不加入idx
+            if !flags.contains(OpenFlags::O_ASK_SYMLINK) && !t.is_dynamic() {
+                //符号链接文件和动态节点不加入idx
                 insert_inode_idx(abs_path, t.clone());
             }
             inode = Some(t);
         }
     }
     if let Some(inode) = inode {
+        //devfs 中的设备节点直接打开为设备文件
+        if let Some(device) = inode.as_any().downcast_ref::<DevNode>().and_then(|n| n.open_device()) {
+            return Ok(FileDescriptor{flags,file:FileClass::Abs(device?)});
+        }
         if flags.contains(OpenFlags::O_DIRECTORY) && !inode.is_dir() {
             warn!("[open_file] Error: A component in the path is not a directory: {:?}", path);
             return Err(SysErrNo::ENOTDIR);
@@ -343,14 +361,14 @@ pub fn open_file(mut abs_path: &str, flags: OpenFlags, mode: u32) -> Result<File
             osfile.lseek(0, SEEK_END )?;
         }
         if flags.contains(OpenFlags::O_TRUNC) {
-            inode.truncate(0)?;
+            osfile.truncate(0)?;
         }
         return Ok(FileDescriptor::new(flags,FileClass::File(Arc::new(osfile))));
     }
 
     // 节点不存在
     if flags.contains(OpenFlags::O_CREATE) {
-        return create_file(&path, flags, mode,root_inode());
+        return create_file(&path, flags, mode);
     }
     warn!("[open_file] Error: File or directory not found: {:?}", path);
     Err(SysErrNo::ENOENT)
@@ -359,6 +377,29 @@ pub fn open_file(mut abs_path: &str, flags: OpenFlags, mode: u32) -> Result<File
 
 
 
+/// O_TMPFILE：在目录下建一个匿名文件，建好后立即从目录中摘除
+fn open_tmpfile(dir: &str, flags: OpenFlags, mode: u32) -> Result<FileDescriptor, SysErrNo> {
+    static TMPFILE_SEQ: AtomicUsize = AtomicUsize::new(0);
+    if !flags.read_write().1 {
+        return Err(SysErrNo::EINVAL);
+    }
+    let dir_node = VfsManager::lookup(dir, OpenFlags::O_DIRECTORY)?;
+    let path = format!(
+        "{}/.tmpfile-{}",
+        dir_node.path().trim_end_matches('/'),
+        TMPFILE_SEQ.fetch_add(1, Ordering::Relaxed)
+    );
+    // 只有 tmpfs 的节点在摘除后仍然可用，其他文件系统上的匿名文件暂时留在目录里
+    let anonymous = VfsManager::fs_of(&path).lock().name() == "tmpfs";
+    let file_flags = (flags - OpenFlags::O_TMPFILE) | OpenFlags::O_CREATE | OpenFlags::O_EXCL;
+    let fd = create_file(&path, file_flags, mode)?;
+    if anonymous {
+        find_inode_idx(&path).ok_or(SysErrNo::ENOENT)?.unlink(&path)?;
+        remove_inode_idx(&path);
+    }
+    Ok(fd)
+}
+
 pub static FD2NODE: Lazy<RwLock<HashMap<String, Arc<dyn VfsNodeOps>>>> =
     Lazy::new(|| RwLock::new(HashMap::new()));
 
@@ -476,52 +517,7 @@ cd /glibc
 ./iozone_testcode.sh
 
 ";
-const MOUNTS: &str = " ext4 / ext rw 0 0\n";
 const PASSWD: &str = "root:x:0:0:root:/root:/bin/bash\nnobody:x:1:0:nobody:/nobody:/bin/bash\n";
-const MEMINFO: &str = r"
-MemTotal:         944564 kB
-MemFree:          835248 kB
-MemAvailable:     873464 kB
-Buffers:            6848 kB
-Cached:            36684 kB
-SwapCached:            0 kB
-Active:            19032 kB
-Inactive:          32676 kB
-Active(anon):        128 kB
-Inactive(anon):     8260 kB
-Active(file):      18904 kB
-Inactive(file):    24416 kB
-Unevictable:           0 kB
-Mlocked:               0 kB
-SwapTotal:             0 kB
-SwapFree:              0 kB
-Dirty:                 0 kB
-Writeback:             0 kB
-AnonPages:          8172 kB
-Mapped:            16376 kB
-Shmem:               216 kB
-KReclaimable:       9960 kB
-Slab:              17868 kB
-SReclaimable:       9960 kB
-SUnreclaim:         7908 kB
-KernelStack:        1072 kB
-PageTables:          600 kB
-NFS_Unstable:          0 kB
-Bounce:                0 kB
-WritebackTmp:          0 kB
-CommitLimit:      472280 kB
-Committed_AS:      64684 kB
-VmallocTotal:   67108863 kB
-VmallocUsed:       15740 kB
-VmallocChunk:          0 kB
-Percpu:              496 kB
-HugePages_Total:       0
-HugePages_Free:        0
-HugePages_Rsvd:        0
-HugePages_Surp:        0
-Hugepagesize:       2048 kB
-Hugetlb:               0 kB
-";
 const ADJTIME: &str = "0.000000 0.000000 UTC\n";
 const LOCALTIME: &str =
     "lrwxrwxrwx 1 root root 33 11月 18  2023 /etc/localtime -> /usr/share/zoneinfo/Asia/Shanghai\n";
@@ -534,73 +530,27 @@ pub async  fn create_init_files() -> GeneralRet {
         OpenFlags::O_CREATE | OpenFlags::O_RDWR | OpenFlags::O_DIRECTORY,
         DEFAULT_DIR_MODE,
     )?;
-    //创建/proc/mounts文件系统使用情况
-    let mountsfile = open_file(
-        "/proc/mounts",
-        OpenFlags::O_CREATE | OpenFlags::O_RDWR,
-        DEFAULT_FILE_MODE,
-    )?
-    .file()?;
-    let mut mountsinfo = String::from(MOUNTS);
-    let mut mountsvec = Vec::new();
-    unsafe {
-        let mounts = mountsinfo.as_bytes_mut();
-        mountsvec.push(core::slice::from_raw_parts_mut(
-            mounts.as_mut_ptr(),
-            mounts.len(),
-        ));
-    }
-    let mountbuf = UserBuffer::new(mountsvec);
-    let mountssize = mountsfile.write(mountbuf).await?;
-    debug!("create /proc/mounts with {} sizes", mountssize);
-    //创建/proc/meminfo系统内存使用情况
-    let memfile = open_file(
-        "/proc/meminfo",
-        OpenFlags::O_CREATE | OpenFlags::O_RDWR,
-        DEFAULT_FILE_MODE,
-    )?
-    .file()?;
-    let mut meminfo = String::from(MEMINFO);
-    let mut memvec = Vec::new();
-    unsafe {
-        let mem = meminfo.as_bytes_mut();
-        memvec.push(core::slice::from_raw_parts_mut(mem.as_mut_ptr(), mem.len()));
-    }
-    let membuf = UserBuffer::new(memvec);
-    let memsize = memfile.write(membuf).await?;
-    debug!("create /proc/meminfo with {} sizes", memsize);
+    // /proc 的内容按需从内核状态生成
+    VfsManager::mount("proc", "/proc", "proc", 0, None)?;
     //创建/dev文件夹
     open_file(
         "/dev",
         OpenFlags::O_CREATE | OpenFlags::O_RDWR | OpenFlags::O_DIRECTORY,
         DEFAULT_DIR_MODE,
     )?;
-    //注册设备/dev/rtc和/dev/rtc0
-    register_device("/dev/rtc");
-    register_device("/dev/rtc0");
-    //注册设备/dev/tty
-    register_device("/dev/tty");
-    //注册设备/dev/zero
-    register_device("/dev/zero");
-    //注册设备/dev/numm
-    register_device("/dev/null");
-    //注册设备/dev/cpu_dma_latency
-    register_device("/dev/cpu_dma_latency");
-
- 
-    //创建./dev/misc文件夹
-    open_file(
-        "/dev/misc",
-        OpenFlags::O_CREATE | OpenFlags::O_RDWR | OpenFlags::O_DIRECTORY,
-        DEFAULT_DIR_MODE,
-    )?;
+    // /dev 的节点由内核登记，驱动层探测到的块设备、串口一并登记
+    dev::register_builtin_devices();
+    devfs::register_driver_devices();
+    devfs::register_dir("shm");
+    VfsManager::mount("udev", "/dev", "devtmpfs", 0, None)?;
+    VfsManager::mount("shm", "/dev/shm", "tmpfs", 0, None)?;
     open_file(
         "/tmp",
         OpenFlags::O_CREATE | OpenFlags::O_RDWR | OpenFlags::O_DIRECTORY,
         DEFAULT_DIR_MODE,
     )?;
-    //注册设备/dev/misc/rtc
-    register_device("/dev/misc/rtc");
+    // /tmp 放在内存里，不写脏磁盘镜像，重启即清空
+    VfsManager::mount("tmpfs", "/tmp", "tmpfs", 0, None)?;
     //创建/etc文件夹
     open_file(
         "/etc",
@@ -710,11 +660,12 @@ pub fn init(){
     }
         // get devices and init
         crate::devices::regist_devices_irq();
+    net::stack::init();
 
     
     if !get_blk_devices().is_empty()  {
     VfsManager::mount("/dev/vda", "/", "ext4", 0, None).unwrap();
-    create_file("/usr", OpenFlags::O_CREATE |OpenFlags:: O_DIRECTORY, DEFAULT_DIR_MODE, root_inode()).unwrap();
+    create_file("/usr", OpenFlags::O_CREATE |OpenFlags:: O_DIRECTORY, DEFAULT_DIR_MODE).unwrap();
     // VfsManager::mount("/dev/vdb", "/usr", "ext4", 0, None).unwrap();
     }
     else{
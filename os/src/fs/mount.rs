@@ -10,7 +10,7 @@ use log:: info ;
 use crate::{config::MNT_TABLE_MAX_ENTRIES, fs::VfsOps, utils::error::{GeneralRet, SysErrNo}};
 
 
-const MS_RDONLY: u32 = 1;      // Mount read-only
+pub const MS_RDONLY: u32 = 1;      // Mount read-only
 const MS_NOSUID: u32 = 2;      // Ignore SUID and SGID bits
 const MS_NODEV: u32 = 4;       // Disallow access to device special files
 const MS_NOEXEC: u32 = 8;      // Disallow program execution
@@ -117,6 +117,13 @@ impl MountTable {
             Err(SysErrNo::EINVAL)
         }
     }
+    /// 找到管理 `abs_path` 的挂载：挂载点是 `abs_path` 的路径前缀且最长的那一项
+    pub fn find_mount(&self, abs_path: &str) -> Option<&MountEntry> {
+        self.entries
+            .iter()
+            .filter(|entry| is_path_prefix(&entry.mount_point, abs_path))
+            .max_by_key(|entry| entry.mount_point.len())
+    }
 /// 根据目录路径查找挂载信息。
     /// 如果给定的 `dir` 是一个挂载点，则返回其挂载信息。
     pub fn get_mount_info_by_dir(&self, dir_path: &str) -> Option<MountEntry> {
@@ -128,6 +135,14 @@ impl MountTable {
    
 }
 
+/// `prefix` 是否是 `path` 本身或它的某个祖先目录
+pub fn is_path_prefix(prefix: &str, path: &str) -> bool {
+    let prefix = prefix.trim_end_matches('/');
+    prefix.is_empty()
+        || path == prefix
+        || (path.starts_with(prefix) && path.as_bytes().get(prefix.len()) == Some(&b'/'))
+}
+
 /// 全局挂载表实例。
 /// 使用 `Lazy` 来延迟初始化，`Arc<Mutex<...>>` 来实现线程安全的共享访问。
 pub static MNT_TABLE: Lazy<Arc<Mutex<MountTable>>> = Lazy::new(|| {
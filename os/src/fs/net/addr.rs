@@ -0,0 +1,240 @@
+//! socket 地址相关的类型与用户态 sockaddr 的转换
+
+use alloc::{string::String, vec, vec::Vec};
+use core::fmt;
+
+use crate::{
+    mm::{
+        page_table::{copy_from_user_bytes, copy_from_user_exact, copy_to_user_bytes},
+        translated_refmut, VirtAddr,
+    },
+    utils::error::{SysErrNo, TemplateRet},
+};
+
+pub const AF_UNSPEC: u16 = 0;
+pub const AF_UNIX: u16 = 1;
+pub const AF_INET: u16 = 2;
+pub const AF_INET6: u16 = 10;
+
+/// IPv4 地址，按网络字节序保存四个字节
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub struct Ipv4Addr(pub [u8; 4]);
+
+impl Ipv4Addr {
+    pub const UNSPECIFIED: Self = Self([0, 0, 0, 0]);
+    pub const LOOPBACK: Self = Self([127, 0, 0, 1]);
+    pub const BROADCAST: Self = Self([255, 255, 255, 255]);
+
+    pub const fn new(a: u8, b: u8, c: u8, d: u8) -> Self {
+        Self([a, b, c, d])
+    }
+    pub fn is_unspecified(&self) -> bool {
+        self.0 == [0, 0, 0, 0]
+    }
+    pub fn is_loopback(&self) -> bool {
+        self.0[0] == 127
+    }
+    pub fn is_broadcast(&self) -> bool {
+        *self == Self::BROADCAST
+    }
+    pub fn to_u32(&self) -> u32 {
+        u32::from_be_bytes(self.0)
+    }
+    pub fn from_u32(v: u32) -> Self {
+        Self(v.to_be_bytes())
+    }
+}
+
+impl fmt::Debug for Ipv4Addr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}.{}", self.0[0], self.0[1], self.0[2], self.0[3])
+    }
+}
+
+impl fmt::Display for Ipv4Addr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+/// IPv4 地址 + 端口，端口为主机字节序
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub struct SocketAddrV4 {
+    pub addr: Ipv4Addr,
+    pub port: u16,
+}
+
+impl SocketAddrV4 {
+    pub const fn new(addr: Ipv4Addr, port: u16) -> Self {
+        Self { addr, port }
+    }
+}
+
+impl fmt::Debug for SocketAddrV4 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.addr, self.port)
+    }
+}
+
+/// 用户态的 `struct sockaddr_in`
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct SockAddrIn {
+    pub sin_family: u16,
+    /// 网络字节序
+    pub sin_port: u16,
+    /// 网络字节序
+    pub sin_addr: [u8; 4],
+    pub sin_zero: [u8; 8],
+}
+
+impl From<SockAddrIn> for SocketAddrV4 {
+    fn from(sa: SockAddrIn) -> Self {
+        Self {
+            addr: Ipv4Addr(sa.sin_addr),
+            port: u16::from_be(sa.sin_port),
+        }
+    }
+}
+
+impl From<SocketAddrV4> for SockAddrIn {
+    fn from(addr: SocketAddrV4) -> Self {
+        Self {
+            sin_family: AF_INET,
+            sin_port: addr.port.to_be(),
+            sin_addr: addr.addr.0,
+            sin_zero: [0; 8],
+        }
+    }
+}
+
+/// AF_UNIX 地址
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum UnixAddr {
+    /// 未绑定
+    Unnamed,
+    /// 文件系统中的路径，bind 后总是绝对路径
+    Path(String),
+    /// 抽象命名空间(sun_path[0] == 0)，不含开头的 0
+    Abstract(Vec<u8>),
+}
+
+/// `struct sockaddr_un` 中 sun_path 的长度
+pub const UNIX_PATH_MAX: usize = 108;
+
+/// 各协议族 socket 地址的统一表示
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum SockAddr {
+    Inet(SocketAddrV4),
+    Unix(UnixAddr),
+}
+
+impl SockAddr {
+    /// 转成用户态 sockaddr 的字节表示
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            SockAddr::Inet(v4) => {
+                let sa: SockAddrIn = (*v4).into();
+                unsafe {
+                    core::slice::from_raw_parts(
+                        &sa as *const SockAddrIn as *const u8,
+                        core::mem::size_of::<SockAddrIn>(),
+                    )
+                }
+                .to_vec()
+            }
+            SockAddr::Unix(unix) => {
+                let mut bytes = AF_UNIX.to_ne_bytes().to_vec();
+                match unix {
+                    UnixAddr::Unnamed => {}
+                    UnixAddr::Path(path) => {
+                        bytes.extend_from_slice(path.as_bytes());
+                        bytes.push(0);
+                    }
+                    UnixAddr::Abstract(name) => {
+                        bytes.push(0);
+                        bytes.extend_from_slice(name);
+                    }
+                }
+                bytes
+            }
+        }
+    }
+}
+
+/// 读取用户传入 sockaddr 的 family 字段
+pub fn read_sockaddr_family(token: usize, addr: *const u8, addrlen: u32) -> TemplateRet<u16> {
+    if addr.is_null() {
+        return Err(SysErrNo::EFAULT);
+    }
+    if (addrlen as usize) < core::mem::size_of::<u16>() {
+        return Err(SysErrNo::EINVAL);
+    }
+    Ok(unsafe { copy_from_user_exact::<u16>(token, addr as *const u16) }?)
+}
+
+/// 从用户空间读取一个 `sockaddr_in`
+pub fn read_sockaddr_in(token: usize, addr: *const u8, addrlen: u32) -> TemplateRet<SocketAddrV4> {
+    if addr.is_null() {
+        return Err(SysErrNo::EFAULT);
+    }
+    if (addrlen as usize) < core::mem::size_of::<SockAddrIn>() {
+        return Err(SysErrNo::EINVAL);
+    }
+    let sa = unsafe { copy_from_user_exact::<SockAddrIn>(token, addr as *const SockAddrIn) }?;
+    match sa.sin_family {
+        AF_INET => Ok(sa.into()),
+        // connect(AF_UNSPEC) 在 Linux 上用于解除 UDP 的关联，这里交给调用者区分
+        AF_UNSPEC => Ok(SocketAddrV4::default()),
+        _ => Err(SysErrNo::EAFNOSUPPORT),
+    }
+}
+
+/// 从用户空间读取一个 `sockaddr_un`，路径保持用户给出的原样(可能是相对路径)
+///
+/// 只有 family 字段时表示自动绑定，返回 [`UnixAddr::Unnamed`]
+pub fn read_sockaddr_un(token: usize, addr: *const u8, addrlen: u32) -> TemplateRet<UnixAddr> {
+    if read_sockaddr_family(token, addr, addrlen)? != AF_UNIX {
+        return Err(SysErrNo::EINVAL);
+    }
+    let len = (addrlen as usize).min(2 + UNIX_PATH_MAX) - 2;
+    if len == 0 {
+        return Ok(UnixAddr::Unnamed);
+    }
+    let mut path = vec![0u8; len];
+    unsafe { copy_from_user_bytes(token, &mut path, VirtAddr::from(addr as usize + 2), len) }?;
+    if path[0] == 0 {
+        return Ok(UnixAddr::Abstract(path[1..].to_vec()));
+    }
+    let end = path.iter().position(|&b| b == 0).unwrap_or(len);
+    let path = core::str::from_utf8(&path[..end]).map_err(|_| SysErrNo::EINVAL)?;
+    Ok(UnixAddr::Path(path.into()))
+}
+
+/// 把地址写回用户空间的 `sockaddr`，并按 Linux 语义更新 `*addrlen`
+///
+/// 若用户缓冲区较小则截断，`*addrlen` 仍写入完整长度
+pub fn write_sockaddr(token: usize, addr: *mut u8, addrlen: *mut u32, bytes: &[u8]) -> TemplateRet<()> {
+    if addr.is_null() || addrlen.is_null() {
+        return Ok(());
+    }
+    let len = translated_refmut(token, addrlen)?;
+    let copy_len = (*len as usize).min(bytes.len());
+    unsafe { copy_to_user_bytes(token, VirtAddr::from(addr as usize), &bytes[..copy_len]) }?;
+    *len = bytes.len() as u32;
+    Ok(())
+}
+
+/// 把 IPv4 地址写回用户空间的 `sockaddr_in`
+pub fn write_sockaddr_in(token: usize, addr: *mut u8, addrlen: *mut u32, v4: SocketAddrV4) -> TemplateRet<()> {
+    let sa: SockAddrIn = v4.into();
+    let bytes = unsafe {
+        core::slice::from_raw_parts(&sa as *const SockAddrIn as *const u8, core::mem::size_of::<SockAddrIn>())
+    };
+    write_sockaddr(token, addr, addrlen, bytes)
+}
+
+/// 把任意协议族的地址写回用户空间
+pub fn write_sock_addr(token: usize, addr: *mut u8, addrlen: *mut u32, sa: &SockAddr) -> TemplateRet<()> {
+    write_sockaddr(token, addr, addrlen, &sa.to_bytes())
+}
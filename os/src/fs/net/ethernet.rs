@@ -0,0 +1,253 @@
+//! 以太网链路层与 ARP
+//!
+//! 以太网接口上的 IPv4 报文先经 ARP 解析下一跳的 MAC 地址再封装成帧，
+//! 解析完成前的报文暂存在 ARP 表里，收到应答后再发出。没有应答时由 [`EthernetLink::tick`]
+//! 每隔 `ARP_RETRY_US` 重发请求，`ARP_MAX_TRIES` 次后丢弃暂存的报文。
+
+use alloc::{collections::BTreeMap, sync::Arc, vec, vec::Vec};
+use spin::Mutex;
+
+use super::{
+    addr::Ipv4Addr,
+    iface::{Interface, NetDevice},
+    ip,
+};
+use crate::{
+    devices::device::NetDriver,
+    timer::get_time_us,
+};
+
+pub type MacAddr = [u8; 6];
+
+pub const ETH_BROADCAST: MacAddr = [0xff; 6];
+pub const ETH_HEADER_LEN: usize = 14;
+/// 以太网负载的最大长度，即接口的 IP MTU
+pub const ETH_MTU: usize = 1500;
+
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_ARP: u16 = 0x0806;
+
+const ARP_HTYPE_ETHERNET: u16 = 1;
+const ARP_OP_REQUEST: u16 = 1;
+const ARP_OP_REPLY: u16 = 2;
+const ARP_PACKET_LEN: usize = 28;
+
+/// ARP 表项的有效期
+const ARP_ENTRY_TTL_US: u64 = 60 * 1_000_000;
+/// 同一地址两次 ARP 请求之间的最小间隔
+const ARP_RETRY_US: u64 = 1_000_000;
+/// 每个未解析地址最多暂存的报文数
+const ARP_PENDING_MAX: usize = 8;
+/// 放弃解析前最多发送的 ARP 请求数
+const ARP_MAX_TRIES: usize = 3;
+
+/// 把 [`NetDriver`] 适配成收发以太网帧的 [`NetDevice`]
+pub struct EthernetDevice {
+    driver: Arc<dyn NetDriver>,
+}
+
+impl EthernetDevice {
+    pub fn new(driver: Arc<dyn NetDriver>) -> Self {
+        Self { driver }
+    }
+
+    pub fn mac_address(&self) -> MacAddr {
+        self.driver.mac_address()
+    }
+}
+
+impl NetDevice for EthernetDevice {
+    fn transmit(&self, frame: Vec<u8>) {
+        if let Err(e) = self.driver.send(&frame) {
+            warn!("[eth] {} failed to send frame: {:?}", self.driver.get_id(), e);
+        }
+    }
+    fn receive(&self) -> Option<Vec<u8>> {
+        let mut buf = vec![0u8; ETH_HEADER_LEN + ETH_MTU];
+        let len = self.driver.recv(&mut buf).ok()?;
+        buf.truncate(len);
+        Some(buf)
+    }
+    fn mtu(&self) -> usize {
+        ETH_MTU
+    }
+}
+
+struct ArpEntry {
+    mac: MacAddr,
+    expire_us: u64,
+}
+
+/// 等待 ARP 应答的报文
+struct ArpPending {
+    packets: Vec<Vec<u8>>,
+    last_request_us: u64,
+    /// 已经发送的请求数
+    tries: usize,
+}
+
+#[derive(Default)]
+struct ArpTable {
+    entries: BTreeMap<Ipv4Addr, ArpEntry>,
+    pending: BTreeMap<Ipv4Addr, ArpPending>,
+}
+
+/// 以太网接口的链路层状态
+pub struct EthernetLink {
+    pub mac: MacAddr,
+    arp: Mutex<ArpTable>,
+}
+
+fn frame(dst: MacAddr, src: MacAddr, ethertype: u16, payload: &[u8]) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(ETH_HEADER_LEN + payload.len());
+    buf.extend_from_slice(&dst);
+    buf.extend_from_slice(&src);
+    buf.extend_from_slice(&ethertype.to_be_bytes());
+    buf.extend_from_slice(payload);
+    buf
+}
+
+impl EthernetLink {
+    pub fn new(mac: MacAddr) -> Self {
+        Self {
+            mac,
+            arp: Mutex::new(ArpTable::default()),
+        }
+    }
+
+    fn arp_packet(&self, op: u16, sender_ip: Ipv4Addr, target_mac: MacAddr, target_ip: Ipv4Addr) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(ARP_PACKET_LEN);
+        buf.extend_from_slice(&ARP_HTYPE_ETHERNET.to_be_bytes());
+        buf.extend_from_slice(&ETHERTYPE_IPV4.to_be_bytes());
+        buf.push(6);
+        buf.push(4);
+        buf.extend_from_slice(&op.to_be_bytes());
+        buf.extend_from_slice(&self.mac);
+        buf.extend_from_slice(&sender_ip.0);
+        buf.extend_from_slice(&target_mac);
+        buf.extend_from_slice(&target_ip.0);
+        buf
+    }
+
+    /// 发送 IPv4 报文，下一跳的 MAC 地址未知时先发 ARP 请求
+    pub fn send_ip(&self, iface: &Interface, dev: &dyn NetDevice, packet: Vec<u8>, next_hop: Ipv4Addr) {
+        if next_hop.is_broadcast() || next_hop == iface.broadcast() {
+            dev.transmit(frame(ETH_BROADCAST, self.mac, ETHERTYPE_IPV4, &packet));
+            return;
+        }
+        let now = get_time_us() as u64;
+        let mut arp = self.arp.lock();
+        if let Some(entry) = arp.entries.get(&next_hop) {
+            if entry.expire_us > now {
+                let mac = entry.mac;
+                drop(arp);
+                dev.transmit(frame(mac, self.mac, ETHERTYPE_IPV4, &packet));
+                return;
+            }
+            arp.entries.remove(&next_hop);
+        }
+        let pending = arp.pending.entry(next_hop).or_insert(ArpPending {
+            packets: Vec::new(),
+            last_request_us: 0,
+            tries: 0,
+        });
+        if pending.packets.len() < ARP_PENDING_MAX {
+            pending.packets.push(packet);
+        }
+        if pending.last_request_us != 0 && now - pending.last_request_us < ARP_RETRY_US {
+            return;
+        }
+        pending.last_request_us = now;
+        pending.tries += 1;
+        drop(arp);
+        self.send_request(iface, dev, next_hop);
+    }
+
+    fn send_request(&self, iface: &Interface, dev: &dyn NetDevice, target: Ipv4Addr) {
+        trace!("[arp] who-has {} tell {}", target, iface.addr);
+        let req = self.arp_packet(ARP_OP_REQUEST, iface.addr, [0; 6], target);
+        dev.transmit(frame(ETH_BROADCAST, self.mac, ETHERTYPE_ARP, &req));
+    }
+
+    /// 重发超时未应答的 ARP 请求，重试次数用尽的地址丢弃暂存的报文
+    pub fn tick(&self, iface: &Interface, dev: &dyn NetDevice, now: u64) {
+        let mut retry = Vec::new();
+        {
+            let mut arp = self.arp.lock();
+            arp.pending.retain(|ip, pending| {
+                if now - pending.last_request_us < ARP_RETRY_US {
+                    return true;
+                }
+                if pending.tries >= ARP_MAX_TRIES {
+                    debug!("[arp] {} unreachable, dropping {} packets", ip, pending.packets.len());
+                    return false;
+                }
+                pending.last_request_us = now;
+                pending.tries += 1;
+                retry.push(*ip);
+                true
+            });
+        }
+        for ip in retry {
+            self.send_request(iface, dev, ip);
+        }
+    }
+
+    /// 处理收到的以太网帧
+    pub fn input(&self, iface: &Interface, dev: &dyn NetDevice, data: &[u8]) {
+        if data.len() < ETH_HEADER_LEN {
+            return;
+        }
+        let dst: MacAddr = data[0..6].try_into().unwrap();
+        if dst != self.mac && dst != ETH_BROADCAST && dst[0] & 1 == 0 {
+            return;
+        }
+        let payload = &data[ETH_HEADER_LEN..];
+        match u16::from_be_bytes([data[12], data[13]]) {
+            ETHERTYPE_IPV4 => ip::input(iface, payload),
+            ETHERTYPE_ARP => self.arp_input(iface, dev, payload),
+            _ => {}
+        }
+    }
+
+    fn arp_input(&self, iface: &Interface, dev: &dyn NetDevice, pkt: &[u8]) {
+        if pkt.len() < ARP_PACKET_LEN
+            || u16::from_be_bytes([pkt[0], pkt[1]]) != ARP_HTYPE_ETHERNET
+            || u16::from_be_bytes([pkt[2], pkt[3]]) != ETHERTYPE_IPV4
+            || pkt[4] != 6
+            || pkt[5] != 4
+        {
+            return;
+        }
+        let op = u16::from_be_bytes([pkt[6], pkt[7]]);
+        let sender_mac: MacAddr = pkt[8..14].try_into().unwrap();
+        let sender_ip = Ipv4Addr([pkt[14], pkt[15], pkt[16], pkt[17]]);
+        let target_ip = Ipv4Addr([pkt[24], pkt[25], pkt[26], pkt[27]]);
+        let for_us = target_ip == iface.addr;
+
+        // 只记录发给本机的请求的发送方，或是已经在表中/正在解析的地址
+        let flushed = {
+            let mut arp = self.arp.lock();
+            let known = arp.entries.contains_key(&sender_ip) || arp.pending.contains_key(&sender_ip);
+            if !sender_ip.is_unspecified() && (for_us || known) {
+                arp.entries.insert(
+                    sender_ip,
+                    ArpEntry {
+                        mac: sender_mac,
+                        expire_us: get_time_us() as u64 + ARP_ENTRY_TTL_US,
+                    },
+                );
+            }
+            arp.pending.remove(&sender_ip).map(|p| p.packets).unwrap_or_default()
+        };
+        for packet in flushed {
+            dev.transmit(frame(sender_mac, self.mac, ETHERTYPE_IPV4, &packet));
+        }
+
+        if op == ARP_OP_REQUEST && for_us {
+            trace!("[arp] {} is-at {:x?}, tell {}", iface.addr, self.mac, sender_ip);
+            let reply = self.arp_packet(ARP_OP_REPLY, iface.addr, sender_mac, sender_ip);
+            dev.transmit(frame(sender_mac, self.mac, ETHERTYPE_ARP, &reply));
+        }
+    }
+}
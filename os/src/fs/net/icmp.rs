@@ -0,0 +1,269 @@
+//! ICMP：回显应答、端口不可达，以及 ping 使用的 ICMP socket
+//!
+//! SOCK_RAW 的 ICMP socket 收到的是包含 IP 首部的完整报文，
+//! SOCK_DGRAM 的 ICMP socket(ping socket)只能发送回显请求，
+//! 内核会改写其中的标识符，并只把标识符匹配的回显应答交给它。
+
+use alloc::{
+    collections::VecDeque,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use core::{
+    future::poll_fn,
+    sync::atomic::{AtomicU16, Ordering},
+    task::{Poll, Waker},
+};
+use spin::Mutex;
+
+use super::{
+    addr::{Ipv4Addr, SocketAddrV4},
+    iface::Interface,
+    ip,
+    stack::{self, poll_stack},
+};
+use crate::{
+    fs::PollEvents,
+    utils::error::{SysErrNo, TemplateRet},
+};
+
+pub const ICMP_ECHO_REPLY: u8 = 0;
+pub const ICMP_DEST_UNREACH: u8 = 3;
+pub const ICMP_ECHO_REQUEST: u8 = 8;
+const ICMP_PORT_UNREACH: u8 = 3;
+const ICMP_HEADER_LEN: usize = 8;
+/// 单个 ICMP 报文的最大长度，受 IPv4 总长度字段限制
+pub(super) const ICMP_MAX_MSG: usize = 0xffff - ip::IPV4_HEADER_LEN;
+/// 接收队列中允许积压的字节数
+const ICMP_RECV_BUF: usize = 0x10000;
+
+/// 填写校验和并发送一个 ICMP 报文
+fn send(src: Ipv4Addr, dst: Ipv4Addr, mut msg: Vec<u8>) -> bool {
+    msg[2..4].copy_from_slice(&[0, 0]);
+    let csum = ip::checksum(&msg, 0);
+    msg[2..4].copy_from_slice(&csum.to_be_bytes());
+    ip::output(src, dst, ip::IPPROTO_ICMP, msg)
+}
+
+/// 网络层交付的 ICMP 报文入口，`packet` 是包含 IP 首部的完整报文
+pub fn input(iface: &Interface, src: Ipv4Addr, dst: Ipv4Addr, packet: &[u8], msg: &[u8]) {
+    if msg.len() < ICMP_HEADER_LEN || ip::checksum(msg, 0) != 0 {
+        return;
+    }
+    deliver(src, packet, msg);
+    if msg[0] == ICMP_ECHO_REQUEST && msg[1] == 0 {
+        let mut reply = msg.to_vec();
+        reply[0] = ICMP_ECHO_REPLY;
+        // 发给广播地址的请求用接口地址应答
+        let from = if ip::is_local(dst) { dst } else { iface.addr };
+        send(from, src, reply);
+    }
+}
+
+/// 对没有 socket 接收的 UDP 报文回复端口不可达，`orig` 为原报文的 IP 首部和前 8 字节
+pub fn send_port_unreachable(orig_src: Ipv4Addr, orig_dst: Ipv4Addr, orig: &[u8]) {
+    if orig_src.is_unspecified() || orig_src.is_broadcast() {
+        return;
+    }
+    let mut msg = Vec::with_capacity(ICMP_HEADER_LEN + orig.len());
+    msg.extend_from_slice(&[ICMP_DEST_UNREACH, ICMP_PORT_UNREACH, 0, 0, 0, 0, 0, 0]);
+    msg.extend_from_slice(orig);
+    send(orig_dst, orig_src, msg);
+}
+
+struct IcmpInner {
+    raw: bool,
+    local: Ipv4Addr,
+    /// ping socket 的回显标识符
+    ident: u16,
+    peer: Option<Ipv4Addr>,
+    rx: VecDeque<(Ipv4Addr, Vec<u8>)>,
+    rx_bytes: usize,
+    readers: Vec<Waker>,
+}
+
+impl IcmpInner {
+    fn register_reader(&mut self, w: &Waker) {
+        if !self.readers.iter().any(|rw| rw.will_wake(w)) {
+            self.readers.push(w.clone());
+        }
+    }
+    fn notify_readers(&mut self) {
+        for w in self.readers.drain(..) {
+            w.wake();
+        }
+    }
+}
+
+static ICMP_SOCKETS: Mutex<Vec<Weak<Mutex<IcmpInner>>>> = Mutex::new(Vec::new());
+static NEXT_IDENT: AtomicU16 = AtomicU16::new(1);
+
+fn deliver(src: Ipv4Addr, packet: &[u8], msg: &[u8]) {
+    let socks: Vec<Arc<Mutex<IcmpInner>>> = ICMP_SOCKETS.lock().iter().filter_map(|w| w.upgrade()).collect();
+    let ident = u16::from_be_bytes([msg[4], msg[5]]);
+    for sock in socks {
+        let mut inner = sock.lock();
+        if inner.peer.is_some_and(|p| p != src) {
+            continue;
+        }
+        let data = if inner.raw {
+            packet
+        } else if msg[0] == ICMP_ECHO_REPLY && ident == inner.ident {
+            msg
+        } else {
+            continue;
+        };
+        if inner.rx_bytes + data.len() > ICMP_RECV_BUF {
+            continue;
+        }
+        inner.rx_bytes += data.len();
+        inner.rx.push_back((src, data.to_vec()));
+        inner.notify_readers();
+    }
+}
+
+/// 用户持有的 ICMP socket
+pub struct IcmpSocket {
+    inner: Arc<Mutex<IcmpInner>>,
+}
+
+impl IcmpSocket {
+    pub fn new(raw: bool) -> Self {
+        let inner = Arc::new(Mutex::new(IcmpInner {
+            raw,
+            local: Ipv4Addr::UNSPECIFIED,
+            ident: NEXT_IDENT.fetch_add(1, Ordering::Relaxed),
+            peer: None,
+            rx: VecDeque::new(),
+            rx_bytes: 0,
+            readers: Vec::new(),
+        }));
+        let mut socks = ICMP_SOCKETS.lock();
+        socks.retain(|w| w.strong_count() > 0);
+        socks.push(Arc::downgrade(&inner));
+        Self { inner }
+    }
+
+    pub fn is_raw(&self) -> bool {
+        self.inner.lock().raw
+    }
+
+    /// ping socket 绑定的端口作为回显标识符
+    pub fn bind(&self, addr: SocketAddrV4) -> TemplateRet<()> {
+        if !stack::is_local_addr(addr.addr) {
+            return Err(SysErrNo::EADDRNOTAVAIL);
+        }
+        let mut inner = self.inner.lock();
+        inner.local = addr.addr;
+        if !inner.raw && addr.port != 0 {
+            inner.ident = addr.port;
+        }
+        Ok(())
+    }
+
+    pub fn local_addr(&self) -> SocketAddrV4 {
+        let inner = self.inner.lock();
+        SocketAddrV4::new(inner.local, if inner.raw { 0 } else { inner.ident })
+    }
+
+    pub fn peer_addr(&self) -> TemplateRet<SocketAddrV4> {
+        let peer = self.inner.lock().peer.ok_or(SysErrNo::ENOTCONN)?;
+        Ok(SocketAddrV4::new(peer, 0))
+    }
+
+    pub fn connect(&self, peer: Option<SocketAddrV4>) -> TemplateRet<()> {
+        self.inner.lock().peer = peer.map(|p| p.addr);
+        Ok(())
+    }
+
+    pub fn send_to(&self, data: &[u8], dst: Option<SocketAddrV4>) -> TemplateRet<usize> {
+        let (src, dst, msg) = {
+            let inner = self.inner.lock();
+            let dst = dst.map(|d| d.addr).or(inner.peer).ok_or(SysErrNo::EDESTADDRREQ)?;
+            if data.len() < ICMP_HEADER_LEN {
+                return Err(SysErrNo::EINVAL);
+            }
+            if data.len() > ICMP_MAX_MSG {
+                return Err(SysErrNo::EMSGSIZE);
+            }
+            let mut msg = data.to_vec();
+            if !inner.raw {
+                if msg[0] != ICMP_ECHO_REQUEST || msg[1] != 0 {
+                    return Err(SysErrNo::EINVAL);
+                }
+                msg[4..6].copy_from_slice(&inner.ident.to_be_bytes());
+            }
+            let src = if inner.local.is_unspecified() {
+                stack::source_addr_for(dst)?
+            } else {
+                inner.local
+            };
+            (src, dst, msg)
+        };
+        let sent = if self.is_raw() {
+            // 原始 socket 的校验和由用户负责
+            ip::output(src, dst, ip::IPPROTO_ICMP, msg)
+        } else {
+            send(src, dst, msg)
+        };
+        if !sent {
+            return Err(SysErrNo::EMSGSIZE);
+        }
+        poll_stack();
+        Ok(data.len())
+    }
+
+    pub async fn recv_from(
+        &self,
+        len: usize,
+        nonblock: bool,
+        peek: bool,
+    ) -> TemplateRet<(Vec<u8>, usize, SocketAddrV4)> {
+        poll_fn(|cx| {
+            let mut inner = self.inner.lock();
+            if let Some((src, payload)) = inner.rx.front() {
+                let (src, full_len) = (*src, payload.len());
+                let data = payload[..full_len.min(len)].to_vec();
+                if !peek {
+                    inner.rx.pop_front();
+                    inner.rx_bytes -= full_len;
+                }
+                return Poll::Ready(Ok((data, full_len, SocketAddrV4::new(src, 0))));
+            }
+            if nonblock {
+                return Poll::Ready(Err(SysErrNo::EAGAIN));
+            }
+            inner.register_reader(cx.waker());
+            Poll::Pending
+        })
+        .await
+    }
+
+    pub fn recv_queue_len(&self) -> usize {
+        self.inner.lock().rx.front().map_or(0, |(_, p)| p.len())
+    }
+
+    pub fn poll(&self, events: PollEvents, waker: &Waker) -> PollEvents {
+        let mut inner = self.inner.lock();
+        let mut re = PollEvents::empty();
+        if events.contains(PollEvents::POLLIN) {
+            if !inner.rx.is_empty() {
+                re |= PollEvents::POLLIN;
+            } else {
+                inner.register_reader(waker);
+            }
+        }
+        if events.contains(PollEvents::POLLOUT) {
+            re |= PollEvents::POLLOUT;
+        }
+        re
+    }
+}
+
+impl Drop for IcmpSocket {
+    fn drop(&mut self) {
+        ICMP_SOCKETS
+            .lock()
+            .retain(|w| w.strong_count() > 0 && !core::ptr::eq(w.as_ptr(), Arc::as_ptr(&self.inner)));
+    }
+}
@@ -0,0 +1,217 @@
+//! 网络接口
+//!
+//! 每个接口绑定一个 [`NetDevice`] 和一个 IPv4 地址。
+//! 回环接口 lo 的设备直接收发 IPv4 报文，以太网接口(eth0)的设备收发以太网帧，
+//! 由 [`EthernetLink`] 负责封装和 ARP 解析。
+
+use alloc::{collections::VecDeque, sync::Arc, vec::Vec};
+use core::sync::atomic::{AtomicUsize, Ordering};
+use spin::Mutex;
+
+use super::{
+    addr::Ipv4Addr,
+    ethernet::{EthernetDevice, EthernetLink},
+    ip,
+};
+use crate::devices::get_net_devices;
+
+/// eth0 的静态配置，与 QEMU user 网络(slirp)的默认网段一致
+const ETH0_ADDR: Ipv4Addr = Ipv4Addr([10, 0, 2, 15]);
+const ETH0_PREFIX_LEN: u8 = 24;
+const ETH0_GATEWAY: Ipv4Addr = Ipv4Addr([10, 0, 2, 2]);
+
+/// 接口下面的链路层设备
+pub trait NetDevice: Send + Sync {
+    /// 发送一帧
+    fn transmit(&self, frame: Vec<u8>);
+    /// 取出一帧收到的数据
+    fn receive(&self) -> Option<Vec<u8>>;
+    fn mtu(&self) -> usize;
+}
+
+/// 回环设备，发送的报文直接进入自己的接收队列
+pub struct LoopbackDevice {
+    queue: Mutex<VecDeque<Vec<u8>>>,
+}
+
+impl LoopbackDevice {
+    pub const MTU: usize = 65536;
+
+    pub fn new() -> Self {
+        Self {
+            queue: Mutex::new(VecDeque::new()),
+        }
+    }
+}
+
+impl NetDevice for LoopbackDevice {
+    fn transmit(&self, frame: Vec<u8>) {
+        self.queue.lock().push_back(frame);
+    }
+    fn receive(&self) -> Option<Vec<u8>> {
+        self.queue.lock().pop_front()
+    }
+    fn mtu(&self) -> usize {
+        Self::MTU
+    }
+}
+
+/// 接口上的收发统计
+#[derive(Default)]
+pub struct IfaceStats {
+    pub rx_packets: AtomicUsize,
+    pub rx_bytes: AtomicUsize,
+    pub tx_packets: AtomicUsize,
+    pub tx_bytes: AtomicUsize,
+}
+
+pub struct Interface {
+    pub name: &'static str,
+    pub addr: Ipv4Addr,
+    pub prefix_len: u8,
+    /// 默认网关，不在本网段的地址经它转发
+    pub gateway: Option<Ipv4Addr>,
+    pub stats: IfaceStats,
+    dev: Arc<dyn NetDevice>,
+    /// 以太网接口的链路层状态，回环接口为 `None`
+    eth: Option<EthernetLink>,
+}
+
+impl Interface {
+    pub fn new(name: &'static str, addr: Ipv4Addr, prefix_len: u8, dev: Arc<dyn NetDevice>) -> Self {
+        Self {
+            name,
+            addr,
+            prefix_len,
+            gateway: None,
+            stats: IfaceStats::default(),
+            dev,
+            eth: None,
+        }
+    }
+
+    /// 以太网接口
+    pub fn new_ethernet(
+        name: &'static str,
+        addr: Ipv4Addr,
+        prefix_len: u8,
+        gateway: Option<Ipv4Addr>,
+        dev: Arc<EthernetDevice>,
+    ) -> Self {
+        let mac = dev.mac_address();
+        Self {
+            name,
+            addr,
+            prefix_len,
+            gateway,
+            stats: IfaceStats::default(),
+            dev,
+            eth: Some(EthernetLink::new(mac)),
+        }
+    }
+
+    pub fn netmask(&self) -> Ipv4Addr {
+        let mask = if self.prefix_len == 0 {
+            0
+        } else {
+            u32::MAX << (32 - self.prefix_len as u32)
+        };
+        Ipv4Addr::from_u32(mask)
+    }
+
+    pub fn broadcast(&self) -> Ipv4Addr {
+        Ipv4Addr::from_u32(self.addr.to_u32() | !self.netmask().to_u32())
+    }
+
+    /// `ip` 是否位于该接口所在的子网
+    pub fn contains(&self, ip: Ipv4Addr) -> bool {
+        let mask = self.netmask().to_u32();
+        ip.to_u32() & mask == self.addr.to_u32() & mask
+    }
+
+    pub fn mtu(&self) -> usize {
+        self.dev.mtu()
+    }
+
+    pub fn is_loopback(&self) -> bool {
+        self.addr.is_loopback()
+    }
+
+    pub fn mac_address(&self) -> Option<[u8; 6]> {
+        self.eth.as_ref().map(|eth| eth.mac)
+    }
+
+    /// 发送一个完整的 IPv4 报文，`next_hop` 用于以太网上的地址解析
+    pub fn send_ip(&self, packet: Vec<u8>, next_hop: Ipv4Addr) {
+        self.stats.tx_packets.fetch_add(1, Ordering::Relaxed);
+        self.stats.tx_bytes.fetch_add(packet.len(), Ordering::Relaxed);
+        match &self.eth {
+            Some(eth) => eth.send_ip(self, &*self.dev, packet, next_hop),
+            None => self.dev.transmit(packet),
+        }
+    }
+
+    /// 链路层的定时任务
+    pub fn tick(&self, now: u64) {
+        if let Some(eth) = &self.eth {
+            eth.tick(self, &*self.dev, now);
+        }
+    }
+
+    /// 处理设备上所有已收到的报文，返回是否处理了报文
+    pub fn poll(&self) -> bool {
+        let mut received = false;
+        while let Some(frame) = self.dev.receive() {
+            received = true;
+            self.stats.rx_packets.fetch_add(1, Ordering::Relaxed);
+            self.stats.rx_bytes.fetch_add(frame.len(), Ordering::Relaxed);
+            match &self.eth {
+                Some(eth) => eth.input(self, &*self.dev, &frame),
+                None => ip::input(self, &frame),
+            }
+        }
+        received
+    }
+}
+
+static INTERFACES: Mutex<Vec<Arc<Interface>>> = Mutex::new(Vec::new());
+
+pub fn add_interface(iface: Interface) {
+    info!(
+        "[net] {} up, addr {}/{}, mtu {}",
+        iface.name,
+        iface.addr,
+        iface.prefix_len,
+        iface.mtu()
+    );
+    INTERFACES.lock().push(Arc::new(iface));
+}
+
+pub fn interfaces() -> Vec<Arc<Interface>> {
+    INTERFACES.lock().clone()
+}
+
+/// 创建回环接口 lo(127.0.0.1/8)
+pub fn init_loopback() {
+    add_interface(Interface::new(
+        "lo",
+        Ipv4Addr::LOOPBACK,
+        8,
+        Arc::new(LoopbackDevice::new()),
+    ));
+}
+
+/// 把第一个网卡配置为 eth0
+pub fn init_ethernet() {
+    let Some(driver) = get_net_devices().into_iter().next() else {
+        info!("[net] no network device, only lo is available");
+        return;
+    };
+    add_interface(Interface::new_ethernet(
+        "eth0",
+        ETH0_ADDR,
+        ETH0_PREFIX_LEN,
+        Some(ETH0_GATEWAY),
+        Arc::new(EthernetDevice::new(driver)),
+    ));
+}
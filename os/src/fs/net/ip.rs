@@ -0,0 +1,171 @@
+//! IPv4 网络层：报文封装/解析、校验和与路由
+
+use alloc::{sync::Arc, vec::Vec};
+use core::sync::atomic::{AtomicU16, Ordering};
+
+use super::{
+    addr::Ipv4Addr,
+    icmp,
+    iface::{interfaces, Interface},
+    tcp::{self, TcpSegment},
+    udp::{self, UdpDatagram},
+};
+
+pub const IPPROTO_ICMP: u8 = 1;
+pub const IPPROTO_TCP: u8 = 6;
+pub const IPPROTO_UDP: u8 = 17;
+
+pub const IPV4_HEADER_LEN: usize = 20;
+const DEFAULT_TTL: u8 = 64;
+/// Don't Fragment
+const IP_DF: u16 = 0x4000;
+/// More Fragments
+const IP_MF: u16 = 0x2000;
+
+static IP_IDENT: AtomicU16 = AtomicU16::new(1);
+
+/// 计算 Internet 校验和，`initial` 为已经累加的部分(如伪首部)
+pub fn checksum(data: &[u8], initial: u32) -> u16 {
+    let mut sum = initial;
+    let mut chunks = data.chunks_exact(2);
+    for c in &mut chunks {
+        sum += u16::from_be_bytes([c[0], c[1]]) as u32;
+    }
+    if let [last] = chunks.remainder() {
+        sum += (*last as u32) << 8;
+    }
+    while sum >> 16 != 0 {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+/// TCP/UDP 伪首部的累加和
+pub fn pseudo_header_sum(src: Ipv4Addr, dst: Ipv4Addr, protocol: u8, len: usize) -> u32 {
+    let s = src.0;
+    let d = dst.0;
+    u16::from_be_bytes([s[0], s[1]]) as u32
+        + u16::from_be_bytes([s[2], s[3]]) as u32
+        + u16::from_be_bytes([d[0], d[1]]) as u32
+        + u16::from_be_bytes([d[2], d[3]]) as u32
+        + protocol as u32
+        + len as u32
+}
+
+/// 本机的地址：任一接口的地址或 127.0.0.0/8
+pub fn is_local(addr: Ipv4Addr) -> bool {
+    addr.is_loopback() || interfaces().iter().any(|i| i.addr == addr)
+}
+
+/// 查找发往 `dst` 的出接口和下一跳
+///
+/// 发给本机地址的报文一律走回环接口；本网段的地址直接投递，
+/// 其余的交给有默认网关的接口转发
+pub fn route(dst: Ipv4Addr) -> Option<(Arc<Interface>, Ipv4Addr)> {
+    let ifaces = interfaces();
+    if is_local(dst) {
+        return ifaces.iter().find(|i| i.is_loopback()).map(|i| (i.clone(), dst));
+    }
+    let mut external = ifaces.iter().filter(|i| !i.is_loopback());
+    if dst.is_broadcast() {
+        return external.next().map(|i| (i.clone(), dst));
+    }
+    if let Some(iface) = external
+        .clone()
+        .filter(|i| i.contains(dst))
+        .max_by_key(|i| i.prefix_len)
+    {
+        return Some((iface.clone(), dst));
+    }
+    external.find_map(|i| i.gateway.map(|gw| (i.clone(), gw)))
+}
+
+/// 发往 `dst` 时使用的源地址
+pub fn source_addr_for(dst: Ipv4Addr) -> Option<Ipv4Addr> {
+    if is_local(dst) {
+        return Some(if dst.is_loopback() { Ipv4Addr::LOOPBACK } else { dst });
+    }
+    route(dst).map(|(iface, _)| iface.addr)
+}
+
+/// 路由的 MTU
+pub fn mtu_for(dst: Ipv4Addr) -> Option<usize> {
+    route(dst).map(|(iface, _)| iface.mtu())
+}
+
+/// 封装并发送一个 IPv4 报文，没有路由时丢弃
+pub fn output(src: Ipv4Addr, dst: Ipv4Addr, protocol: u8, payload: Vec<u8>) -> bool {
+    let Some((iface, next_hop)) = route(dst) else {
+        debug!("[ip] no route to {}", dst);
+        return false;
+    };
+    let total_len = IPV4_HEADER_LEN + payload.len();
+    if total_len > iface.mtu() || total_len > u16::MAX as usize {
+        // 不支持分片
+        warn!("[ip] packet to {} too large for {} ({} bytes)", dst, iface.name, total_len);
+        return false;
+    }
+    let mut pkt = Vec::with_capacity(total_len);
+    pkt.push(0x45);
+    pkt.push(0);
+    pkt.extend_from_slice(&(total_len as u16).to_be_bytes());
+    pkt.extend_from_slice(&IP_IDENT.fetch_add(1, Ordering::Relaxed).to_be_bytes());
+    pkt.extend_from_slice(&IP_DF.to_be_bytes());
+    pkt.push(DEFAULT_TTL);
+    pkt.push(protocol);
+    pkt.extend_from_slice(&[0, 0]);
+    pkt.extend_from_slice(&src.0);
+    pkt.extend_from_slice(&dst.0);
+    let csum = checksum(&pkt[..IPV4_HEADER_LEN], 0);
+    pkt[10..12].copy_from_slice(&csum.to_be_bytes());
+    pkt.extend_from_slice(&payload);
+    iface.send_ip(pkt, next_hop);
+    true
+}
+
+/// 处理接口收到的 IPv4 报文
+pub fn input(iface: &Interface, pkt: &[u8]) {
+    if pkt.len() < IPV4_HEADER_LEN || pkt[0] >> 4 != 4 {
+        return;
+    }
+    let ihl = ((pkt[0] & 0xf) as usize) * 4;
+    let total_len = u16::from_be_bytes([pkt[2], pkt[3]]) as usize;
+    if ihl < IPV4_HEADER_LEN || total_len < ihl || total_len > pkt.len() {
+        return;
+    }
+    if checksum(&pkt[..ihl], 0) != 0 {
+        debug!("[ip] bad header checksum on {}", iface.name);
+        return;
+    }
+    let frag = u16::from_be_bytes([pkt[6], pkt[7]]);
+    if frag & IP_MF != 0 || frag & 0x1fff != 0 {
+        debug!("[ip] dropping fragment on {}", iface.name);
+        return;
+    }
+    let protocol = pkt[9];
+    let src = Ipv4Addr([pkt[12], pkt[13], pkt[14], pkt[15]]);
+    let dst = Ipv4Addr([pkt[16], pkt[17], pkt[18], pkt[19]]);
+    if !(is_local(dst) || dst.is_broadcast() || dst == iface.broadcast()) {
+        return;
+    }
+    let payload = &pkt[ihl..total_len];
+    match protocol {
+        IPPROTO_ICMP => icmp::input(iface, src, dst, &pkt[..total_len], payload),
+        IPPROTO_TCP => {
+            if let Some(seg) = TcpSegment::decode(src, dst, payload) {
+                tcp::input(seg);
+            }
+        }
+        IPPROTO_UDP => {
+            if let Some(dgram) = UdpDatagram::decode(src, dst, payload) {
+                let unicast = !(dst.is_broadcast() || dst == iface.broadcast());
+                if !udp::input(dgram) && unicast {
+                    icmp::send_port_unreachable(src, dst, &pkt[..total_len.min(ihl + 8)]);
+                }
+            }
+        }
+        _ => {
+            trace!("[ip] unsupported protocol {} from {}", protocol, src);
+        }
+    }
+}
@@ -0,0 +1,525 @@
+//! 对用户暴露的 socket 文件
+
+use alloc::{boxed::Box, format, string::String, sync::Arc, vec::Vec};
+use async_trait::async_trait;
+use core::{
+    sync::atomic::{AtomicBool, Ordering},
+    task::Waker,
+};
+use spin::Mutex;
+
+use super::{
+    addr::{SockAddr, SocketAddrV4, AF_INET, AF_UNIX},
+    icmp::{IcmpSocket, ICMP_MAX_MSG},
+    tcp::{TcpSocket, TCP_BUF_SIZE},
+    udp::{UdpSocket, UDP_MAX_PAYLOAD},
+    unix::{UnixSocket, UnixType},
+};
+use crate::{
+    fs::{stat::StMode, File, FileDescriptor, Kstat, PollEvents},
+    mm::UserBuffer,
+    syscall::flags::FIONREAD,
+    task::current_process,
+    utils::error::{SysErrNo, SyscallRet, TemplateRet},
+};
+
+pub const SOCK_STREAM: u32 = 1;
+pub const SOCK_DGRAM: u32 = 2;
+pub const SOCK_RAW: u32 = 3;
+pub const SOCK_SEQPACKET: u32 = 5;
+pub const SOCK_TYPE_MASK: u32 = 0xf;
+pub const SOCK_NONBLOCK: u32 = 0o4000;
+pub const SOCK_CLOEXEC: u32 = 0o2000000;
+
+pub const IPPROTO_IP: u32 = 0;
+pub const IPPROTO_ICMP: u32 = 1;
+pub const IPPROTO_TCP: u32 = 6;
+pub const IPPROTO_UDP: u32 = 17;
+
+pub const SOL_SOCKET: u32 = 1;
+pub const SO_REUSEADDR: u32 = 2;
+pub const SO_TYPE: u32 = 3;
+pub const SO_ERROR: u32 = 4;
+pub const SO_DONTROUTE: u32 = 5;
+pub const SO_BROADCAST: u32 = 6;
+pub const SO_SNDBUF: u32 = 7;
+pub const SO_RCVBUF: u32 = 8;
+pub const SO_KEEPALIVE: u32 = 9;
+pub const SO_LINGER: u32 = 13;
+pub const SO_REUSEPORT: u32 = 15;
+pub const SO_RCVTIMEO: u32 = 20;
+pub const SO_SNDTIMEO: u32 = 21;
+pub const SO_ACCEPTCONN: u32 = 30;
+pub const SO_PROTOCOL: u32 = 38;
+pub const SO_DOMAIN: u32 = 39;
+
+pub const TCP_NODELAY: u32 = 1;
+pub const TCP_MAXSEG: u32 = 2;
+
+pub const MSG_PEEK: u32 = 0x2;
+pub const MSG_TRUNC: u32 = 0x20;
+pub const MSG_DONTWAIT: u32 = 0x40;
+pub const MSG_WAITALL: u32 = 0x100;
+pub const MSG_NOSIGNAL: u32 = 0x4000;
+pub const MSG_CMSG_CLOEXEC: u32 = 0x40000000;
+/// recvmsg 返回的 msg_flags：控制消息被截断
+pub const MSG_CTRUNC: u32 = 0x8;
+
+/// 控制消息类型：传递文件描述符
+pub const SCM_RIGHTS: u32 = 1;
+/// 一次 sendmsg 最多传递的文件描述符数
+pub const SCM_MAX_FD: usize = 253;
+/// 控制消息缓冲区的上限，对应 Linux 默认的 net.core.optmem_max
+pub const OPTMEM_MAX: usize = 20480;
+
+pub const SHUT_RD: u32 = 0;
+pub const SHUT_WR: u32 = 1;
+pub const SHUT_RDWR: u32 = 2;
+
+pub enum SocketInner {
+    Tcp(TcpSocket),
+    Udp(UdpSocket),
+    Icmp(IcmpSocket),
+    Unix(UnixSocket),
+}
+
+/// 只保存而不影响协议行为的选项，getsockopt 时原样返回
+struct SocketOptions {
+    reuse_addr: bool,
+    keepalive: bool,
+    broadcast: bool,
+    nodelay: bool,
+    sndbuf: u32,
+    rcvbuf: u32,
+}
+
+impl Default for SocketOptions {
+    fn default() -> Self {
+        Self {
+            reuse_addr: false,
+            keepalive: false,
+            broadcast: false,
+            nodelay: false,
+            sndbuf: 0x10000,
+            rcvbuf: 0x10000,
+        }
+    }
+}
+
+/// AF_INET 或 AF_UNIX socket
+pub struct Socket {
+    pub inner: SocketInner,
+    nonblock: AtomicBool,
+    opts: Mutex<SocketOptions>,
+}
+
+impl Socket {
+    pub fn new(domain: u32, stype: u32, protocol: u32) -> TemplateRet<Self> {
+        if domain == AF_UNIX as u32 {
+            // AF_UNIX 只接受 0 或 PF_UNIX 作为协议号
+            let inner = match (stype & SOCK_TYPE_MASK, protocol) {
+                (SOCK_STREAM, 0 | 1) => SocketInner::Unix(UnixSocket::new(UnixType::Stream)),
+                (SOCK_DGRAM, 0 | 1) => SocketInner::Unix(UnixSocket::new(UnixType::Dgram)),
+                (SOCK_STREAM | SOCK_DGRAM, _) => return Err(SysErrNo::EPROTONOSUPPORT),
+                _ => return Err(SysErrNo::ESOCKTNOSUPPORT),
+            };
+            return Ok(Self::from_inner(inner, stype & SOCK_NONBLOCK != 0));
+        }
+        if domain != AF_INET as u32 {
+            return Err(SysErrNo::EAFNOSUPPORT);
+        }
+        let inner = match (stype & SOCK_TYPE_MASK, protocol) {
+            (SOCK_STREAM, IPPROTO_IP | IPPROTO_TCP) => SocketInner::Tcp(TcpSocket::new()),
+            (SOCK_DGRAM, IPPROTO_IP | IPPROTO_UDP) => SocketInner::Udp(UdpSocket::new()),
+            (SOCK_DGRAM, IPPROTO_ICMP) => SocketInner::Icmp(IcmpSocket::new(false)),
+            (SOCK_RAW, IPPROTO_ICMP) => SocketInner::Icmp(IcmpSocket::new(true)),
+            (SOCK_STREAM | SOCK_DGRAM | SOCK_RAW, _) => return Err(SysErrNo::EPROTONOSUPPORT),
+            _ => return Err(SysErrNo::ESOCKTNOSUPPORT),
+        };
+        Ok(Self::from_inner(inner, stype & SOCK_NONBLOCK != 0))
+    }
+
+    /// 一对互相连接的 AF_UNIX socket
+    pub fn pair(domain: u32, stype: u32, protocol: u32) -> TemplateRet<(Self, Self)> {
+        let Self {
+            inner: SocketInner::Unix(unix),
+            ..
+        } = Self::new(domain, stype, protocol)?
+        else {
+            return Err(SysErrNo::EOPNOTSUPP);
+        };
+        let nonblock = stype & SOCK_NONBLOCK != 0;
+        let (a, b) = UnixSocket::pair(unix.socket_type());
+        Ok((
+            Self::from_inner(SocketInner::Unix(a), nonblock),
+            Self::from_inner(SocketInner::Unix(b), nonblock),
+        ))
+    }
+
+    fn from_inner(inner: SocketInner, nonblock: bool) -> Self {
+        Self {
+            inner,
+            nonblock: AtomicBool::new(nonblock),
+            opts: Mutex::new(SocketOptions::default()),
+        }
+    }
+
+    /// 一次发送最多拷入内核的字节数，以及是否为流式 socket。
+    /// 流式 socket 按这个大小分块发送，数据报超过它直接返回 EMSGSIZE
+    pub fn send_limit(&self) -> (usize, bool) {
+        match &self.inner {
+            SocketInner::Tcp(_) => (TCP_BUF_SIZE, true),
+            SocketInner::Udp(_) => (UDP_MAX_PAYLOAD, false),
+            SocketInner::Icmp(_) => (ICMP_MAX_MSG, false),
+            SocketInner::Unix(unix) => unix.send_limit(),
+        }
+    }
+
+    pub fn is_nonblock(&self) -> bool {
+        self.nonblock.load(Ordering::Relaxed)
+    }
+    pub fn set_nonblock(&self, nonblock: bool) {
+        self.nonblock.store(nonblock, Ordering::Relaxed);
+    }
+
+    pub fn domain(&self) -> u16 {
+        match &self.inner {
+            SocketInner::Unix(_) => AF_UNIX,
+            _ => AF_INET,
+        }
+    }
+
+    pub fn bind(&self, addr: SockAddr) -> TemplateRet<()> {
+        let reuse = self.opts.lock().reuse_addr;
+        match (&self.inner, addr) {
+            (SocketInner::Tcp(tcp), SockAddr::Inet(addr)) => tcp.bind(addr, reuse),
+            (SocketInner::Udp(udp), SockAddr::Inet(addr)) => udp.bind(addr, reuse),
+            (SocketInner::Icmp(icmp), SockAddr::Inet(addr)) => icmp.bind(addr),
+            (SocketInner::Unix(unix), SockAddr::Unix(addr)) => unix.bind(addr),
+            _ => Err(SysErrNo::EINVAL),
+        }
+    }
+
+    pub fn listen(&self, backlog: usize) -> TemplateRet<()> {
+        match &self.inner {
+            SocketInner::Tcp(tcp) => tcp.listen(backlog),
+            SocketInner::Unix(unix) => unix.listen(backlog),
+            SocketInner::Udp(_) | SocketInner::Icmp(_) => Err(SysErrNo::EOPNOTSUPP),
+        }
+    }
+
+    pub async fn accept(&self, nonblock: bool) -> TemplateRet<(Socket, SockAddr)> {
+        let nonblock = nonblock || self.is_nonblock();
+        match &self.inner {
+            SocketInner::Tcp(tcp) => {
+                let conn = tcp.accept(nonblock).await?;
+                let peer = conn.peer_addr().unwrap_or_default();
+                Ok((Socket::from_inner(SocketInner::Tcp(conn), false), SockAddr::Inet(peer)))
+            }
+            SocketInner::Unix(unix) => {
+                let (conn, peer) = unix.accept(nonblock).await?;
+                Ok((Socket::from_inner(SocketInner::Unix(conn), false), SockAddr::Unix(peer)))
+            }
+            SocketInner::Udp(_) | SocketInner::Icmp(_) => Err(SysErrNo::EOPNOTSUPP),
+        }
+    }
+
+    /// `addr` 为 `None` 表示 AF_UNSPEC
+    pub async fn connect(&self, addr: Option<SockAddr>, nonblock: bool) -> TemplateRet<()> {
+        let nonblock = nonblock || self.is_nonblock();
+        match (&self.inner, addr) {
+            (SocketInner::Unix(unix), Some(SockAddr::Unix(addr))) => unix.connect(Some(addr), nonblock).await,
+            (SocketInner::Unix(unix), None) => unix.connect(None, nonblock).await,
+            (SocketInner::Unix(_), _) => Err(SysErrNo::EINVAL),
+            (_, Some(SockAddr::Unix(_))) => Err(SysErrNo::EAFNOSUPPORT),
+            (SocketInner::Tcp(tcp), addr) => {
+                let Some(SockAddr::Inet(addr)) = addr else {
+                    return Err(SysErrNo::EAFNOSUPPORT);
+                };
+                tcp.connect(addr, nonblock).await
+            }
+            (SocketInner::Udp(udp), addr) => udp.connect(addr.map(inet_addr)),
+            (SocketInner::Icmp(icmp), addr) => icmp.connect(addr.map(inet_addr)),
+        }
+    }
+
+    pub fn local_addr(&self) -> SockAddr {
+        match &self.inner {
+            SocketInner::Tcp(tcp) => SockAddr::Inet(tcp.local_addr()),
+            SocketInner::Udp(udp) => SockAddr::Inet(udp.local_addr()),
+            SocketInner::Icmp(icmp) => SockAddr::Inet(icmp.local_addr()),
+            SocketInner::Unix(unix) => SockAddr::Unix(unix.local_addr()),
+        }
+    }
+
+    pub fn peer_addr(&self) -> TemplateRet<SockAddr> {
+        match &self.inner {
+            SocketInner::Tcp(tcp) => tcp.peer_addr().map(SockAddr::Inet),
+            SocketInner::Udp(udp) => udp.peer_addr().map(SockAddr::Inet),
+            SocketInner::Icmp(icmp) => icmp.peer_addr().map(SockAddr::Inet),
+            SocketInner::Unix(unix) => unix.peer_addr().map(SockAddr::Unix),
+        }
+    }
+
+    pub async fn send_to(&self, data: &[u8], dst: Option<SockAddr>, flags: u32) -> TemplateRet<usize> {
+        self.send_msg(data, dst, Vec::new(), flags).await
+    }
+
+    /// 发送数据，`rights` 为 SCM_RIGHTS 传递的文件描述符，只有 AF_UNIX 支持
+    pub async fn send_msg(
+        &self,
+        data: &[u8],
+        dst: Option<SockAddr>,
+        rights: Vec<FileDescriptor>,
+        flags: u32,
+    ) -> TemplateRet<usize> {
+        let nonblock = flags & MSG_DONTWAIT != 0 || self.is_nonblock();
+        if let SocketInner::Unix(unix) = &self.inner {
+            let dst = match dst {
+                Some(SockAddr::Unix(addr)) => Some(addr),
+                Some(SockAddr::Inet(_)) => return Err(SysErrNo::EINVAL),
+                None => None,
+            };
+            return unix.send(data, dst, rights, nonblock).await;
+        }
+        if !rights.is_empty() {
+            return Err(SysErrNo::EINVAL);
+        }
+        let dst = match dst {
+            Some(SockAddr::Inet(addr)) => Some(addr),
+            Some(SockAddr::Unix(_)) => return Err(SysErrNo::EAFNOSUPPORT),
+            None => None,
+        };
+        match &self.inner {
+            // 已连接的流式 socket 忽略目标地址
+            SocketInner::Tcp(tcp) => tcp.send(data, nonblock).await,
+            SocketInner::Udp(udp) => {
+                if dst.is_some_and(|d| d.addr.is_broadcast()) && !self.opts.lock().broadcast {
+                    return Err(SysErrNo::EACCES);
+                }
+                udp.send_to(data, dst)
+            }
+            SocketInner::Icmp(icmp) => icmp.send_to(data, dst),
+            SocketInner::Unix(_) => unreachable!(),
+        }
+    }
+
+    /// 返回 (数据, 报文原始长度, 来源地址)，随数据到达的文件描述符被丢弃
+    pub async fn recv_from(&self, len: usize, flags: u32) -> TemplateRet<(Vec<u8>, usize, Option<SockAddr>)> {
+        let (data, full, src, _) = self.recv_msg(len, flags).await?;
+        Ok((data, full, src))
+    }
+
+    /// 返回 (数据, 报文原始长度, 来源地址, SCM_RIGHTS 收到的文件描述符)
+    pub async fn recv_msg(
+        &self,
+        len: usize,
+        flags: u32,
+    ) -> TemplateRet<(Vec<u8>, usize, Option<SockAddr>, Vec<FileDescriptor>)> {
+        let nonblock = flags & MSG_DONTWAIT != 0 || self.is_nonblock();
+        let peek = flags & MSG_PEEK != 0;
+        match &self.inner {
+            SocketInner::Tcp(tcp) => {
+                let mut data = tcp.recv(len, nonblock, peek).await?;
+                if flags & MSG_WAITALL != 0 && !peek && !nonblock {
+                    while data.len() < len {
+                        let more = tcp.recv(len - data.len(), false, false).await?;
+                        if more.is_empty() {
+                            break;
+                        }
+                        data.extend(more);
+                    }
+                }
+                let n = data.len();
+                Ok((data, n, tcp.peer_addr().ok().map(SockAddr::Inet), Vec::new()))
+            }
+            SocketInner::Udp(udp) => {
+                let (data, full, src) = udp.recv_from(len, nonblock, peek).await?;
+                Ok((data, full, Some(SockAddr::Inet(src)), Vec::new()))
+            }
+            SocketInner::Icmp(icmp) => {
+                let (data, full, src) = icmp.recv_from(len, nonblock, peek).await?;
+                Ok((data, full, Some(SockAddr::Inet(src)), Vec::new()))
+            }
+            SocketInner::Unix(unix) => {
+                let (mut data, mut full, src, mut rights) = unix.recv(len, nonblock, peek).await?;
+                if unix.socket_type() == UnixType::Stream && flags & MSG_WAITALL != 0 && !peek && !nonblock {
+                    // 描述符边界会截断一次读取，MSG_WAITALL 时继续读满
+                    while data.len() < len {
+                        let (more, _, _, more_rights) = unix.recv(len - data.len(), false, false).await?;
+                        if more.is_empty() {
+                            break;
+                        }
+                        data.extend(more);
+                        rights.extend(more_rights);
+                    }
+                    full = data.len();
+                }
+                Ok((data, full, src.map(SockAddr::Unix), rights))
+            }
+        }
+    }
+
+    pub fn shutdown(&self, how: u32) -> TemplateRet<()> {
+        let (read, write) = match how {
+            SHUT_RD => (true, false),
+            SHUT_WR => (false, true),
+            SHUT_RDWR => (true, true),
+            _ => return Err(SysErrNo::EINVAL),
+        };
+        match &self.inner {
+            SocketInner::Tcp(tcp) => tcp.shutdown(read, write),
+            SocketInner::Udp(udp) => udp.shutdown(read),
+            SocketInner::Icmp(_) => Err(SysErrNo::ENOTCONN),
+            SocketInner::Unix(unix) => unix.shutdown(read, write),
+        }
+    }
+
+    /// 接收队列中可读的字节数
+    pub fn recv_queue_len(&self) -> usize {
+        match &self.inner {
+            SocketInner::Tcp(tcp) => tcp.recv_queue_len(),
+            SocketInner::Udp(udp) => udp.recv_queue_len(),
+            SocketInner::Icmp(icmp) => icmp.recv_queue_len(),
+            SocketInner::Unix(unix) => unix.recv_queue_len(),
+        }
+    }
+
+    pub fn setsockopt(&self, level: u32, optname: u32, optval: &[u8]) -> TemplateRet<()> {
+        let val = optval
+            .get(..4)
+            .map(|b| u32::from_ne_bytes([b[0], b[1], b[2], b[3]]))
+            .unwrap_or(0);
+        let mut opts = self.opts.lock();
+        match (level, optname) {
+            (SOL_SOCKET, SO_REUSEADDR | SO_REUSEPORT) => opts.reuse_addr = val != 0,
+            (SOL_SOCKET, SO_KEEPALIVE) => opts.keepalive = val != 0,
+            (SOL_SOCKET, SO_BROADCAST) => opts.broadcast = val != 0,
+            (SOL_SOCKET, SO_SNDBUF) => opts.sndbuf = val.saturating_mul(2),
+            (SOL_SOCKET, SO_RCVBUF) => opts.rcvbuf = val.saturating_mul(2),
+            (SOL_SOCKET, SO_LINGER | SO_RCVTIMEO | SO_SNDTIMEO | SO_DONTROUTE) => {}
+            (IPPROTO_TCP, TCP_NODELAY) => opts.nodelay = val != 0,
+            (IPPROTO_TCP, TCP_MAXSEG) => {}
+            (IPPROTO_IP, _) => {}
+            (SOL_SOCKET | IPPROTO_TCP, _) => {
+                warn!("[setsockopt] unsupported option level {} name {}", level, optname);
+            }
+            _ => return Err(SysErrNo::ENOPROTOOPT),
+        }
+        Ok(())
+    }
+
+    pub fn getsockopt(&self, level: u32, optname: u32) -> TemplateRet<u32> {
+        let opts = self.opts.lock();
+        let val = match (level, optname) {
+            (SOL_SOCKET, SO_REUSEADDR | SO_REUSEPORT) => opts.reuse_addr as u32,
+            (SOL_SOCKET, SO_KEEPALIVE) => opts.keepalive as u32,
+            (SOL_SOCKET, SO_BROADCAST) => opts.broadcast as u32,
+            (SOL_SOCKET, SO_SNDBUF) => opts.sndbuf,
+            (SOL_SOCKET, SO_RCVBUF) => opts.rcvbuf,
+            (SOL_SOCKET, SO_TYPE) => match &self.inner {
+                SocketInner::Tcp(_) => SOCK_STREAM,
+                SocketInner::Udp(_) => SOCK_DGRAM,
+                SocketInner::Icmp(icmp) => if icmp.is_raw() { SOCK_RAW } else { SOCK_DGRAM },
+                SocketInner::Unix(unix) => match unix.socket_type() {
+                    UnixType::Stream => SOCK_STREAM,
+                    UnixType::Dgram => SOCK_DGRAM,
+                },
+            },
+            (SOL_SOCKET, SO_PROTOCOL) => match &self.inner {
+                SocketInner::Tcp(_) => IPPROTO_TCP,
+                SocketInner::Udp(_) => IPPROTO_UDP,
+                SocketInner::Icmp(_) => IPPROTO_ICMP,
+                SocketInner::Unix(_) => 0,
+            },
+            (SOL_SOCKET, SO_DOMAIN) => self.domain() as u32,
+            (SOL_SOCKET, SO_ACCEPTCONN) => match &self.inner {
+                SocketInner::Tcp(tcp) => (tcp.state() == super::tcp::TcpState::Listen) as u32,
+                _ => 0,
+            },
+            (SOL_SOCKET, SO_ERROR) => match &self.inner {
+                SocketInner::Tcp(tcp) => tcp.take_error().map_or(0, |e| e as u32),
+                _ => 0,
+            },
+            (IPPROTO_TCP, TCP_NODELAY) => opts.nodelay as u32,
+            (IPPROTO_TCP, TCP_MAXSEG) => super::tcp::TCP_MSS as u32,
+            _ => return Err(SysErrNo::ENOPROTOOPT),
+        };
+        Ok(val)
+    }
+}
+
+fn inet_addr(addr: SockAddr) -> SocketAddrV4 {
+    match addr {
+        SockAddr::Inet(addr) => addr,
+        SockAddr::Unix(_) => SocketAddrV4::default(),
+    }
+}
+
+/// 创建一个 socket 文件
+pub fn make_socket(domain: u32, stype: u32, protocol: u32) -> TemplateRet<Arc<dyn File>> {
+    Ok(Arc::new(Socket::new(domain, stype, protocol)?))
+}
+
+#[async_trait]
+impl File for Socket {
+    fn readable(&self) -> TemplateRet<bool> {
+        Ok(true)
+    }
+
+    fn writable(&self) -> TemplateRet<bool> {
+        Ok(true)
+    }
+
+    fn set_nonblock(&self, nonblock: bool) {
+        Socket::set_nonblock(self, nonblock);
+    }
+
+    async fn read<'a>(&self, mut buf: UserBuffer<'a>) -> Result<usize, SysErrNo> {
+        let (data, _, _) = self.recv_from(buf.len(), 0).await?;
+        buf.write_all(&data)?;
+        Ok(data.len())
+    }
+
+    async fn write<'a>(&self, buf: UserBuffer<'a>) -> Result<usize, SysErrNo> {
+        let data = buf.read(buf.len());
+        self.send_to(&data, None, 0).await
+    }
+
+    fn poll(&self, events: PollEvents, waker: &Waker) -> PollEvents {
+        match &self.inner {
+            SocketInner::Tcp(tcp) => tcp.poll(events, waker),
+            SocketInner::Udp(udp) => udp.poll(events, waker),
+            SocketInner::Icmp(icmp) => icmp.poll(events, waker),
+            SocketInner::Unix(unix) => unix.poll(events, waker),
+        }
+    }
+
+    fn fstat(&self) -> Kstat {
+        Kstat {
+            st_mode: StMode::FSOCK.bits(),
+            st_nlink: 1,
+            st_blksize: 4096,
+            ..Default::default()
+        }
+    }
+
+    fn as_any(&self) -> &dyn core::any::Any {
+        self
+    }
+
+    fn get_path(&self) -> String {
+        format!("socket:[{}]", self as *const Self as usize)
+    }
+
+    async fn ioctl(&self, cmd: usize, arg: usize) -> SyscallRet {
+        match cmd {
+            FIONREAD => {
+                let n = self.recv_queue_len();
+                current_process().memory_set.lock().await.put_user(arg, n as i32).await?;
+                Ok(0)
+            }
+            _ => Err(SysErrNo::ENOTTY),
+        }
+    }
+}
@@ -0,0 +1,139 @@
+//! 协议栈的分发层
+//!
+//! 传输层(tcp/udp)产生的报文经 [`transmit`] 编码后交给 IP 层发送，
+//! 设备只负责排队，接收由 [`poll_stack`] 在锁外统一驱动。
+//! 这样 connect/accept 两端在同一个内核里(走回环接口)时不会发生重入死锁。
+
+use core::sync::atomic::{AtomicBool, AtomicU16, AtomicU32, AtomicU64, Ordering};
+
+use super::{
+    addr::Ipv4Addr,
+    iface::{self, interfaces},
+    ip,
+    tcp::{self, TcpSegment},
+    udp::UdpDatagram,
+};
+use crate::{
+    devices::get_net_devices,
+    timer::get_time_us,
+    utils::error::{SysErrNo, TemplateRet},
+};
+
+/// 传输层报文
+pub enum Packet {
+    Tcp(TcpSegment),
+    Udp(UdpDatagram),
+}
+
+/// 发送一个传输层报文，可以在持有 socket 锁时调用
+pub fn transmit(pkt: Packet) {
+    match pkt {
+        Packet::Tcp(seg) => {
+            ip::output(seg.src.addr, seg.dst.addr, ip::IPPROTO_TCP, seg.encode());
+        }
+        Packet::Udp(dgram) => {
+            ip::output(dgram.src.addr, dgram.dst.addr, ip::IPPROTO_UDP, dgram.encode());
+        }
+    }
+}
+
+/// 轮询所有接口，直到没有新的报文
+///
+/// 不能在持有任何 socket 锁时调用
+pub fn poll_stack() {
+    loop {
+        let mut received = false;
+        for iface in interfaces() {
+            received |= iface.poll();
+        }
+        if !received {
+            break;
+        }
+    }
+}
+
+/// 网卡中断到来后置位，由 [`poll_deferred`] 在任务上下文中处理
+static RX_PENDING: AtomicBool = AtomicBool::new(false);
+
+/// 网卡驱动在中断处理中调用，只做标记
+pub fn notify_rx() {
+    RX_PENDING.store(true, Ordering::Release);
+}
+
+/// 所有网卡的接收中断都能送达
+fn rx_irq_wired() -> bool {
+    crate::devices::has_int_device() && get_net_devices().iter().all(|d| !d.interrupts().is_empty())
+}
+
+/// 协议栈定时器的粒度
+const TIMER_TICK_US: u64 = 100_000;
+/// 下一次运行协议栈定时器的时刻
+static NEXT_TIMER_US: AtomicU64 = AtomicU64::new(0);
+
+/// 在 trap 返回前和空闲时调用，处理中断期间到达的报文，并按 `TIMER_TICK_US`
+/// 驱动 TCP 重传和 ARP 重试
+///
+/// 没有中断控制器或网卡的中断没有接上时收不到网卡中断，退化为每次调用都轮询
+pub fn poll_deferred() {
+    if RX_PENDING.swap(false, Ordering::AcqRel) || !rx_irq_wired() {
+        poll_stack();
+    }
+    let now = get_time_us() as u64;
+    let next = NEXT_TIMER_US.load(Ordering::Relaxed);
+    if now >= next
+        && NEXT_TIMER_US
+            .compare_exchange(next, now + TIMER_TICK_US, Ordering::AcqRel, Ordering::Relaxed)
+            .is_ok()
+    {
+        for iface in interfaces() {
+            iface.tick(now);
+        }
+        tcp::on_timer(now);
+        poll_stack();
+    }
+}
+
+/// 发往 `dst` 时使用的本地地址
+pub fn source_addr_for(dst: Ipv4Addr) -> TemplateRet<Ipv4Addr> {
+    ip::source_addr_for(dst).ok_or(SysErrNo::ENETUNREACH)
+}
+
+/// `addr` 是否是本机可以 bind 的地址
+pub fn is_local_addr(addr: Ipv4Addr) -> bool {
+    addr.is_unspecified() || ip::is_local(addr)
+}
+
+/// 初始化网络接口
+pub fn init() {
+    iface::init_loopback();
+    iface::init_ethernet();
+}
+
+const EPHEMERAL_START: u16 = 49152;
+const EPHEMERAL_END: u16 = 65535;
+static NEXT_EPHEMERAL: AtomicU16 = AtomicU16::new(EPHEMERAL_START);
+
+/// 分配一个临时端口，`in_use` 用于判断端口是否已被占用
+pub fn alloc_ephemeral_port(in_use: impl Fn(u16) -> bool) -> Option<u16> {
+    let count = (EPHEMERAL_END - EPHEMERAL_START) as usize + 1;
+    for _ in 0..count {
+        let port = NEXT_EPHEMERAL.fetch_add(1, Ordering::Relaxed);
+        let port = if port < EPHEMERAL_START {
+            NEXT_EPHEMERAL.store(EPHEMERAL_START + 1, Ordering::Relaxed);
+            EPHEMERAL_START
+        } else {
+            port
+        };
+        if !in_use(port) {
+            return Some(port);
+        }
+    }
+    None
+}
+
+static ISN_COUNTER: AtomicU32 = AtomicU32::new(0);
+
+/// 生成 TCP 初始序列号(时钟 + 计数器，RFC 793 风格)
+pub fn gen_isn() -> u32 {
+    (get_time_us() as u32 >> 2).wrapping_add(ISN_COUNTER.fetch_add(64000, Ordering::Relaxed))
+}
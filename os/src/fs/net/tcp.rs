@@ -0,0 +1,1091 @@
+//! TCP 传输层
+//!
+//! 每个连接对应一个 [`Tcb`]，全局 [`TCP_TABLE`] 按四元组/监听地址索引。
+//! 状态机按 RFC 793 实现。丢失的报文段由重传定时器恢复：RTO 从 1 秒开始，
+//! 每次超时加倍，收到新的确认后复位（没有 RTT 估计和拥塞控制），超时时只重发
+//! 最早的未确认报文段；对端窗口为 0 时由同一个定时器发送窗口探测。
+//! 定时器由 [`on_timer`] 驱动。没有 2MSL 定时器，TIME_WAIT 会立即进入 CLOSED，
+//! 最后的 ACK 丢失时对端重传的 FIN 会收到 RST。
+
+use alloc::{
+    collections::{BTreeMap, VecDeque},
+    sync::{Arc, Weak},
+    vec,
+    vec::Vec,
+};
+use core::{
+    future::poll_fn,
+    task::{Poll, Waker},
+};
+use spin::Mutex;
+
+use super::{
+    addr::{Ipv4Addr, SocketAddrV4},
+    ip,
+    stack::{self, alloc_ephemeral_port, gen_isn, poll_stack, Packet},
+};
+use crate::{
+    fs::PollEvents,
+    timer::get_time_us,
+    utils::error::{SysErrNo, TemplateRet},
+};
+
+bitflags! {
+    pub struct TcpFlags: u8 {
+        const FIN = 0x01;
+        const SYN = 0x02;
+        const RST = 0x04;
+        const PSH = 0x08;
+        const ACK = 0x10;
+        const URG = 0x20;
+    }
+}
+
+/// 传输层的 TCP 报文段
+pub struct TcpSegment {
+    pub src: SocketAddrV4,
+    pub dst: SocketAddrV4,
+    pub seq: u32,
+    pub ack: u32,
+    pub flags: TcpFlags,
+    pub window: u16,
+    pub payload: Vec<u8>,
+}
+
+const TCP_HEADER_LEN: usize = 20;
+
+impl TcpSegment {
+    /// 编码为带校验和的 TCP 报文(不含 IP 首部)
+    pub fn encode(&self) -> Vec<u8> {
+        let len = TCP_HEADER_LEN + self.payload.len();
+        let mut buf = Vec::with_capacity(len);
+        buf.extend_from_slice(&self.src.port.to_be_bytes());
+        buf.extend_from_slice(&self.dst.port.to_be_bytes());
+        buf.extend_from_slice(&self.seq.to_be_bytes());
+        buf.extend_from_slice(&self.ack.to_be_bytes());
+        buf.push(((TCP_HEADER_LEN / 4) as u8) << 4);
+        buf.push(self.flags.bits());
+        buf.extend_from_slice(&self.window.to_be_bytes());
+        buf.extend_from_slice(&[0, 0, 0, 0]);
+        buf.extend_from_slice(&self.payload);
+        let csum = ip::checksum(&buf, ip::pseudo_header_sum(self.src.addr, self.dst.addr, ip::IPPROTO_TCP, len));
+        buf[16..18].copy_from_slice(&csum.to_be_bytes());
+        buf
+    }
+
+    /// 解析并校验 TCP 报文，选项被忽略
+    pub fn decode(src: Ipv4Addr, dst: Ipv4Addr, data: &[u8]) -> Option<Self> {
+        if data.len() < TCP_HEADER_LEN {
+            return None;
+        }
+        if ip::checksum(data, ip::pseudo_header_sum(src, dst, ip::IPPROTO_TCP, data.len())) != 0 {
+            debug!("[tcp] bad checksum from {}", src);
+            return None;
+        }
+        let off = ((data[12] >> 4) as usize) * 4;
+        if off < TCP_HEADER_LEN || off > data.len() {
+            return None;
+        }
+        Some(Self {
+            src: SocketAddrV4::new(src, u16::from_be_bytes([data[0], data[1]])),
+            dst: SocketAddrV4::new(dst, u16::from_be_bytes([data[2], data[3]])),
+            seq: u32::from_be_bytes([data[4], data[5], data[6], data[7]]),
+            ack: u32::from_be_bytes([data[8], data[9], data[10], data[11]]),
+            flags: TcpFlags::from_bits_truncate(data[13]),
+            window: u16::from_be_bytes([data[14], data[15]]),
+            payload: data[off..].to_vec(),
+        })
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TcpState {
+    Closed,
+    Listen,
+    SynSent,
+    SynReceived,
+    Established,
+    FinWait1,
+    FinWait2,
+    CloseWait,
+    Closing,
+    LastAck,
+    TimeWait,
+}
+
+/// 收发缓冲区大小，不使用窗口扩大选项，所以不超过 u16::MAX
+pub(super) const TCP_BUF_SIZE: usize = 0xffff;
+/// 默认 MSS，实际值按路由的 MTU 计算
+pub const TCP_MSS: usize = 1460;
+
+/// 初始重传超时(RFC 6298)
+const TCP_RTO_INIT_US: u64 = 1_000_000;
+/// 重传超时的上限
+const TCP_RTO_MAX_US: u64 = 60 * 1_000_000;
+/// SYN 和 SYN-ACK 的最大重传次数
+const TCP_SYN_RETRIES: usize = 6;
+/// 已建立连接上数据和 FIN 的最大重传次数
+const TCP_RETRIES: usize = 8;
+
+/// 发往 `dst` 时的 MSS
+fn mss_for(dst: Ipv4Addr) -> usize {
+    ip::mtu_for(dst).map_or(TCP_MSS, |mtu| mtu - ip::IPV4_HEADER_LEN - TCP_HEADER_LEN).min(TCP_BUF_SIZE / 2)
+}
+
+#[inline]
+fn seq_lt(a: u32, b: u32) -> bool {
+    (a.wrapping_sub(b) as i32) < 0
+}
+#[inline]
+fn seq_le(a: u32, b: u32) -> bool {
+    (a.wrapping_sub(b) as i32) <= 0
+}
+
+/// TCP 控制块
+pub struct Tcb {
+    state: TcpState,
+    local: Option<SocketAddrV4>,
+    remote: Option<SocketAddrV4>,
+    /// 本地端口是否登记在 TCP_TABLE.bound 中
+    bound: bool,
+
+    snd_una: u32,
+    snd_nxt: u32,
+    snd_wnd: u32,
+    rcv_nxt: u32,
+    mss: usize,
+    /// 上次通告给对端的窗口
+    last_adv_wnd: usize,
+
+    /// 当前的重传超时
+    rto_us: u64,
+    /// 重传定时器的到期时刻，0 表示定时器未启动
+    rtx_deadline_us: u64,
+    /// 没有收到新确认的连续超时次数
+    rtx_count: usize,
+
+    /// 从 snd_una 开始的数据，前 sent 字节已发送未确认
+    send_buf: VecDeque<u8>,
+    sent: usize,
+    recv_buf: VecDeque<u8>,
+
+    /// 用户已关闭写方向，数据发完后发送 FIN
+    fin_pending: bool,
+    fin_sent: bool,
+    peer_fin: bool,
+    rd_shutdown: bool,
+    /// 是否曾经建立过连接，用于区分新建 socket 和已断开的连接
+    was_connected: bool,
+    error: Option<SysErrNo>,
+
+    backlog: usize,
+    syn_count: usize,
+    accept_queue: VecDeque<Arc<Mutex<Tcb>>>,
+    /// SYN_RECEIVED 状态的连接所属的监听 socket
+    parent: Weak<Mutex<Tcb>>,
+
+    readers: Vec<Waker>,
+    writers: Vec<Waker>,
+}
+
+impl Tcb {
+    fn new() -> Self {
+        Self {
+            state: TcpState::Closed,
+            local: None,
+            remote: None,
+            bound: false,
+            snd_una: 0,
+            snd_nxt: 0,
+            snd_wnd: 0,
+            rcv_nxt: 0,
+            mss: TCP_MSS,
+            last_adv_wnd: TCP_BUF_SIZE,
+            rto_us: TCP_RTO_INIT_US,
+            rtx_deadline_us: 0,
+            rtx_count: 0,
+            send_buf: VecDeque::new(),
+            sent: 0,
+            recv_buf: VecDeque::new(),
+            fin_pending: false,
+            fin_sent: false,
+            peer_fin: false,
+            rd_shutdown: false,
+            was_connected: false,
+            error: None,
+            backlog: 0,
+            syn_count: 0,
+            accept_queue: VecDeque::new(),
+            parent: Weak::new(),
+            readers: Vec::new(),
+            writers: Vec::new(),
+        }
+    }
+
+    fn rx_window(&self) -> usize {
+        TCP_BUF_SIZE - self.recv_buf.len()
+    }
+
+    fn register_reader(&mut self, w: &Waker) {
+        if !self.readers.iter().any(|rw| rw.will_wake(w)) {
+            self.readers.push(w.clone());
+        }
+    }
+    fn register_writer(&mut self, w: &Waker) {
+        if !self.writers.iter().any(|ww| ww.will_wake(w)) {
+            self.writers.push(w.clone());
+        }
+    }
+    fn notify_readers(&mut self) {
+        for w in self.readers.drain(..) {
+            w.wake();
+        }
+    }
+    fn notify_writers(&mut self) {
+        for w in self.writers.drain(..) {
+            w.wake();
+        }
+    }
+    fn notify_all(&mut self) {
+        self.notify_readers();
+        self.notify_writers();
+    }
+
+    fn send_segment(&mut self, seq: u32, flags: TcpFlags, payload: Vec<u8>) {
+        let (Some(local), Some(remote)) = (self.local, self.remote) else {
+            return;
+        };
+        let window = self.rx_window();
+        self.last_adv_wnd = window;
+        stack::transmit(Packet::Tcp(TcpSegment {
+            src: local,
+            dst: remote,
+            seq,
+            ack: if flags.contains(TcpFlags::ACK) { self.rcv_nxt } else { 0 },
+            flags,
+            window: window as u16,
+            payload,
+        }));
+    }
+
+    fn send_ack(&mut self) {
+        self.send_segment(self.snd_nxt, TcpFlags::ACK, Vec::new());
+    }
+
+    /// 有报文段在途或需要窗口探测时启动重传定时器，已经在运行的不重新计时
+    fn arm_rtx(&mut self) {
+        if self.rtx_deadline_us == 0 {
+            self.rtx_deadline_us = get_time_us() as u64 + self.rto_us;
+        }
+    }
+
+    /// snd_una 前进后复位 RTO，仍有未确认的报文段时重新计时
+    fn ack_progress(&mut self) {
+        self.rtx_count = 0;
+        self.rto_us = TCP_RTO_INIT_US;
+        self.rtx_deadline_us = 0;
+        if self.snd_una != self.snd_nxt {
+            self.arm_rtx();
+        }
+    }
+
+    /// 重传定时器到期：重发最早的未确认报文段并加倍 RTO，重试次数用尽时返回 false
+    fn retransmit(&mut self, now: u64) -> bool {
+        let limit = match self.state {
+            TcpState::SynSent | TcpState::SynReceived => TCP_SYN_RETRIES,
+            _ => TCP_RETRIES,
+        };
+        let una = self.snd_una;
+        let probe = una == self.snd_nxt && self.send_buf.len() > self.sent;
+        if una == self.snd_nxt && !probe {
+            self.rtx_deadline_us = 0;
+            return true;
+        }
+        // 窗口探测不计入重试次数，对端始终回 ACK 时连接不应超时
+        if !probe {
+            if self.rtx_count >= limit {
+                return false;
+            }
+            self.rtx_count += 1;
+        }
+        self.rto_us = (self.rto_us * 2).min(TCP_RTO_MAX_US);
+        self.rtx_deadline_us = now + self.rto_us;
+        match self.state {
+            TcpState::SynSent => self.send_segment(una, TcpFlags::SYN, Vec::new()),
+            TcpState::SynReceived => self.send_segment(una, TcpFlags::SYN | TcpFlags::ACK, Vec::new()),
+            _ if self.sent > 0 => {
+                let n = self.sent.min(self.mss);
+                let payload: Vec<u8> = self.send_buf.range(..n).copied().collect();
+                self.send_segment(una, TcpFlags::ACK | TcpFlags::PSH, payload);
+            }
+            _ if probe => {
+                // 发送窗口外的 1 字节，对端回复的 ACK 会带回最新的窗口
+                let payload = vec![self.send_buf[0]];
+                self.send_segment(una, TcpFlags::ACK, payload);
+            }
+            _ => self.send_segment(una, TcpFlags::FIN | TcpFlags::ACK, Vec::new()),
+        }
+        true
+    }
+
+    /// 在窗口允许的范围内发送缓冲区中的数据，数据发完且用户已关闭写端时发送 FIN
+    fn output(&mut self) -> bool {
+        let mut sent_any = false;
+        if matches!(self.state, TcpState::Established | TcpState::CloseWait) {
+            loop {
+                let unsent = self.send_buf.len() - self.sent;
+                let wnd_avail = (self.snd_wnd as usize).saturating_sub(self.sent);
+                let n = unsent.min(wnd_avail).min(self.mss);
+                if n == 0 {
+                    break;
+                }
+                let payload: Vec<u8> = self.send_buf.range(self.sent..self.sent + n).copied().collect();
+                self.send_segment(self.snd_nxt, TcpFlags::ACK | TcpFlags::PSH, payload);
+                self.snd_nxt = self.snd_nxt.wrapping_add(n as u32);
+                self.sent += n;
+                sent_any = true;
+            }
+            if self.fin_pending && !self.fin_sent && self.sent == self.send_buf.len() {
+                self.send_segment(self.snd_nxt, TcpFlags::FIN | TcpFlags::ACK, Vec::new());
+                self.snd_nxt = self.snd_nxt.wrapping_add(1);
+                self.fin_sent = true;
+                self.state = match self.state {
+                    TcpState::Established => TcpState::FinWait1,
+                    _ => TcpState::LastAck,
+                };
+                sent_any = true;
+            }
+            // 有数据在途，或者还有数据但对端窗口为 0，都需要定时器
+            if self.snd_una != self.snd_nxt || self.send_buf.len() > self.sent {
+                self.arm_rtx();
+            }
+        }
+        sent_any
+    }
+}
+
+/// 所有 TCP 控制块的索引
+struct TcpTable {
+    listeners: BTreeMap<SocketAddrV4, Arc<Mutex<Tcb>>>,
+    conns: BTreeMap<(SocketAddrV4, SocketAddrV4), Arc<Mutex<Tcb>>>,
+    /// 被 bind 占用的本地地址及引用计数
+    bound: BTreeMap<SocketAddrV4, usize>,
+}
+
+impl TcpTable {
+    const fn new() -> Self {
+        Self {
+            listeners: BTreeMap::new(),
+            conns: BTreeMap::new(),
+            bound: BTreeMap::new(),
+        }
+    }
+
+    fn port_in_use(&self, port: u16) -> bool {
+        self.bound.keys().any(|a| a.port == port)
+    }
+
+    /// 检查 `addr` 能否被 bind
+    fn conflicts(&self, addr: SocketAddrV4, reuse_addr: bool) -> bool {
+        let overlap = |a: &SocketAddrV4| {
+            a.port == addr.port
+                && (a.addr == addr.addr || a.addr.is_unspecified() || addr.addr.is_unspecified())
+        };
+        if reuse_addr {
+            self.listeners.keys().any(overlap)
+        } else {
+            self.bound.keys().any(overlap)
+        }
+    }
+
+    fn bind(&mut self, addr: SocketAddrV4) {
+        *self.bound.entry(addr).or_insert(0) += 1;
+    }
+
+    fn unbind(&mut self, addr: SocketAddrV4) {
+        if let Some(cnt) = self.bound.get_mut(&addr) {
+            *cnt -= 1;
+            if *cnt == 0 {
+                self.bound.remove(&addr);
+            }
+        }
+    }
+
+    fn lookup(&self, local: SocketAddrV4, remote: SocketAddrV4) -> Option<Arc<Mutex<Tcb>>> {
+        if let Some(tcb) = self.conns.get(&(local, remote)) {
+            return Some(tcb.clone());
+        }
+        self.listeners
+            .get(&local)
+            .or_else(|| {
+                self.listeners
+                    .get(&SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, local.port))
+            })
+            .cloned()
+    }
+}
+
+static TCP_TABLE: Mutex<TcpTable> = Mutex::new(TcpTable::new());
+
+/// 连接进入 CLOSED 后从表中移除并释放端口
+fn release(t: &mut Tcb) {
+    let mut table = TCP_TABLE.lock();
+    if let (Some(local), Some(remote)) = (t.local, t.remote) {
+        table.conns.remove(&(local, remote));
+    }
+    if t.bound {
+        if let Some(local) = t.local {
+            table.unbind(local);
+        }
+        t.bound = false;
+    }
+}
+
+fn enter_closed(t: &mut Tcb) {
+    t.state = TcpState::Closed;
+    release(t);
+    t.notify_all();
+}
+
+/// 对没有对应连接的报文回复 RST
+fn reply_rst(seg: &TcpSegment) {
+    if seg.flags.contains(TcpFlags::RST) {
+        return;
+    }
+    let (seq, ack, flags) = if seg.flags.contains(TcpFlags::ACK) {
+        (seg.ack, 0, TcpFlags::RST)
+    } else {
+        let mut len = seg.payload.len() as u32;
+        if seg.flags.contains(TcpFlags::SYN) {
+            len += 1;
+        }
+        if seg.flags.contains(TcpFlags::FIN) {
+            len += 1;
+        }
+        (0, seg.seq.wrapping_add(len), TcpFlags::RST | TcpFlags::ACK)
+    };
+    stack::transmit(Packet::Tcp(TcpSegment {
+        src: seg.dst,
+        dst: seg.src,
+        seq,
+        ack,
+        flags,
+        window: 0,
+        payload: Vec::new(),
+    }));
+}
+
+/// 网络层交付的 TCP 报文入口
+pub fn input(seg: TcpSegment) {
+    let tcb = TCP_TABLE.lock().lookup(seg.dst, seg.src);
+    let Some(tcb) = tcb else {
+        reply_rst(&seg);
+        return;
+    };
+    let state = tcb.lock().state;
+    match state {
+        TcpState::Listen => listen_input(&tcb, seg),
+        TcpState::Closed => reply_rst(&seg),
+        _ => conn_input(&tcb, seg),
+    }
+}
+
+fn listen_input(listener: &Arc<Mutex<Tcb>>, seg: TcpSegment) {
+    if seg.flags.contains(TcpFlags::RST) {
+        return;
+    }
+    if seg.flags.contains(TcpFlags::ACK) || !seg.flags.contains(TcpFlags::SYN) {
+        reply_rst(&seg);
+        return;
+    }
+    {
+        let mut l = listener.lock();
+        if l.state != TcpState::Listen {
+            drop(l);
+            reply_rst(&seg);
+            return;
+        }
+        // 队列满时和 Linux 一样丢弃 SYN，等对端重传
+        if l.accept_queue.len() + l.syn_count >= l.backlog {
+            return;
+        }
+        l.syn_count += 1;
+    }
+    let iss = gen_isn();
+    let mut child = Tcb::new();
+    child.state = TcpState::SynReceived;
+    child.local = Some(seg.dst);
+    child.remote = Some(seg.src);
+    child.rcv_nxt = seg.seq.wrapping_add(1);
+    child.snd_una = iss;
+    child.snd_nxt = iss.wrapping_add(1);
+    child.snd_wnd = seg.window as u32;
+    child.mss = mss_for(seg.src.addr);
+    child.parent = Arc::downgrade(listener);
+    child.send_segment(iss, TcpFlags::SYN | TcpFlags::ACK, Vec::new());
+    child.arm_rtx();
+    let child = Arc::new(Mutex::new(child));
+    TCP_TABLE.lock().conns.insert((seg.dst, seg.src), child);
+}
+
+fn conn_input(tcb: &Arc<Mutex<Tcb>>, seg: TcpSegment) {
+    let mut t = tcb.lock();
+    let mut established_child = false;
+
+    if seg.flags.contains(TcpFlags::RST) {
+        let refused = t.state == TcpState::SynSent;
+        if refused && !(seg.flags.contains(TcpFlags::ACK) && seg.ack == t.snd_nxt) {
+            return;
+        }
+        if t.state == TcpState::SynReceived {
+            if let Some(parent) = t.parent.upgrade() {
+                drop(t);
+                let mut p = parent.lock();
+                p.syn_count = p.syn_count.saturating_sub(1);
+                drop(p);
+                t = tcb.lock();
+            }
+        }
+        t.error = Some(if refused { SysErrNo::ECONNREFUSED } else { SysErrNo::ECONNRESET });
+        enter_closed(&mut t);
+        return;
+    }
+
+    if t.state == TcpState::SynSent {
+        if seg.flags.contains(TcpFlags::ACK) && seg.ack != t.snd_nxt {
+            drop(t);
+            reply_rst(&seg);
+            return;
+        }
+        if !seg.flags.contains(TcpFlags::SYN) {
+            return;
+        }
+        t.rcv_nxt = seg.seq.wrapping_add(1);
+        t.snd_wnd = seg.window as u32;
+        if seg.flags.contains(TcpFlags::ACK) {
+            t.snd_una = seg.ack;
+            t.ack_progress();
+            t.state = TcpState::Established;
+            t.was_connected = true;
+            t.send_ack();
+            t.output();
+            t.notify_all();
+        } else {
+            // 同时打开
+            t.state = TcpState::SynReceived;
+            let una = t.snd_una;
+            t.send_segment(una, TcpFlags::SYN | TcpFlags::ACK, Vec::new());
+        }
+        return;
+    }
+
+    if seg.flags.contains(TcpFlags::SYN) {
+        if t.state == TcpState::SynReceived {
+            // 对端重传了 SYN，说明 SYN-ACK 丢了
+            let una = t.snd_una;
+            t.send_segment(una, TcpFlags::SYN | TcpFlags::ACK, Vec::new());
+        } else {
+            // 重复的 SYN，回一个 ACK 即可
+            t.send_ack();
+        }
+        return;
+    }
+    if !seg.flags.contains(TcpFlags::ACK) {
+        return;
+    }
+
+    // ---- ACK 处理 ----
+    if t.state == TcpState::SynReceived {
+        if seg.ack != t.snd_nxt {
+            drop(t);
+            reply_rst(&seg);
+            return;
+        }
+        t.snd_una = seg.ack;
+        t.ack_progress();
+        t.state = TcpState::Established;
+        t.was_connected = true;
+        established_child = t.parent.upgrade().is_some();
+    }
+    if seq_lt(t.snd_una, seg.ack) && seq_le(seg.ack, t.snd_nxt) {
+        let mut acked = seg.ack.wrapping_sub(t.snd_una) as usize;
+        t.snd_una = seg.ack;
+        t.ack_progress();
+        let fin_acked = t.fin_sent && seg.ack == t.snd_nxt;
+        if fin_acked {
+            acked -= 1;
+        }
+        let acked = acked.min(t.sent);
+        t.send_buf.drain(..acked);
+        t.sent -= acked;
+        if fin_acked {
+            match t.state {
+                TcpState::FinWait1 => t.state = TcpState::FinWait2,
+                TcpState::Closing => t.state = TcpState::TimeWait,
+                TcpState::LastAck => t.state = TcpState::Closed,
+                _ => {}
+            }
+        }
+        t.notify_writers();
+    }
+    t.snd_wnd = seg.window as u32;
+
+    // ---- 数据与 FIN ----
+    let mut need_ack = false;
+    let mut fin_ok = seg.flags.contains(TcpFlags::FIN);
+    if !seg.payload.is_empty() || fin_ok {
+        if seg.seq != t.rcv_nxt {
+            // 乱序或重复的报文，回 ACK 告知期望的序号
+            fin_ok = false;
+            need_ack = true;
+        } else if !seg.payload.is_empty() {
+            if matches!(t.state, TcpState::Established | TcpState::FinWait1 | TcpState::FinWait2) {
+                let n = seg.payload.len().min(t.rx_window());
+                if !t.rd_shutdown {
+                    t.recv_buf.extend(&seg.payload[..n]);
+                }
+                t.rcv_nxt = t.rcv_nxt.wrapping_add(n as u32);
+                fin_ok &= n == seg.payload.len();
+                t.notify_readers();
+            } else {
+                fin_ok = false;
+            }
+            need_ack = true;
+        }
+    }
+    if fin_ok && !t.peer_fin {
+        t.rcv_nxt = t.rcv_nxt.wrapping_add(1);
+        t.peer_fin = true;
+        t.state = match t.state {
+            TcpState::Established | TcpState::SynReceived => TcpState::CloseWait,
+            TcpState::FinWait1 => TcpState::Closing,
+            TcpState::FinWait2 => TcpState::TimeWait,
+            s => s,
+        };
+        need_ack = true;
+        t.notify_readers();
+    }
+
+    if !t.output() && need_ack {
+        t.send_ack();
+    }
+
+    // 没有 2MSL 定时器，TIME_WAIT 直接关闭
+    if matches!(t.state, TcpState::TimeWait | TcpState::Closed) {
+        enter_closed(&mut t);
+        return;
+    }
+
+    if established_child {
+        let parent = t.parent.upgrade();
+        t.parent = Weak::new();
+        drop(t);
+        if let Some(parent) = parent {
+            let mut p = parent.lock();
+            p.syn_count = p.syn_count.saturating_sub(1);
+            if p.state == TcpState::Listen {
+                p.accept_queue.push_back(tcb.clone());
+                p.notify_readers();
+                return;
+            }
+        }
+        // 监听 socket 已经关闭
+        let mut t = tcb.lock();
+        let una = t.snd_nxt;
+        t.send_segment(una, TcpFlags::RST | TcpFlags::ACK, Vec::new());
+        enter_closed(&mut t);
+    }
+}
+
+/// 检查各连接的重传定时器，由协议栈定时调用
+pub fn on_timer(now: u64) {
+    let conns: Vec<Arc<Mutex<Tcb>>> = TCP_TABLE.lock().conns.values().cloned().collect();
+    for tcb in conns {
+        let mut t = tcb.lock();
+        if t.rtx_deadline_us == 0 || now < t.rtx_deadline_us || t.retransmit(now) {
+            continue;
+        }
+        // 重传次数用尽，放弃连接
+        let parent = match t.state {
+            TcpState::SynReceived => t.parent.upgrade(),
+            _ => None,
+        };
+        debug!("[tcp] {:?} -> {:?} timed out in {:?}", t.local, t.remote, t.state);
+        drop(t);
+        if let Some(parent) = parent {
+            let mut p = parent.lock();
+            p.syn_count = p.syn_count.saturating_sub(1);
+        }
+        let mut t = tcb.lock();
+        if t.state != TcpState::Closed {
+            t.error = Some(SysErrNo::ETIMEDOUT);
+            enter_closed(&mut t);
+        }
+    }
+}
+
+/// 用户持有的 TCP socket
+pub struct TcpSocket {
+    tcb: Arc<Mutex<Tcb>>,
+}
+
+impl TcpSocket {
+    pub fn new() -> Self {
+        Self {
+            tcb: Arc::new(Mutex::new(Tcb::new())),
+        }
+    }
+
+    pub fn state(&self) -> TcpState {
+        self.tcb.lock().state
+    }
+
+    pub fn local_addr(&self) -> SocketAddrV4 {
+        self.tcb.lock().local.unwrap_or_default()
+    }
+
+    pub fn peer_addr(&self) -> TemplateRet<SocketAddrV4> {
+        let t = self.tcb.lock();
+        match t.state {
+            TcpState::Closed | TcpState::Listen | TcpState::SynSent => Err(SysErrNo::ENOTCONN),
+            _ => t.remote.ok_or(SysErrNo::ENOTCONN),
+        }
+    }
+
+    pub fn bind(&self, mut addr: SocketAddrV4, reuse_addr: bool) -> TemplateRet<()> {
+        let mut t = self.tcb.lock();
+        if t.local.is_some() || t.state != TcpState::Closed {
+            return Err(SysErrNo::EINVAL);
+        }
+        if !stack::is_local_addr(addr.addr) {
+            return Err(SysErrNo::EADDRNOTAVAIL);
+        }
+        let mut table = TCP_TABLE.lock();
+        if addr.port == 0 {
+            addr.port = alloc_ephemeral_port(|p| table.port_in_use(p)).ok_or(SysErrNo::EADDRINUSE)?;
+        } else if table.conflicts(addr, reuse_addr) {
+            return Err(SysErrNo::EADDRINUSE);
+        }
+        table.bind(addr);
+        t.local = Some(addr);
+        t.bound = true;
+        Ok(())
+    }
+
+    pub fn listen(&self, backlog: usize) -> TemplateRet<()> {
+        if self.tcb.lock().local.is_none() {
+            self.bind(SocketAddrV4::default(), false)?;
+        }
+        let mut t = self.tcb.lock();
+        let backlog = backlog.clamp(1, 4096);
+        match t.state {
+            TcpState::Listen => {
+                t.backlog = backlog;
+                Ok(())
+            }
+            TcpState::Closed => {
+                let local = t.local.unwrap();
+                let mut table = TCP_TABLE.lock();
+                if table.listeners.contains_key(&local) {
+                    return Err(SysErrNo::EADDRINUSE);
+                }
+                table.listeners.insert(local, self.tcb.clone());
+                t.state = TcpState::Listen;
+                t.backlog = backlog;
+                Ok(())
+            }
+            _ => Err(SysErrNo::EINVAL),
+        }
+    }
+
+    pub async fn accept(&self, nonblock: bool) -> TemplateRet<TcpSocket> {
+        poll_fn(|cx| {
+            let mut t = self.tcb.lock();
+            if t.state != TcpState::Listen {
+                return Poll::Ready(Err(SysErrNo::EINVAL));
+            }
+            if let Some(child) = t.accept_queue.pop_front() {
+                return Poll::Ready(Ok(TcpSocket { tcb: child }));
+            }
+            if nonblock {
+                return Poll::Ready(Err(SysErrNo::EAGAIN));
+            }
+            t.register_reader(cx.waker());
+            Poll::Pending
+        })
+        .await
+    }
+
+    pub async fn connect(&self, remote: SocketAddrV4, nonblock: bool) -> TemplateRet<()> {
+        {
+            let mut t = self.tcb.lock();
+            match t.state {
+                TcpState::Closed => {}
+                TcpState::SynSent | TcpState::SynReceived => return Err(SysErrNo::EALREADY),
+                _ => return Err(SysErrNo::EISCONN),
+            }
+            if t.was_connected {
+                return Err(t.error.take().unwrap_or(SysErrNo::EISCONN));
+            }
+            if remote.port == 0 {
+                return Err(SysErrNo::ECONNREFUSED);
+            }
+            // 连接 0.0.0.0 等价于连接本机
+            let remote = if remote.addr.is_unspecified() {
+                SocketAddrV4::new(Ipv4Addr::LOOPBACK, remote.port)
+            } else {
+                remote
+            };
+            let mut table = TCP_TABLE.lock();
+            let mut local = t.local.unwrap_or_default();
+            if local.port == 0 {
+                local.port = alloc_ephemeral_port(|p| table.port_in_use(p)).ok_or(SysErrNo::EADDRNOTAVAIL)?;
+            }
+            if local.addr.is_unspecified() {
+                local.addr = stack::source_addr_for(remote.addr)?;
+            }
+            if t.local != Some(local) {
+                if let (true, Some(old)) = (t.bound, t.local) {
+                    table.unbind(old);
+                }
+                table.bind(local);
+                t.bound = true;
+            }
+            if table.conns.contains_key(&(local, remote)) {
+                return Err(SysErrNo::EADDRINUSE);
+            }
+            table.conns.insert((local, remote), self.tcb.clone());
+            drop(table);
+
+            let iss = gen_isn();
+            t.local = Some(local);
+            t.remote = Some(remote);
+            t.snd_una = iss;
+            t.snd_nxt = iss.wrapping_add(1);
+            t.mss = mss_for(remote.addr);
+            t.state = TcpState::SynSent;
+            t.error = None;
+            t.rto_us = TCP_RTO_INIT_US;
+            t.rtx_count = 0;
+            t.send_segment(iss, TcpFlags::SYN, Vec::new());
+            t.arm_rtx();
+        }
+        poll_stack();
+
+        poll_fn(|cx| {
+            let mut t = self.tcb.lock();
+            match t.state {
+                TcpState::SynSent | TcpState::SynReceived => {
+                    if nonblock {
+                        return Poll::Ready(Err(SysErrNo::EINPROGRESS));
+                    }
+                    t.register_writer(cx.waker());
+                    Poll::Pending
+                }
+                TcpState::Closed => Poll::Ready(Err(t.error.take().unwrap_or(SysErrNo::ECONNREFUSED))),
+                _ => Poll::Ready(Ok(())),
+            }
+        })
+        .await
+    }
+
+    pub async fn recv(&self, len: usize, nonblock: bool, peek: bool) -> TemplateRet<Vec<u8>> {
+        let res = poll_fn(|cx| {
+            let mut t = self.tcb.lock();
+            if !t.recv_buf.is_empty() {
+                let n = len.min(t.recv_buf.len());
+                let data: Vec<u8> = if peek {
+                    t.recv_buf.range(..n).copied().collect()
+                } else {
+                    t.recv_buf.drain(..n).collect()
+                };
+                // 窗口从很小恢复时主动发送窗口更新
+                if !peek && t.last_adv_wnd < t.mss && t.rx_window() >= TCP_BUF_SIZE / 2 {
+                    t.send_ack();
+                }
+                return Poll::Ready(Ok(data));
+            }
+            if let Some(err) = t.error.take() {
+                return Poll::Ready(Err(err));
+            }
+            if t.peer_fin || t.rd_shutdown || len == 0 {
+                return Poll::Ready(Ok(Vec::new()));
+            }
+            match t.state {
+                TcpState::Closed if t.was_connected => return Poll::Ready(Ok(Vec::new())),
+                TcpState::Closed | TcpState::Listen => return Poll::Ready(Err(SysErrNo::ENOTCONN)),
+                _ => {}
+            }
+            if nonblock {
+                return Poll::Ready(Err(SysErrNo::EAGAIN));
+            }
+            t.register_reader(cx.waker());
+            Poll::Pending
+        })
+        .await;
+        poll_stack();
+        res
+    }
+
+    /// 发送数据，阻塞模式下直到全部写入发送缓冲区才返回
+    pub async fn send(&self, data: &[u8], nonblock: bool) -> TemplateRet<usize> {
+        let mut written = 0;
+        loop {
+            let res = poll_fn(|cx| {
+                let mut t = self.tcb.lock();
+                if let Some(err) = t.error.take() {
+                    return Poll::Ready(Err(err));
+                }
+                match t.state {
+                    TcpState::Established | TcpState::CloseWait if !t.fin_pending => {}
+                    TcpState::SynSent | TcpState::SynReceived => {
+                        if nonblock {
+                            return Poll::Ready(Err(SysErrNo::EAGAIN));
+                        }
+                        t.register_writer(cx.waker());
+                        return Poll::Pending;
+                    }
+                    TcpState::Closed if !t.was_connected => return Poll::Ready(Err(SysErrNo::ENOTCONN)),
+                    TcpState::Listen => return Poll::Ready(Err(SysErrNo::ENOTCONN)),
+                    _ => return Poll::Ready(Err(SysErrNo::EPIPE)),
+                }
+                let free = TCP_BUF_SIZE - t.send_buf.len();
+                if free > 0 {
+                    let n = free.min(data.len() - written);
+                    t.send_buf.extend(&data[written..written + n]);
+                    t.output();
+                    return Poll::Ready(Ok(n));
+                }
+                if nonblock {
+                    return Poll::Ready(Err(SysErrNo::EAGAIN));
+                }
+                t.register_writer(cx.waker());
+                Poll::Pending
+            })
+            .await;
+            poll_stack();
+            match res {
+                Ok(n) => written += n,
+                Err(e) if written == 0 => return Err(e),
+                Err(_) => break,
+            }
+            if written == data.len() || nonblock {
+                break;
+            }
+        }
+        Ok(written)
+    }
+
+    pub fn shutdown(&self, read: bool, write: bool) -> TemplateRet<()> {
+        {
+            let mut t = self.tcb.lock();
+            match t.state {
+                TcpState::Closed | TcpState::Listen | TcpState::SynSent => return Err(SysErrNo::ENOTCONN),
+                _ => {}
+            }
+            if read {
+                t.rd_shutdown = true;
+                t.recv_buf.clear();
+                t.notify_readers();
+            }
+            if write {
+                t.fin_pending = true;
+                t.output();
+                t.notify_writers();
+            }
+        }
+        poll_stack();
+        Ok(())
+    }
+
+    /// 取出待处理的错误(SO_ERROR)
+    pub fn take_error(&self) -> Option<SysErrNo> {
+        self.tcb.lock().error.take()
+    }
+
+    pub fn recv_queue_len(&self) -> usize {
+        let t = self.tcb.lock();
+        if t.state == TcpState::Listen {
+            t.accept_queue.len()
+        } else {
+            t.recv_buf.len()
+        }
+    }
+
+    pub fn poll(&self, events: PollEvents, waker: &Waker) -> PollEvents {
+        let mut t = self.tcb.lock();
+        let mut re = PollEvents::empty();
+        let hup = (t.state == TcpState::Closed) || (t.peer_fin && t.fin_sent);
+        if t.error.is_some() {
+            re |= PollEvents::POLLERR;
+        }
+        if hup {
+            re |= PollEvents::POLLHUP;
+        }
+        if events.contains(PollEvents::POLLIN) {
+            let readable = match t.state {
+                TcpState::Listen => !t.accept_queue.is_empty(),
+                _ => !t.recv_buf.is_empty() || t.peer_fin || t.rd_shutdown || (t.state == TcpState::Closed && t.was_connected),
+            };
+            if readable {
+                re |= PollEvents::POLLIN;
+                if t.peer_fin {
+                    re |= PollEvents::POLLRDHUP;
+                }
+            } else if t.error.is_none() {
+                t.register_reader(waker);
+            }
+        }
+        if events.contains(PollEvents::POLLOUT) {
+            let writable = match t.state {
+                TcpState::Established | TcpState::CloseWait => t.send_buf.len() < TCP_BUF_SIZE,
+                TcpState::SynSent | TcpState::SynReceived | TcpState::Listen => false,
+                _ => true,
+            };
+            if writable {
+                re |= PollEvents::POLLOUT;
+            } else if t.error.is_none() && t.state != TcpState::Listen {
+                t.register_writer(waker);
+            }
+        }
+        re
+    }
+}
+
+impl Drop for TcpSocket {
+    fn drop(&mut self) {
+        let mut pending = Vec::new();
+        let mut t = self.tcb.lock();
+        match t.state {
+            TcpState::Listen => {
+                TCP_TABLE.lock().listeners.remove(&t.local.unwrap());
+                pending = t.accept_queue.drain(..).collect();
+                t.state = TcpState::Closed;
+                release(&mut t);
+            }
+            TcpState::Closed => release(&mut t),
+            TcpState::SynSent => enter_closed(&mut t),
+            TcpState::SynReceived | TcpState::Established | TcpState::CloseWait => {
+                if !t.recv_buf.is_empty() {
+                    // 关闭时还有未读数据，按 RFC 2525 发送 RST
+                    let nxt = t.snd_nxt;
+                    t.send_segment(nxt, TcpFlags::RST | TcpFlags::ACK, Vec::new());
+                    enter_closed(&mut t);
+                } else {
+                    t.fin_pending = true;
+                    t.output();
+                }
+            }
+            _ => {}
+        }
+        drop(t);
+        // 还没被 accept 的连接直接复位
+        for child in pending {
+            let mut c = child.lock();
+            let nxt = c.snd_nxt;
+            c.send_segment(nxt, TcpFlags::RST | TcpFlags::ACK, Vec::new());
+            enter_closed(&mut c);
+        }
+        poll_stack();
+    }
+}
@@ -0,0 +1,326 @@
+//! UDP 传输层
+
+use alloc::{
+    collections::{BTreeMap, VecDeque},
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use core::{
+    future::poll_fn,
+    task::{Poll, Waker},
+};
+use spin::Mutex;
+
+use super::{
+    addr::{Ipv4Addr, SocketAddrV4},
+    ip,
+    stack::{self, alloc_ephemeral_port, poll_stack, Packet},
+};
+use crate::{
+    fs::PollEvents,
+    utils::error::{SysErrNo, TemplateRet},
+};
+
+/// 传输层的 UDP 数据报
+pub struct UdpDatagram {
+    pub src: SocketAddrV4,
+    pub dst: SocketAddrV4,
+    pub payload: Vec<u8>,
+}
+
+const UDP_HEADER_LEN: usize = 8;
+
+impl UdpDatagram {
+    /// 编码为带校验和的 UDP 报文(不含 IP 首部)
+    pub fn encode(&self) -> Vec<u8> {
+        let len = UDP_HEADER_LEN + self.payload.len();
+        let mut buf = Vec::with_capacity(len);
+        buf.extend_from_slice(&self.src.port.to_be_bytes());
+        buf.extend_from_slice(&self.dst.port.to_be_bytes());
+        buf.extend_from_slice(&(len as u16).to_be_bytes());
+        buf.extend_from_slice(&[0, 0]);
+        buf.extend_from_slice(&self.payload);
+        let mut csum = ip::checksum(&buf, ip::pseudo_header_sum(self.src.addr, self.dst.addr, ip::IPPROTO_UDP, len));
+        if csum == 0 {
+            csum = 0xffff;
+        }
+        buf[6..8].copy_from_slice(&csum.to_be_bytes());
+        buf
+    }
+
+    pub fn decode(src: Ipv4Addr, dst: Ipv4Addr, data: &[u8]) -> Option<Self> {
+        if data.len() < UDP_HEADER_LEN {
+            return None;
+        }
+        let len = u16::from_be_bytes([data[4], data[5]]) as usize;
+        if len < UDP_HEADER_LEN || len > data.len() {
+            return None;
+        }
+        let data = &data[..len];
+        // 校验和为 0 表示发送方没有计算
+        if u16::from_be_bytes([data[6], data[7]]) != 0
+            && ip::checksum(data, ip::pseudo_header_sum(src, dst, ip::IPPROTO_UDP, len)) != 0
+        {
+            debug!("[udp] bad checksum from {}", src);
+            return None;
+        }
+        Some(Self {
+            src: SocketAddrV4::new(src, u16::from_be_bytes([data[0], data[1]])),
+            dst: SocketAddrV4::new(dst, u16::from_be_bytes([data[2], data[3]])),
+            payload: data[UDP_HEADER_LEN..].to_vec(),
+        })
+    }
+}
+
+/// 单个数据报的最大负载(65535 - IP 头 - UDP 头)
+pub const UDP_MAX_PAYLOAD: usize = 65507;
+/// 接收队列中允许积压的字节数
+const UDP_RECV_BUF: usize = 0x40000;
+
+struct UdpInner {
+    local: Option<SocketAddrV4>,
+    peer: Option<SocketAddrV4>,
+    rx: VecDeque<(SocketAddrV4, Vec<u8>)>,
+    rx_bytes: usize,
+    rd_shutdown: bool,
+    readers: Vec<Waker>,
+}
+
+impl UdpInner {
+    fn register_reader(&mut self, w: &Waker) {
+        if !self.readers.iter().any(|rw| rw.will_wake(w)) {
+            self.readers.push(w.clone());
+        }
+    }
+    fn notify_readers(&mut self) {
+        for w in self.readers.drain(..) {
+            w.wake();
+        }
+    }
+}
+
+/// 本地地址到 socket 的索引，SO_REUSEADDR 时同一地址可以对应多个 socket
+static UDP_TABLE: Mutex<BTreeMap<SocketAddrV4, Vec<Weak<Mutex<UdpInner>>>>> = Mutex::new(BTreeMap::new());
+
+fn port_in_use(table: &BTreeMap<SocketAddrV4, Vec<Weak<Mutex<UdpInner>>>>, port: u16) -> bool {
+    table.keys().any(|a| a.port == port)
+}
+
+/// 网络层交付的 UDP 报文入口，返回是否有 socket 接收
+pub fn input(dgram: UdpDatagram) -> bool {
+    let targets: Vec<Arc<Mutex<UdpInner>>> = {
+        let table = UDP_TABLE.lock();
+        let lookup = |addr: SocketAddrV4| -> Vec<Arc<Mutex<UdpInner>>> {
+            table
+                .get(&addr)
+                .map(|v| v.iter().filter_map(|w| w.upgrade()).collect())
+                .unwrap_or_default()
+        };
+        let mut found = lookup(dgram.dst);
+        if found.is_empty() {
+            found = lookup(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, dgram.dst.port));
+        }
+        found
+    };
+    let mut delivered = false;
+    // 广播交给所有匹配的 socket，单播只交给最后绑定的一个
+    for sock in targets.iter().rev() {
+        let mut inner = sock.lock();
+        if inner.peer.is_some_and(|p| p != dgram.src) {
+            continue;
+        }
+        delivered = true;
+        if inner.rd_shutdown || inner.rx_bytes + dgram.payload.len() > UDP_RECV_BUF {
+            // 接收缓冲区满时丢弃
+        } else {
+            inner.rx_bytes += dgram.payload.len();
+            inner.rx.push_back((dgram.src, dgram.payload.clone()));
+            inner.notify_readers();
+        }
+        if !dgram.dst.addr.is_broadcast() {
+            break;
+        }
+    }
+    delivered
+}
+
+/// 用户持有的 UDP socket
+pub struct UdpSocket {
+    inner: Arc<Mutex<UdpInner>>,
+}
+
+impl UdpSocket {
+    pub fn new() -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(UdpInner {
+                local: None,
+                peer: None,
+                rx: VecDeque::new(),
+                rx_bytes: 0,
+                rd_shutdown: false,
+                readers: Vec::new(),
+            })),
+        }
+    }
+
+    fn bind_locked(&self, inner: &mut UdpInner, mut addr: SocketAddrV4, reuse_addr: bool) -> TemplateRet<()> {
+        if inner.local.is_some() {
+            return Err(SysErrNo::EINVAL);
+        }
+        if !stack::is_local_addr(addr.addr) && !addr.addr.is_broadcast() {
+            return Err(SysErrNo::EADDRNOTAVAIL);
+        }
+        let mut table = UDP_TABLE.lock();
+        if addr.port == 0 {
+            addr.port = alloc_ephemeral_port(|p| port_in_use(&table, p)).ok_or(SysErrNo::EADDRINUSE)?;
+        } else if !reuse_addr
+            && table.iter().any(|(a, v)| {
+                a.port == addr.port
+                    && (a.addr == addr.addr || a.addr.is_unspecified() || addr.addr.is_unspecified())
+                    && v.iter().any(|w| w.strong_count() > 0)
+            })
+        {
+            return Err(SysErrNo::EADDRINUSE);
+        }
+        let entry = table.entry(addr).or_default();
+        entry.retain(|w| w.strong_count() > 0);
+        entry.push(Arc::downgrade(&self.inner));
+        inner.local = Some(addr);
+        Ok(())
+    }
+
+    pub fn bind(&self, addr: SocketAddrV4, reuse_addr: bool) -> TemplateRet<()> {
+        let mut inner = self.inner.lock();
+        self.bind_locked(&mut inner, addr, reuse_addr)
+    }
+
+    pub fn local_addr(&self) -> SocketAddrV4 {
+        self.inner.lock().local.unwrap_or_default()
+    }
+
+    pub fn peer_addr(&self) -> TemplateRet<SocketAddrV4> {
+        self.inner.lock().peer.ok_or(SysErrNo::ENOTCONN)
+    }
+
+    /// 设置默认对端，`None` 表示解除关联(connect AF_UNSPEC)
+    pub fn connect(&self, peer: Option<SocketAddrV4>) -> TemplateRet<()> {
+        let mut inner = self.inner.lock();
+        if inner.local.is_none() {
+            self.bind_locked(&mut inner, SocketAddrV4::default(), false)?;
+        }
+        inner.peer = peer.map(|p| {
+            if p.addr.is_unspecified() {
+                SocketAddrV4::new(Ipv4Addr::LOOPBACK, p.port)
+            } else {
+                p
+            }
+        });
+        Ok(())
+    }
+
+    pub fn send_to(&self, data: &[u8], dst: Option<SocketAddrV4>) -> TemplateRet<usize> {
+        if data.len() > UDP_MAX_PAYLOAD {
+            return Err(SysErrNo::EMSGSIZE);
+        }
+        {
+            let mut inner = self.inner.lock();
+            let dst = match dst.or(inner.peer) {
+                Some(d) if d.port == 0 => return Err(SysErrNo::EINVAL),
+                Some(d) => d,
+                None => return Err(SysErrNo::EDESTADDRREQ),
+            };
+            if inner.local.is_none() {
+                self.bind_locked(&mut inner, SocketAddrV4::default(), false)?;
+            }
+            let mut src = inner.local.unwrap();
+            if src.addr.is_unspecified() {
+                src.addr = stack::source_addr_for(dst.addr)?;
+            }
+            stack::transmit(Packet::Udp(UdpDatagram {
+                src,
+                dst,
+                payload: data.to_vec(),
+            }));
+        }
+        poll_stack();
+        Ok(data.len())
+    }
+
+    /// 接收一个数据报，超过 `len` 的部分被截断，同时返回数据报的原始长度
+    pub async fn recv_from(
+        &self,
+        len: usize,
+        nonblock: bool,
+        peek: bool,
+    ) -> TemplateRet<(Vec<u8>, usize, SocketAddrV4)> {
+        poll_fn(|cx| {
+            let mut inner = self.inner.lock();
+            if let Some((src, payload)) = inner.rx.front() {
+                let (src, full_len) = (*src, payload.len());
+                let data = payload[..full_len.min(len)].to_vec();
+                if !peek {
+                    inner.rx.pop_front();
+                    inner.rx_bytes -= full_len;
+                }
+                return Poll::Ready(Ok((data, full_len, src)));
+            }
+            if inner.rd_shutdown {
+                return Poll::Ready(Ok((Vec::new(), 0, SocketAddrV4::default())));
+            }
+            if nonblock {
+                return Poll::Ready(Err(SysErrNo::EAGAIN));
+            }
+            inner.register_reader(cx.waker());
+            Poll::Pending
+        })
+        .await
+    }
+
+    pub fn shutdown(&self, read: bool) -> TemplateRet<()> {
+        let mut inner = self.inner.lock();
+        if inner.peer.is_none() {
+            return Err(SysErrNo::ENOTCONN);
+        }
+        if read {
+            inner.rd_shutdown = true;
+            inner.notify_readers();
+        }
+        Ok(())
+    }
+
+    /// 下一个数据报的长度(FIONREAD)
+    pub fn recv_queue_len(&self) -> usize {
+        self.inner.lock().rx.front().map_or(0, |(_, p)| p.len())
+    }
+
+    pub fn poll(&self, events: PollEvents, waker: &Waker) -> PollEvents {
+        let mut inner = self.inner.lock();
+        let mut re = PollEvents::empty();
+        if events.contains(PollEvents::POLLIN) {
+            if !inner.rx.is_empty() || inner.rd_shutdown {
+                re |= PollEvents::POLLIN;
+            } else {
+                inner.register_reader(waker);
+            }
+        }
+        if events.contains(PollEvents::POLLOUT) {
+            re |= PollEvents::POLLOUT;
+        }
+        re
+    }
+}
+
+impl Drop for UdpSocket {
+    fn drop(&mut self) {
+        let local = self.inner.lock().local;
+        if let Some(local) = local {
+            let mut table = UDP_TABLE.lock();
+            if let Some(v) = table.get_mut(&local) {
+                v.retain(|w| w.strong_count() > 0 && !core::ptr::eq(w.as_ptr(), Arc::as_ptr(&self.inner)));
+                if v.is_empty() {
+                    table.remove(&local);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,724 @@
+//! AF_UNIX 域 socket
+//!
+//! 绑定到路径时会在文件系统中创建一个 socket 类型的 inode，
+//! 名字到 socket 的映射保存在 [`UNIX_NAMES`] 中；抽象地址只存在于该表里。
+//! 流式连接由两个单向的 [`StreamBuf`] 组成，SCM_RIGHTS 传递的文件描述符
+//! 按流中的字节位置挂在缓冲区上，接收时不会跨过带描述符的边界。
+
+use alloc::{
+    collections::{BTreeMap, VecDeque},
+    format,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use core::{
+    future::poll_fn,
+    sync::atomic::{AtomicU32, Ordering},
+    task::{Poll, Waker},
+};
+use spin::Mutex;
+
+use super::addr::UnixAddr;
+use crate::{
+    fs::{create_special_file, vfs::VfsManager, FileDescriptor, InodeType, OpenFlags, PollEvents},
+    utils::error::{SysErrNo, TemplateRet},
+};
+
+/// 单向流缓冲区的容量
+pub(super) const UNIX_STREAM_BUF: usize = 0x40000;
+/// 数据报接收队列允许积压的字节数
+const UNIX_DGRAM_BUF: usize = 0x40000;
+/// 单个数据报的最大长度
+pub(super) const UNIX_DGRAM_MAX: usize = 0x10000;
+/// 默认的 socket 文件权限
+const UNIX_SOCK_MODE: u32 = 0o755;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum UnixType {
+    Stream,
+    Dgram,
+}
+
+fn register_waker(wakers: &mut Vec<Waker>, w: &Waker) {
+    if !wakers.iter().any(|x| x.will_wake(w)) {
+        wakers.push(w.clone());
+    }
+}
+
+fn wake_all(wakers: &mut Vec<Waker>) {
+    for w in wakers.drain(..) {
+        w.wake();
+    }
+}
+
+/// 流式连接中一个方向的缓冲区
+struct StreamBuf {
+    data: VecDeque<u8>,
+    /// SCM_RIGHTS 描述符及其所附着字节在流中的绝对位置
+    rights: VecDeque<(u64, Vec<FileDescriptor>)>,
+    /// `data` 第一个字节在流中的绝对位置
+    head: u64,
+    /// 写端已关闭，读完剩余数据后读到 EOF
+    write_closed: bool,
+    /// 读端已关闭，写入得到 EPIPE
+    read_closed: bool,
+    readers: Vec<Waker>,
+    writers: Vec<Waker>,
+}
+
+impl StreamBuf {
+    fn new() -> Arc<Mutex<Self>> {
+        Arc::new(Mutex::new(Self {
+            data: VecDeque::new(),
+            rights: VecDeque::new(),
+            head: 0,
+            write_closed: false,
+            read_closed: false,
+            readers: Vec::new(),
+            writers: Vec::new(),
+        }))
+    }
+
+    fn space(&self) -> usize {
+        UNIX_STREAM_BUF - self.data.len()
+    }
+
+    /// 写入尽可能多的数据，`rights` 附着在本次写入的第一个字节上
+    fn push(&mut self, data: &[u8], rights: &mut Option<Vec<FileDescriptor>>) -> usize {
+        let n = data.len().min(self.space());
+        if n == 0 {
+            return 0;
+        }
+        if let Some(fds) = rights.take() {
+            self.rights.push_back((self.head + self.data.len() as u64, fds));
+        }
+        self.data.extend(&data[..n]);
+        wake_all(&mut self.readers);
+        n
+    }
+
+    /// 读出至多 `len` 字节，不跨过下一组描述符的位置
+    fn pop(&mut self, len: usize, peek: bool) -> (Vec<u8>, Vec<FileDescriptor>) {
+        let mut limit = len.min(self.data.len());
+        let mut fds = Vec::new();
+        let mut boundaries = self.rights.iter();
+        if let Some((pos, attached)) = boundaries.clone().next() {
+            if *pos == self.head {
+                fds = attached.clone();
+                boundaries.next();
+            }
+        }
+        if let Some((next, _)) = boundaries.next() {
+            limit = limit.min((*next - self.head) as usize);
+        }
+        let out: Vec<u8> = self.data.iter().take(limit).copied().collect();
+        if !peek {
+            if !fds.is_empty() {
+                self.rights.pop_front();
+            }
+            self.data.drain(..limit);
+            self.head += limit as u64;
+            if limit > 0 {
+                wake_all(&mut self.writers);
+            }
+        }
+        (out, fds)
+    }
+
+    fn close_read(&mut self) {
+        self.read_closed = true;
+        // 对端再也读不到的描述符直接释放
+        self.rights.clear();
+        wake_all(&mut self.writers);
+    }
+
+    fn close_write(&mut self) {
+        self.write_closed = true;
+        wake_all(&mut self.readers);
+    }
+}
+
+struct Conn {
+    rx: Arc<Mutex<StreamBuf>>,
+    tx: Arc<Mutex<StreamBuf>>,
+    peer: UnixAddr,
+}
+
+enum UnixState {
+    Idle,
+    Listening {
+        backlog: usize,
+        queue: VecDeque<UnixSocket>,
+    },
+    Connected(Conn),
+}
+
+/// 数据报 socket 的默认对端
+struct DgramPeer {
+    addr: UnixAddr,
+    core: Weak<Mutex<UnixCore>>,
+}
+
+struct Datagram {
+    src: UnixAddr,
+    data: Vec<u8>,
+    rights: Vec<FileDescriptor>,
+}
+
+struct UnixCore {
+    addr: UnixAddr,
+    state: UnixState,
+    dgram_rx: VecDeque<Datagram>,
+    dgram_bytes: usize,
+    dgram_peer: Option<DgramPeer>,
+    shut_rd: bool,
+    shut_wr: bool,
+    /// 等待 accept 或数据报到达
+    readers: Vec<Waker>,
+    /// 等待监听队列或数据报队列腾出空间
+    writers: Vec<Waker>,
+}
+
+/// 已绑定的名字，值为 socket 类型及其状态
+static UNIX_NAMES: Mutex<BTreeMap<UnixAddr, (UnixType, Weak<Mutex<UnixCore>>)>> = Mutex::new(BTreeMap::new());
+static AUTOBIND_SEQ: AtomicU32 = AtomicU32::new(1);
+
+/// 按名字查找对端，找不到时按 Linux 的语义返回 ENOENT 或 ECONNREFUSED
+fn lookup(addr: &UnixAddr, stype: UnixType) -> TemplateRet<Arc<Mutex<UnixCore>>> {
+    let found = UNIX_NAMES.lock().get(addr).and_then(|(t, w)| w.upgrade().map(|c| (*t, c)));
+    match found {
+        Some((t, _)) if t != stype => Err(SysErrNo::EPROTOTYPE),
+        Some((_, core)) => Ok(core),
+        None => match addr {
+            UnixAddr::Path(path) if VfsManager::lookup(path, OpenFlags::empty()).is_err() => {
+                Err(SysErrNo::ENOENT)
+            }
+            UnixAddr::Unnamed => Err(SysErrNo::EINVAL),
+            _ => Err(SysErrNo::ECONNREFUSED),
+        },
+    }
+}
+
+/// 用户持有的 AF_UNIX socket
+pub struct UnixSocket {
+    stype: UnixType,
+    core: Arc<Mutex<UnixCore>>,
+}
+
+impl UnixSocket {
+    pub fn new(stype: UnixType) -> Self {
+        Self::with_state(stype, UnixAddr::Unnamed, UnixState::Idle)
+    }
+
+    fn with_state(stype: UnixType, addr: UnixAddr, state: UnixState) -> Self {
+        Self {
+            stype,
+            core: Arc::new(Mutex::new(UnixCore {
+                addr,
+                state,
+                dgram_rx: VecDeque::new(),
+                dgram_bytes: 0,
+                dgram_peer: None,
+                shut_rd: false,
+                shut_wr: false,
+                readers: Vec::new(),
+                writers: Vec::new(),
+            })),
+        }
+    }
+
+    /// 一次发送最多拷入内核的字节数，以及是否为流式 socket
+    pub fn send_limit(&self) -> (usize, bool) {
+        match self.stype {
+            UnixType::Stream => (UNIX_STREAM_BUF, true),
+            UnixType::Dgram => (UNIX_DGRAM_MAX, false),
+        }
+    }
+
+    /// socketpair 创建的一对已连接 socket
+    pub fn pair(stype: UnixType) -> (Self, Self) {
+        match stype {
+            UnixType::Stream => {
+                let (a, b) = (StreamBuf::new(), StreamBuf::new());
+                let conn = |rx: &Arc<Mutex<StreamBuf>>, tx: &Arc<Mutex<StreamBuf>>| {
+                    UnixState::Connected(Conn {
+                        rx: rx.clone(),
+                        tx: tx.clone(),
+                        peer: UnixAddr::Unnamed,
+                    })
+                };
+                (
+                    Self::with_state(stype, UnixAddr::Unnamed, conn(&a, &b)),
+                    Self::with_state(stype, UnixAddr::Unnamed, conn(&b, &a)),
+                )
+            }
+            UnixType::Dgram => {
+                let (s1, s2) = (Self::new(stype), Self::new(stype));
+                s1.core.lock().dgram_peer = Some(DgramPeer {
+                    addr: UnixAddr::Unnamed,
+                    core: Arc::downgrade(&s2.core),
+                });
+                s2.core.lock().dgram_peer = Some(DgramPeer {
+                    addr: UnixAddr::Unnamed,
+                    core: Arc::downgrade(&s1.core),
+                });
+                (s1, s2)
+            }
+        }
+    }
+
+    pub fn socket_type(&self) -> UnixType {
+        self.stype
+    }
+
+    /// `addr` 中的路径必须已经是绝对路径；`Unnamed` 表示自动绑定到一个抽象地址
+    pub fn bind(&self, addr: UnixAddr) -> TemplateRet<()> {
+        if self.core.lock().addr != UnixAddr::Unnamed {
+            return Err(SysErrNo::EINVAL);
+        }
+        // 创建 socket 文件会进入文件系统，不能持有自旋锁，先建好 inode 再加锁发布名字
+        let UnixAddr::Path(path) = &addr else {
+            return self.publish(addr);
+        };
+        let path = path.clone();
+        let inode = create_special_file(&path, InodeType::Socket, UNIX_SOCK_MODE).map_err(|e| match e {
+            SysErrNo::EEXIST => SysErrNo::EADDRINUSE,
+            e => e,
+        })?;
+        let res = self.publish(addr);
+        if res.is_err() {
+            let _ = inode.unlink(&path);
+        }
+        res
+    }
+
+    /// 在持锁的情况下登记名字，自动绑定时在这里分配抽象名字
+    fn publish(&self, addr: UnixAddr) -> TemplateRet<()> {
+        let mut core = self.core.lock();
+        if core.addr != UnixAddr::Unnamed {
+            return Err(SysErrNo::EINVAL);
+        }
+        let mut names = UNIX_NAMES.lock();
+        names.retain(|_, (_, w)| w.strong_count() > 0);
+        let addr = match addr {
+            UnixAddr::Unnamed => loop {
+                // Linux 自动绑定使用 5 位十六进制的抽象名字
+                let seq = AUTOBIND_SEQ.fetch_add(1, Ordering::Relaxed) & 0xfffff;
+                let name = UnixAddr::Abstract(format!("{:05x}", seq).into_bytes());
+                if !names.contains_key(&name) {
+                    break name;
+                }
+            },
+            addr => {
+                if names.contains_key(&addr) {
+                    return Err(SysErrNo::EADDRINUSE);
+                }
+                addr
+            }
+        };
+        names.insert(addr.clone(), (self.stype, Arc::downgrade(&self.core)));
+        core.addr = addr;
+        Ok(())
+    }
+
+    pub fn listen(&self, backlog: usize) -> TemplateRet<()> {
+        if self.stype != UnixType::Stream {
+            return Err(SysErrNo::EOPNOTSUPP);
+        }
+        let mut core = self.core.lock();
+        if core.addr == UnixAddr::Unnamed {
+            return Err(SysErrNo::EINVAL);
+        }
+        match &mut core.state {
+            UnixState::Idle => {
+                core.state = UnixState::Listening {
+                    backlog: backlog.max(1),
+                    queue: VecDeque::new(),
+                };
+            }
+            UnixState::Listening { backlog: b, .. } => *b = backlog.max(1),
+            UnixState::Connected(_) => return Err(SysErrNo::EINVAL),
+        }
+        Ok(())
+    }
+
+    pub async fn accept(&self, nonblock: bool) -> TemplateRet<(UnixSocket, UnixAddr)> {
+        if self.stype != UnixType::Stream {
+            return Err(SysErrNo::EOPNOTSUPP);
+        }
+        poll_fn(|cx| {
+            let mut core = self.core.lock();
+            let UnixState::Listening { queue, .. } = &mut core.state else {
+                return Poll::Ready(Err(SysErrNo::EINVAL));
+            };
+            if let Some(conn) = queue.pop_front() {
+                wake_all(&mut core.writers);
+                let peer = conn.peer_addr().unwrap_or(UnixAddr::Unnamed);
+                return Poll::Ready(Ok((conn, peer)));
+            }
+            if nonblock {
+                return Poll::Ready(Err(SysErrNo::EAGAIN));
+            }
+            register_waker(&mut core.readers, cx.waker());
+            Poll::Pending
+        })
+        .await
+    }
+
+    /// `addr` 为 `None` 时解除数据报 socket 的默认对端
+    pub async fn connect(&self, addr: Option<UnixAddr>, nonblock: bool) -> TemplateRet<()> {
+        match self.stype {
+            UnixType::Dgram => {
+                let peer = match addr {
+                    Some(addr) => {
+                        let core = lookup(&addr, self.stype)?;
+                        Some(DgramPeer {
+                            addr,
+                            core: Arc::downgrade(&core),
+                        })
+                    }
+                    None => None,
+                };
+                self.core.lock().dgram_peer = peer;
+                Ok(())
+            }
+            UnixType::Stream => {
+                let addr = addr.ok_or(SysErrNo::EAFNOSUPPORT)?;
+                let my_addr = {
+                    let core = self.core.lock();
+                    match core.state {
+                        UnixState::Idle => core.addr.clone(),
+                        UnixState::Connected(_) => return Err(SysErrNo::EISCONN),
+                        UnixState::Listening { .. } => return Err(SysErrNo::EINVAL),
+                    }
+                };
+                let target = lookup(&addr, self.stype)?;
+                let conn = poll_fn(|cx| {
+                    let mut t = target.lock();
+                    let peer_addr = t.addr.clone();
+                    let UnixState::Listening { backlog, queue } = &mut t.state else {
+                        return Poll::Ready(Err(SysErrNo::ECONNREFUSED));
+                    };
+                    if queue.len() >= *backlog {
+                        if nonblock {
+                            return Poll::Ready(Err(SysErrNo::EAGAIN));
+                        }
+                        register_waker(&mut t.writers, cx.waker());
+                        return Poll::Pending;
+                    }
+                    let (c2s, s2c) = (StreamBuf::new(), StreamBuf::new());
+                    queue.push_back(UnixSocket::with_state(
+                        UnixType::Stream,
+                        peer_addr.clone(),
+                        UnixState::Connected(Conn {
+                            rx: c2s.clone(),
+                            tx: s2c.clone(),
+                            peer: my_addr.clone(),
+                        }),
+                    ));
+                    wake_all(&mut t.readers);
+                    Poll::Ready(Ok(Conn {
+                        rx: s2c,
+                        tx: c2s,
+                        peer: peer_addr,
+                    }))
+                })
+                .await?;
+                self.core.lock().state = UnixState::Connected(conn);
+                Ok(())
+            }
+        }
+    }
+
+    pub fn local_addr(&self) -> UnixAddr {
+        self.core.lock().addr.clone()
+    }
+
+    pub fn peer_addr(&self) -> TemplateRet<UnixAddr> {
+        let core = self.core.lock();
+        match (&core.state, &core.dgram_peer) {
+            (UnixState::Connected(conn), _) => Ok(conn.peer.clone()),
+            (_, Some(peer)) => Ok(peer.addr.clone()),
+            _ => Err(SysErrNo::ENOTCONN),
+        }
+    }
+
+    fn stream_conn(&self) -> TemplateRet<(Arc<Mutex<StreamBuf>>, Arc<Mutex<StreamBuf>>)> {
+        match &self.core.lock().state {
+            UnixState::Connected(conn) => Ok((conn.rx.clone(), conn.tx.clone())),
+            _ => Err(SysErrNo::ENOTCONN),
+        }
+    }
+
+    /// 发送数据，`rights` 为随数据传递的文件描述符
+    pub async fn send(
+        &self,
+        data: &[u8],
+        dst: Option<UnixAddr>,
+        rights: Vec<FileDescriptor>,
+        nonblock: bool,
+    ) -> TemplateRet<usize> {
+        if self.core.lock().shut_wr {
+            return Err(SysErrNo::EPIPE);
+        }
+        let mut rights = if rights.is_empty() { None } else { Some(rights) };
+        match self.stype {
+            UnixType::Stream => {
+                if dst.is_some() {
+                    return Err(SysErrNo::EISCONN);
+                }
+                let (_, tx) = self.stream_conn()?;
+                let mut written = 0;
+                while written < data.len() {
+                    let n = poll_fn(|cx| {
+                        let mut buf = tx.lock();
+                        if buf.read_closed {
+                            return Poll::Ready(Err(SysErrNo::EPIPE));
+                        }
+                        let n = buf.push(&data[written..], &mut rights);
+                        if n > 0 {
+                            return Poll::Ready(Ok(n));
+                        }
+                        if nonblock {
+                            return Poll::Ready(Err(SysErrNo::EAGAIN));
+                        }
+                        register_waker(&mut buf.writers, cx.waker());
+                        Poll::Pending
+                    })
+                    .await;
+                    match n {
+                        Ok(n) => written += n,
+                        Err(SysErrNo::EAGAIN) if written > 0 => break,
+                        Err(e) => return Err(e),
+                    }
+                }
+                Ok(written)
+            }
+            UnixType::Dgram => {
+                if data.len() > UNIX_DGRAM_MAX {
+                    return Err(SysErrNo::EMSGSIZE);
+                }
+                let (peer, src) = {
+                    let core = self.core.lock();
+                    (core.dgram_peer.as_ref().map(|p| p.core.clone()), core.addr.clone())
+                };
+                let target = match (&dst, peer) {
+                    (Some(addr), _) => lookup(addr, self.stype)?,
+                    (None, Some(peer)) => peer.upgrade().ok_or(SysErrNo::ECONNREFUSED)?,
+                    (None, None) => return Err(SysErrNo::ENOTCONN),
+                };
+                let me = &self.core;
+                let mut rights = rights.take().unwrap_or_default();
+                poll_fn(|cx| {
+                    let mut t = target.lock();
+                    if t.dgram_peer.as_ref().is_some_and(|p| !core::ptr::eq(p.core.as_ptr(), Arc::as_ptr(me))) {
+                        return Poll::Ready(Err(SysErrNo::EPERM));
+                    }
+                    if t.shut_rd {
+                        return Poll::Ready(Err(SysErrNo::ECONNREFUSED));
+                    }
+                    if t.dgram_bytes + data.len() > UNIX_DGRAM_BUF {
+                        if nonblock {
+                            return Poll::Ready(Err(SysErrNo::EAGAIN));
+                        }
+                        register_waker(&mut t.writers, cx.waker());
+                        return Poll::Pending;
+                    }
+                    t.dgram_bytes += data.len();
+                    t.dgram_rx.push_back(Datagram {
+                        src: src.clone(),
+                        data: data.to_vec(),
+                        rights: core::mem::take(&mut rights),
+                    });
+                    wake_all(&mut t.readers);
+                    Poll::Ready(Ok(data.len()))
+                })
+                .await
+            }
+        }
+    }
+
+    /// 接收数据，返回 (数据, 数据报原始长度, 来源地址, 收到的文件描述符)
+    pub async fn recv(
+        &self,
+        len: usize,
+        nonblock: bool,
+        peek: bool,
+    ) -> TemplateRet<(Vec<u8>, usize, Option<UnixAddr>, Vec<FileDescriptor>)> {
+        match self.stype {
+            UnixType::Stream => {
+                let (rx, _) = self.stream_conn()?;
+                poll_fn(|cx| {
+                    let mut buf = rx.lock();
+                    if !buf.data.is_empty() {
+                        let (data, fds) = buf.pop(len, peek);
+                        let n = data.len();
+                        return Poll::Ready(Ok((data, n, None, fds)));
+                    }
+                    if buf.write_closed || buf.read_closed {
+                        return Poll::Ready(Ok((Vec::new(), 0, None, Vec::new())));
+                    }
+                    if nonblock {
+                        return Poll::Ready(Err(SysErrNo::EAGAIN));
+                    }
+                    register_waker(&mut buf.readers, cx.waker());
+                    Poll::Pending
+                })
+                .await
+            }
+            UnixType::Dgram => {
+                poll_fn(|cx| {
+                    let mut core = self.core.lock();
+                    if let Some(dgram) = core.dgram_rx.front() {
+                        let full = dgram.data.len();
+                        let data = dgram.data[..full.min(len)].to_vec();
+                        let src = dgram.src.clone();
+                        let fds = if peek {
+                            dgram.rights.clone()
+                        } else {
+                            let dgram = core.dgram_rx.pop_front().unwrap();
+                            core.dgram_bytes -= full;
+                            wake_all(&mut core.writers);
+                            dgram.rights
+                        };
+                        return Poll::Ready(Ok((data, full, Some(src), fds)));
+                    }
+                    if core.shut_rd {
+                        return Poll::Ready(Ok((Vec::new(), 0, None, Vec::new())));
+                    }
+                    if nonblock {
+                        return Poll::Ready(Err(SysErrNo::EAGAIN));
+                    }
+                    register_waker(&mut core.readers, cx.waker());
+                    Poll::Pending
+                })
+                .await
+            }
+        }
+    }
+
+    pub fn shutdown(&self, read: bool, write: bool) -> TemplateRet<()> {
+        let mut core = self.core.lock();
+        core.shut_rd |= read;
+        core.shut_wr |= write;
+        match &core.state {
+            UnixState::Connected(conn) => {
+                let (rx, tx) = (conn.rx.clone(), conn.tx.clone());
+                drop(core);
+                if read {
+                    rx.lock().close_read();
+                }
+                if write {
+                    tx.lock().close_write();
+                }
+                Ok(())
+            }
+            _ if self.stype == UnixType::Stream => Err(SysErrNo::ENOTCONN),
+            _ => {
+                wake_all(&mut core.readers);
+                Ok(())
+            }
+        }
+    }
+
+    pub fn recv_queue_len(&self) -> usize {
+        let core = self.core.lock();
+        match &core.state {
+            UnixState::Connected(conn) => conn.rx.lock().data.len(),
+            _ => core.dgram_rx.front().map_or(0, |d| d.data.len()),
+        }
+    }
+
+    pub fn poll(&self, events: PollEvents, waker: &Waker) -> PollEvents {
+        let mut re = PollEvents::empty();
+        let mut core = self.core.lock();
+        match &mut core.state {
+            UnixState::Connected(conn) => {
+                let (rx, tx) = (conn.rx.clone(), conn.tx.clone());
+                drop(core);
+                let mut rx = rx.lock();
+                if rx.write_closed {
+                    re |= PollEvents::POLLRDHUP;
+                }
+                if events.contains(PollEvents::POLLIN) {
+                    if !rx.data.is_empty() || rx.write_closed || rx.read_closed {
+                        re |= PollEvents::POLLIN;
+                    } else {
+                        register_waker(&mut rx.readers, waker);
+                    }
+                }
+                drop(rx);
+                let mut tx = tx.lock();
+                if tx.read_closed {
+                    re |= PollEvents::POLLERR;
+                    if re.contains(PollEvents::POLLRDHUP) {
+                        re |= PollEvents::POLLHUP;
+                    }
+                }
+                if events.contains(PollEvents::POLLOUT) {
+                    if tx.space() > 0 || tx.read_closed {
+                        re |= PollEvents::POLLOUT;
+                    } else {
+                        register_waker(&mut tx.writers, waker);
+                    }
+                }
+            }
+            UnixState::Listening { queue, .. } => {
+                if events.contains(PollEvents::POLLIN) {
+                    if !queue.is_empty() {
+                        re |= PollEvents::POLLIN;
+                    } else {
+                        register_waker(&mut core.readers, waker);
+                    }
+                }
+            }
+            UnixState::Idle if self.stype == UnixType::Stream => {
+                // 未连接的流式 socket
+                re |= PollEvents::POLLHUP;
+                if events.contains(PollEvents::POLLOUT) {
+                    re |= PollEvents::POLLOUT;
+                }
+            }
+            UnixState::Idle => {
+                if events.contains(PollEvents::POLLIN) {
+                    if !core.dgram_rx.is_empty() || core.shut_rd {
+                        re |= PollEvents::POLLIN;
+                    } else {
+                        register_waker(&mut core.readers, waker);
+                    }
+                }
+                if events.contains(PollEvents::POLLOUT) {
+                    re |= PollEvents::POLLOUT;
+                }
+            }
+        }
+        re
+    }
+}
+
+impl Drop for UnixSocket {
+    fn drop(&mut self) {
+        let mut core = self.core.lock();
+        {
+            let mut names = UNIX_NAMES.lock();
+            if names
+                .get(&core.addr)
+                .is_some_and(|(_, w)| core::ptr::eq(w.as_ptr(), Arc::as_ptr(&self.core)))
+            {
+                names.remove(&core.addr);
+            }
+        }
+        let state = core::mem::replace(&mut core.state, UnixState::Idle);
+        let dgrams = core::mem::take(&mut core.dgram_rx);
+        wake_all(&mut core.readers);
+        wake_all(&mut core.writers);
+        drop(core);
+        if let UnixState::Connected(conn) = &state {
+            conn.rx.lock().close_read();
+            conn.tx.lock().close_write();
+        }
+        // 未被 accept 的连接和队列中的描述符在锁外释放
+        drop(state);
+        drop(dgrams);
+    }
+}
@@ -0,0 +1,283 @@
+//! 普通文件的页缓存
+//!
+//! 同一个文件（按 `(st_dev, st_ino)` 区分）的所有打开实例共享一份 `PageCache`，
+//! read/write 和文件 mmap 都经过它：MAP_SHARED 直接映射缓存页，
+//! MAP_PRIVATE 以 COW 方式映射缓存页。
+//!
+//! 不改变文件长度的写只修改缓存页并记为脏页，在 fsync/msync/sync/umount
+//! 或最后一个打开实例关闭时写回；扩展文件的写直接写穿到底层文件系统，
+//! 这样按路径 stat 得到的文件大小始终正确。
+//!
+//! 内存不足时 `shrink` 丢弃干净且没有被映射的页，下次访问时重新从文件读入。
+
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use spin::{Lazy, Mutex};
+
+use super::vfs::vfs_ops::VfsNodeOps;
+use crate::config::{PAGE_SIZE, PAGE_SIZE_BITS};
+use crate::mm::{frame_alloc, FrameTracker};
+use crate::utils::error::{GeneralRet, SysErrNo, SyscallRet, TemplateRet};
+
+/// 页缓存的键：(st_dev, st_ino)
+pub type CacheKey = (usize, usize);
+
+/// 所有存活的页缓存，供 sync/umount 遍历
+static PAGE_CACHES: Lazy<Mutex<BTreeMap<CacheKey, Weak<PageCache>>>> =
+    Lazy::new(|| Mutex::new(BTreeMap::new()));
+
+pub struct PageCache {
+    /// 用于填充和写回的底层节点
+    inode: Arc<dyn VfsNodeOps>,
+    inner: Mutex<PageCacheInner>,
+}
+
+struct PageCacheInner {
+    /// 文件长度
+    size: usize,
+    /// 页号 -> 缓存页
+    pages: BTreeMap<usize, Arc<FrameTracker>>,
+    /// 需要写回的页号
+    dirty: BTreeSet<usize>,
+}
+
+impl PageCache {
+    /// 取得 `inode` 对应文件的页缓存，不经过页缓存的节点返回 None
+    pub fn of(inode: &Arc<dyn VfsNodeOps>) -> Option<Arc<Self>> {
+        if !inode.page_cached() {
+            return None;
+        }
+        let stat = inode.fstat();
+        let key = (stat.st_dev, stat.st_ino);
+        let mut caches = PAGE_CACHES.lock();
+        if let Some(cache) = caches.get(&key).and_then(Weak::upgrade) {
+            return Some(cache);
+        }
+        caches.retain(|_, cache| cache.strong_count() > 0);
+        let cache = Arc::new(Self {
+            inode: inode.clone(),
+            inner: Mutex::new(PageCacheInner {
+                size: stat.st_size as usize,
+                pages: BTreeMap::new(),
+                dirty: BTreeSet::new(),
+            }),
+        });
+        caches.insert(key, Arc::downgrade(&cache));
+        Some(cache)
+    }
+
+    pub fn size(&self) -> usize {
+        self.inner.lock().size
+    }
+
+    /// 取得第 `idx` 页，不在缓存中时从文件读入；文件末尾之后的部分为 0
+    pub fn get_page(&self, idx: usize) -> TemplateRet<Arc<FrameTracker>> {
+        let mut inner = self.inner.lock();
+        self.page_locked(&mut inner, idx)
+    }
+
+    fn page_locked(&self, inner: &mut PageCacheInner, idx: usize) -> TemplateRet<Arc<FrameTracker>> {
+        if let Some(frame) = inner.pages.get(&idx) {
+            return Ok(frame.clone());
+        }
+        let frame = frame_alloc().ok_or(SysErrNo::ENOMEM)?;
+        let start = idx << PAGE_SIZE_BITS;
+        if start < inner.size {
+            let len = (inner.size - start).min(PAGE_SIZE);
+            let bytes = &mut frame.ppn().get_bytes_array()[..len];
+            let mut filled = 0;
+            while filled < len {
+                let n = self.inode.read_at((start + filled) as u64, &mut bytes[filled..])?;
+                if n == 0 {
+                    break;
+                }
+                filled += n;
+            }
+        }
+        inner.pages.insert(idx, frame.clone());
+        Ok(frame)
+    }
+
+    /// 标记第 `idx` 页为脏页，可写的共享映射建立时调用
+    pub fn mark_dirty(&self, idx: usize) {
+        self.inner.lock().dirty.insert(idx);
+    }
+
+    pub fn read_at(&self, offset: usize, buf: &mut [u8]) -> SyscallRet {
+        let mut inner = self.inner.lock();
+        if offset >= inner.size {
+            return Ok(0);
+        }
+        let end = (offset + buf.len()).min(inner.size);
+        let mut pos = offset;
+        while pos < end {
+            let frame = self.page_locked(&mut inner, pos >> PAGE_SIZE_BITS)?;
+            let page_off = pos & (PAGE_SIZE - 1);
+            let n = (PAGE_SIZE - page_off).min(end - pos);
+            buf[pos - offset..pos - offset + n]
+                .copy_from_slice(&frame.ppn().get_bytes_array()[page_off..page_off + n]);
+            pos += n;
+        }
+        Ok(end - offset)
+    }
+
+    pub fn write_at(&self, offset: usize, buf: &[u8]) -> SyscallRet {
+        let mut inner = self.inner.lock();
+        let end = offset + buf.len();
+        if end > inner.size {
+            // 扩展文件：直接写穿，只同步已缓存的页
+            let n = self.inode.write_at(offset as u64, buf).map_err(SysErrNo::from)?;
+            let end = offset + n;
+            let mut pos = offset;
+            while pos < end {
+                let page_off = pos & (PAGE_SIZE - 1);
+                let len = (PAGE_SIZE - page_off).min(end - pos);
+                if let Some(frame) = inner.pages.get(&(pos >> PAGE_SIZE_BITS)) {
+                    frame.ppn().get_bytes_array()[page_off..page_off + len]
+                        .copy_from_slice(&buf[pos - offset..pos - offset + len]);
+                }
+                pos += len;
+            }
+            inner.size = inner.size.max(end);
+            return Ok(n);
+        }
+        let mut pos = offset;
+        while pos < end {
+            let idx = pos >> PAGE_SIZE_BITS;
+            let frame = self.page_locked(&mut inner, idx)?;
+            let page_off = pos & (PAGE_SIZE - 1);
+            let n = (PAGE_SIZE - page_off).min(end - pos);
+            frame.ppn().get_bytes_array()[page_off..page_off + n]
+                .copy_from_slice(&buf[pos - offset..pos - offset + n]);
+            inner.dirty.insert(idx);
+            pos += n;
+        }
+        Ok(buf.len())
+    }
+
+    /// 截断文件，丢弃新长度之后的缓存页
+    pub fn truncate(&self, size: usize) -> SyscallRet {
+        let mut inner = self.inner.lock();
+        let ret = self.inode.truncate(size as u64).map_err(SysErrNo::from)?;
+        let first_gone = (size + PAGE_SIZE - 1) >> PAGE_SIZE_BITS;
+        inner.pages.split_off(&first_gone);
+        inner.dirty.split_off(&first_gone);
+        // 旧的末页中文件末尾之后可能残留共享映射写入的数据，长度变化后要清零
+        let tail = size.min(inner.size);
+        if tail & (PAGE_SIZE - 1) != 0 {
+            if let Some(frame) = inner.pages.get(&(tail >> PAGE_SIZE_BITS)) {
+                frame.ppn().get_bytes_array()[tail & (PAGE_SIZE - 1)..].fill(0);
+            }
+        }
+        inner.size = size;
+        Ok(ret)
+    }
+
+    /// 写回 [first, last) 页中的脏页。
+    /// 写文件系统可能睡眠，先在锁内取出脏页并清除脏标记，再逐页在锁外写回
+    pub fn writeback_range(&self, first: usize, last: usize) -> GeneralRet {
+        let pages: Vec<(usize, Arc<FrameTracker>)> = {
+            let mut inner = self.inner.lock();
+            let dirty: Vec<usize> = inner.dirty.range(first..last).copied().collect();
+            let mut pages = Vec::new();
+            for idx in dirty {
+                inner.dirty.remove(&idx);
+                if let Some(frame) = inner.pages.get(&idx) {
+                    pages.push((idx, frame.clone()));
+                }
+            }
+            pages
+        };
+        for (i, (idx, frame)) in pages.iter().enumerate() {
+            // 写回前确认该页没有被截断掉，并按当前长度写
+            let len = {
+                let inner = self.inner.lock();
+                let start = idx << PAGE_SIZE_BITS;
+                match inner.pages.get(idx) {
+                    Some(cur) if Arc::ptr_eq(cur, frame) && start < inner.size => (inner.size - start).min(PAGE_SIZE),
+                    _ => continue,
+                }
+            };
+            if let Err(e) = self.inode.write_at((idx << PAGE_SIZE_BITS) as u64, &frame.ppn().get_bytes_array()[..len]) {
+                // 没写成的页恢复脏状态
+                self.inner.lock().dirty.extend(pages[i..].iter().map(|(idx, _)| *idx));
+                return Err(SysErrNo::from(e));
+            }
+            // 仍被映射的页随时可能经由 MAP_SHARED 被写入，保持脏状态
+            if Arc::strong_count(frame) > 2 {
+                self.inner.lock().dirty.insert(*idx);
+            }
+        }
+        Ok(())
+    }
+
+    /// 丢弃至多 `want` 个干净且只被页缓存引用的页，返回丢弃的页数
+    fn shrink(&self, want: usize) -> usize {
+        // 回收路径不能在这里等锁，正被读写的缓存跳过
+        let Some(mut inner) = self.inner.try_lock() else { return 0 };
+        let victims: Vec<usize> = inner
+            .pages
+            .iter()
+            .filter(|(idx, frame)| Arc::strong_count(frame) == 1 && !inner.dirty.contains(idx))
+            .map(|(idx, _)| *idx)
+            .take(want)
+            .collect();
+        for idx in victims.iter() {
+            inner.pages.remove(idx);
+        }
+        victims.len()
+    }
+
+    /// 写回全部脏页
+    pub fn writeback(&self) -> GeneralRet {
+        self.writeback_range(0, usize::MAX)
+    }
+
+    /// 写回脏页并让底层文件系统落盘
+    pub fn sync(&self) -> GeneralRet {
+        self.writeback()?;
+        self.inode.sync();
+        Ok(())
+    }
+}
+
+impl Drop for PageCache {
+    fn drop(&mut self) {
+        if let Err(e) = self.writeback() {
+            warn!("[PageCache] writeback on drop failed: {:?}", e);
+        }
+    }
+}
+
+/// 内存不足时从各文件的页缓存中丢弃至多 `want` 个干净、未被映射的页，
+/// 返回释放的页数；由 `swap::reclaim` 在换出之前调用
+pub fn shrink(want: usize) -> usize {
+    let caches: Vec<Arc<PageCache>> = PAGE_CACHES
+        .lock()
+        .values()
+        .filter_map(Weak::upgrade)
+        .collect();
+    let mut freed = 0;
+    for cache in caches {
+        if freed >= want {
+            break;
+        }
+        freed += cache.shrink(want - freed);
+    }
+    freed
+}
+
+/// 写回所有页缓存中的脏页，sync 和 umount 时调用
+pub fn sync_all() {
+    let caches: Vec<Arc<PageCache>> = PAGE_CACHES
+        .lock()
+        .values()
+        .filter_map(Weak::upgrade)
+        .collect();
+    for cache in caches {
+        if let Err(e) = cache.writeback() {
+            warn!("[PageCache] writeback failed: {:?}", e);
+        }
+    }
+}
@@ -1,5 +1,7 @@
 use alloc::{
     boxed::Box,
+    format,
+    string::String,
     sync::{Arc, Weak},
     vec::Vec,
 };
@@ -14,8 +16,9 @@ use spin::Mutex;
 use crate::{
     fs::{stat::StMode, File, Kstat, OpenFlags, PollEvents }, // 假设 SeekWhence 在这里
     mm::UserBuffer,
-    task::yield_now, 
-    utils::error::{SysErrNo, TemplateRet},
+    syscall::flags::FIONREAD,
+    task::{current_process, yield_now},
+    utils::error::{SysErrNo, SyscallRet, TemplateRet},
 };
 
 const RING_BUFFER_SIZE: usize = 0x4000; // 16KB
@@ -221,6 +224,20 @@ impl File for Pipe {
     fn as_any(&self) -> &dyn core::any::Any {
         self
     }
+    /// 和 Linux 一样以 `pipe:[id]` 表示，两端共享同一个 id
+    fn get_path(&self) -> String {
+        format!("pipe:[{}]", Arc::as_ptr(&self.buffer) as usize)
+    }
+    async fn ioctl(&self, cmd: usize, arg: usize) -> SyscallRet {
+        match cmd {
+            FIONREAD => {
+                let n = self.buffer.lock().available_read();
+                current_process().memory_set.lock().await.put_user(arg, n as i32).await?;
+                Ok(0)
+            }
+            _ => Err(SysErrNo::ENOTTY),
+        }
+    }
     fn poll(&self, requested_events: PollEvents, waker_to_register: &Waker) -> PollEvents {
         let mut revents = PollEvents::empty();
         let mut buffer_guard = self.buffer.lock(); // 获取共享缓冲区的锁
@@ -6,8 +6,8 @@ use core::pin::Pin;
 use core::task::{Context, Poll, Waker};
 use core::mem;
 
-use crate::task::{current_process, ProcessControlBlock};
-use crate::fs::{FileDescriptor, PollEvents, PollFd}; // PollFdUser 是用户空间版本
+use crate::task::{current_process, ProcessControlBlock, Task, TaskStatus};
+use crate::fs::{File, FileDescriptor, PollEvents, PollFd}; // PollFdUser 是用户空间版本
 use crate::mm::page_table::{copy_from_user_array, copy_to_user_bytes_exact}; // 假设有 copy_to_user_bytes_exact
 use crate::mm::{VirtAddr, TranslateError};
 use crate::utils::error::{SysErrNo, SyscallRet};
@@ -174,4 +174,36 @@ impl Future for PollFuture {
             return Poll::Pending;
         }
     }
-}
\ No newline at end of file
+}
+/// 阻塞直到文件报告 `events` 中的事件。
+///
+/// 任务被其它原因唤醒（例如收到信号）时也会返回，调用者需要重新检查条件，
+/// 并用 `signal_pending` 判断是否应该返回 EINTR/ERESTART。
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct WaitPoll<'a> {
+    file: &'a dyn File,
+    events: PollEvents,
+    pending: bool,
+}
+
+pub fn wait_poll(file: &dyn File, events: PollEvents) -> WaitPoll<'_> {
+    WaitPoll {
+        file,
+        events,
+        pending: false,
+    }
+}
+
+impl Future for WaitPoll<'_> {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if self.pending || !self.file.poll(self.events, cx.waker()).is_empty() {
+            return Poll::Ready(());
+        }
+        self.pending = true;
+        let task = cx.waker().data() as *const Task;
+        unsafe { &*task }.set_state(TaskStatus::Blocking);
+        Poll::Pending
+    }
+}
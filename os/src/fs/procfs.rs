@@ -0,0 +1,744 @@
+//! 动态生成的 procfs
+//!
+//! 不保存任何数据，每次读取时按当前内核状态（进程表、帧分配器、挂载表等）现场生成内容。
+//! 进程相关的锁是异步锁，这里只能 `try_lock`，拿不到时对应字段留空。
+
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::fmt::Write;
+use lwext4_rust::InodeTypes;
+
+use crate::config::PAGE_SIZE;
+use crate::fs::inode::InodeType;
+use crate::drivers::bcache;
+use crate::fs::mount::{is_path_prefix, MNT_TABLE, MS_RDONLY};
+use crate::fs::stat::Kstat;
+use crate::fs::vfs::vfs_ops::{VfsNodeOps, VfsOps};
+use crate::fs::{Dirent, OpenFlags, Statfs};
+use crate::mm::frame_allocator::{self, remaining_frames, total_frames};
+use crate::mm::{MapAreaType, MapPermission, MmapFlags};
+use crate::syscall::trace::{clear_trace_log, trace_control, trace_log, traced_pids};
+use crate::syscall::unimplemented::unimplemented_report;
+use crate::task::{current_process, ProcessRef, TaskStatus, PID2PC, TID2TC};
+use crate::timer::get_time_ms;
+use crate::utils::error::{GeneralRet, SysErrNo, SyscallRet};
+
+const PROC_SUPER_MAGIC: i64 = 0x9fa0;
+/// /proc/<pid>/stat 中时间的单位，和 Linux 的 USER_HZ 一致
+const CLK_TCK: usize = 100;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ProcKind {
+    Root,
+    Meminfo,
+    Mounts,
+    Uptime,
+    Cpuinfo,
+    Stat,
+    Loadavg,
+    SelfLink,
+    LingosDir,
+    UnimplementedSyscalls,
+    Trace,
+    TracePids,
+    BlockCache,
+    Buddyinfo,
+    FrameAllocator,
+    PidDir(usize),
+    PidStat(usize),
+    PidStatus(usize),
+    PidCmdline(usize),
+    PidMaps(usize),
+    PidExe(usize),
+    PidCwd(usize),
+    FdDir(usize),
+    Fd(usize, usize),
+}
+
+/// /proc 根目录下的固定条目
+const ROOT_ENTRIES: [(&str, ProcKind); 9] = [
+    ("buddyinfo", ProcKind::Buddyinfo),
+    ("cpuinfo", ProcKind::Cpuinfo),
+    ("lingos", ProcKind::LingosDir),
+    ("loadavg", ProcKind::Loadavg),
+    ("meminfo", ProcKind::Meminfo),
+    ("mounts", ProcKind::Mounts),
+    ("self", ProcKind::SelfLink),
+    ("stat", ProcKind::Stat),
+    ("uptime", ProcKind::Uptime),
+];
+
+/// /proc/lingos 下的内核调试信息
+const LINGOS_ENTRIES: [(&str, ProcKind); 5] = [
+    ("block_cache", ProcKind::BlockCache),
+    ("frame_allocator", ProcKind::FrameAllocator),
+    ("trace", ProcKind::Trace),
+    ("trace_pids", ProcKind::TracePids),
+    ("unimplemented_syscalls", ProcKind::UnimplementedSyscalls),
+];
+
+/// /proc/<pid> 下的条目
+const PID_ENTRIES: [(&str, fn(usize) -> ProcKind); 7] = [
+    ("cmdline", ProcKind::PidCmdline),
+    ("cwd", ProcKind::PidCwd),
+    ("exe", ProcKind::PidExe),
+    ("fd", ProcKind::FdDir),
+    ("maps", ProcKind::PidMaps),
+    ("stat", ProcKind::PidStat),
+    ("status", ProcKind::PidStatus),
+];
+
+impl ProcKind {
+    fn node_type(self) -> InodeType {
+        match self {
+            Self::Root | Self::LingosDir | Self::PidDir(_) | Self::FdDir(_) => InodeType::Dir,
+            Self::SelfLink | Self::PidExe(_) | Self::PidCwd(_) | Self::Fd(..) => InodeType::SymLink,
+            _ => InodeType::File,
+        }
+    }
+
+    /// 可写的控制文件
+    fn writable(self) -> bool {
+        matches!(self, Self::Trace | Self::TracePids | Self::BlockCache)
+    }
+
+    /// 合成的 inode 号，同一条目每次查找都相同
+    fn ino(self) -> usize {
+        let (pid, idx) = match self {
+            Self::Root => (0, 1),
+            Self::Meminfo => (0, 2),
+            Self::Mounts => (0, 3),
+            Self::Uptime => (0, 4),
+            Self::Cpuinfo => (0, 5),
+            Self::Stat => (0, 6),
+            Self::Loadavg => (0, 7),
+            Self::SelfLink => (0, 8),
+            Self::LingosDir => (0, 9),
+            Self::UnimplementedSyscalls => (0, 10),
+            Self::Trace => (0, 11),
+            Self::TracePids => (0, 12),
+            Self::BlockCache => (0, 13),
+            Self::Buddyinfo => (0, 14),
+            Self::FrameAllocator => (0, 15),
+            Self::PidDir(pid) => (pid, 1),
+            Self::PidStat(pid) => (pid, 2),
+            Self::PidStatus(pid) => (pid, 3),
+            Self::PidCmdline(pid) => (pid, 4),
+            Self::PidMaps(pid) => (pid, 5),
+            Self::PidExe(pid) => (pid, 6),
+            Self::PidCwd(pid) => (pid, 7),
+            Self::FdDir(pid) => (pid, 8),
+            Self::Fd(pid, fd) => (pid, 16 + fd),
+        };
+        (pid << 16) | idx
+    }
+}
+
+fn process(pid: usize) -> Result<ProcessRef, SysErrNo> {
+    PID2PC.lock().get(&pid).cloned().ok_or(SysErrNo::ENOENT)
+}
+
+/// 进程名：取 exec 参数中第一个参数的文件名部分
+fn comm(proc: &ProcessRef) -> String {
+    let cmdline = proc.cmdline.lock();
+    let name = cmdline.first().map(|s| s.as_str()).unwrap_or("");
+    name.rsplit('/').next().unwrap_or(name).chars().take(15).collect()
+}
+
+/// 进程的状态字符和线程数，以及所有线程的用户态/内核态时间（毫秒）
+struct ThreadSummary {
+    state: char,
+    threads: usize,
+    utime: usize,
+    stime: usize,
+    cutime: usize,
+    cstime: usize,
+    uid: usize,
+}
+
+fn thread_summary(pid: usize) -> ThreadSummary {
+    let mut sum = ThreadSummary { state: 'Z', threads: 0, utime: 0, stime: 0, cutime: 0, cstime: 0, uid: 0 };
+    let mut running = false;
+    let mut sleeping = false;
+    for task in TID2TC.lock().values().filter(|t| t.get_pid() == pid) {
+        sum.threads += 1;
+        match *task.state.lock() {
+            TaskStatus::Running | TaskStatus::Runnable | TaskStatus::Waked => running = true,
+            TaskStatus::Blocking | TaskStatus::Blocked => sleeping = true,
+            TaskStatus::Zombie => {}
+        }
+        // 只读取计时数据，最坏读到一次更新中的旧值
+        let tms = unsafe { *task.tms.get() };
+        sum.utime += tms.utime.max(0) as usize;
+        sum.stime += tms.stime.max(0) as usize;
+        sum.cutime += tms.cutime.max(0) as usize;
+        sum.cstime += tms.cstime.max(0) as usize;
+        if task.is_leader() {
+            sum.uid = task.uid.load(core::sync::atomic::Ordering::Relaxed);
+        }
+    }
+    sum.state = if running {
+        'R'
+    } else if sleeping {
+        'S'
+    } else {
+        'Z'
+    };
+    sum
+}
+
+/// 进程的内存用量（字节），拿不到地址空间锁时全为 0
+#[derive(Default)]
+struct VmUsage {
+    size: usize,
+    rss: usize,
+    hwm: usize,
+    data: usize,
+    stack: usize,
+}
+
+fn vm_usage(proc: &ProcessRef) -> VmUsage {
+    match proc.memory_set.try_lock() {
+        Some(ms) => VmUsage {
+            size: ms.vm_size(),
+            rss: ms.resident_pages() * PAGE_SIZE,
+            hwm: ms.peak_resident_pages() * PAGE_SIZE,
+            data: ms.data_size(),
+            stack: ms.stack_size(),
+        },
+        None => VmUsage::default(),
+    }
+}
+
+fn ms_to_ticks(ms: usize) -> usize {
+    ms * CLK_TCK / 1000
+}
+
+fn gen_meminfo() -> String {
+    let total = total_frames() * PAGE_SIZE / 1024;
+    let free = remaining_frames() * PAGE_SIZE / 1024;
+    let (swap_total, swap_free) = crate::mm::swap::stats();
+    let mut s = String::new();
+    let _ = write!(
+        s,
+        "MemTotal:       {:>8} kB\n\
+         MemFree:        {:>8} kB\n\
+         MemAvailable:   {:>8} kB\n\
+         Buffers:        {:>8} kB\n\
+         Cached:         {:>8} kB\n\
+         SwapCached:     {:>8} kB\n\
+         Active:         {:>8} kB\n\
+         Inactive:       {:>8} kB\n\
+         SwapTotal:      {:>8} kB\n\
+         SwapFree:       {:>8} kB\n\
+         Shmem:          {:>8} kB\n\
+         SReclaimable:   {:>8} kB\n",
+        total,
+        free,
+        free,
+        0,
+        0,
+        0,
+        total - free,
+        0,
+        swap_total * PAGE_SIZE / 1024,
+        swap_free * PAGE_SIZE / 1024,
+        0,
+        0
+    );
+    s
+}
+
+fn gen_mounts() -> String {
+    let mut s = String::new();
+    for entry in MNT_TABLE.lock().entries.iter() {
+        let rw = if entry.flags & MS_RDONLY != 0 { "ro" } else { "rw" };
+        let fstype = entry.fs_instance.lock().name();
+        let _ = writeln!(s, "{} {} {} {} 0 0", entry.special_device, entry.mount_point, fstype, rw);
+    }
+    s
+}
+
+fn gen_uptime() -> String {
+    let ms = get_time_ms();
+    format!("{}.{:02} 0.00\n", ms / 1000, ms % 1000 / 10)
+}
+
+fn gen_cpuinfo() -> String {
+    #[cfg(target_arch = "riscv64")]
+    let detail = "isa\t\t: rv64imafdc\nmmu\t\t: sv39\n";
+    #[cfg(target_arch = "loongarch64")]
+    let detail = "cpu family\t: Loongson-64bit\nmodel name\t: Loongson-3A5000\n";
+    #[cfg(not(any(target_arch = "riscv64", target_arch = "loongarch64")))]
+    let detail = "";
+    format!("processor\t: 0\n{}bogomips\t: {}\n\n", detail, crate::config::CLOCK_FREQ / 500_000)
+}
+
+fn gen_stat() -> String {
+    let (mut user, mut system) = (0, 0);
+    let (mut running, mut blocked) = (0, 0);
+    for task in TID2TC.lock().values() {
+        let tms = unsafe { *task.tms.get() };
+        user += tms.utime.max(0) as usize;
+        system += tms.stime.max(0) as usize;
+        match *task.state.lock() {
+            TaskStatus::Running | TaskStatus::Runnable | TaskStatus::Waked => running += 1,
+            TaskStatus::Blocked => blocked += 1,
+            _ => {}
+        }
+    }
+    let idle = get_time_ms().saturating_sub(user + system);
+    let (user, system, idle) = (ms_to_ticks(user), ms_to_ticks(system), ms_to_ticks(idle));
+    format!(
+        "cpu  {} 0 {} {} 0 0 0 0 0 0\ncpu0 {} 0 {} {} 0 0 0 0 0 0\nbtime 0\nprocesses {}\nprocs_running {}\nprocs_blocked {}\n",
+        user, system, idle, user, system, idle, PID2PC.lock().len(), running, blocked
+    )
+}
+
+fn gen_loadavg() -> String {
+    let tasks = TID2TC.lock().len();
+    let last_pid = PID2PC.lock().keys().next_back().copied().unwrap_or(0);
+    format!("0.00 0.00 0.00 1/{} {}\n", tasks, last_pid)
+}
+
+fn gen_pid_stat(pid: usize) -> Result<String, SysErrNo> {
+    let proc = process(pid)?;
+    let t = thread_summary(pid);
+    let vm = vm_usage(&proc);
+    let start = ms_to_ticks(proc.start_time);
+    Ok(format!(
+        "{} ({}) {} {} {} {} 0 -1 0 0 0 0 0 {} {} {} {} 20 0 {} 0 {} {} {} 18446744073709551615 0 0 0 0 0 0 0 0 0 0 0 0 17 0 0 0 0 0 0 0 0 0 0 0 0 0 {}\n",
+        pid,
+        comm(&proc),
+        t.state,
+        proc.parent(),
+        pid,
+        pid,
+        ms_to_ticks(t.utime),
+        ms_to_ticks(t.stime),
+        ms_to_ticks(t.cutime),
+        ms_to_ticks(t.cstime),
+        t.threads,
+        start,
+        vm.size,
+        vm.rss / PAGE_SIZE,
+        proc.exit_code(),
+    ))
+}
+
+fn gen_pid_status(pid: usize) -> Result<String, SysErrNo> {
+    let proc = process(pid)?;
+    let t = thread_summary(pid);
+    let vm = vm_usage(&proc);
+    let state = match t.state {
+        'R' => "R (running)",
+        'S' => "S (sleeping)",
+        _ => "Z (zombie)",
+    };
+    Ok(format!(
+        "Name:\t{}\nState:\t{}\nTgid:\t{}\nPid:\t{}\nPPid:\t{}\nUid:\t{uid}\t{uid}\t{uid}\t{uid}\nGid:\t0\t0\t0\t0\nVmSize:\t{:>8} kB\nVmHWM:\t{:>8} kB\nVmRSS:\t{:>8} kB\nVmData:\t{:>8} kB\nVmStk:\t{:>8} kB\nThreads:\t{}\n",
+        comm(&proc),
+        state,
+        pid,
+        pid,
+        proc.parent(),
+        vm.size / 1024,
+        vm.hwm / 1024,
+        vm.rss / 1024,
+        vm.data / 1024,
+        vm.stack / 1024,
+        t.threads,
+        uid = t.uid,
+    ))
+}
+
+fn gen_pid_cmdline(pid: usize) -> Result<Vec<u8>, SysErrNo> {
+    let proc = process(pid)?;
+    let mut out = Vec::new();
+    for arg in proc.cmdline.lock().iter() {
+        out.extend_from_slice(arg.as_bytes());
+        out.push(0);
+    }
+    Ok(out)
+}
+
+fn gen_pid_maps(pid: usize) -> Result<String, SysErrNo> {
+    let proc = process(pid)?;
+    let exe = proc.exe.try_lock().map(|e| e.clone()).unwrap_or_default();
+    let mut s = String::new();
+    let Some(ms) = proc.memory_set.try_lock() else {
+        return Ok(s);
+    };
+    for area in ms.areatree.values() {
+        let start = area.vpn_range.get_start().0 * PAGE_SIZE;
+        let end = area.vpn_range.get_end().0 * PAGE_SIZE;
+        let perm = area.map_perm;
+        let shared = area.mmap_flags.contains(MmapFlags::MAP_SHARED) || matches!(area.area_type, MapAreaType::Shm { .. });
+        let (offset, name) = match (&area.area_type, &area.fd) {
+            (_, Some(f)) => (f.offset, f.file.any().get_path()),
+            (MapAreaType::Elf, None) => (0, exe.clone()),
+            (MapAreaType::Stack, None) => (0, String::from("[stack]")),
+            (MapAreaType::Brk, None) => (0, String::from("[heap]")),
+            (MapAreaType::Shm { shmid }, None) => (0, format!("/SYSV{:08x} (deleted)", shmid)),
+            _ => (0, String::new()),
+        };
+        let _ = writeln!(
+            s,
+            "{:08x}-{:08x} {}{}{}{} {:08x} 00:00 0 {}",
+            start,
+            end,
+            if perm.contains(MapPermission::R) { 'r' } else { '-' },
+            if perm.contains(MapPermission::W) { 'w' } else { '-' },
+            if perm.contains(MapPermission::X) { 'x' } else { '-' },
+            if shared { 's' } else { 'p' },
+            offset,
+            name
+        );
+    }
+    Ok(s)
+}
+
+fn fd_list(pid: usize) -> Vec<usize> {
+    let Ok(proc) = process(pid) else {
+        return Vec::new();
+    };
+    let Some(table) = proc.fd_table.try_lock() else {
+        return Vec::new();
+    };
+    table
+        .table
+        .iter()
+        .enumerate()
+        .filter(|(_, fd)| fd.is_some())
+        .map(|(i, _)| i)
+        .collect()
+}
+
+pub struct ProcFs {
+    root: Arc<ProcNode>,
+}
+
+impl ProcFs {
+    pub fn new(mount_point: &str) -> Self {
+        Self {
+            root: Arc::new(ProcNode {
+                mount_point: Arc::from(mount_point.trim_end_matches('/')),
+                path: mount_point.to_string(),
+                kind: ProcKind::Root,
+            }),
+        }
+    }
+}
+
+impl VfsOps for ProcFs {
+    fn sync(&mut self) -> GeneralRet {
+        Ok(())
+    }
+
+    fn name(&self) -> String {
+        String::from("proc")
+    }
+
+    fn root_inode(&self) -> Arc<dyn VfsNodeOps> {
+        self.root.clone()
+    }
+
+    fn statfs(&self) -> Result<Statfs, i32> {
+        Ok(Statfs {
+            f_type: PROC_SUPER_MAGIC,
+            f_bsize: PAGE_SIZE as i64,
+            f_name_len: 255,
+            f_frsize: PAGE_SIZE as i64,
+            ..Default::default()
+        })
+    }
+}
+
+pub struct ProcNode {
+    mount_point: Arc<str>,
+    path: String,
+    kind: ProcKind,
+}
+
+impl ProcNode {
+    fn child(&self, name: &str, kind: ProcKind) -> Arc<ProcNode> {
+        Arc::new(ProcNode {
+            mount_point: self.mount_point.clone(),
+            path: format!("{}/{}", self.path.trim_end_matches('/'), name),
+            kind,
+        })
+    }
+
+    /// 目录下的所有条目
+    fn entries(&self) -> Vec<(String, ProcKind)> {
+        match self.kind {
+            ProcKind::Root => {
+                let mut v: Vec<(String, ProcKind)> =
+                    ROOT_ENTRIES.iter().map(|(n, k)| (n.to_string(), *k)).collect();
+                v.extend(PID2PC.lock().keys().map(|pid| (pid.to_string(), ProcKind::PidDir(*pid))));
+                v
+            }
+            ProcKind::LingosDir => LINGOS_ENTRIES.iter().map(|(n, k)| (n.to_string(), *k)).collect(),
+            ProcKind::PidDir(pid) => PID_ENTRIES.iter().map(|(n, k)| (n.to_string(), k(pid))).collect(),
+            ProcKind::FdDir(pid) => fd_list(pid).into_iter().map(|fd| (fd.to_string(), ProcKind::Fd(pid, fd))).collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// 在目录中查找一个名字
+    fn lookup_child(&self, name: &str) -> Option<ProcKind> {
+        match self.kind {
+            ProcKind::Root => ROOT_ENTRIES.iter().find(|(n, _)| *n == name).map(|(_, k)| *k).or_else(|| {
+                let pid = name.parse().ok()?;
+                PID2PC.lock().contains_key(&pid).then_some(ProcKind::PidDir(pid))
+            }),
+            ProcKind::LingosDir => LINGOS_ENTRIES.iter().find(|(n, _)| *n == name).map(|(_, k)| *k),
+            ProcKind::PidDir(pid) => {
+                process(pid).ok()?;
+                PID_ENTRIES.iter().find(|(n, _)| *n == name).map(|(_, k)| k(pid))
+            }
+            ProcKind::FdDir(pid) => {
+                let fd = name.parse().ok()?;
+                fd_list(pid).contains(&fd).then_some(ProcKind::Fd(pid, fd))
+            }
+            _ => None,
+        }
+    }
+
+    fn link_target(&self) -> Result<String, SysErrNo> {
+        match self.kind {
+            ProcKind::SelfLink => Ok(current_process().get_pid().to_string()),
+            ProcKind::PidExe(pid) => {
+                process(pid)?.exe.try_lock().map(|e| e.clone()).ok_or(SysErrNo::EAGAIN)
+            }
+            ProcKind::PidCwd(pid) => {
+                process(pid)?.cwd.try_lock().map(|c| c.clone()).ok_or(SysErrNo::EAGAIN)
+            }
+            ProcKind::Fd(pid, fd) => {
+                let proc = process(pid)?;
+                let table = proc.fd_table.try_lock().ok_or(SysErrNo::EAGAIN)?;
+                let file = table.table.get(fd).and_then(|f| f.as_ref()).ok_or(SysErrNo::ENOENT)?;
+                Ok(file.any().get_path())
+            }
+            _ => Err(SysErrNo::EINVAL),
+        }
+    }
+
+    /// 现场生成文件内容
+    fn content(&self) -> Result<Vec<u8>, SysErrNo> {
+        let s = match self.kind {
+            ProcKind::Meminfo => gen_meminfo(),
+            ProcKind::Mounts => gen_mounts(),
+            ProcKind::Uptime => gen_uptime(),
+            ProcKind::Cpuinfo => gen_cpuinfo(),
+            ProcKind::Stat => gen_stat(),
+            ProcKind::Loadavg => gen_loadavg(),
+            ProcKind::UnimplementedSyscalls => unimplemented_report(),
+            ProcKind::Trace => trace_log(),
+            ProcKind::TracePids => traced_pids(),
+            ProcKind::BlockCache => bcache::report(),
+            ProcKind::Buddyinfo => frame_allocator::buddyinfo(),
+            ProcKind::FrameAllocator => frame_allocator::report(),
+            ProcKind::PidStat(pid) => gen_pid_stat(pid)?,
+            ProcKind::PidStatus(pid) => gen_pid_status(pid)?,
+            ProcKind::PidMaps(pid) => gen_pid_maps(pid)?,
+            ProcKind::PidCmdline(pid) => return gen_pid_cmdline(pid),
+            _ => return Err(SysErrNo::EISDIR),
+        };
+        Ok(s.into_bytes())
+    }
+}
+
+impl VfsNodeOps for ProcNode {
+    fn path(&self) -> String {
+        self.path.clone()
+    }
+
+    fn fstat(&self) -> Kstat {
+        let ty = self.kind.node_type();
+        let perm = match ty {
+            InodeType::Dir => 0o555,
+            InodeType::SymLink => 0o777,
+            _ if self.kind.writable() => 0o644,
+            _ => 0o444,
+        };
+        let t = (get_time_ms() / 1000) as isize;
+        Kstat {
+            st_ino: self.kind.ino(),
+            st_mode: ((ty as u32) << 12) | perm,
+            st_nlink: if ty == InodeType::Dir { 2 } else { 1 },
+            st_blksize: PAGE_SIZE as i32,
+            st_atime: t,
+            st_mtime: t,
+            st_ctime: t,
+            ..Default::default()
+        }
+    }
+
+    fn size(&self) -> usize {
+        self.content().map_or(0, |c| c.len())
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> SyscallRet {
+        let data = self.content()?;
+        let offset = offset as usize;
+        if offset >= data.len() {
+            return Ok(0);
+        }
+        let n = buf.len().min(data.len() - offset);
+        buf[..n].copy_from_slice(&data[offset..offset + n]);
+        Ok(n)
+    }
+
+    fn write_at(&self, _offset: u64, buf: &[u8]) -> Result<usize, i32> {
+        match self.kind {
+            // 写入任何内容都清空跟踪缓冲区
+            ProcKind::Trace => clear_trace_log(),
+            ProcKind::TracePids => {
+                let cmd = core::str::from_utf8(buf).map_err(|_| SysErrNo::EINVAL as i32)?;
+                trace_control(cmd).map_err(|e| e as i32)?;
+            }
+            // 写入块数调整缓存容量，写入 flush 立即写回脏块
+            ProcKind::BlockCache => {
+                let cmd = core::str::from_utf8(buf).map_err(|_| SysErrNo::EINVAL as i32)?.trim();
+                if cmd == "flush" {
+                    bcache::flush_all();
+                } else {
+                    let blocks = cmd.parse::<usize>().map_err(|_| SysErrNo::EINVAL as i32)?;
+                    bcache::set_capacity(blocks);
+                }
+            }
+            _ => return Err(SysErrNo::EACCES as i32),
+        }
+        Ok(buf.len())
+    }
+
+    fn truncate(&self, _size: u64) -> Result<usize, i32> {
+        // 控制文件允许 O_TRUNC 打开，方便 shell 重定向写入
+        if self.kind.writable() {
+            return Ok(0);
+        }
+        Err(SysErrNo::EACCES as i32)
+    }
+
+    fn fsync(&self) -> Result<usize, i32> {
+        Ok(0)
+    }
+
+    fn sync(&self) {}
+
+    fn create(&self, _path: &str, _ty: InodeTypes) -> Result<usize, i32> {
+        Err(SysErrNo::EACCES as i32)
+    }
+
+    fn unlink(&self, _path: &str) -> SyscallRet {
+        Err(SysErrNo::EPERM)
+    }
+
+    fn rename(&self, _src_path: &str, _dst_path: &str) -> Result<usize, i32> {
+        Err(SysErrNo::EPERM as i32)
+    }
+
+    fn read_dentry(&self, off: usize, len: usize) -> Result<(Vec<u8>, isize), SysErrNo> {
+        if self.kind.node_type() != InodeType::Dir {
+            return Err(SysErrNo::ENOTDIR);
+        }
+        let entries = [
+            (String::from("."), self.kind),
+            (String::from(".."), ProcKind::Root),
+        ]
+        .into_iter()
+        .chain(self.entries());
+        let mut de: Vec<u8> = Vec::new();
+        let mut f_off = -1isize;
+        for (idx, (name, kind)) in entries.enumerate().skip(off) {
+            let dirent = Dirent::new(name, idx as i64 + 1, kind.ino() as u64, kind.node_type() as u8);
+            if de.len() + dirent.len() > len {
+                f_off = idx as isize;
+                break;
+            }
+            de.extend_from_slice(dirent.as_bytes());
+            f_off = dirent.off() as isize;
+        }
+        Ok((de, f_off))
+    }
+
+    fn read_link(&self, buf: &mut [u8], bufsize: usize) -> SyscallRet {
+        let target = self.link_target()?;
+        let n = target.len().min(bufsize).min(buf.len());
+        buf[..n].copy_from_slice(&target.as_bytes()[..n]);
+        Ok(n)
+    }
+
+    fn as_any(&self) -> &dyn core::any::Any {
+        self
+    }
+
+    fn find(
+        &self,
+        path: &str,
+        flags: OpenFlags,
+        _loop_times: usize,
+    ) -> Result<Arc<dyn VfsNodeOps>, SysErrNo> {
+        if !is_path_prefix(&self.mount_point, path) {
+            return Err(SysErrNo::ENOENT);
+        }
+        let rel = &path[self.mount_point.len()..];
+        let mut node = Arc::new(ProcNode {
+            mount_point: self.mount_point.clone(),
+            path: if self.mount_point.is_empty() { String::from("/") } else { self.mount_point.to_string() },
+            kind: ProcKind::Root,
+        });
+        for name in rel.split('/').filter(|c| !c.is_empty() && *c != ".") {
+            if node.kind.node_type() != InodeType::Dir {
+                return Err(SysErrNo::ENOTDIR);
+            }
+            let kind = node.lookup_child(name).ok_or(SysErrNo::ENOENT)?;
+            node = node.child(name, kind);
+        }
+        if flags.contains(OpenFlags::O_DIRECTORY) && node.kind.node_type() != InodeType::Dir {
+            return Err(SysErrNo::ENOTDIR);
+        }
+        Ok(node)
+    }
+
+    fn fmode(&self) -> Result<u32, SysErrNo> {
+        Ok(self.fstat().st_mode)
+    }
+
+    fn fmode_set(&self, _mode: u32) -> SyscallRet {
+        Err(SysErrNo::EPERM)
+    }
+
+    fn set_owner(&self, _uid: u32, _gid: u32) -> SyscallRet {
+        Err(SysErrNo::EPERM)
+    }
+
+    fn set_timestamps(&self, _atime: Option<u32>, _mtime: Option<u32>, _ctime: Option<u32>) -> SyscallRet {
+        Ok(0)
+    }
+
+    fn is_dir(&self) -> bool {
+        self.kind.node_type() == InodeType::Dir
+    }
+
+    fn is_symlink(&self) -> bool {
+        self.kind.node_type() == InodeType::SymLink
+    }
+
+    fn is_dynamic(&self) -> bool {
+        true
+    }
+
+    fn link_cnt(&self) -> SyscallRet {
+        Ok(self.fstat().st_nlink as usize)
+    }
+
+    fn delay(&self) {}
+
+    fn if_delay(&self) -> bool {
+        false
+    }
+}
@@ -0,0 +1,212 @@
+//! signalfd：以读 fd 的方式接收信号
+//!
+//! read 从调用线程的挂起信号（线程私有的 `sigpending` 和进程共享的
+//! `shared_sigpending`）中取走属于掩码的信号，每个信号返回一个
+//! `SignalfdSiginfo`。被取走的信号不会再走信号处理函数，所以用户一般会先用
+//! sigprocmask 屏蔽这些信号。信号到达时 `send_signal` 会唤醒目标任务，
+//! 阻塞在 read 或 poll 上的任务由此重新检查。
+
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use core::task::Waker;
+
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec::Vec;
+use async_trait::async_trait;
+
+use super::stat::StMode;
+use super::{wait_poll, File, Kstat, PollEvents};
+use crate::mm::UserBuffer;
+use crate::signal::{signal_pending, SigSet, Signal, NSIG};
+use crate::task::current_task;
+use crate::utils::error::{SysErrNo, TemplateRet};
+
+pub const SFD_CLOEXEC: u32 = 0o2000000;
+pub const SFD_NONBLOCK: u32 = 0o4000;
+
+/// 用户态的 struct signalfd_siginfo，固定 128 字节
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct SignalfdSiginfo {
+    pub ssi_signo: u32,
+    pub ssi_errno: i32,
+    pub ssi_code: i32,
+    pub ssi_pid: u32,
+    pub ssi_uid: u32,
+    pub ssi_fd: i32,
+    pub ssi_tid: u32,
+    pub ssi_band: u32,
+    pub ssi_overrun: u32,
+    pub ssi_trapno: u32,
+    pub ssi_status: i32,
+    pub ssi_int: i32,
+    pub ssi_ptr: u64,
+    pub ssi_utime: u64,
+    pub ssi_stime: u64,
+    pub ssi_addr: u64,
+    pub ssi_addr_lsb: u16,
+    pub pad2: u16,
+    pub ssi_syscall: i32,
+    pub ssi_call_addr: u64,
+    pub ssi_arch: u32,
+    pub pad: [u8; 28],
+}
+
+impl SignalfdSiginfo {
+    fn new(sig: Signal) -> Self {
+        // 目前不记录发送者信息，ssi_code 为 SI_USER
+        let mut info: Self = unsafe { core::mem::zeroed() };
+        info.ssi_signo = sig as u32;
+        info
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        unsafe {
+            core::slice::from_raw_parts(
+                self as *const Self as *const u8,
+                core::mem::size_of::<Self>(),
+            )
+        }
+    }
+}
+
+pub struct SignalFd {
+    mask: AtomicU64,
+    nonblock: AtomicBool,
+}
+
+impl SignalFd {
+    pub fn new(mask: SigSet, flags: u32) -> Self {
+        Self {
+            mask: AtomicU64::new(Self::effective(mask).bits),
+            nonblock: AtomicBool::new(flags & SFD_NONBLOCK != 0),
+        }
+    }
+
+    /// SIGKILL 和 SIGSTOP 不能通过 signalfd 接收
+    fn effective(mut mask: SigSet) -> SigSet {
+        mask.remove(Signal::SIGKILL);
+        mask.remove(Signal::SIGSTOP);
+        mask
+    }
+
+    /// signalfd4 传入已有的 fd 时更新掩码
+    pub fn set_mask(&self, mask: SigSet) {
+        self.mask.store(Self::effective(mask).bits, Ordering::Relaxed);
+    }
+
+    fn mask(&self) -> SigSet {
+        SigSet {
+            bits: self.mask.load(Ordering::Relaxed),
+        }
+    }
+
+    /// 从当前线程的挂起信号中取走最多 max 个属于掩码的信号
+    async fn dequeue(&self, max: usize) -> Vec<Signal> {
+        let mask = self.mask();
+        let task = current_task();
+        let Some(pcb) = task.get_process() else {
+            return Vec::new();
+        };
+        let mut task_state = task.signal_state.lock().await;
+        let mut process_state = pcb.signal_shared_state.lock().await;
+        let mut signals = Vec::new();
+        for signum in 1..NSIG {
+            if signals.len() >= max {
+                break;
+            }
+            let Some(sig) = Signal::from_usize(signum) else {
+                continue;
+            };
+            if !mask.contains(sig) {
+                continue;
+            }
+            if task_state.sigpending.contains(sig) {
+                task_state.sigpending.remove(sig);
+                signals.push(sig);
+            } else if process_state.shared_sigpending.contains(sig) {
+                process_state.shared_sigpending.remove(sig);
+                signals.push(sig);
+            }
+        }
+        signals
+    }
+}
+
+#[async_trait]
+impl File for SignalFd {
+    fn as_any(&self) -> &dyn core::any::Any {
+        self
+    }
+    fn get_path(&self) -> String {
+        String::from("anon_inode:[signalfd]")
+    }
+    fn readable<'a>(&'a self) -> TemplateRet<bool> {
+        Ok(true)
+    }
+    fn writable<'a>(&'a self) -> TemplateRet<bool> {
+        Ok(false)
+    }
+
+    async fn read<'a>(&self, mut buf: UserBuffer<'a>) -> Result<usize, SysErrNo> {
+        let size = core::mem::size_of::<SignalfdSiginfo>();
+        let max = buf.len() / size;
+        if max == 0 {
+            return Err(SysErrNo::EINVAL);
+        }
+        loop {
+            let signals = self.dequeue(max).await;
+            if !signals.is_empty() {
+                let mut bytes = Vec::with_capacity(signals.len() * size);
+                for sig in signals {
+                    bytes.extend_from_slice(SignalfdSiginfo::new(sig).as_bytes());
+                }
+                return Ok(buf.write(&bytes));
+            }
+            if self.nonblock.load(Ordering::Relaxed) {
+                return Err(SysErrNo::EAGAIN);
+            }
+            // 不在掩码里、也没被屏蔽的信号需要先交给信号处理
+            if signal_pending().await {
+                return Err(SysErrNo::ERESTART);
+            }
+            wait_poll(self, PollEvents::POLLIN).await;
+        }
+    }
+
+    fn poll(&self, events: PollEvents, _waker_to_register: &Waker) -> PollEvents {
+        // 信号到达时 send_signal 直接唤醒目标任务，这里不需要登记 waker
+        if !events.contains(PollEvents::POLLIN) {
+            return PollEvents::empty();
+        }
+        let task = current_task();
+        let Some(pcb) = task.get_process() else {
+            return PollEvents::empty();
+        };
+        let mut pending = match task.signal_state.try_lock() {
+            Some(state) => state.sigpending,
+            None => return PollEvents::empty(),
+        };
+        if let Some(state) = pcb.signal_shared_state.try_lock() {
+            pending.union_with(&state.shared_sigpending);
+        }
+        pending.intersect_with(&self.mask());
+        if pending.is_empty() {
+            PollEvents::empty()
+        } else {
+            PollEvents::POLLIN
+        }
+    }
+
+    fn set_nonblock(&self, nonblock: bool) {
+        self.nonblock.store(nonblock, Ordering::Relaxed);
+    }
+
+    fn fstat(&self) -> Kstat {
+        Kstat {
+            st_mode: StMode::FREG.bits() | 0o600,
+            st_nlink: 1,
+            ..Kstat::default()
+        }
+    }
+}
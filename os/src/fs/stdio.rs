@@ -4,28 +4,28 @@ use core::task::Waker;
 
 use alloc::boxed::Box;
 use alloc::string::String;
-use alloc::vec::Vec;
 use async_trait::async_trait;
 
 use super::stat::StMode;
+use super::tty::{output, tty_ioctl, tty_poll_readable, tty_read};
 use super::{File, Kstat, PollEvents};
 use crate::mm::UserBuffer;
-use crate::sbi::console_getchar;
-use crate::task:: yield_now ;
-use crate::utils::error::{ SysErrNo, TemplateRet} ;
+use crate::utils::error::{SysErrNo, SyscallRet, TemplateRet};
 
 /// stdin file for getting chars from console
 pub struct Stdin;
 
-const LF: usize = 0x0a;
-const CR: usize = 0x0d;
 /// stdout file for putting chars to console
 pub struct Stdout;
+
 #[async_trait]
 impl File for Stdin {
     fn as_any(&self) -> &dyn core::any::Any {
         self
     }
+    fn get_path(&self) -> String {
+        String::from("/dev/console")
+    }
     fn readable<'a>(&'a self) -> TemplateRet<bool> {
       Ok(true) 
     }
@@ -34,40 +34,9 @@ impl File for Stdin {
     }
     async fn read<'a>( 
         & self,                
-        mut user_buf: UserBuffer<'a>  
+        user_buf: UserBuffer<'a>  
     ) -> Result<usize, SysErrNo>{
-     
-
-        let mut c: usize;
-        let mut count: usize = 0;
-        let mut buf = Vec::new();
-        while count < user_buf.len() {
-            c = console_getchar();
-            match c {
-                // `c > 255`是为了兼容OPENSBI，OPENSBI未获取字符时会返回-1
-                0 | 256.. => {
-                    yield_now().await;
-                    continue;
-                }
-                CR => {
-                    buf.push(LF as u8);
-                    count += 1;
-                    break;
-                }
-                LF => {
-                    buf.push(LF as u8);
-                    count += 1;
-                    break;
-                }
-                _ => {
-                    buf.push(c as u8);
-                    count += 1;
-                }
-            }
-        }
-        user_buf.write(buf.as_slice());
-        Ok(count)
-       
+        tty_read(user_buf).await
     }
     async fn write<'buf>(&self, buf: UserBuffer<'buf>) -> Result<usize, SysErrNo>{
         panic!("Cannot write to stdin!");
@@ -75,18 +44,14 @@ impl File for Stdin {
    
     fn poll(&self, events: PollEvents, waker_to_register: &Waker) -> PollEvents {
         let mut revents = PollEvents::empty();
-        
-        
-        if events.contains(PollEvents::POLLIN) {
-            if self.readable().unwrap(){ 
-                revents.insert(PollEvents::POLLIN);
-            
+        if events.contains(PollEvents::POLLIN) && tty_poll_readable(waker_to_register) {
+            revents.insert(PollEvents::POLLIN);
         }
+        revents
     }
-      
 
-       
-        return revents;
+    async fn ioctl(&self, cmd: usize, arg: usize) -> SyscallRet {
+        tty_ioctl(cmd, arg).await
     }
 
     fn fstat(&self) -> Kstat {
@@ -104,6 +69,9 @@ impl File for Stdout {
     fn as_any(&self) -> &dyn core::any::Any {
         self
     }
+    fn get_path(&self) -> String {
+        String::from("/dev/console")
+    }
     fn readable<'a>(&'a self) -> TemplateRet<bool> {
         Ok(false) 
     }
@@ -118,12 +86,13 @@ impl File for Stdout {
     }
     async fn write<'buf>(&self, buf: UserBuffer<'buf>) -> Result<usize, SysErrNo> {
         for buffer in buf.buffers.iter() {
-            // Use from_utf8_lossy to safely handle any byte sequence.
-            // Invalid bytes will be replaced with '�'.
-            print!("{}", String::from_utf8_lossy(*buffer));
+            output(buffer);
         }
         Ok(buf.len())
     }
+    async fn ioctl(&self, cmd: usize, arg: usize) -> SyscallRet {
+        tty_ioctl(cmd, arg).await
+    }
     fn fstat(&self) -> Kstat {
         Kstat {
             st_mode: StMode::FCHR.bits(),
@@ -0,0 +1,268 @@
+//! timerfd：通过 fd 读取定时器到期次数
+//!
+//! 到期次数按时间惰性计算，不需要周期性的内核定时器。有人等待（read 阻塞或
+//! poll/epoll 登记了 waker）而定时器还没到期时，往 `sleeplist` 的全局睡眠队列里
+//! 挂一个下次到期时间的节点，到期后由它唤醒所有等待者；周期定时器的下一次
+//! 到期在等待者重新 poll 时再挂上。
+
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::task::Waker;
+
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::task::Wake;
+use alloc::vec::Vec;
+use async_trait::async_trait;
+use spin::Mutex;
+
+use super::stat::StMode;
+use super::{wait_poll, File, Kstat, PollEvents};
+use crate::mm::UserBuffer;
+use crate::signal::signal_pending;
+use crate::task::sleeplist::{SleepNode, GLOBAL_SLEEPER_QUEUE};
+use crate::timer::{get_time_ns, TimeVal, UserTimeSpec};
+use crate::utils::error::{SysErrNo, TemplateRet};
+
+pub const CLOCK_REALTIME: usize = 0;
+pub const CLOCK_MONOTONIC: usize = 1;
+pub const CLOCK_BOOTTIME: usize = 7;
+
+pub const TFD_CLOEXEC: u32 = 0o2000000;
+pub const TFD_NONBLOCK: u32 = 0o4000;
+pub const TFD_TIMER_ABSTIME: u32 = 1;
+pub const TFD_TIMER_CANCEL_ON_SET: u32 = 2;
+
+const NSEC_PER_SEC: u64 = 1_000_000_000;
+
+/// 用户态的 struct itimerspec
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ITimerSpec {
+    pub it_interval: UserTimeSpec,
+    pub it_value: UserTimeSpec,
+}
+
+fn timespec_to_ns(ts: &UserTimeSpec) -> Result<u64, SysErrNo> {
+    if ts.tv_nsec >= NSEC_PER_SEC as usize {
+        return Err(SysErrNo::EINVAL);
+    }
+    Ok(ts.tv_sec as u64 * NSEC_PER_SEC + ts.tv_nsec as u64)
+}
+
+fn ns_to_timespec(ns: u64) -> UserTimeSpec {
+    UserTimeSpec {
+        tv_sec: (ns / NSEC_PER_SEC) as usize,
+        tv_nsec: (ns % NSEC_PER_SEC) as usize,
+    }
+}
+
+/// 到期时由睡眠队列唤醒，转而唤醒 timerfd 的所有等待者
+struct TimerWake {
+    waiters: Arc<Mutex<Vec<Waker>>>,
+}
+
+impl Wake for TimerWake {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        for waker in self.waiters.lock().drain(..) {
+            waker.wake();
+        }
+    }
+}
+
+struct TimerFdInner {
+    /// 下一次到期的时刻（纳秒），None 表示未启动
+    next: Option<u64>,
+    /// 周期，0 表示一次性定时器
+    interval: u64,
+    /// 上次 read 之后累计的到期次数
+    expirations: u64,
+    /// 已挂到睡眠队列上的节点及其到期时刻
+    queued: Option<(u64, Arc<SleepNode>)>,
+}
+
+impl TimerFdInner {
+    /// 把已经过去的到期折算进 expirations
+    fn update(&mut self, now: u64) {
+        let Some(next) = self.next else {
+            return;
+        };
+        if now < next {
+            return;
+        }
+        if self.interval == 0 {
+            self.expirations += 1;
+            self.next = None;
+        } else {
+            let n = (now - next) / self.interval + 1;
+            self.expirations += n;
+            self.next = Some(next + n * self.interval);
+        }
+    }
+
+    fn dequeue(&mut self) {
+        if let Some((_, node)) = self.queued.take() {
+            GLOBAL_SLEEPER_QUEUE.lock().remove_sleeper(&node);
+        }
+    }
+}
+
+pub struct TimerFd {
+    inner: Mutex<TimerFdInner>,
+    waiters: Arc<Mutex<Vec<Waker>>>,
+    nonblock: AtomicBool,
+}
+
+impl TimerFd {
+    pub fn new(flags: u32) -> Self {
+        Self {
+            inner: Mutex::new(TimerFdInner {
+                next: None,
+                interval: 0,
+                expirations: 0,
+                queued: None,
+            }),
+            waiters: Arc::new(Mutex::new(Vec::new())),
+            nonblock: AtomicBool::new(flags & TFD_NONBLOCK != 0),
+        }
+    }
+
+    /// 当前设置，值为距下次到期的剩余时间
+    fn current(inner: &TimerFdInner, now: u64) -> ITimerSpec {
+        ITimerSpec {
+            it_interval: ns_to_timespec(inner.interval),
+            it_value: ns_to_timespec(inner.next.map_or(0, |next| next.saturating_sub(now))),
+        }
+    }
+
+    /// timerfd_gettime
+    pub fn get_time(&self) -> ITimerSpec {
+        let now = get_time_ns() as u64;
+        let mut inner = self.inner.lock();
+        inner.update(now);
+        Self::current(&inner, now)
+    }
+
+    /// timerfd_settime，返回旧的设置
+    pub fn set_time(&self, flags: u32, new: &ITimerSpec) -> Result<ITimerSpec, SysErrNo> {
+        let value = timespec_to_ns(&new.it_value)?;
+        let interval = timespec_to_ns(&new.it_interval)?;
+        let now = get_time_ns() as u64;
+        let mut inner = self.inner.lock();
+        inner.update(now);
+        let old = Self::current(&inner, now);
+        inner.dequeue();
+        inner.expirations = 0;
+        inner.interval = interval;
+        inner.next = match value {
+            0 => None,
+            // CLOCK_REALTIME 和 CLOCK_MONOTONIC 目前共用同一个时间基准
+            v if flags & TFD_TIMER_ABSTIME != 0 => Some(v),
+            v => Some(now + v),
+        };
+        drop(inner);
+        // 让已经在等待的任务按新的设置重新检查
+        for waker in self.waiters.lock().drain(..) {
+            waker.wake();
+        }
+        Ok(old)
+    }
+
+    fn take_expirations(&self) -> Option<u64> {
+        let mut inner = self.inner.lock();
+        inner.update(get_time_ns() as u64);
+        match core::mem::take(&mut inner.expirations) {
+            0 => None,
+            n => Some(n),
+        }
+    }
+}
+
+impl Drop for TimerFd {
+    fn drop(&mut self) {
+        self.inner.lock().dequeue();
+    }
+}
+
+#[async_trait]
+impl File for TimerFd {
+    fn as_any(&self) -> &dyn core::any::Any {
+        self
+    }
+    fn get_path(&self) -> String {
+        String::from("anon_inode:[timerfd]")
+    }
+    fn readable<'a>(&'a self) -> TemplateRet<bool> {
+        Ok(true)
+    }
+    fn writable<'a>(&'a self) -> TemplateRet<bool> {
+        Ok(false)
+    }
+
+    async fn read<'a>(&self, mut buf: UserBuffer<'a>) -> Result<usize, SysErrNo> {
+        if buf.len() < 8 {
+            return Err(SysErrNo::EINVAL);
+        }
+        loop {
+            if let Some(n) = self.take_expirations() {
+                return Ok(buf.write(&n.to_ne_bytes()));
+            }
+            if self.nonblock.load(Ordering::Relaxed) {
+                return Err(SysErrNo::EAGAIN);
+            }
+            if signal_pending().await {
+                return Err(SysErrNo::ERESTART);
+            }
+            wait_poll(self, PollEvents::POLLIN).await;
+        }
+    }
+
+    fn poll(&self, events: PollEvents, waker_to_register: &Waker) -> PollEvents {
+        if !events.contains(PollEvents::POLLIN) {
+            return PollEvents::empty();
+        }
+        let mut inner = self.inner.lock();
+        inner.update(get_time_ns() as u64);
+        if inner.expirations > 0 {
+            return PollEvents::POLLIN;
+        }
+        {
+            let mut waiters = self.waiters.lock();
+            if !waiters.iter().any(|w| w.will_wake(waker_to_register)) {
+                waiters.push(waker_to_register.clone());
+            }
+        }
+        // 未启动的定时器只有重新 settime 时才会唤醒等待者
+        let Some(next) = inner.next else {
+            return PollEvents::empty();
+        };
+        if inner.queued.as_ref().map(|(at, _)| *at) != Some(next) {
+            inner.dequeue();
+            // TimeVal 只有微秒精度，向上取整保证节点被唤醒时定时器确实已到期
+            let deadline = TimeVal::from_ns(next + 999);
+            let waker = Waker::from(Arc::new(TimerWake {
+                waiters: self.waiters.clone(),
+            }));
+            let node = Arc::new(SleepNode::new(Some(deadline), waker, 0));
+            GLOBAL_SLEEPER_QUEUE.lock().add_sleeper(node.clone());
+            inner.queued = Some((next, node));
+        }
+        PollEvents::empty()
+    }
+
+    fn set_nonblock(&self, nonblock: bool) {
+        self.nonblock.store(nonblock, Ordering::Relaxed);
+    }
+
+    fn fstat(&self) -> Kstat {
+        Kstat {
+            st_mode: StMode::FREG.bits() | 0o600,
+            st_nlink: 1,
+            ..Kstat::default()
+        }
+    }
+}
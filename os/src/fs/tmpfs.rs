@@ -0,0 +1,734 @@
+//! 基于内存的 tmpfs
+//!
+//! 所有文件内容和目录树都保存在内核堆中，卸载或重启后即全部丢失。
+//! 容量和 inode 数量由挂载选项 `size=`、`nr_inodes=` 限制，超出时返回 ENOSPC。
+
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use lwext4_rust::InodeTypes;
+use spin::{Mutex, MutexGuard, Once};
+
+use crate::config::{PAGE_SIZE, TMPFS_DEFAULT_INODES, TMPFS_DEFAULT_SIZE};
+use crate::fs::inode::InodeType;
+use crate::fs::mount::is_path_prefix;
+use crate::fs::stat::Kstat;
+use crate::fs::vfs::vfs_ops::{VfsNodeOps, VfsOps};
+use crate::fs::vfs::VfsManager;
+use crate::fs::{as_inode_type, Dirent, OpenFlags, Statfs};
+use crate::timer::get_time_ms;
+use crate::utils::error::{GeneralRet, SysErrNo, SyscallRet};
+use crate::utils::string::get_parent_path_and_filename;
+
+const TMPFS_MAGIC: i64 = 0x0102_1994;
+const TMPFS_NAME_LEN: usize = 255;
+
+/// 每个 tmpfs 实例分配一个独立的设备号，stat 时区分不同的挂载
+static NEXT_DEV: AtomicUsize = AtomicUsize::new(0x20);
+
+fn now() -> u32 {
+    (get_time_ms() / 1000) as u32
+}
+
+/// `mount -t tmpfs -o ...` 支持的选项
+struct TmpfsOptions {
+    size: usize,
+    nr_inodes: usize,
+    mode: u32,
+}
+
+impl TmpfsOptions {
+    /// 解析形如 `size=16m,nr_inodes=1k,mode=1777` 的选项串，不认识的选项忽略
+    fn parse(data: Option<&str>) -> Result<Self, SysErrNo> {
+        let mut opts = Self {
+            size: TMPFS_DEFAULT_SIZE,
+            nr_inodes: TMPFS_DEFAULT_INODES,
+            mode: 0o1777,
+        };
+        for opt in data.unwrap_or("").split(',').filter(|s| !s.is_empty()) {
+            let (key, value) = opt.split_once('=').unwrap_or((opt, ""));
+            match key {
+                "size" => opts.size = parse_size(value)?,
+                "nr_inodes" => opts.nr_inodes = parse_size(value)?,
+                "mode" => {
+                    opts.mode = u32::from_str_radix(value, 8).map_err(|_| SysErrNo::EINVAL)? & 0o7777
+                }
+                _ => warn!("[tmpfs] ignore mount option '{}'", opt),
+            }
+        }
+        Ok(opts)
+    }
+}
+
+/// 解析带 k/m/g 后缀的数值
+fn parse_size(value: &str) -> Result<usize, SysErrNo> {
+    let (num, shift) = match value.as_bytes().last() {
+        Some(b'k' | b'K') => (&value[..value.len() - 1], 10),
+        Some(b'm' | b'M') => (&value[..value.len() - 1], 20),
+        Some(b'g' | b'G') => (&value[..value.len() - 1], 30),
+        _ => (value, 0),
+    };
+    let num: usize = num.parse().map_err(|_| SysErrNo::EINVAL)?;
+    num.checked_mul(1 << shift).ok_or(SysErrNo::EINVAL)
+}
+
+/// 一个 tmpfs 实例内所有节点共享的信息
+struct TmpSuper {
+    mount_point: String,
+    dev: usize,
+    max_bytes: usize,
+    max_inodes: usize,
+    used_bytes: AtomicUsize,
+    used_inodes: AtomicUsize,
+    next_ino: AtomicUsize,
+    root: Once<Weak<TmpNode>>,
+    /// 串行化 rename/exchange。目录只会被它们移动，持有该锁时祖先关系不会改变
+    rename_lock: Mutex<()>,
+}
+
+impl TmpSuper {
+    fn reserve(counter: &AtomicUsize, limit: usize, n: usize) -> Result<(), SysErrNo> {
+        counter
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |used| {
+                used.checked_add(n).filter(|&v| v <= limit)
+            })
+            .map(|_| ())
+            .map_err(|_| SysErrNo::ENOSPC)
+    }
+
+    fn reserve_bytes(&self, n: usize) -> Result<(), SysErrNo> {
+        Self::reserve(&self.used_bytes, self.max_bytes, n)
+    }
+
+    fn release_bytes(&self, n: usize) {
+        self.used_bytes.fetch_sub(n, Ordering::AcqRel);
+    }
+
+    fn root(&self) -> Arc<TmpNode> {
+        self.root.get().and_then(Weak::upgrade).expect("tmpfs root dropped")
+    }
+
+    /// 在本实例中按绝对路径查找节点，不跟随符号链接
+    fn walk(&self, abs_path: &str) -> Result<Arc<TmpNode>, SysErrNo> {
+        if !is_path_prefix(&self.mount_point, abs_path) {
+            return Err(SysErrNo::EXDEV);
+        }
+        let rel = &abs_path[self.mount_point.trim_end_matches('/').len()..];
+        let mut node = self.root();
+        for name in rel.split('/').filter(|c| !c.is_empty() && *c != ".") {
+            let next = {
+                let inner = node.inner.lock();
+                if node.ty != InodeType::Dir {
+                    return Err(SysErrNo::ENOTDIR);
+                }
+                if name == ".." {
+                    inner.parent.upgrade().unwrap_or_else(|| node.clone())
+                } else {
+                    inner.children.get(name).cloned().ok_or(SysErrNo::ENOENT)?
+                }
+            };
+            node = next;
+        }
+        Ok(node)
+    }
+
+    /// 查找 `abs_path` 的父目录，返回父目录和最后一级名字
+    fn walk_parent(&self, abs_path: &str) -> Result<(Arc<TmpNode>, String), SysErrNo> {
+        let (parent, name) = get_parent_path_and_filename(abs_path);
+        if name.len() > TMPFS_NAME_LEN {
+            return Err(SysErrNo::ENAMETOOLONG);
+        }
+        let dir = self.walk(&parent)?;
+        if dir.ty != InodeType::Dir {
+            return Err(SysErrNo::ENOTDIR);
+        }
+        Ok((dir, name))
+    }
+}
+
+pub struct TmpFs {
+    root: Arc<TmpNode>,
+    sb: Arc<TmpSuper>,
+}
+
+impl TmpFs {
+    pub fn new(mount_point: &str, data: Option<&str>) -> Result<Self, SysErrNo> {
+        let opts = TmpfsOptions::parse(data)?;
+        let sb = Arc::new(TmpSuper {
+            mount_point: mount_point.to_string(),
+            dev: NEXT_DEV.fetch_add(1, Ordering::Relaxed),
+            max_bytes: opts.size,
+            max_inodes: opts.nr_inodes,
+            used_bytes: AtomicUsize::new(0),
+            used_inodes: AtomicUsize::new(0),
+            next_ino: AtomicUsize::new(1),
+            root: Once::new(),
+            rename_lock: Mutex::new(()),
+        });
+        let root = TmpNode::new(&sb, InodeType::Dir, String::new(), Weak::new(), opts.mode)?;
+        sb.root.call_once(|| Arc::downgrade(&root));
+        info!(
+            "[tmpfs] mount on {} size={} nr_inodes={}",
+            mount_point, opts.size, opts.nr_inodes
+        );
+        Ok(Self { root, sb })
+    }
+}
+
+impl VfsOps for TmpFs {
+    fn sync(&mut self) -> GeneralRet {
+        Ok(())
+    }
+
+    fn name(&self) -> String {
+        String::from("tmpfs")
+    }
+
+    fn root_inode(&self) -> Arc<dyn VfsNodeOps> {
+        self.root.clone()
+    }
+
+    fn statfs(&self) -> Result<Statfs, i32> {
+        let blocks = self.sb.max_bytes / PAGE_SIZE;
+        let used = self.sb.used_bytes.load(Ordering::Acquire).div_ceil(PAGE_SIZE);
+        let files = self.sb.max_inodes;
+        let ffree = files - self.sb.used_inodes.load(Ordering::Acquire).min(files);
+        Ok(Statfs {
+            f_type: TMPFS_MAGIC,
+            f_bsize: PAGE_SIZE as i64,
+            f_blocks: blocks as i64,
+            f_bfree: blocks.saturating_sub(used) as i64,
+            f_bavail: blocks.saturating_sub(used) as i64,
+            f_files: files as i64,
+            f_ffree: ffree as i64,
+            f_fsid: self.sb.dev as i64,
+            f_name_len: TMPFS_NAME_LEN as i64,
+            f_frsize: PAGE_SIZE as i64,
+            f_flags: 0,
+            f_spare: [0; 4],
+        })
+    }
+}
+
+struct TmpNodeInner {
+    name: String,
+    parent: Weak<TmpNode>,
+    mode: u32,
+    uid: u32,
+    gid: u32,
+    nlink: u32,
+    atime: u32,
+    mtime: u32,
+    ctime: u32,
+    /// 普通文件的内容，或符号链接的目标
+    data: Vec<u8>,
+    children: BTreeMap<String, Arc<TmpNode>>,
+}
+
+pub struct TmpNode {
+    sb: Arc<TmpSuper>,
+    ino: usize,
+    ty: InodeType,
+    inner: Mutex<TmpNodeInner>,
+}
+
+impl TmpNodeInner {
+    /// 把名为 `name` 的子节点从本目录摘下，调用者持有本目录的锁
+    fn take_child(&mut self, name: &str) -> Option<Arc<TmpNode>> {
+        let child = self.children.remove(name)?;
+        if child.ty == InodeType::Dir {
+            self.nlink -= 1;
+        }
+        self.mtime = now();
+        self.ctime = self.mtime;
+        let mut c = child.inner.lock();
+        c.parent = Weak::new();
+        c.nlink = 0;
+        drop(c);
+        Some(child)
+    }
+
+    /// 把 `child` 以 `name` 挂到本目录 `dir` 下，覆盖同名节点，调用者持有本目录的锁
+    fn put_child(&mut self, dir: &Arc<TmpNode>, name: &str, child: Arc<TmpNode>) {
+        {
+            let mut c = child.inner.lock();
+            c.name = name.to_string();
+            c.parent = Arc::downgrade(dir);
+            c.nlink = if child.ty == InodeType::Dir { 2 + c.children.values().filter(|n| n.ty == InodeType::Dir).count() as u32 } else { 1 };
+            c.ctime = now();
+        }
+        if child.ty == InodeType::Dir {
+            self.nlink += 1;
+        }
+        if let Some(old) = self.children.insert(name.to_string(), child) {
+            if old.ty == InodeType::Dir {
+                self.nlink -= 1;
+            }
+            let mut o = old.inner.lock();
+            o.parent = Weak::new();
+            o.nlink = 0;
+        }
+        self.mtime = now();
+        self.ctime = self.mtime;
+    }
+}
+
+impl TmpNode {
+    fn new(
+        sb: &Arc<TmpSuper>,
+        ty: InodeType,
+        name: String,
+        parent: Weak<TmpNode>,
+        mode: u32,
+    ) -> Result<Arc<Self>, SysErrNo> {
+        TmpSuper::reserve(&sb.used_inodes, sb.max_inodes, 1)?;
+        let t = now();
+        Ok(Arc::new(Self {
+            sb: sb.clone(),
+            ino: sb.next_ino.fetch_add(1, Ordering::Relaxed),
+            ty,
+            inner: Mutex::new(TmpNodeInner {
+                name,
+                parent,
+                mode,
+                uid: 0,
+                gid: 0,
+                nlink: if ty == InodeType::Dir { 2 } else { 1 },
+                atime: t,
+                mtime: t,
+                ctime: t,
+                data: Vec::new(),
+                children: BTreeMap::new(),
+            }),
+        }))
+    }
+
+    /// 在本目录下新建名为 `name` 的子节点，已存在时返回 EEXIST
+    fn add_child(self: &Arc<Self>, name: &str, ty: InodeType, data: &[u8]) -> Result<Arc<TmpNode>, SysErrNo> {
+        if name.len() > TMPFS_NAME_LEN {
+            return Err(SysErrNo::ENAMETOOLONG);
+        }
+        if self.inner.lock().children.contains_key(name) {
+            return Err(SysErrNo::EEXIST);
+        }
+        let mode = if ty == InodeType::Dir { 0o755 } else { 0o644 };
+        let child = TmpNode::new(&self.sb, ty, name.to_string(), Arc::downgrade(self), mode)?;
+        if !data.is_empty() {
+            self.sb.reserve_bytes(data.len())?;
+            child.inner.lock().data.extend_from_slice(data);
+        }
+        let mut inner = self.inner.lock();
+        if inner.children.contains_key(name) {
+            return Err(SysErrNo::EEXIST);
+        }
+        if ty == InodeType::Dir {
+            inner.nlink += 1;
+        }
+        inner.children.insert(name.to_string(), child.clone());
+        inner.mtime = now();
+        inner.ctime = inner.mtime;
+        Ok(child)
+    }
+
+    /// 把名为 `name` 的子节点从本目录摘下，非空目录返回 ENOTEMPTY
+    fn detach_child(&self, name: &str, allow_nonempty: bool) -> Result<Arc<TmpNode>, SysErrNo> {
+        let mut inner = self.inner.lock();
+        let child = inner.children.get(name).cloned().ok_or(SysErrNo::ENOENT)?;
+        if !allow_nonempty && !child.inner.lock().children.is_empty() {
+            return Err(SysErrNo::ENOTEMPTY);
+        }
+        inner.take_child(name);
+        Ok(child)
+    }
+
+    /// 按 ino 顺序锁住目录 `a` 和 `b`，返回两者的锁；同一个目录时只锁一次，第二项为 None
+    fn lock_dirs<'a>(
+        a: &'a TmpNode,
+        b: &'a TmpNode,
+    ) -> (MutexGuard<'a, TmpNodeInner>, Option<MutexGuard<'a, TmpNodeInner>>) {
+        if a.ino == b.ino {
+            (a.inner.lock(), None)
+        } else if a.ino < b.ino {
+            let ga = a.inner.lock();
+            (ga, Some(b.inner.lock()))
+        } else {
+            let gb = b.inner.lock();
+            (a.inner.lock(), Some(gb))
+        }
+    }
+
+    /// `node` 是否是 `self` 自身或其祖先
+    fn has_ancestor(&self, node: &Arc<TmpNode>) -> bool {
+        if self.ino == node.ino {
+            return true;
+        }
+        let mut cur = self.inner.lock().parent.upgrade();
+        while let Some(p) = cur {
+            if p.ino == node.ino {
+                return true;
+            }
+            cur = p.inner.lock().parent.upgrade();
+        }
+        false
+    }
+
+    /// 相对路径相对于本节点，绝对路径原样返回
+    fn join(&self, path: &str) -> String {
+        if path.starts_with('/') {
+            return path.to_string();
+        }
+        let p = path.trim_matches('/');
+        if p.is_empty() || p == "." {
+            return self.path();
+        }
+        format!("{}/{}", self.path().trim_end_matches('/'), p)
+    }
+
+    fn set_len(&self, inner: &mut TmpNodeInner, len: usize) -> Result<(), SysErrNo> {
+        let old = inner.data.len();
+        if len > old {
+            self.sb.reserve_bytes(len - old)?;
+        } else {
+            self.sb.release_bytes(old - len);
+        }
+        inner.data.resize(len, 0);
+        Ok(())
+    }
+}
+
+impl VfsNodeOps for TmpNode {
+    fn path(&self) -> String {
+        let mut names = Vec::new();
+        let (mut name, mut parent) = {
+            let inner = self.inner.lock();
+            (inner.name.clone(), inner.parent.upgrade())
+        };
+        while let Some(p) = parent {
+            names.push(name);
+            let inner = p.inner.lock();
+            name = inner.name.clone();
+            parent = inner.parent.upgrade();
+        }
+        let mut path = self.sb.mount_point.trim_end_matches('/').to_string();
+        for name in names.iter().rev() {
+            path.push('/');
+            path.push_str(name);
+        }
+        if path.is_empty() {
+            path.push('/');
+        }
+        path
+    }
+
+    fn fstat(&self) -> Kstat {
+        let inner = self.inner.lock();
+        let size = match self.ty {
+            InodeType::Dir => (inner.children.len() + 2) * 20,
+            _ => inner.data.len(),
+        };
+        Kstat {
+            st_dev: self.sb.dev,
+            st_ino: self.ino,
+            st_mode: ((self.ty as u32) << 12) | inner.mode,
+            st_nlink: inner.nlink,
+            st_uid: inner.uid,
+            st_gid: inner.gid,
+            st_size: size as isize,
+            st_blksize: PAGE_SIZE as i32,
+            st_blocks: inner.data.len().div_ceil(512) as isize,
+            st_atime: inner.atime as isize,
+            st_mtime: inner.mtime as isize,
+            st_ctime: inner.ctime as isize,
+            ..Default::default()
+        }
+    }
+
+    fn size(&self) -> usize {
+        match self.ty {
+            InodeType::File => self.inner.lock().data.len(),
+            _ => 0,
+        }
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> SyscallRet {
+        if self.ty == InodeType::Dir {
+            return Err(SysErrNo::EISDIR);
+        }
+        let mut inner = self.inner.lock();
+        let offset = offset as usize;
+        if offset >= inner.data.len() {
+            return Ok(0);
+        }
+        let n = buf.len().min(inner.data.len() - offset);
+        buf[..n].copy_from_slice(&inner.data[offset..offset + n]);
+        inner.atime = now();
+        Ok(n)
+    }
+
+    fn write_at(&self, offset: u64, buf: &[u8]) -> Result<usize, i32> {
+        if self.ty == InodeType::Dir {
+            return Err(SysErrNo::EISDIR as i32);
+        }
+        let mut inner = self.inner.lock();
+        let offset = offset as usize;
+        let end = offset.checked_add(buf.len()).ok_or(SysErrNo::EFBIG as i32)?;
+        if end > inner.data.len() {
+            // 空间不够时尽量多写，一个字节都写不下才报 ENOSPC
+            let avail = self.sb.max_bytes.saturating_sub(self.sb.used_bytes.load(Ordering::Acquire));
+            let end = end.min(inner.data.len() + avail);
+            if end <= offset {
+                return Err(SysErrNo::ENOSPC as i32);
+            }
+            self.set_len(&mut inner, end).map_err(|e| e as i32)?;
+        }
+        let n = buf.len().min(inner.data.len() - offset);
+        inner.data[offset..offset + n].copy_from_slice(&buf[..n]);
+        inner.mtime = now();
+        inner.ctime = inner.mtime;
+        Ok(n)
+    }
+
+    fn truncate(&self, size: u64) -> Result<usize, i32> {
+        if self.ty == InodeType::Dir {
+            return Err(SysErrNo::EISDIR as i32);
+        }
+        let mut inner = self.inner.lock();
+        self.set_len(&mut inner, size as usize).map_err(|e| e as i32)?;
+        inner.mtime = now();
+        inner.ctime = inner.mtime;
+        Ok(0)
+    }
+
+    fn fsync(&self) -> Result<usize, i32> {
+        Ok(0)
+    }
+
+    fn sync(&self) {}
+
+    fn parent(&self) -> Option<Arc<dyn VfsNodeOps>> {
+        let parent = self.inner.lock().parent.upgrade()?;
+        Some(parent)
+    }
+
+    fn create(&self, path: &str, ty: InodeTypes) -> Result<usize, i32> {
+        let fpath = self.join(path);
+        let (dir, name) = self.sb.walk_parent(&fpath).map_err(|e| e as i32)?;
+        dir.add_child(&name, as_inode_type(ty), &[]).map_err(|e| e as i32)?;
+        Ok(0)
+    }
+
+    fn remove(&self, path: &str) -> Result<usize, i32> {
+        let fpath = self.join(path);
+        let (dir, name) = self.sb.walk_parent(&fpath).map_err(|e| e as i32)?;
+        dir.detach_child(&name, true).map_err(|e| e as i32)?;
+        Ok(0)
+    }
+
+    fn unlink(&self, path: &str) -> SyscallRet {
+        let (dir, name) = self.sb.walk_parent(&self.join(path))?;
+        dir.detach_child(&name, false)?;
+        Ok(0)
+    }
+
+    fn read_dentry(&self, off: usize, len: usize) -> Result<(Vec<u8>, isize), SysErrNo> {
+        if self.ty != InodeType::Dir {
+            return Err(SysErrNo::ENOTDIR);
+        }
+        let inner = self.inner.lock();
+        let parent_ino = inner.parent.upgrade().map_or(self.ino, |p| p.ino);
+        let entries = [
+            (String::from("."), self.ino, InodeType::Dir),
+            (String::from(".."), parent_ino, InodeType::Dir),
+        ]
+        .into_iter()
+        .chain(inner.children.iter().map(|(name, n)| (name.clone(), n.ino, n.ty)));
+        let mut de: Vec<u8> = Vec::new();
+        let mut f_off = -1isize;
+        for (idx, (name, ino, ty)) in entries.enumerate().skip(off) {
+            let dirent = Dirent::new(name, idx as i64 + 1, ino as u64, ty as u8);
+            if de.len() + dirent.len() > len {
+                // 缓冲区放不下下一项，下次从这一项继续
+                f_off = idx as isize;
+                break;
+            }
+            de.extend_from_slice(dirent.as_bytes());
+            f_off = dirent.off() as isize;
+        }
+        Ok((de, f_off))
+    }
+
+    fn rename(&self, src_path: &str, dst_path: &str) -> Result<usize, i32> {
+        let do_rename = || -> Result<usize, SysErrNo> {
+            let (src_dir, src_name) = self.sb.walk_parent(&self.join(src_path))?;
+            let (dst_dir, dst_name) = self.sb.walk_parent(&self.join(dst_path))?;
+            let _rename = self.sb.rename_lock.lock();
+            let src = src_dir.inner.lock().children.get(&src_name).cloned().ok_or(SysErrNo::ENOENT)?;
+            // 祖先检查要逐级锁住父目录，必须在锁住两个父目录之前完成
+            if src.ty == InodeType::Dir && dst_dir.has_ancestor(&src) {
+                return Err(SysErrNo::EINVAL);
+            }
+            let (mut src_inner, mut dst_inner) = TmpNode::lock_dirs(&src_dir, &dst_dir);
+            // 检查之后可能被并发 unlink 或替换
+            if !src_inner.children.get(&src_name).is_some_and(|n| Arc::ptr_eq(n, &src)) {
+                return Err(SysErrNo::ENOENT);
+            }
+            let dst = dst_inner.as_ref().unwrap_or(&src_inner).children.get(&dst_name).cloned();
+            if let Some(dst) = dst {
+                if Arc::ptr_eq(&src, &dst) {
+                    return Ok(0);
+                }
+                match (src.ty == InodeType::Dir, dst.ty == InodeType::Dir) {
+                    (true, false) => return Err(SysErrNo::ENOTDIR),
+                    (false, true) => return Err(SysErrNo::EISDIR),
+                    (true, true) if !dst.inner.lock().children.is_empty() => {
+                        return Err(SysErrNo::ENOTEMPTY)
+                    }
+                    _ => {}
+                }
+            }
+            let src = src_inner.take_child(&src_name).unwrap();
+            dst_inner
+                .as_deref_mut()
+                .unwrap_or(&mut src_inner)
+                .put_child(&dst_dir, &dst_name, src);
+            Ok(0)
+        };
+        do_rename().map_err(|e| e as i32)
+    }
+
+    fn exchange(&self, path1: &str, path2: &str) -> Result<(), SysErrNo> {
+        let (dir1, name1) = self.sb.walk_parent(&self.join(path1))?;
+        let (dir2, name2) = self.sb.walk_parent(&self.join(path2))?;
+        let _rename = self.sb.rename_lock.lock();
+        let node1 = dir1.inner.lock().children.get(&name1).cloned().ok_or(SysErrNo::ENOENT)?;
+        let node2 = dir2.inner.lock().children.get(&name2).cloned().ok_or(SysErrNo::ENOENT)?;
+        if Arc::ptr_eq(&node1, &node2) {
+            return Ok(());
+        }
+        if dir2.has_ancestor(&node1) || dir1.has_ancestor(&node2) {
+            return Err(SysErrNo::EINVAL);
+        }
+        let (mut inner1, mut inner2) = TmpNode::lock_dirs(&dir1, &dir2);
+        let still = |inner: &TmpNodeInner, name: &str, node: &Arc<TmpNode>| {
+            inner.children.get(name).is_some_and(|n| Arc::ptr_eq(n, node))
+        };
+        if !still(&inner1, &name1, &node1) || !still(inner2.as_ref().unwrap_or(&inner1), &name2, &node2) {
+            return Err(SysErrNo::ENOENT);
+        }
+        let node1 = inner1.take_child(&name1).unwrap();
+        let dir2_inner = inner2.as_deref_mut().unwrap_or(&mut inner1);
+        let node2 = dir2_inner.take_child(&name2).unwrap();
+        dir2_inner.put_child(&dir2, &name2, node1);
+        inner1.put_child(&dir1, &name1, node2);
+        Ok(())
+    }
+
+    fn sym_link(&self, target: &str, path: &str) -> SyscallRet {
+        let (dir, name) = self.sb.walk_parent(&self.join(path))?;
+        let link = dir.add_child(&name, InodeType::SymLink, target.as_bytes())?;
+        link.inner.lock().mode = 0o777;
+        Ok(0)
+    }
+
+    fn read_link(&self, buf: &mut [u8], bufsize: usize) -> SyscallRet {
+        if self.ty != InodeType::SymLink {
+            return Err(SysErrNo::EINVAL);
+        }
+        let inner = self.inner.lock();
+        let n = inner.data.len().min(bufsize).min(buf.len());
+        buf[..n].copy_from_slice(&inner.data[..n]);
+        Ok(n)
+    }
+
+    fn as_any(&self) -> &dyn core::any::Any {
+        self
+    }
+
+    fn find(
+        &self,
+        path: &str,
+        flags: OpenFlags,
+        _loop_times: usize,
+    ) -> Result<Arc<dyn VfsNodeOps>, SysErrNo> {
+        let node = self.sb.walk(path)?;
+        if node.ty == InodeType::SymLink && !flags.contains(OpenFlags::O_ASK_SYMLINK) {
+            // 链接目标可能在其他文件系统上，交给 VFS 逐级解析
+            return VfsManager::lookup(path, flags);
+        }
+        if flags.contains(OpenFlags::O_DIRECTORY) && node.ty != InodeType::Dir {
+            return Err(SysErrNo::ENOTDIR);
+        }
+        Ok(node)
+    }
+
+    fn fmode(&self) -> Result<u32, SysErrNo> {
+        Ok(((self.ty as u32) << 12) | self.inner.lock().mode)
+    }
+
+    fn fmode_set(&self, mode: u32) -> SyscallRet {
+        let mut inner = self.inner.lock();
+        inner.mode = mode & 0o7777;
+        inner.ctime = now();
+        Ok(0)
+    }
+
+    fn set_owner(&self, uid: u32, gid: u32) -> SyscallRet {
+        let mut inner = self.inner.lock();
+        inner.uid = uid;
+        inner.gid = gid;
+        inner.ctime = now();
+        Ok(0)
+    }
+
+    fn set_timestamps(&self, atime: Option<u32>, mtime: Option<u32>, ctime: Option<u32>) -> SyscallRet {
+        let mut inner = self.inner.lock();
+        if let Some(atime) = atime {
+            inner.atime = atime;
+        }
+        if let Some(mtime) = mtime {
+            inner.mtime = mtime;
+        }
+        if let Some(ctime) = ctime {
+            inner.ctime = ctime;
+        }
+        Ok(0)
+    }
+
+    fn is_dir(&self) -> bool {
+        self.ty == InodeType::Dir
+    }
+
+    fn is_symlink(&self) -> bool {
+        self.ty == InodeType::SymLink
+    }
+
+    fn link_cnt(&self) -> SyscallRet {
+        Ok(self.inner.lock().nlink as usize)
+    }
+
+    /// 节点被摘下后只要还有人持有就一直可用，所以直接从目录中移除
+    fn delay(&self) {
+        let (parent, name) = {
+            let inner = self.inner.lock();
+            (inner.parent.upgrade(), inner.name.clone())
+        };
+        if let Some(parent) = parent {
+            let _ = parent.detach_child(&name, false);
+        }
+    }
+
+    fn if_delay(&self) -> bool {
+        false
+    }
+}
+
+impl Drop for TmpNode {
+    fn drop(&mut self) {
+        let len = self.inner.get_mut().data.len();
+        self.sb.release_bytes(len);
+        self.sb.used_inodes.fetch_sub(1, Ordering::AcqRel);
+    }
+}
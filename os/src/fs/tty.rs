@@ -0,0 +1,588 @@
+//! 控制台终端与行规程
+//!
+//! 串口收到的字节经`receive`进入行规程：按 termios 做输入转换、回显、
+//! 行编辑（规范模式）和作业控制字符（^C/^\/^Z）的处理，整理好的数据放进
+//! 就绪队列，由`tty_read`交给用户。串口驱动打开接收中断后调用`set_rx_irq`，
+//! 之后字符由中断处理函数经`receive_all`送进来；没有串口中断的平台上，
+//! `poll_input`在调度循环和 trap 返回路径上把控制台里的字符搬进来。
+//!
+//! 行规程里产生的信号先记在`TtyInner::pending_signals`，由`deliver_signals`
+//! 在异步上下文中发送给前台进程组。
+//!
+//! 控制台的 fd 在启动时就已打开，没有经过 open，所以终端在第一次 tcsetpgrp
+//! 或 TIOCSCTTY 时才成为调用者会话的控制终端。后台进程组读终端时收到 SIGTTIN。
+
+use core::cmp::min;
+use core::future::Future;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::task::{Context, Poll, Waker};
+
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+use crate::devices::utils::{get_char, puts};
+use crate::mm::UserBuffer;
+use crate::signal::{
+    is_orphaned_pgrp, pgrp_members, send_signal, send_signal_to_pgrp, signal_ignored_or_blocked,
+    signal_pending, Signal,
+};
+use crate::syscall::flags::{
+    TCGETS, TCSETS, TCSETSF, TCSETSW, TIOCGPGRP, TIOCGSID, TIOCGWINSZ, TIOCNOTTY, TIOCSCTTY,
+    TIOCSPGRP, TIOCSWINSZ,
+};
+use crate::task::{current_process, Task, TaskStatus};
+use crate::utils::error::{SysErrNo, SyscallRet};
+
+// c_iflag
+const INLCR: u32 = 0o100;
+const IGNCR: u32 = 0o200;
+const ICRNL: u32 = 0o400;
+// c_oflag
+const OPOST: u32 = 0o1;
+const ONLCR: u32 = 0o4;
+// c_lflag
+const ISIG: u32 = 0o1;
+const ICANON: u32 = 0o2;
+const ECHO: u32 = 0o10;
+const ECHOE: u32 = 0o20;
+const ECHOK: u32 = 0o40;
+const ECHONL: u32 = 0o100;
+const NOFLSH: u32 = 0o200;
+const ECHOCTL: u32 = 0o1000;
+const ECHOKE: u32 = 0o4000;
+// c_cc 下标
+const VINTR: usize = 0;
+const VQUIT: usize = 1;
+const VERASE: usize = 2;
+const VKILL: usize = 3;
+const VEOF: usize = 4;
+const VMIN: usize = 6;
+const VSUSP: usize = 10;
+const VEOL: usize = 11;
+const VWERASE: usize = 14;
+
+/// 内核与用户态交换的 termios（不含波特率字段）
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct Termios {
+    pub c_iflag: u32,
+    pub c_oflag: u32,
+    pub c_cflag: u32,
+    pub c_lflag: u32,
+    pub c_line: u8,
+    pub c_cc: [u8; 19],
+}
+
+impl Termios {
+    /// 和 Linux 控制台初始状态一致：ICRNL|IXON，OPOST|ONLCR，B38400|CS8|CREAD|HUPCL，
+    /// ISIG|ICANON|ECHO|ECHOE|ECHOK|ECHOCTL|ECHOKE|IEXTEN
+    pub const CONSOLE: Self = Self {
+        c_iflag: 0o2400,
+        c_oflag: 0o5,
+        c_cflag: 0o2277,
+        c_lflag: 0o105073,
+        c_line: 0,
+        c_cc: [
+            3, 0x1c, 0x7f, 0x15, 4, 0, 1, 0, 0x11, 0x13, 0x1a, 0, 0x12, 0xf, 0x17, 0x16, 0, 0, 0,
+        ],
+    };
+
+    fn lflag(&self, flag: u32) -> bool {
+        self.c_lflag & flag != 0
+    }
+
+    /// 判断 c 是否是某个已启用的控制字符（值为 0 表示禁用）
+    fn is_cc(&self, idx: usize, c: u8) -> bool {
+        self.c_cc[idx] != 0 && self.c_cc[idx] == c
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct WinSize {
+    pub ws_row: u16,
+    pub ws_col: u16,
+    pub ws_xpixel: u16,
+    pub ws_ypixel: u16,
+}
+
+/// 控制台终端的状态，stdin/stdout 和 /dev/tty 共用
+struct TtyInner {
+    termios: Termios,
+    winsize: WinSize,
+    /// 以本终端为控制终端的会话，0 表示没有
+    session: usize,
+    /// 前台进程组，0 表示还没有人设置过
+    fg_pgrp: usize,
+    /// 最近一次读终端的进程，前台进程组未设置时信号发给它
+    last_reader: usize,
+    /// 规范模式下正在编辑、还没提交的一行
+    line: Vec<u8>,
+    /// 可以被 read 取走的数据，`None`是 ^D 留下的行边界
+    ready: VecDeque<Option<u8>>,
+    /// 等待输入的读者
+    readers: Vec<Waker>,
+    /// 行规程产生、尚未发送的作业控制信号
+    pending_signals: Vec<Signal>,
+}
+
+static TTY: Mutex<TtyInner> = Mutex::new(TtyInner {
+    termios: Termios::CONSOLE,
+    winsize: WinSize { ws_row: 24, ws_col: 80, ws_xpixel: 0, ws_ypixel: 0 },
+    session: 0,
+    fg_pgrp: 0,
+    last_reader: 0,
+    line: Vec::new(),
+    ready: VecDeque::new(),
+    readers: Vec::new(),
+    pending_signals: Vec::new(),
+});
+
+impl TtyInner {
+    fn wake_readers(&mut self) {
+        for waker in self.readers.drain(..) {
+            waker.wake();
+        }
+    }
+
+    fn register_reader(&mut self, waker: &Waker) {
+        if !self.readers.iter().any(|w| w.will_wake(waker)) {
+            self.readers.push(waker.clone());
+        }
+    }
+
+    /// 是否有数据可以交给 read
+    fn has_input(&self) -> bool {
+        if self.termios.lflag(ICANON) {
+            !self.ready.is_empty()
+        } else {
+            self.ready.iter().any(|c| c.is_some())
+        }
+    }
+
+    /// 把当前编辑行提交到就绪队列
+    fn commit_line(&mut self) {
+        let line = core::mem::take(&mut self.line);
+        self.ready.extend(line.into_iter().map(Some));
+    }
+
+    /// 按 ECHOCTL 的规则回显一个字符
+    fn echo_char(&self, c: u8, out: &mut Vec<u8>) {
+        if self.termios.lflag(ECHOCTL) && is_ctrl(c) {
+            out.push(b'^');
+            out.push(c ^ 0x40);
+        } else {
+            out.push(c);
+        }
+    }
+
+    /// 从编辑行末尾擦掉一个字符，回显退格
+    fn erase_char(&mut self, out: &mut Vec<u8>) -> Option<u8> {
+        let c = self.line.pop()?;
+        if self.termios.lflag(ECHO) && self.termios.lflag(ECHOE) {
+            let width = if self.termios.lflag(ECHOCTL) && is_ctrl(c) { 2 } else { 1 };
+            for _ in 0..width {
+                out.extend_from_slice(b"\x08 \x08");
+            }
+        }
+        Some(c)
+    }
+
+    /// 行规程：处理一个输入字节，需要回显的内容追加到 out
+    fn input(&mut self, mut c: u8, out: &mut Vec<u8>) {
+        let t = self.termios;
+        match c {
+            b'\r' if t.c_iflag & IGNCR != 0 => return,
+            b'\r' if t.c_iflag & ICRNL != 0 => c = b'\n',
+            b'\n' if t.c_iflag & INLCR != 0 => c = b'\r',
+            _ => {}
+        }
+
+        if t.lflag(ISIG) {
+            let sig = if t.is_cc(VINTR, c) {
+                Some(Signal::SIGINT)
+            } else if t.is_cc(VQUIT, c) {
+                Some(Signal::SIGQUIT)
+            } else if t.is_cc(VSUSP, c) {
+                Some(Signal::SIGTSTP)
+            } else {
+                None
+            };
+            if let Some(sig) = sig {
+                if !t.lflag(NOFLSH) {
+                    self.line.clear();
+                    self.ready.clear();
+                }
+                if t.lflag(ECHO) {
+                    self.echo_char(c, out);
+                }
+                self.pending_signals.push(sig);
+                self.wake_readers();
+                return;
+            }
+        }
+
+        if !t.lflag(ICANON) {
+            if t.lflag(ECHO) {
+                self.echo_char(c, out);
+            } else if c == b'\n' && t.lflag(ECHONL) {
+                out.push(c);
+            }
+            self.ready.push_back(Some(c));
+            self.wake_readers();
+            return;
+        }
+
+        if t.is_cc(VERASE, c) {
+            self.erase_char(out);
+        } else if t.is_cc(VWERASE, c) {
+            while self.line.last() == Some(&b' ') {
+                self.erase_char(out);
+            }
+            while self.line.last().is_some_and(|&c| c != b' ') {
+                self.erase_char(out);
+            }
+        } else if t.is_cc(VKILL, c) {
+            if t.lflag(ECHO) && t.lflag(ECHOKE) {
+                while self.erase_char(out).is_some() {}
+            } else {
+                self.line.clear();
+                if t.lflag(ECHO) {
+                    self.echo_char(c, out);
+                    if t.lflag(ECHOK) {
+                        out.push(b'\n');
+                    }
+                }
+            }
+        } else if t.is_cc(VEOF, c) {
+            // ^D：把已输入的内容直接交出去；空行时 read 返回 0，即 EOF
+            self.commit_line();
+            self.ready.push_back(None);
+            self.wake_readers();
+        } else if c == b'\n' || t.is_cc(VEOL, c) {
+            if t.lflag(ECHO) || t.lflag(ECHONL) {
+                out.push(c);
+            }
+            self.line.push(c);
+            self.commit_line();
+            self.wake_readers();
+        } else {
+            if t.lflag(ECHO) {
+                self.echo_char(c, out);
+            }
+            self.line.push(c);
+        }
+    }
+
+    /// 从就绪队列取出本次 read 能拿到的数据，数据不够时返回 None
+    fn take(&mut self, max: usize) -> Option<Vec<u8>> {
+        let mut buf = Vec::new();
+        if self.termios.lflag(ICANON) {
+            // 规范模式一次最多返回一行
+            if self.ready.is_empty() {
+                return None;
+            }
+            while buf.len() < max {
+                match self.ready.pop_front() {
+                    Some(Some(c)) => {
+                        buf.push(c);
+                        if c == b'\n' || self.termios.is_cc(VEOL, c) {
+                            break;
+                        }
+                    }
+                    Some(None) | None => break,
+                }
+            }
+        } else {
+            // 非规范模式：至少凑够 min(VMIN, max) 个字节，VMIN 为 0 时立即返回
+            self.ready.retain(|c| c.is_some());
+            let need = min(self.termios.c_cc[VMIN] as usize, max);
+            if self.ready.len() < need {
+                return None;
+            }
+            while buf.len() < max {
+                match self.ready.pop_front() {
+                    Some(Some(c)) => buf.push(c),
+                    _ => break,
+                }
+            }
+        }
+        Some(buf)
+    }
+}
+
+fn is_ctrl(c: u8) -> bool {
+    (c < 0x20 && c != b'\t' && c != b'\n') || c == 0x7f
+}
+
+/// 串口收到一个字节，交给行规程处理
+pub fn receive(c: u8) {
+    let mut echo = Vec::new();
+    TTY.lock().input(c, &mut echo);
+    if !echo.is_empty() {
+        output(&echo);
+    }
+}
+
+/// 串口接收中断已经接上，不需要再轮询
+static RX_IRQ: AtomicBool = AtomicBool::new(false);
+
+/// 串口驱动打开接收中断后调用
+pub fn set_rx_irq() {
+    RX_IRQ.store(true, Ordering::Release);
+}
+
+/// 把控制台里已到达的字符全部搬进行规程，串口接收中断的处理函数调用
+pub fn receive_all() {
+    while let Some(c) = get_char() {
+        receive(c);
+    }
+}
+
+/// 没有串口接收中断时轮询控制台
+pub fn poll_input() {
+    if !RX_IRQ.load(Ordering::Acquire) {
+        receive_all();
+    }
+}
+
+/// 发送行规程产生的作业控制信号
+pub async fn deliver_signals() {
+    let (signals, target) = {
+        let mut tty = TTY.lock();
+        if tty.pending_signals.is_empty() {
+            return;
+        }
+        let target = match tty.fg_pgrp {
+            0 => (tty.last_reader, false),
+            pgrp => (pgrp, true),
+        };
+        (core::mem::take(&mut tty.pending_signals), target)
+    };
+    let (target, is_pgrp) = target;
+    if target == 0 {
+        return;
+    }
+    for sig in signals {
+        let res = if is_pgrp {
+            send_signal_to_pgrp(target, sig).await
+        } else {
+            send_signal(target, None, sig).await
+        };
+        if let Err(e) = res {
+            log::warn!("[tty] failed to send {:?} to {}: {:?}", sig, target, e);
+        }
+    }
+}
+
+/// 会话首进程退出：终端不再是该会话的控制终端，前台进程组收到 SIGHUP 和 SIGCONT
+pub async fn hangup_session(sid: usize) {
+    let fg_pgrp = {
+        let mut tty = TTY.lock();
+        if tty.session != sid {
+            return;
+        }
+        tty.session = 0;
+        core::mem::take(&mut tty.fg_pgrp)
+    };
+    if fg_pgrp != 0 {
+        let _ = send_signal_to_pgrp(fg_pgrp, Signal::SIGHUP).await;
+        let _ = send_signal_to_pgrp(fg_pgrp, Signal::SIGCONT).await;
+    }
+}
+
+/// 按 OPOST/ONLCR 输出到控制台
+pub fn output(buf: &[u8]) {
+    let oflag = TTY.lock().termios.c_oflag;
+    if oflag & OPOST != 0 && oflag & ONLCR != 0 {
+        for chunk in buf.split_inclusive(|&c| c == b'\n') {
+            match chunk.split_last() {
+                Some((b'\n', head)) => {
+                    puts(head);
+                    puts(b"\r\n");
+                }
+                _ => puts(chunk),
+            }
+        }
+    } else {
+        puts(buf);
+    }
+}
+
+/// 等待终端有新输入；被唤醒（新数据或信号）后即返回，由调用者重新检查
+struct WaitInput {
+    registered: bool,
+}
+
+impl Future for WaitInput {
+    type Output = ();
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if self.registered {
+            return Poll::Ready(());
+        }
+        let mut tty = TTY.lock();
+        if tty.has_input() || !tty.pending_signals.is_empty() {
+            Poll::Ready(())
+        } else {
+            tty.register_reader(cx.waker());
+            self.registered = true;
+            let task = cx.waker().data() as *const Task;
+            unsafe { &*task }.set_state(TaskStatus::Blocking);
+            Poll::Pending
+        }
+    }
+}
+
+/// 从控制台终端读
+pub async fn tty_read(mut user_buf: UserBuffer<'_>) -> SyscallRet {
+    if user_buf.is_empty() {
+        return Ok(0);
+    }
+    let proc = current_process();
+    let pid = proc.get_pid();
+    // 后台进程组读控制终端：停下整个进程组，继续后重新读
+    let background = {
+        let tty = TTY.lock();
+        tty.session == proc.sid() && tty.fg_pgrp != 0 && tty.fg_pgrp != proc.pgid()
+    };
+    if background {
+        if signal_ignored_or_blocked(Signal::SIGTTIN).await || is_orphaned_pgrp(proc.pgid(), 0).await {
+            return Err(SysErrNo::EIO);
+        }
+        let _ = send_signal_to_pgrp(proc.pgid(), Signal::SIGTTIN).await;
+        return Err(SysErrNo::ERESTART);
+    }
+    loop {
+        poll_input();
+        deliver_signals().await;
+        let data = {
+            let mut tty = TTY.lock();
+            tty.last_reader = pid;
+            tty.take(user_buf.len())
+        };
+        if let Some(data) = data {
+            return Ok(user_buf.write(&data));
+        }
+        if signal_pending().await {
+            return Err(SysErrNo::ERESTART);
+        }
+        WaitInput { registered: false }.await;
+    }
+}
+
+/// 有数据可读时返回 true，否则登记 waker
+pub fn tty_poll_readable(waker: &Waker) -> bool {
+    poll_input();
+    let mut tty = TTY.lock();
+    if tty.has_input() {
+        true
+    } else {
+        tty.register_reader(waker);
+        false
+    }
+}
+
+/// 控制台终端的 ioctl
+pub async fn tty_ioctl(cmd: usize, arg: usize) -> SyscallRet {
+    let proc = current_process();
+    let mut ms = proc.memory_set.lock().await;
+    match cmd {
+        TCGETS => {
+            let termios = TTY.lock().termios;
+            ms.put_user(arg, termios).await?;
+        }
+        TCSETS | TCSETSW | TCSETSF => {
+            let termios = ms.get_user::<Termios>(arg).await?;
+            let mut tty = TTY.lock();
+            if cmd == TCSETSF {
+                tty.line.clear();
+                tty.ready.clear();
+            }
+            // 离开规范模式时，编辑到一半的行直接变成可读数据
+            if tty.termios.lflag(ICANON) && !termios.lflag(ICANON) {
+                tty.commit_line();
+            }
+            tty.termios = termios;
+            if tty.has_input() {
+                tty.wake_readers();
+            }
+        }
+        TIOCGWINSZ => {
+            let winsize = TTY.lock().winsize;
+            ms.put_user(arg, winsize).await?;
+        }
+        TIOCSWINSZ => {
+            let winsize = ms.get_user::<WinSize>(arg).await?;
+            TTY.lock().winsize = winsize;
+        }
+        TIOCGPGRP => {
+            let pgrp = {
+                let tty = TTY.lock();
+                if tty.session != 0 && tty.session != proc.sid() {
+                    return Err(SysErrNo::ENOTTY);
+                }
+                match tty.fg_pgrp {
+                    0 => proc.pgid(),
+                    pgrp => pgrp,
+                }
+            };
+            ms.put_user(arg, pgrp as i32).await?;
+        }
+        TIOCSPGRP => {
+            let pgrp = ms.get_user::<i32>(arg).await?;
+            if pgrp <= 0 {
+                return Err(SysErrNo::EINVAL);
+            }
+            let pgrp = pgrp as usize;
+            let sid = proc.sid();
+            // 目标进程组必须存在于调用者的会话中
+            if !pgrp_members(pgrp).iter().any(|p| p.sid() == sid) {
+                return Err(SysErrNo::EPERM);
+            }
+            let mut tty = TTY.lock();
+            if tty.session == 0 {
+                tty.session = sid;
+            } else if tty.session != sid {
+                return Err(SysErrNo::ENOTTY);
+            }
+            tty.fg_pgrp = pgrp;
+        }
+        TIOCSCTTY => {
+            let sid = proc.sid();
+            if sid != proc.get_pid() {
+                return Err(SysErrNo::EPERM);
+            }
+            let mut tty = TTY.lock();
+            if tty.session == sid {
+                return Ok(0);
+            }
+            // 已是其他会话的控制终端时，只有 arg 为 1 才抢占（目前只有 root 用户）
+            if tty.session != 0 && arg != 1 {
+                return Err(SysErrNo::EPERM);
+            }
+            tty.session = sid;
+            tty.fg_pgrp = proc.pgid();
+        }
+        TIOCNOTTY => {
+            let sid = proc.sid();
+            if TTY.lock().session != sid {
+                return Err(SysErrNo::ENOTTY);
+            }
+            if sid == proc.get_pid() {
+                drop(ms);
+                hangup_session(sid).await;
+            }
+        }
+        TIOCGSID => {
+            let session = TTY.lock().session;
+            if session == 0 || session != proc.sid() {
+                return Err(SysErrNo::ENOTTY);
+            }
+            ms.put_user(arg, session as i32).await?;
+        }
+        _ => return Err(SysErrNo::ENOTTY),
+    }
+    Ok(0)
+}
@@ -9,23 +9,32 @@
 
 pub mod vfs_ops;
 
+use alloc::collections::VecDeque;
 use alloc::format;
 use alloc::string::{String, ToString};
 use alloc::sync::Arc;
 use log::{info, warn};
+use spin::Mutex;
 
+use crate::config::MAX_SYMLINK_DEPTH;
+use crate::devices::get_blk_devices;
 use crate::drivers::{ parse_virtio_device_name, Ext4DiskWrapper};
 use crate::fs::ext4::ops::Ext4FileSystem;
+use crate::fs::devfs::DevFs;
+use crate::fs::procfs::ProcFs;
+use crate::fs::tmpfs::TmpFs;
 // 假设你有一个 Ext4VfsOps 的实现
-use crate::fs::mount::{MountEntry, MNT_TABLE};
-use crate::fs::{VfsOps, EXT4FS};
+use crate::fs::mount::{is_path_prefix, MountEntry, MNT_TABLE};
+use crate::fs::{OpenFlags, VfsOps, EXT4FS, FD2NODE};
 use crate::utils::error::{GeneralRet, SysErrNo};
+use crate::utils::string::get_parent_path_and_filename;
+use vfs_ops::VfsNodeOps;
 
 pub struct VfsManager;
 
 impl VfsManager {
     pub fn sync(){
-       
+        crate::fs::pagecache::sync_all();
         let mut mnt_table = MNT_TABLE.lock();
         EXT4FS.lock().sync().unwrap_or_else(|e| {
             warn!("Failed to sync EXT4 filesystem: {:?}", e);
@@ -42,16 +51,27 @@ impl VfsManager {
         mount_point: &str,
         fstype: &str,
         flags: u32,
-        _data: Option<String>,
+        data: Option<String>,
     ) -> GeneralRet {
         info!("VFS: Attempting to mount '{}' ({}) on '{}'", special_device, fstype, mount_point);
 
+        // 根文件系统之外的挂载点必须是已存在的目录
+        if mount_point != "/" {
+            Self::lookup(mount_point, OpenFlags::O_DIRECTORY)?;
+            if MNT_TABLE.lock().get_mount_info_by_dir(mount_point).is_some() {
+                return Err(SysErrNo::EBUSY);
+            }
+        }
+
         // --- 步骤 1: 驱动层挂载 ---
         // 根据 fstype 创建和初始化文件系统驱动实例
         let fs_instance: Arc<spin::Mutex<dyn VfsOps>> = match fstype {
             "ext4" => {
                 // a. 找到并初始化块设备
-                let block_id = parse_virtio_device_name(special_device).unwrap();
+                let block_id = parse_virtio_device_name(special_device).ok_or(SysErrNo::ENOTBLK)?;
+                if block_id >= get_blk_devices().len() {
+                    return Err(SysErrNo::ENXIO);
+                }
                 println!("init block_id:{}",block_id);
                 let disk = Ext4DiskWrapper::new(block_id);
                 // b. 创建 Ext4VfsOps 实例
@@ -65,7 +85,9 @@ impl VfsManager {
                 res
                 
             },
-            // "tmpfs" => { /* 创建一个 TmpFs 实例 */ },
+            "tmpfs" => Arc::new(spin::Mutex::new(TmpFs::new(mount_point, data.as_deref())?)),
+            "proc" => Arc::new(spin::Mutex::new(ProcFs::new(mount_point))),
+            "devtmpfs" => Arc::new(spin::Mutex::new(DevFs::new(mount_point))),
             _ => {
                 warn!("VFS: Unsupported filesystem type '{}'", fstype);
                 return Err(SysErrNo::ENODEV); // No such device (or filesystem)
@@ -88,14 +110,129 @@ impl VfsManager {
             mount_point: mount_point.to_string(),
             flags,
             fs_instance,
-        })
+        })?;
+        drop(mnt_table);
+        // 挂载点之下原先缓存的节点已被遮住
+        Self::forget_nodes_under(mount_point);
+        Ok(())
     }
 
     /// 卸载一个文件系统
     pub fn umount(path_or_device: &str) -> GeneralRet {
         // ... umount 逻辑，需要先从 MountTable 获取 fs_instance，
         // 调用 fs_instance 的 umount 方法（如果需要），然后再从表中移除 ...
-        MNT_TABLE.lock().umount(path_or_device).map(|_|())
+        if path_or_device == "/" {
+            return Err(SysErrNo::EBUSY);
+        }
+        // 卸载前先写回页缓存中的脏页
+        crate::fs::pagecache::sync_all();
+        let mount_point = {
+            let mut mnt_table = MNT_TABLE.lock();
+            let entry = mnt_table
+                .entries
+                .iter()
+                .find(|e| e.mount_point == path_or_device || e.special_device == path_or_device)
+                .ok_or(SysErrNo::EINVAL)?;
+            if entry.mount_point == "/" {
+                return Err(SysErrNo::EBUSY);
+            }
+            let mount_point = entry.mount_point.clone();
+            mnt_table.umount(path_or_device)?.lock().umount()?;
+            mount_point
+        };
+        crate::drivers::bcache::flush_all();
+        Self::forget_nodes_under(&mount_point);
+        Ok(())
+    }
+
+    fn forget_nodes_under(mount_point: &str) {
+        FD2NODE.write().retain(|path, _| !is_path_prefix(mount_point, path));
+    }
+
+    /// 返回管理 `abs_path` 的文件系统
+    pub fn fs_of(abs_path: &str) -> Arc<Mutex<dyn VfsOps>> {
+        match MNT_TABLE.lock().find_mount(abs_path) {
+            Some(entry) => entry.fs_instance.clone(),
+            None => EXT4FS.clone(),
+        }
+    }
+
+    /// 两个路径是否位于同一个文件系统上，rename/link 不能跨文件系统
+    pub fn same_fs(path1: &str, path2: &str) -> bool {
+        Arc::ptr_eq(&Self::fs_of(path1), &Self::fs_of(path2))
+    }
+
+    /// 在 `abs_path` 所在的文件系统中查找节点，不跟随符号链接
+    ///
+    /// `abs_path` 必须是不含 `.`、`..` 的绝对路径；挂载点本身返回被挂载文件系统的根
+    fn node_at(abs_path: &str, flags: OpenFlags) -> Result<Arc<dyn VfsNodeOps>, SysErrNo> {
+        let (fs, is_mount_point) = match MNT_TABLE.lock().find_mount(abs_path) {
+            Some(entry) => (entry.fs_instance.clone(), entry.mount_point == abs_path),
+            None => (EXT4FS.clone(), abs_path == "/"),
+        };
+        let root = fs.lock().root_inode();
+        if is_mount_point {
+            return Ok(root);
+        }
+        root.find(abs_path, flags | OpenFlags::O_ASK_SYMLINK, 0)
+    }
+
+    /// 按挂载表逐级解析绝对路径
+    ///
+    /// 每一级目录都在其所在的文件系统中查找，遇到挂载点时进入被挂载文件系统的根，
+    /// `..` 回到挂载点的父目录所在的文件系统。中间的符号链接总是被跟随，
+    /// 最后一级只有在 `flags` 不含 `O_ASK_SYMLINK` 时才跟随。
+    pub fn lookup(abs_path: &str, flags: OpenFlags) -> Result<Arc<dyn VfsNodeOps>, SysErrNo> {
+        let mut comps: VecDeque<String> = abs_path
+            .split('/')
+            .filter(|c| !c.is_empty())
+            .map(String::from)
+            .collect();
+        let mut cur = String::from("/");
+        let mut node = Self::node_at(&cur, OpenFlags::empty())?;
+        let mut links = 0;
+        while let Some(name) = comps.pop_front() {
+            if name == "." {
+                continue;
+            }
+            if name == ".." {
+                if cur != "/" {
+                    cur = get_parent_path_and_filename(&cur).0;
+                    node = Self::node_at(&cur, OpenFlags::empty())?;
+                }
+                continue;
+            }
+            if !node.is_dir() {
+                return Err(SysErrNo::ENOTDIR);
+            }
+            let next = if cur == "/" { format!("/{}", name) } else { format!("{}/{}", cur, name) };
+            let last = comps.is_empty();
+            let child = Self::node_at(&next, if last { flags } else { OpenFlags::empty() })?;
+            if child.is_symlink() && (!last || !flags.contains(OpenFlags::O_ASK_SYMLINK)) {
+                links += 1;
+                if links > MAX_SYMLINK_DEPTH {
+                    return Err(SysErrNo::ELOOP);
+                }
+                let mut buf = [0u8; 256];
+                let len = child.read_link(&mut buf, 256)?;
+                let end = buf[..len].iter().position(|&b| b == 0).unwrap_or(len);
+                let target = core::str::from_utf8(&buf[..end]).map_err(|_| SysErrNo::EINVAL)?;
+                if target.starts_with('/') {
+                    cur = String::from("/");
+                    node = Self::node_at(&cur, OpenFlags::empty())?;
+                }
+                for c in target.rsplit('/').filter(|c| !c.is_empty()) {
+                    comps.push_front(String::from(c));
+                }
+                continue;
+            }
+            cur = next;
+            node = child;
+        }
+        if flags.contains(OpenFlags::O_DIRECTORY) && !node.is_dir() {
+            return Err(SysErrNo::ENOTDIR);
+        }
+        Ok(node)
     }
 
  
@@ -179,6 +179,18 @@ pub trait VfsNodeOps: Send + Sync {
     fn is_dir(&self) -> bool {
         unimplemented!()
     }
+    /// 是否是符号链接，路径解析时据此决定是否跟随
+    fn is_symlink(&self) -> bool {
+        false
+    }
+    /// 内容随内核状态变化的节点（如 procfs），不能按路径缓存
+    fn is_dynamic(&self) -> bool {
+        false
+    }
+    /// 数据是否经过页缓存（见 `fs::pagecache`）
+    fn page_cached(&self) -> bool {
+        false
+    }
 
 
     fn link_cnt(&self) -> SyscallRet {
@@ -42,6 +42,7 @@ pub mod lang_items;
 pub mod logging;
 pub mod mm;
 pub mod sbi;
+pub mod smp;
 pub mod sync;
 pub mod syscall;
 pub mod task;
@@ -63,18 +64,6 @@ use trap::user_task_top;
 use crate::{config::PAGE_SIZE, fs::{open_file, OpenFlags}, mm::{frame_allocator::{frame_alloc_persist, frame_dealloc_persist}, frame_dealloc}};
 use polyhal_boot::define_entry;
 // global_asm!(include_str!("entry.asm"));
-/// clear BSS segment
-fn clear_bss() {
-    extern "C" {
-        fn _sbss();
-        fn _ebss();
-    }
-    unsafe {
-        core::slice::from_raw_parts_mut(_sbss as usize as *mut u8, _ebss as usize -_sbss as usize)
-            .fill(0);
-    }
-
-}
 pub struct PageAllocImpl;
 
 impl polyhal::common::PageAlloc for PageAllocImpl {
@@ -144,8 +133,7 @@ impl polyhal::common::PageAlloc for PageAllocImpl {
 /// the rust entry-point of os
 /// 
 pub fn main(hart_id:usize) -> ! {
-    #[cfg(target_arch="riscv64")]
-    clear_bss();
+    // BSS 已由 polyhal-boot 在启动从核之前清零，这里不能再清，否则会抹掉从核已写入的状态
     println!("[kernel] Hello, !");
     polyhal::irq::IRQ::int_disable();
     println!("dmw1:{:#x},dmw0 :{:#x}",loongArch64::register::dmw1::read().raw(),loongArch64::register::dmw0::read().raw());
@@ -194,6 +182,8 @@ pub fn main(hart_id:usize) -> ! {
     //  task::add_initproc("/musl", "/musl/busybox", "sh /musl/run-static.sh");
     //  task::add_initproc("/libctest", "/glibc/busybox", "sh /libctest/run-static.sh");
     // open_file("/usr/lib", OpenFlags::O_PATH,0).unwrap();
+    info!("[kernel] boot cpu {} done, {} cpus in device tree", hart_id, polyhal::common::get_cpu_num());
+    smp::finish_boot();
     extern  "C" {
         fn trampoline(tc: usize, has_trap: bool, from_user: bool) -> !;
     }
@@ -205,7 +195,30 @@ pub fn main(hart_id:usize) -> ! {
 
 #[no_mangle]
 pub static mut __stack_chk_guard: usize = 0xdead_beef_aaad_beef;
-define_entry!(main);
+define_entry!(main, secondary_main);
+
+/// 从核入口：等主核完成全局初始化后，初始化本核的中断、时钟和内核栈，进入调度循环
+pub fn secondary_main(hart_id: usize) -> ! {
+    smp::wait_for_boot();
+    if hart_id >= config::MAX_CPUS {
+        warn!("[kernel] cpu {} exceeds MAX_CPUS, parked", hart_id);
+        loop {
+            core::hint::spin_loop();
+        }
+    }
+    polyhal::irq::IRQ::int_disable();
+    trap::init();
+    mm::activate_by_token(mm::kernel_token());
+    trap::enable_irqs();
+    timer::set_next_trigger();
+    task::init_secondary();
+    extern "C" {
+        fn trampoline(tc: usize, has_trap: bool, from_user: bool) -> !;
+    }
+    unsafe {
+        trampoline(0, false, false);
+    }
+}
 // 栈溢出检测失败时调用的函数
 #[no_mangle]
 pub extern "C" fn __stack_chk_fail() {
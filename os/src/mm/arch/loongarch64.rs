@@ -88,6 +88,18 @@ impl PageTableEntry {
         bits = bits | flags.bits;
         PageTableEntry { bits }
     }
+    /// 2 MiB 大页的目录项，GH 位在目录项中表示大页
+    pub fn new_huge(ppn: PhysPageNum, flags: PTEFlags) -> Self {
+        Self::new(ppn, flags | PTEFlags::GH)
+    }
+    /// 目录项是否为大页
+    pub fn is_huge(&self) -> bool {
+        self.bits & PTEFlags::GH.bits() != 0
+    }
+    /// 大页中第 `index` 个 4K 页对应的页表项
+    pub fn huge_subpage(&self, index: usize) -> Self {
+        Self::new((self.ppn().0 + index).into(), self.flags() - PTEFlags::GH)
+    }
     // 空页表项
     pub fn empty() -> Self {
         PageTableEntry { bits: 0 }
@@ -48,6 +48,14 @@ impl PageTableEntry {
             bits: ppn.0 << 10 | flags.bits as usize,
         }
     }
+    /// Create a 2 MiB leaf entry in a level-1 page table
+    pub fn new_huge(ppn: PhysPageNum, flags: PTEFlags) -> Self {
+        Self::new(ppn, flags | PTEFlags::V)
+    }
+    /// The 4 KiB entry for the `index`-th page inside a huge page
+    pub fn huge_subpage(&self, index: usize) -> Self {
+        Self::new((self.ppn().0 + index).into(), self.flags())
+    }
     /// Create an empty page table entry
     pub fn empty() -> Self {
         PageTableEntry { bits: 0 }
@@ -4,19 +4,17 @@ use core::{
 };
 
 use alloc::{collections::btree_map::BTreeMap, format, sync::Arc, vec::Vec};
-use linux_raw_sys::general::SEEK_CUR;
-use lwext4_rust::bindings::SEEK_SET;
 
 use crate::{
-    config::{KERNEL_PGNUM_OFFSET, MMAP_BASE, MMAP_PGNUM_TOP, MMAP_TOP, PAGE_SIZE, PAGE_SIZE_BITS, USER_STACK_TOP},
-    fs::{File, FileDescriptor, OsInode},
+    config::{HUGE_PAGE_PAGES, KERNEL_PGNUM_OFFSET, MMAP_BASE, THP_DEFAULT_ENABLED, MMAP_PGNUM_TOP, MMAP_TOP, PAGE_SIZE, PAGE_SIZE_BITS, USER_STACK_TOP},
+    fs::{FileDescriptor, OsInode},
     mm::StepByOne,
     syscall::flags::MmapProt,
     utils::error::{GeneralRet, SyscallRet, TemplateRet},
 };
 
 use super::{
-    flush_all, frame_alloc, PTEFlags, FrameTracker, PageTable, PhysPageNum, VPNRange, VirtAddr, VirtPageNum
+    flush_all, frame_alloc, frame_allocator::frame_alloc_huge, PTEFlags, FrameTracker, PageTable, PhysPageNum, VPNRange, VirtAddr, VirtPageNum
 };
 
 /// 虚拟内存区域树：Key 按照 Range.start_vpn 排序
@@ -320,6 +318,8 @@ pub struct MapArea {
     ///只有Osinnoder才能映射
     pub fd: Option<MmapFile>,
     pub mmap_flags: MmapFlags,
+    /// 是否允许用透明大页映射，由 madvise(MADV_HUGEPAGE / MADV_NOHUGEPAGE) 设置
+    pub hugepage: bool,
 }
 
 impl MapArea {
@@ -341,6 +341,71 @@ impl MapArea {
     pub fn allocated(&self, vpn: VirtPageNum) -> bool {
         self.data_frames.contains_key(&vpn)
     }
+    /// 记录 `vpn` 对应的物理页，新增的页计入常驻页数
+    pub fn insert_frame(
+        &mut self,
+        page_table: &mut PageTable,
+        vpn: VirtPageNum,
+        frame: Arc<FrameTracker>,
+    ) -> Option<Arc<FrameTracker>> {
+        let old = self.data_frames.insert(vpn, frame);
+        if old.is_none() {
+            page_table.rss_add(1);
+        }
+        old
+    }
+    /// 移除 `vpn` 对应的物理页，并从常驻页数中扣除
+    pub fn remove_frame(
+        &mut self,
+        page_table: &mut PageTable,
+        vpn: VirtPageNum,
+    ) -> Option<Arc<FrameTracker>> {
+        let old = self.data_frames.remove(&vpn);
+        if old.is_some() {
+            page_table.rss_sub(1);
+        }
+        old
+    }
+    /// 是否可以换出：只换出不与文件或其他进程共享的匿名页
+    pub fn is_swappable(&self) -> bool {
+        self.map_type == MapType::Framed
+            && self.fd.is_none()
+            && !self.mmap_flags.contains(MmapFlags::MAP_SHARED)
+            && matches!(self.area_type, MapAreaType::Mmap | MapAreaType::Brk | MapAreaType::Stack)
+    }
+    /// 是否可以用透明大页映射：只用于不与文件或其他进程共享的匿名映射和堆
+    pub fn thp_eligible(&self) -> bool {
+        self.hugepage
+            && self.map_type == MapType::Framed
+            && self.fd.is_none()
+            && !self.mmap_flags.contains(MmapFlags::MAP_SHARED)
+            && matches!(self.area_type, MapAreaType::Mmap | MapAreaType::Brk)
+    }
+    /// `vpn` 所在的大页块整个落在区域内且其中还没有分配任何页时，返回块的起始页号
+    pub fn huge_block(&self, vpn: VirtPageNum) -> Option<VirtPageNum> {
+        let start = VirtPageNum(vpn.0 & !(HUGE_PAGE_PAGES - 1));
+        let end = VirtPageNum(start.0 + HUGE_PAGE_PAGES);
+        if !self.thp_eligible() || start < self.start_vpn() || end > self.end_vpn() {
+            return None;
+        }
+        self.data_frames.range(start..end).next().is_none().then_some(start)
+    }
+    /// 分配一个对齐的 2 MiB 物理块，用大页映射从 `start` 开始的 512 页
+    ///
+    /// 物理页仍按 4K 记录在 `data_frames` 中，拆分大页后写时复制、换出和 munmap 不需要区分大页。
+    /// 没有连续的空闲物理内存时返回 `false`，调用者退回到 4K 页。
+    pub fn map_huge(&mut self, page_table: &mut PageTable, start: VirtPageNum) -> bool {
+        let Some(frames) = frame_alloc_huge() else {
+            return false;
+        };
+        if !page_table.map_huge(start, frames[0].ppn(), PTEFlags::from(self.map_perm)) {
+            return false;
+        }
+        for (i, frame) in frames.into_iter().enumerate() {
+            self.insert_frame(page_table, VirtPageNum(start.0 + i), frame);
+        }
+        true
+    }
     pub fn set_fd(&mut self, fd: Option<MmapFile>) {
         self.fd = fd;
     }
@@ -361,6 +426,7 @@ impl MapArea {
             area_type,
             fd: None,
             mmap_flags: MmapFlags::empty(),
+            hugepage: THP_DEFAULT_ENABLED,
         }
     }
     pub fn new_by_vpn(
@@ -378,6 +444,7 @@ impl MapArea {
             area_type,
             fd: None,
             mmap_flags: MmapFlags::empty(),
+            hugepage: THP_DEFAULT_ENABLED,
         }
     }
     pub fn from_another(another: &Self) -> Self {
@@ -390,6 +457,7 @@ impl MapArea {
             fd: another.fd.clone(),
 
             mmap_flags: MmapFlags::empty(),
+            hugepage: another.hugepage,
         }
     }
        /// Map each provided frame to its corresponding VPN.
@@ -420,9 +488,10 @@ impl MapArea {
                 // println!("pte:{:?}",pte.flags());
                 // 记录到 data_frames 中
 
-            self.data_frames.insert(*vpn, frame.clone());
+            self.insert_frame(page_table, *vpn, frame.clone());
             }
         }
+        page_table.flush_remote();
     }
     /// Update area's mapping flags and write it to page table. You need to flush TLB after calling
     /// this function.
@@ -444,7 +513,7 @@ impl MapArea {
                 };
                 ppn = frame.ppn();
 
-                self.data_frames.insert(vpn, frame);
+                self.insert_frame(page_table, vpn, frame);
             }
             MapType::Direct => {
                 ppn = PhysPageNum::from(vpn.raw() - KERNEL_PGNUM_OFFSET);
@@ -454,37 +523,69 @@ impl MapArea {
         page_table.map(vpn, ppn, pte_flags);
         Ok(())
     }
+    /// 把已有的物理页（如页缓存中的页）映射到 `vpn`，`cow` 时以写时复制方式映射
+    pub fn map_frame(
+        &mut self,
+        page_table: &mut PageTable,
+        vpn: VirtPageNum,
+        frame: Arc<FrameTracker>,
+        cow: bool,
+    ) {
+        page_table.map(vpn, frame.ppn(), PTEFlags::from(self.map_perm));
+        if cow {
+            page_table.find_pte(vpn).unwrap().set_cow();
+        }
+        self.insert_frame(page_table, vpn, frame);
+    }
     pub fn unmap_one(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) {
         if self.map_type == MapType::Framed {
-            self.data_frames.remove(&vpn);
+            self.remove_frame(page_table, vpn);
+        }
+        // 懒分配尚未分配或已经换出的页没有映射
+        if page_table.is_mapped(vpn) {
+            page_table.unmap(vpn);
         }
-        page_table.unmap(vpn);
     }
 
     pub fn map(&mut self, page_table: &mut PageTable)->GeneralRet {
-        for vpn in self.vpn_range {
-            self.map_one(page_table, vpn)?
-        }
-        Ok(())
+        let res = self.vpn_range.into_iter().try_for_each(|vpn| self.map_one(page_table, vpn));
+        page_table.flush_remote();
+        res
     }
     pub fn unmap(&mut self, page_table: &mut PageTable) {
         for vpn in self.vpn_range {
             self.unmap_one(page_table, vpn);
         }
+        page_table.flush_remote();
     }
     #[allow(unused)]
     pub fn shrink_to(&mut self, page_table: &mut PageTable, new_end: VirtPageNum) {
         for vpn in VPNRange::new(new_end, self.vpn_range.get_end()) {
             self.unmap_one(page_table, vpn)
         }
+        page_table.flush_remote();
         self.vpn_range = VPNRange::new(self.vpn_range.get_start(), new_end);
     }
     #[allow(unused)]
     pub fn append_to(&mut self, page_table: &mut PageTable, new_end: VirtPageNum)->GeneralRet {
-        for vpn in VPNRange::new(self.vpn_range.get_end(), new_end) {
-            self.map_one(page_table, vpn)?
-        }
+        let old_end = self.vpn_range.get_end();
         self.vpn_range = VPNRange::new(self.vpn_range.get_start(), new_end);
+        let mut vpn = old_end;
+        while vpn < new_end {
+            // 新增部分中完整的大页块直接用大页映射
+            if self.huge_block(vpn) == Some(vpn) && self.map_huge(page_table, vpn) {
+                vpn.0 += HUGE_PAGE_PAGES;
+                continue;
+            }
+            if let Err(e) = self.map_one(page_table, vpn) {
+                // 内存不足：只保留已经映射的部分
+                self.vpn_range = VPNRange::new(self.vpn_range.get_start(), vpn);
+                page_table.flush_remote();
+                return Err(e);
+            }
+            vpn.step();
+        }
+        page_table.flush_remote();
         Ok(())
     }
     /// data: start-aligned but maybe with shorter length
@@ -523,11 +624,19 @@ impl MapArea {
             map_type: self.map_type,
             map_perm: self.map_perm,
             area_type: self.area_type,
-            fd: None,
+            fd: self.fd_from(start_vpn),
 
-            mmap_flags: MmapFlags::empty(),
+            mmap_flags: self.mmap_flags,
+            hugepage: self.hugepage,
         }
     }
+    /// 从 `vpn` 开始的子区域对应的文件映射，文件偏移随之后移
+    fn fd_from(&self, vpn: VirtPageNum) -> Option<MmapFile> {
+        self.fd.clone().map(|mut mmap_file| {
+            mmap_file.offset += (vpn.raw() - self.start_vpn().raw()) << PAGE_SIZE_BITS;
+            mmap_file
+        })
+    }
     pub fn start_vpn(&self) -> VirtPageNum {
         self.vpn_range.get_start()
     }
@@ -564,18 +673,7 @@ impl MapArea {
         let mid_data_frames = self.data_frames.split_off(&start_vpn);
         //    self.pages 现在只剩下 key < start（就是左段）
         // 3. 准备中段的 backend
-        let mid_file = match self.fd.clone() {
-            Some(mut mmap_file) => {
-                let off = (start_vpn.raw() - self.start_vpn().raw()) << PAGE_SIZE_BITS;
-                //目前 mmap_file一定是OsInode
-                let file = mmap_file.file.file().unwrap();
-                let new_off=file.lseek(off as isize, SEEK_CUR).unwrap() ;
-                file.lseek((new_off-off) as isize, SEEK_SET).unwrap();
-                mmap_file.offset=new_off;
-                Some(mmap_file)
-            }
-            None => None,
-        };
+        let mid_file = self.fd_from(start_vpn);
 
        
 
@@ -588,21 +686,11 @@ impl MapArea {
             area_type: self.area_type,
             fd: mid_file,
             mmap_flags: self.mmap_flags,
+            hugepage: self.hugepage,
         };
 
         // 5. 准备右段的 backend
-        let right_file = match self.fd.clone() {
-            Some(mut mmap_file) => {
-                let off = (end_vpn.raw() - self.start_vpn().raw()) << PAGE_SIZE_BITS;
-                //目前 mmap_file一定是OsInode
-                let file = mmap_file.file.file().unwrap();
-                let new_off=file.lseek(off as isize, SEEK_CUR).unwrap();
-                file.lseek((new_off-off) as isize, SEEK_SET).unwrap();
-                mmap_file.offset=new_off;
-                Some(mmap_file)
-            }
-            None => None,
-        };
+        let right_file = self.fd_from(end_vpn);
 
 
         // 6. 构造 right 区域
@@ -614,6 +702,7 @@ impl MapArea {
             area_type: self.area_type,
             fd: right_file,
             mmap_flags: self.mmap_flags,
+            hugepage: self.hugepage,
         };
         //修改left区域
         self.vpn_range.set_end(start_vpn);
@@ -624,18 +713,7 @@ impl MapArea {
     pub async fn split(&mut self, vpn: VirtPageNum) -> Self {
         let right_data_frames = self.data_frames.split_off(&vpn);
         //  准备mmap_file
-        let right_file = match self.fd.clone() {
-            Some(mut mmap_file) => {
-                let off = (vpn.raw() - self.start_vpn().raw()) << PAGE_SIZE_BITS;
-                //目前 mmap_file一定是OsInode
-                let file = mmap_file.file.file().unwrap();
-                let new_off=file.lseek(off as isize, SEEK_CUR).unwrap() ;
-                file.lseek((new_off-off) as isize, SEEK_SET).unwrap();
-                mmap_file.offset=new_off;
-                Some(mmap_file)
-            }
-            None => None,
-        };
+        let right_file = self.fd_from(vpn);
 
         // 6. 构造 right 区域
         let right = MapArea {
@@ -646,6 +724,7 @@ impl MapArea {
             area_type: self.area_type,
             fd: right_file,
             mmap_flags: self.mmap_flags,
+            hugepage: self.hugepage,
         };
         //修改left区域
         self.vpn_range.set_end(vpn);
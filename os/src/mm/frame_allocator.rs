@@ -1,18 +1,25 @@
 // os/src/mm/frame_allocator.rs
 
-use crate::config::{PAGE_SIZE, PAGE_SIZE_BITS};
+use crate::config::{
+    FRAME_MAX_ORDER, FRAME_PCP_BATCH, FRAME_PCP_HIGH, HUGE_PAGE_PAGES, MAX_CPUS, OOM_RESERVE_FRAMES, PAGE_SIZE,
+    PAGE_SIZE_BITS,
+};
 use crate::mm::PhysPageNum;
+use crate::smp::cpu_id;
 use crate::sync::UPSafeCell;
-use crate::task::current_task_may_uninit;
 use crate::utils::bpoint;
 use alloc::collections::btree_map::BTreeMap;
+use alloc::format;
+use alloc::string::String;
 use alloc::sync::Arc;
+use alloc::vec;
 use alloc::vec::Vec;
-use core::fmt::{self, Debug, Formatter};
-use bit_field::{BitArray, BitField};
+use core::cmp::{max, min};
+use core::fmt::{self, Debug, Formatter, Write};
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use lazy_static::lazy_static;
 use log::{error, info};
-use spin::Mutex; 
+use spin::{Mutex, RwLock};
 use crate::mm::{PhysAddr};
  #[derive(Clone)]
 pub struct FrameTracker {
@@ -59,154 +66,280 @@ impl Drop for FrameTracker {
     }
 }
 
-/// 页帧分布图
-/// 利用位图管理一个连续的物理内存区域
-#[derive(Debug)]
-struct FrameRegionMap {
-    bits: Vec<usize>,
+/// 分配器的累计统计
+struct FrameStats {
+    /// 从伙伴系统分配的块数
+    allocs: AtomicUsize,
+    /// 归还给伙伴系统的页数
+    frees: AtomicUsize,
+    /// 拆分大块的次数
+    splits: AtomicUsize,
+    /// 与伙伴合并的次数
+    merges: AtomicUsize,
+    /// 分配失败的次数
+    failures: AtomicUsize,
+    /// 直接由每 CPU 页缓存满足的单页分配
+    pcp_hits: AtomicUsize,
+    /// 每 CPU 页缓存从伙伴系统补充的次数
+    pcp_refills: AtomicUsize,
+    /// 每 CPU 页缓存归还给伙伴系统的次数
+    pcp_drains: AtomicUsize,
+}
+
+impl FrameStats {
+    const fn new() -> Self {
+        Self {
+            allocs: AtomicUsize::new(0),
+            frees: AtomicUsize::new(0),
+            splits: AtomicUsize::new(0),
+            merges: AtomicUsize::new(0),
+            failures: AtomicUsize::new(0),
+            pcp_hits: AtomicUsize::new(0),
+            pcp_refills: AtomicUsize::new(0),
+            pcp_drains: AtomicUsize::new(0),
+        }
+    }
+}
+
+static STATS: FrameStats = FrameStats::new();
+
+fn bump(counter: &AtomicUsize, n: usize) {
+    counter.fetch_add(n, Ordering::Relaxed);
+}
+
+/// 空闲链表的结束标记
+const NIL: usize = usize::MAX;
+
+/// `BuddyZone::state` 中不是空闲块首页的页
+const NOT_HEAD: u8 = u8::MAX;
+
+/// 空闲块首页开头存放的双向链表指针（页号），空闲页本身没有别的用途，链表不占用堆内存
+#[repr(C)]
+struct FreeLink {
+    next: usize,
+    prev: usize,
+}
+
+/// 伙伴系统管理的一个连续物理内存区域
+///
+/// 阶为 k 的块包含 2^k 页，起始页号按 2^k 对齐；两个同阶的块只在第 k 位不同时互为伙伴，
+/// 释放时若伙伴也空闲就合并为 k + 1 阶的块，一直合并到伙伴不空闲或超出区域为止。
+struct BuddyZone {
     start_ppn: usize,
     end_ppn: usize,
+    /// 每一阶空闲链表的第一个块
+    heads: [usize; FRAME_MAX_ORDER + 1],
+    /// 每一阶空闲块的个数
+    counts: [usize; FRAME_MAX_ORDER + 1],
+    /// 每页一项：空闲块的首页记录块的阶，其余页为 `NOT_HEAD`
+    state: Vec<u8>,
+    /// 空闲页数
+    free: usize,
+}
+
+impl Debug for BuddyZone {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BuddyZone")
+            .field("start_ppn", &format_args!("{:#x}", self.start_ppn))
+            .field("end_ppn", &format_args!("{:#x}", self.end_ppn))
+            .field("counts", &self.counts)
+            .field("free", &self.free)
+            .finish()
+    }
 }
 
-impl FrameRegionMap {
-    /// 创建页帧分布图
+impl BuddyZone {
+    /// 建立区域并把其中的页全部放入空闲链表；`state` 在这里一次分配好，
+    /// 调用者应在获取分配器的锁之前调用
     fn new(start_paddr: PhysAddr, end_paddr: PhysAddr) -> Self {
         let start_ppn = start_paddr.floor().0;
         let end_ppn = end_paddr.floor().0;
-        let num_pages = end_ppn - start_ppn;
-        // 使用 .div_ceil() 来确保分配足够的 usize 来存储所有位
-        let mut bits = alloc::vec![0usize; num_pages.div_ceil(usize::BITS as usize)];
-
-        // 将超出实际内存范围的位标记为已使用，防止分配到不存在的内存
-        for i in num_pages..(bits.len() * usize::BITS as usize) {
-            bits.set_bit(i, true);
-        }
-
-        Self {
-            bits,
+        let mut zone = Self {
             start_ppn,
             end_ppn,
+            heads: [NIL; FRAME_MAX_ORDER + 1],
+            counts: [0; FRAME_MAX_ORDER + 1],
+            state: vec![NOT_HEAD; end_ppn - start_ppn],
+            free: 0,
+        };
+        zone.free_range(start_ppn, end_ppn);
+        zone
+    }
+
+    fn contains(&self, ppn: usize) -> bool {
+        self.start_ppn <= ppn && ppn < self.end_ppn
+    }
+
+    fn link(ppn: usize) -> &'static mut FreeLink {
+        PhysAddr::from(PhysPageNum(ppn)).get_mut()
+    }
+
+    /// `start` 是否是一个 `order` 阶空闲块的首页
+    fn is_free_head(&self, start: usize, order: usize) -> bool {
+        self.state[start - self.start_ppn] == order as u8
+    }
+
+    /// 把 `order` 阶的块放到空闲链表头部
+    fn push(&mut self, start: usize, order: usize) {
+        let next = self.heads[order];
+        *Self::link(start) = FreeLink { next, prev: NIL };
+        if next != NIL {
+            Self::link(next).prev = start;
         }
+        self.heads[order] = start;
+        self.counts[order] += 1;
+        self.state[start - self.start_ppn] = order as u8;
     }
 
-    /// 申请一个空闲页
-    fn alloc(&mut self, _policy: usize) -> Option<PhysPageNum> {
-        // TODO: policy 可以用来决定是从头找还是从尾找，这里暂时只实现一种
-        for i in 0..self.bits.len() {
-            if self.bits[i] != usize::MAX { // 如果这个 usize 块中还有空位
-                for bit_index in 0..usize::BITS as usize {
-                    if !self.bits[i].get_bit(bit_index) {
-                        self.bits[i].set_bit(bit_index, true);
-                        let page_offset = i * (usize::BITS as usize) + bit_index;
-                        return Some(PhysPageNum::from(self.start_ppn + page_offset));
-                    }
-                }
-            }
+    /// 把 `order` 阶的空闲块从链表中摘下
+    fn remove(&mut self, start: usize, order: usize) {
+        let FreeLink { next, prev } = *Self::link(start);
+        if prev == NIL {
+            self.heads[order] = next;
+        } else {
+            Self::link(prev).next = next;
         }
-        None
+        if next != NIL {
+            Self::link(next).prev = prev;
+        }
+        self.counts[order] -= 1;
+        self.state[start - self.start_ppn] = NOT_HEAD;
     }
 
-    /// 申请多个连续的空闲页
-    fn alloc_contiguous(&mut self, count: usize, _policy: usize) -> Option<Vec<FrameTracker>> {
-        if count == 0 { return Some(Vec::new()); }
-        
-        let num_pages = self.end_ppn - self.start_ppn;
-        if count > num_pages { return None; }
-        
-        let mut found_start = 0;
-        let mut consecutive_free = 0;
-
-        for i in 0..num_pages {
-            if !self.bits.get_bit(i) {
-                if consecutive_free == 0 {
-                    found_start = i;
-                }
-                consecutive_free += 1;
-                if consecutive_free == count {
-                    // 找到了！标记并分配
-                    let mut frames = Vec::with_capacity(count);
-                    for j in 0..count {
-                        let bit_index = found_start + j;
-                        self.bits.set_bit(bit_index, true);
-                        frames.push(FrameTracker::new(PhysPageNum(self.start_ppn + bit_index)));
-                    }
-                    return Some(frames);
-                }
-            } else {
-                consecutive_free = 0;
+    /// 把 [start, end) 拆成尽量大的对齐块放回空闲链表
+    fn free_range(&mut self, mut start: usize, end: usize) {
+        while start < end {
+            let mut order = min(start.trailing_zeros() as usize, FRAME_MAX_ORDER);
+            while start + (1 << order) > end {
+                order -= 1;
             }
+            self.free_block(start, order);
+            start += 1 << order;
         }
-        None // 没有找到足够大的连续块
     }
 
-    /// 释放一个已经使用的页
-    fn dealloc(&mut self, ppn: PhysPageNum) {
-        let bit_index = ppn.raw() - self.start_ppn;
-        if self.bits.get_bit(bit_index) {
-            self.bits.set_bit(bit_index, false);
-        } else {
-            panic!("Deallocating a frame that was not allocated: PPN {:#x}", ppn.raw());
+    /// 分配一个 `order` 阶的块，没有时拆分更大的块，返回起始页号
+    fn alloc_block(&mut self, order: usize) -> Option<usize> {
+        let mut found = (order..=FRAME_MAX_ORDER).find(|&k| self.heads[k] != NIL)?;
+        let start = self.heads[found];
+        self.remove(start, found);
+        // 拆下来的后一半放回低一阶的空闲链表
+        while found > order {
+            found -= 1;
+            self.push(start + (1 << found), found);
+            bump(&STATS.splits, 1);
         }
+        self.free -= 1 << order;
+        Some(start)
     }
-    
-    /// 获取空闲页数量
-    fn free_pages(&self) -> usize {
-        self.bits.iter().map(|&word| word.count_zeros() as usize).sum()
+
+    /// 释放一个 `order` 阶的块，并尽量与伙伴合并
+    fn free_block(&mut self, mut start: usize, mut order: usize) {
+        // 任意阶的空闲块包含 `start` 都说明重复释放：包含它的 k 阶块首页只能是 `start` 按 2^k 向下对齐
+        for k in 0..=FRAME_MAX_ORDER {
+            let head = start & !((1 << k) - 1);
+            if head < self.start_ppn {
+                break;
+            }
+            if self.is_free_head(head, k) {
+                panic!("Deallocating a frame that was not allocated: PPN {:#x}", start);
+            }
+        }
+        self.free += 1 << order;
+        while order < FRAME_MAX_ORDER {
+            let buddy = start ^ (1 << order);
+            if !self.contains(buddy)
+                || buddy + (1 << order) > self.end_ppn
+                || !self.is_free_head(buddy, order)
+            {
+                break;
+            }
+            self.remove(buddy, order);
+            start = min(start, buddy);
+            order += 1;
+            bump(&STATS.merges, 1);
+        }
+        self.push(start, order);
     }
 }
 
 /// 一个总的页帧分配器，可以管理多个不连续的内存区域
+///
+/// 所有区域都属于同一个内存节点，NUMA 内存策略不影响从哪里分配
 #[derive(Debug)]
 pub struct FrameAllocator {
-    regions: Vec<FrameRegionMap>,
+    zones: Vec<BuddyZone>,
+    /// 伙伴系统中的空闲页数，不含每 CPU 页缓存中的页
+    free: usize,
 }
 
 impl FrameAllocator {
     pub const fn new() -> Self {
         Self {
-            regions: Vec::new(),
+            zones: Vec::new(),
+            free: 0,
         }
     }
 
     /// 添加一块可供分配的物理内存区域
-    pub fn add_memory_region(&mut self, start_paddr: PhysAddr, end_paddr: PhysAddr) {
-        info!(
-            "FrameAllocator: adding region [{:#x}, {:#x})",
-            start_paddr.0, end_paddr.0
-        );
-        self.regions.push(FrameRegionMap::new(start_paddr, end_paddr));
+    fn add_zone(&mut self, zone: BuddyZone) {
+        self.free += zone.free;
+        self.zones.push(zone);
     }
 
-    pub fn alloc(&mut self, policy: usize) -> Option<PhysPageNum> {
-        for region in &mut self.regions {
-            if let Some(ppn) = region.alloc(policy) {
-                return Some(ppn);
+    fn alloc_block(&mut self, order: usize) -> Option<usize> {
+        for zone in &mut self.zones {
+            if let Some(start) = zone.alloc_block(order) {
+                self.free -= 1 << order;
+                bump(&STATS.allocs, 1);
+                return Some(start);
             }
         }
         None
     }
 
-    pub fn dealloc(&mut self, ppn: PhysPageNum) {
-        for region in &mut self.regions {
-            if ppn.raw() >= region.start_ppn && ppn.raw() < region.end_ppn {
-                region.dealloc(ppn);
-                return;
-            }
+    fn zone_of(&mut self, ppn: usize) -> &mut BuddyZone {
+        match self.zones.iter_mut().find(|zone| zone.contains(ppn)) {
+            Some(zone) => zone,
+            None => panic!("Deallocating a frame in an unknown memory region: PPN {:#x}", ppn),
         }
-        panic!("Deallocating a frame in an unknown memory region: PPN {:#x}", ppn.raw());
     }
-    
-    pub fn alloc_contiguous(&mut self, count: usize, policy: usize) -> Option<Vec<FrameTracker>> {
-        for region in &mut self.regions {
-            if let Some(frames) = region.alloc_contiguous(count, policy) {
-                return Some(frames);
-            }
+
+    pub fn alloc(&mut self) -> Option<PhysPageNum> {
+        self.alloc_block(0).map(PhysPageNum)
+    }
+
+    pub fn dealloc(&mut self, ppn: PhysPageNum) {
+        self.zone_of(ppn.raw()).free_block(ppn.raw(), 0);
+        self.free += 1;
+        bump(&STATS.frees, 1);
+    }
+
+    /// 分配 `count` 个连续页，起始页号按 `align` 页对齐
+    ///
+    /// 从伙伴系统取一个足够大的块，多出的尾部立即归还
+    pub fn alloc_contiguous(&mut self, count: usize, align: usize) -> Option<PhysPageNum> {
+        assert!(count > 0 && align.is_power_of_two());
+        let order = max(
+            count.next_power_of_two().trailing_zeros(),
+            align.trailing_zeros(),
+        ) as usize;
+        if order > FRAME_MAX_ORDER {
+            return None;
         }
-        None
+        let start = self.alloc_block(order)?;
+        let end = start + (1 << order);
+        self.zone_of(start).free_range(start + count, end);
+        self.free += end - start - count;
+        Some(PhysPageNum(start))
     }
 
     pub fn remaining_frames(&self) -> usize {
-        self.regions.iter().map(|r| r.free_pages()).sum()
+        self.free
+    }
+
+    pub fn total_frames(&self) -> usize {
+        self.zones.iter().map(|z| z.end_ppn - z.start_ppn).sum()
     }
 }
 
@@ -222,6 +355,78 @@ lazy_static! {
          Mutex::new(FrameAllocator::new());
 }
 
+/// 空闲页数：伙伴系统中的加上各 CPU 页缓存中的
+static FREE_FRAMES: AtomicUsize = AtomicUsize::new(0);
+
+/// 可分配的总页数；OOM 报告在内核堆耗尽时调用，不能获取分配器的锁
+static TOTAL_FRAMES: AtomicUsize = AtomicUsize::new(0);
+
+/// 每个 CPU 的单页缓存，大多数单页分配和释放不需要获取全局分配器的锁
+static PAGE_CACHES: [Mutex<PcpList>; MAX_CPUS] = [const { Mutex::new(PcpList::new()) }; MAX_CPUS];
+
+/// 一个 CPU 的页缓存，容量固定，放入和取出都不分配堆内存
+struct PcpList {
+    pages: [usize; FRAME_PCP_HIGH + 1],
+    len: usize,
+}
+
+impl PcpList {
+    const fn new() -> Self {
+        Self { pages: [0; FRAME_PCP_HIGH + 1], len: 0 }
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn push(&mut self, ppn: usize) {
+        self.pages[self.len] = ppn;
+        self.len += 1;
+    }
+
+    fn pop(&mut self) -> Option<usize> {
+        if self.len == 0 {
+            return None;
+        }
+        self.len -= 1;
+        Some(self.pages[self.len])
+    }
+
+    /// 只保留前 `keep` 页，返回被移出的页
+    fn drain_from(&mut self, keep: usize) -> &[usize] {
+        let len = self.len;
+        self.len = keep;
+        &self.pages[keep..len]
+    }
+}
+
+/// 一个内存区域中每页是否在某个 CPU 的页缓存中
+///
+/// 放入页缓存的页不经过伙伴系统，靠它发现重复释放；只用原子操作，不需要分配器的锁
+struct PcpMarks {
+    start_ppn: usize,
+    marks: Vec<AtomicBool>,
+}
+
+/// 各内存区域的 [`PcpMarks`]，只在添加区域时写
+static PCP_MARKS: RwLock<Vec<PcpMarks>> = RwLock::new(Vec::new());
+
+/// 设置 `ppn` 是否在页缓存中，返回原来的值
+fn set_cached(ppn: usize, cached: bool) -> bool {
+    let zones = PCP_MARKS.read();
+    let Some(zone) = zones
+        .iter()
+        .find(|zone| zone.start_ppn <= ppn && ppn < zone.start_ppn + zone.marks.len())
+    else {
+        panic!("Deallocating a frame in an unknown memory region: PPN {:#x}", ppn);
+    };
+    zone.marks[ppn - zone.start_ppn].swap(cached, Ordering::Relaxed)
+}
+
 /// 初始化帧分配器，由外部调用者负责添加内存区域
 pub fn init_frame_allocator() {
     // 这里的逻辑现在由外部的 add_memory_region 调用来完成
@@ -237,48 +442,252 @@ pub fn init_frame_allocator() {
 
 /// 添加一块可供分配的物理内存区域 (来自 ByteOS 的接口)
 pub fn add_memory_region(start_paddr: PhysAddr, end_paddr: PhysAddr) {
-
-    FRAME_ALLOCATOR.lock().add_memory_region(start_paddr, end_paddr);
     info!(
-        "Total free pages: {}",
-        FRAME_ALLOCATOR.lock().remaining_frames()
+        "FrameAllocator: adding region [{:#x}, {:#x})",
+        start_paddr.0, end_paddr.0
     );
+    // 区域的页状态数组在堆上分配，不能在分配器的锁内进行
+    let zone = BuddyZone::new(start_paddr, end_paddr);
+    let marks = (zone.start_ppn..zone.end_ppn).map(|_| AtomicBool::new(false)).collect();
+    PCP_MARKS.write().push(PcpMarks { start_ppn: zone.start_ppn, marks });
+    let mut allocator = FRAME_ALLOCATOR.lock();
+    let before = allocator.remaining_frames();
+    allocator.add_zone(zone);
+    FREE_FRAMES.fetch_add(allocator.remaining_frames() - before, Ordering::AcqRel);
+    TOTAL_FRAMES.store(allocator.total_frames(), Ordering::Release);
+    drop(allocator);
+    info!("Total free pages: {}", remaining_frames());
 }
 
 /// 分配单个物理页帧 (保持您的接口)
+///
+/// 不会动用为 OOM 处理保留的页帧，返回 `None` 时调用者应走换出或 OOM 流程
 pub fn frame_alloc() -> Option<Arc<FrameTracker>> {
-    let policy = match current_task_may_uninit() {
-        Some(task) => task.get_noma_policy(),
-        None => 0,
+    alloc_frame_above(OOM_RESERVE_FRAMES)
+}
+
+/// 分配页表等内核必需的页帧，可以动用保留页帧，
+/// 保证内存耗尽时仍能建立页表、把被杀的进程调度起来
+pub fn frame_alloc_reserved() -> Option<Arc<FrameTracker>> {
+    alloc_frame_above(0)
+}
+
+/// 从空闲页计数中预留 `count` 页，空闲页不足 `count + reserve` 时失败
+fn take_free(count: usize, reserve: usize) -> bool {
+    let taken = FREE_FRAMES
+        .fetch_update(Ordering::AcqRel, Ordering::Acquire, |free| {
+            (free >= count + reserve).then(|| free - count)
+        })
+        .is_ok();
+    if !taken {
+        bump(&STATS.failures, 1);
+    }
+    taken
+}
+
+/// 预留成功后却没能分配到页（碎片或被其他 CPU 缓存），把预留还回去
+fn untake_free(count: usize) {
+    FREE_FRAMES.fetch_add(count, Ordering::AcqRel);
+    bump(&STATS.failures, 1);
+}
+
+/// 空闲页数多于 `reserve` 时分配一页
+fn alloc_frame_above(reserve: usize) -> Option<Arc<FrameTracker>> {
+    if !take_free(1, reserve) {
+        return None;
+    }
+    let Some(ppn) = alloc_cached() else {
+        untake_free(1);
+        return None;
     };
-    FRAME_ALLOCATOR
-        .lock()
-        .alloc(policy)
-        .map(FrameTracker::new)
-        .map(Arc::new)
+    Some(Arc::new(FrameTracker::new(ppn)))
+}
+
+/// 当前 CPU 的页缓存
+fn local_cache() -> &'static Mutex<PcpList> {
+    &PAGE_CACHES[cpu_id() % MAX_CPUS]
+}
+
+/// 从当前 CPU 的页缓存取一页，缓存空了从伙伴系统补充一批
+///
+/// 空闲页全在其他 CPU 的缓存中时，把所有缓存归还给伙伴系统后再分配
+fn alloc_cached() -> Option<PhysPageNum> {
+    let mut cache = local_cache().lock();
+    if let Some(ppn) = cache.pop() {
+        set_cached(ppn, false);
+        bump(&STATS.pcp_hits, 1);
+        return Some(PhysPageNum(ppn));
+    }
+    let mut allocator = FRAME_ALLOCATOR.lock();
+    while cache.len() < FRAME_PCP_BATCH {
+        match allocator.alloc() {
+            Some(ppn) => {
+                set_cached(ppn.raw(), true);
+                cache.push(ppn.raw());
+            }
+            None => break,
+        }
+    }
+    if let Some(ppn) = cache.pop() {
+        set_cached(ppn, false);
+        bump(&STATS.pcp_refills, 1);
+        return Some(PhysPageNum(ppn));
+    }
+    drop(allocator);
+    drop(cache);
+    drain_page_caches();
+    FRAME_ALLOCATOR.lock().alloc()
+}
+
+/// 把所有 CPU 页缓存中的页归还给伙伴系统，以便合并出连续的大块
+fn drain_page_caches() {
+    for cache in PAGE_CACHES.iter() {
+        let mut cache = cache.lock();
+        if cache.is_empty() {
+            continue;
+        }
+        let mut allocator = FRAME_ALLOCATOR.lock();
+        for &ppn in cache.drain_from(0) {
+            set_cached(ppn, false);
+            allocator.dealloc(PhysPageNum(ppn));
+        }
+        bump(&STATS.pcp_drains, 1);
+    }
 }
 
 /// 释放单个物理页帧 (保持您的接口)
+///
+/// 先放入当前 CPU 的页缓存，缓存超过上限时归还一批给伙伴系统。
+/// 已在某个页缓存中的页再次释放时 panic，已回到伙伴系统的页由伙伴系统检查
 pub fn frame_dealloc(ppn: PhysPageNum) {
-    FRAME_ALLOCATOR.lock().dealloc(ppn);
+    let mut cache = local_cache().lock();
+    if set_cached(ppn.raw(), true) {
+        panic!("Deallocating a frame that was not allocated: PPN {:#x}", ppn.raw());
+    }
+    cache.push(ppn.raw());
+    if cache.len() > FRAME_PCP_HIGH {
+        let mut allocator = FRAME_ALLOCATOR.lock();
+        let keep = cache.len() - FRAME_PCP_BATCH;
+        for &ppn in cache.drain_from(keep) {
+            set_cached(ppn, false);
+            allocator.dealloc(PhysPageNum(ppn));
+        }
+        bump(&STATS.pcp_drains, 1);
+    }
+    drop(cache);
+    FREE_FRAMES.fetch_add(1, Ordering::AcqRel);
 }
 
 /// 分配多个连续的物理页帧 (适配您的接口)
 pub fn frame_alloc_continue(count: usize) -> Option<Vec<Arc<FrameTracker>>> {
-    let policy = match current_task_may_uninit() {
-        Some(task) => task.get_noma_policy(),
-        None => 0,
-    };
+    alloc_contiguous_aligned(count, 1)
+}
 
-    FRAME_ALLOCATOR
-        .lock()
-        .alloc_contiguous(count, policy)
-        .map(|vec| vec.into_iter().map(Arc::new).collect())
+/// 分配一个透明大页所需的、按大页对齐的连续物理页帧
+pub fn frame_alloc_huge() -> Option<Vec<Arc<FrameTracker>>> {
+    alloc_contiguous_aligned(HUGE_PAGE_PAGES, HUGE_PAGE_PAGES)
+}
+
+/// 分配 `count` 个起始页号按 `align` 页对齐的连续页帧，不动用保留页帧
+fn alloc_contiguous_aligned(count: usize, align: usize) -> Option<Vec<Arc<FrameTracker>>> {
+    if count == 0 {
+        return Some(Vec::new());
+    }
+    if !take_free(count, OOM_RESERVE_FRAMES) {
+        return None;
+    }
+    let start = FRAME_ALLOCATOR.lock().alloc_contiguous(count, align);
+    let start = start.or_else(|| {
+        // 缓存中的单页可能正好拆散了需要的块
+        drain_page_caches();
+        FRAME_ALLOCATOR.lock().alloc_contiguous(count, align)
+    });
+    let Some(start) = start else {
+        untake_free(count);
+        return None;
+    };
+    Some(
+        (0..count)
+            .map(|i| Arc::new(FrameTracker::new(PhysPageNum(start.raw() + i))))
+            .collect(),
+    )
 }
 
 /// 获取剩余页数 (这是新的、来自 ByteOS 的功能)
+///
+/// 包括伙伴系统和各 CPU 页缓存中的空闲页
 pub fn remaining_frames() -> usize {
-    FRAME_ALLOCATOR.lock().remaining_frames()
+    FREE_FRAMES.load(Ordering::Acquire)
+}
+
+/// 获取可分配的总页数
+pub fn total_frames() -> usize {
+    TOTAL_FRAMES.load(Ordering::Acquire)
+}
+
+/// 各 CPU 页缓存中的页数之和
+fn cached_frames() -> usize {
+    PAGE_CACHES.iter().map(|cache| cache.lock().len()).sum()
+}
+
+/// /proc/buddyinfo 的内容：每个区域中各阶空闲块的个数
+pub fn buddyinfo() -> String {
+    let allocator = FRAME_ALLOCATOR.lock();
+    let mut s = String::new();
+    for zone in &allocator.zones {
+        let _ = write!(s, "Node 0, zone   Normal ");
+        for count in &zone.counts {
+            let _ = write!(s, "{:>6} ", count);
+        }
+        s.push('\n');
+    }
+    s
+}
+
+/// /proc/lingos/frame_allocator 的内容：使用情况、累计统计和各阶的碎片化程度
+///
+/// `unusable` 是不可用空闲空间指数：空闲页中因为不在足够大的块里、
+/// 无法满足该阶分配的比例，越接近 1 碎片越严重。
+pub fn report() -> String {
+    let cached = cached_frames();
+    let allocator = FRAME_ALLOCATOR.lock();
+    let mut s = format!(
+        "total\t{}\nfree\t{}\ncached\t{}\nallocs\t{}\nfrees\t{}\nsplits\t{}\nmerges\t{}\nfailures\t{}\npcp_hits\t{}\npcp_refills\t{}\npcp_drains\t{}\n",
+        allocator.total_frames(),
+        remaining_frames(),
+        cached,
+        STATS.allocs.load(Ordering::Relaxed),
+        STATS.frees.load(Ordering::Relaxed),
+        STATS.splits.load(Ordering::Relaxed),
+        STATS.merges.load(Ordering::Relaxed),
+        STATS.failures.load(Ordering::Relaxed),
+        STATS.pcp_hits.load(Ordering::Relaxed),
+        STATS.pcp_refills.load(Ordering::Relaxed),
+        STATS.pcp_drains.load(Ordering::Relaxed),
+    );
+    let mut blocks = [0usize; FRAME_MAX_ORDER + 1];
+    for zone in &allocator.zones {
+        for (order, count) in zone.counts.iter().enumerate() {
+            blocks[order] += count;
+        }
+    }
+    let free = allocator.remaining_frames();
+    s.push_str("order\tblocks\tpages\tunusable\n");
+    for order in 0..=FRAME_MAX_ORDER {
+        // 能满足该阶分配的空闲页：所有不小于该阶的块
+        let usable: usize = (order..=FRAME_MAX_ORDER).map(|k| blocks[k] << k).sum();
+        let permille = if free == 0 { 0 } else { (free - usable) * 1000 / free };
+        let _ = writeln!(
+            s,
+            "{}\t{}\t{}\t{}.{:03}",
+            order,
+            blocks[order],
+            blocks[order] << order,
+            permille / 1000,
+            permille % 1000
+        );
+    }
+    s
 }
 
 /// 申请一个持久化存在的物理页，它不会被自动回收。
@@ -8,7 +8,10 @@ static HEAP_ALLOCATOR: LockedHeap = LockedHeap::empty();
 
 #[alloc_error_handler]
 /// panic when heap allocation error occurs
+///
+/// 内核堆是静态数组，杀进程也无法在这里同步地腾出空间，只打印内存报告
 pub fn handle_alloc_error(layout: core::alloc::Layout) -> ! {
+    super::oom::report();
     panic!("Heap allocation error, layout = {:?}", layout);
 }
 /// heap space ([u8; KERNEL_HEAP_SIZE])
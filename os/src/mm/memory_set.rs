@@ -1,5 +1,5 @@
 //! Implementation of [`MapArea`] and [`MemorySet`].
-use crate::config::{ DL_INTERP_OFFSET, KERNEL_DIRECT_OFFSET, MMAP_PGNUM_TOP, PAGE_SIZE_BITS};
+use crate::config::{ DL_INTERP_OFFSET, HUGE_PAGE_PAGES, KERNEL_DIRECT_OFFSET, MMAP_PGNUM_TOP, PAGE_SIZE_BITS};
 use crate::fs::{map_dynamic_link_file, open_file, File, OpenFlags, NONE_MODE};
 use crate::mm::shm::SHM_MANAGER;
 use crate::mm::{ area, translated_byte_buffer, FrameTracker, UserBuffer, VPNRange, KERNEL_PAGE_TABLE_TOKEN};
@@ -10,7 +10,8 @@ use crate::utils::error::{GeneralRet, SysErrNo, SyscallRet, TemplateRet};
 use super::area::{MapArea, MapAreaType, MapPermission, MapType, VmAreaTree};
 use super::page_table::{ PutDataError, PutDataRet};
 use super::{flush_all, KernelAddr, MmapFlags, PhysAddr, StepByOne, TranslateError, VirtAddr, VirtPageNum};
-use super::{PageTable, PageTableEntry};
+use super::rlimit::MemLimits;
+use super::{frame_alloc, swap, PTEFlags, PageTable, PageTableEntry};
 use crate::config::{MEMORY_END, MMIO, PAGE_SIZE,/*  TRAMPOLINE, TRAP_CONTEXT_BASE,*/};
 use alloc::collections::btree_map::{BTreeMap};
 use alloc::format;
@@ -19,11 +20,8 @@ use alloc::sync::Arc;
 use alloc::vec::Vec;
 use lwext4_rust::bindings::{SEEK_CUR, SEEK_SET};
 use xmas_elf::ElfFile;
-use core::arch::asm;
-use core::ops::Range;
+use core::ops::{Bound, Range};
 use core::{ptr, slice};
-#[cfg(target_arch = "riscv64")]
-use riscv::register::satp;
 
 extern "C" {
     fn stext();
@@ -48,6 +46,12 @@ pub struct MemorySet {
     pub page_table: PageTable,
     ///memoryset的区域
     pub areatree: VmAreaTree,
+    /// 换出时的时钟指针
+    swap_hand: VirtPageNum,
+    /// 内存相关的资源限制
+    pub limits: MemLimits,
+    /// mlock 锁定的页段 [start, end)，互不重叠且不相邻
+    mlocked: BTreeMap<VirtPageNum, VirtPageNum>,
 }
 
 // 新建 PageFaultError 枚举，把原来所有 `return false` 的情况都列出来
@@ -62,6 +66,10 @@ pub enum PageFaultError {
     AlreadyAllocated,
     /// 虚拟页号虽在 area.vpn_range 内，但不满足懒分配条件
     VpnNotHandled,
+    /// 分配不到物理页
+    OutOfMemory,
+    /// 栈的使用超出 RLIMIT_STACK
+    StackOverflow,
     __,
 }
 impl From<PageFaultError> for SysErrNo {
@@ -72,6 +80,8 @@ impl From<PageFaultError> for SysErrNo {
             PageFaultError::RangeEmpty => SysErrNo::EBUSY,
             PageFaultError::VpnNotHandled => SysErrNo::EINVAL,
             PageFaultError::AlreadyAllocated => SysErrNo::EEXIST,
+            PageFaultError::OutOfMemory => SysErrNo::ENOMEM,
+            PageFaultError::StackOverflow => SysErrNo::EFAULT,
             PageFaultError::__ => SysErrNo::EFAULT,
         }
     }
@@ -86,6 +96,9 @@ pub fn munmap(
     new_end: VirtPageNum,
    
 )  {
+    self.adopt_swapped();
+    swap::discard(self.page_table.root_ppn().0, new_start, new_end);
+    self.munlock(new_start, new_end);
     // 1. 找到所有与 [new_start, new_end) 有交集的旧 MapArea
     let mut overlaps = Vec::new();
     for (&start, area) in self.areatree.range(..new_end) {
@@ -96,22 +109,25 @@ pub fn munmap(
 
     // 2. 对每个重叠的 area 进行拆分并保留左右两段
     for start in overlaps {
-        let  area = self.areatree.remove(&start).unwrap();
+        let mut area = self.areatree.remove(&start).unwrap();
         let a0 = area.start_vpn();
         let a1 = area.end_vpn();
         let r0 = new_start;
         let r1 = new_end;
 
+        // 右段：如果有，已分配的页随之转移
+        if a1 > r1 {
+            let mut right = area.from_another_with_range(r1, a1);
+            right.data_frames = area.data_frames.split_off(&r1);
+            self.areatree.push(right);
+        }
         // 左段：如果有
         if a0 < r0 {
-            let  left = area.from_another_with_range(a0, r0);
+            let mut left = area.from_another_with_range(a0, r0);
+            let mid_frames = area.data_frames.split_off(&r0);
+            left.data_frames = core::mem::replace(&mut area.data_frames, mid_frames);
             self.areatree.push(left);
         }
-        // 右段：如果有
-        if a1 > r1 {
-            let  right = area.from_another_with_range(r1, a1);
-            self.areatree.push(right);
-        }
 
         // **中间这部分 [r0, r1) 是要给新 area 用的**，
         // 先把对应的 PTE 一个个清掉
@@ -122,6 +138,7 @@ pub fn munmap(
 
            { self.page_table.unmap(VirtPageNum(vpn));}
         }
+        self.page_table.rss_sub(area.data_frames.len());
     
         flush_all();
     }
@@ -189,19 +206,207 @@ pub fn munmap(
         let page_table = PageTable::new_from_kernel();
 
         let areas=VmAreaTree ::new();
-        Self { page_table, areatree: areas }
+        Self {
+            page_table,
+            areatree: areas,
+            swap_hand: VirtPageNum(0),
+            limits: MemLimits::new(),
+            mlocked: BTreeMap::new(),
+        }
     }
     /// Create a new empty `MemorySet`.
     pub fn new_bare() -> Self {
         Self {
             page_table: PageTable::new(),
             areatree: VmAreaTree::new(),
+            swap_hand: VirtPageNum(0),
+            limits: MemLimits::new(),
+            mlocked: BTreeMap::new(),
         }
     }
     /// Get the page table token
     pub fn token(&self) -> usize {
         self.page_table.token()
     }
+    /// 虚拟地址空间大小（字节）
+    pub fn vm_size(&self) -> usize {
+        self.areatree.values().map(|area| area.range_size()).sum()
+    }
+    /// 常驻内存的页数
+    pub fn resident_pages(&self) -> usize {
+        self.page_table.rss()
+    }
+    /// 常驻页数的峰值
+    pub fn peak_resident_pages(&self) -> usize {
+        self.page_table.max_rss()
+    }
+    /// 计入 RLIMIT_DATA 的大小（字节）
+    pub fn data_size(&self) -> usize {
+        self.areatree
+            .values()
+            .filter(|area| Self::counts_as_data(area))
+            .map(|area| area.range_size())
+            .sum()
+    }
+    /// 栈区域的大小（字节）
+    pub fn stack_size(&self) -> usize {
+        self.areatree
+            .values()
+            .filter(|area| area.area_type == MapAreaType::Stack)
+            .map(|area| area.range_size())
+            .sum()
+    }
+    /// 和 Linux 一样，私有、可写、不是栈的映射计入数据段大小
+    pub fn counts_as_data(area: &MapArea) -> bool {
+        area.map_perm.contains(MapPermission::W)
+            && !area.mmap_flags.contains(MmapFlags::MAP_SHARED)
+            && !matches!(area.area_type, MapAreaType::Stack | MapAreaType::Shm { .. })
+    }
+    /// 地址空间再增加 `len` 字节后是否仍在 RLIMIT_AS 内，
+    /// `data` 表示新增部分计入数据段，此时还要检查 RLIMIT_DATA
+    pub fn may_grow(&self, len: usize, data: bool) -> bool {
+        self.may_replace(VirtPageNum(0), VirtPageNum(0), len, data)
+    }
+    /// 用 `len` 字节的新映射替换 [start, end) 中已有的映射后是否仍在限制内，
+    /// 被替换掉的部分不重复计算（MAP_FIXED）
+    pub fn may_replace(&self, start: VirtPageNum, end: VirtPageNum, len: usize, data: bool) -> bool {
+        let (mut replaced, mut replaced_data) = (0, 0);
+        for area in self.areatree.values() {
+            let lo = area.start_vpn().0.max(start.0);
+            let hi = area.end_vpn().0.min(end.0);
+            if lo < hi {
+                replaced += (hi - lo) << PAGE_SIZE_BITS;
+                if Self::counts_as_data(area) {
+                    replaced_data += (hi - lo) << PAGE_SIZE_BITS;
+                }
+            }
+        }
+        self.limits.address_space.allows(self.vm_size() - replaced + len)
+            && (!data || self.limits.data.allows(self.data_size() - replaced_data + len))
+    }
+    /// 已锁定的大小（字节）
+    pub fn locked_size(&self) -> usize {
+        self.mlocked.iter().map(|(start, end)| (end.0 - start.0) << PAGE_SIZE_BITS).sum()
+    }
+    /// [start, end) 中已锁定的页数
+    fn locked_pages_in(&self, start: VirtPageNum, end: VirtPageNum) -> usize {
+        self.mlocked
+            .range(..end)
+            .map(|(lo, hi)| hi.0.min(end.0).saturating_sub(lo.0.max(start.0)))
+            .sum()
+    }
+    /// `vpn` 是否被 mlock 锁定
+    pub fn is_mlocked(&self, vpn: VirtPageNum) -> bool {
+        self.mlocked.range(..=vpn).next_back().is_some_and(|(_, end)| vpn < *end)
+    }
+    /// 锁定 [start, end)，已锁定的部分不重复计算，锁定总量超过 RLIMIT_MEMLOCK 时失败
+    pub fn mlock(&mut self, start: VirtPageNum, end: VirtPageNum) -> GeneralRet {
+        let added = (end.0 - start.0) - self.locked_pages_in(start, end);
+        if !self.limits.memlock.allows(self.locked_size() + (added << PAGE_SIZE_BITS)) {
+            return Err(SysErrNo::ENOMEM);
+        }
+        // 与 [start, end) 重叠或相邻的段合并成一段
+        let (mut lo, mut hi) = (start, end);
+        let merged: Vec<VirtPageNum> = self
+            .mlocked
+            .range(..=end)
+            .filter(|(_, e)| **e >= start)
+            .map(|(s, _)| *s)
+            .collect();
+        for s in merged {
+            let e = self.mlocked.remove(&s).unwrap();
+            lo = lo.min(s);
+            hi = hi.max(e);
+        }
+        self.mlocked.insert(lo, hi);
+        Ok(())
+    }
+    /// 解除 [start, end) 的锁定，munlock、munmap 和缩小映射时调用
+    pub fn munlock(&mut self, start: VirtPageNum, end: VirtPageNum) {
+        let cut: Vec<VirtPageNum> = self
+            .mlocked
+            .range(..end)
+            .filter(|(_, e)| **e > start)
+            .map(|(s, _)| *s)
+            .collect();
+        for s in cut {
+            let e = self.mlocked.remove(&s).unwrap();
+            if s < start {
+                self.mlocked.insert(s, start);
+            }
+            if e > end {
+                self.mlocked.insert(end, e);
+            }
+        }
+    }
+    /// exec 换入新地址空间时继承旧地址空间的资源限制和峰值
+    pub fn inherit_from(&mut self, old: &MemorySet) {
+        self.limits = old.limits;
+        self.page_table.inherit_max_rss(old.peak_resident_pages());
+    }
+    /// 映射内核经软件页表换入的页，所在区域已经不存在的页直接丢弃
+    pub fn adopt_swapped(&mut self) {
+        let root = self.page_table.root_ppn().0;
+        let MemorySet { areatree, page_table, .. } = self;
+        let mut adopted = false;
+        swap::adopt_swapped_in(root, |vpn, frame, perm| {
+            let Some(start) = areatree.find_area(vpn) else { return };
+            if page_table.is_mapped(vpn) {
+                return;
+            }
+            page_table.map(vpn, frame.ppn(), PTEFlags::from(perm));
+            areatree.get_mut(&start).unwrap().insert_frame(page_table, vpn, frame);
+            adopted = true;
+        });
+        if adopted {
+            page_table.flush_remote();
+        }
+    }
+    /// 从时钟指针处开始换出至多 `want` 个独占、未被 mlock 锁定的匿名页，返回换出的页数
+    pub fn swap_out_pages(&mut self, want: usize, busy: &dyn Fn() -> bool) -> usize {
+        self.adopt_swapped();
+        let hand = self.swap_hand;
+        // 先扫描指针之后的页，再绕回开头
+        let mut picked: Vec<(VirtPageNum, Vec<VirtPageNum>)> = Vec::new();
+        let mut count = 0;
+        for bounds in [
+            (Bound::Included(hand), Bound::Unbounded),
+            (Bound::Unbounded, Bound::Excluded(hand)),
+        ] {
+            for (start, area) in self.areatree.iter() {
+                if count >= want {
+                    break;
+                }
+                if !area.is_swappable() {
+                    continue;
+                }
+                let vpns: Vec<VirtPageNum> = area
+                    .data_frames
+                    .range(bounds)
+                    .filter(|(vpn, frame)| Arc::strong_count(frame) == 1 && !self.is_mlocked(**vpn))
+                    .map(|(vpn, _)| *vpn)
+                    .take(want - count)
+                    .collect();
+                if !vpns.is_empty() {
+                    count += vpns.len();
+                    picked.push((*start, vpns));
+                }
+            }
+        }
+        let mut freed = 0;
+        for (start, vpns) in picked {
+            let area = self.areatree.get_mut(&start).unwrap();
+            let perm = area.map_perm;
+            let n = swap::swap_out(&mut self.page_table, &mut area.data_frames, &vpns, perm, busy);
+            freed += n;
+            if n < vpns.len() {
+                // 交换分区已满，或者有线程进入了系统调用
+                break;
+            }
+            self.swap_hand = VirtPageNum(vpns.last().unwrap().0 + 1);
+        }
+        freed
+    }
     /// Assume that no conflicts.
     pub fn insert_framed_area(
         &mut self,
@@ -221,6 +426,9 @@ pub fn munmap(
     pub fn remove_area_with_start_vpn(&mut self, start_vpn: VirtPageNum) {
        
         if let Some((_, mut area)) = self.areatree.remove_entry(&start_vpn) {
+            self.adopt_swapped();
+            swap::discard(self.page_table.root_ppn().0, area.start_vpn(), area.end_vpn());
+            self.munlock(area.start_vpn(), area.end_vpn());
             area.unmap(&mut self.page_table);
         }
     }
@@ -582,6 +790,8 @@ log::info!("[map_elf_load] segment {}: file_offset=0x{:x}, mem_size=0x{:x}, star
 /// using Copy-On-Write for private mappings and sharing for shared mappings.
 pub async fn from_existed_user(user_space: &mut Self) -> Self {
     let mut memory_set = Self::new_from_kernel();
+    memory_set.limits = user_space.limits;
+    user_space.adopt_swapped();
 
     // Only process each area once
     {
@@ -605,10 +815,10 @@ pub async fn from_existed_user(user_space: &mut Self) -> Self {
 
                 // Insert mapping with shared frames
                 memory_set.push_with_given_frames(new_area, &area.data_frames,true);
+                user_space.page_table.share_swapped(&memory_set.page_table, area.start_vpn(), area.end_vpn());
               // 对每对 (vpn, frame) 做映射并记录
         for (vpn, _) in area.data_frames.iter(){
-              let   pte= user_space.page_table.find_pte(*vpn).unwrap();
-                pte.set_cow();
+                user_space.page_table.set_cow(*vpn);
             }
             }
             else if let MapAreaType::Shm { shmid } = area.area_type {
@@ -708,21 +918,8 @@ pub async  fn from_existed_user1(user_space: &mut Self) -> Self {
 }
     /// Change page table by writing satp CSR Register.
     pub fn activate(&self) {
-        #[cfg(target_arch = "riscv64")]
-        unsafe {
-            let satp = self.page_table.token();
-            satp::write(satp);
-            asm!("sfence.vma");
-        }
-        #[cfg(target_arch = "loongarch64")]
-        unsafe {
-            // LoongArch64 的页表基址寄存器是 PGDL 和 PGDH
-            use loongArch64::register::pgdl;
-            let mut satp = self.page_table.token();
-            satp=satp<<PAGE_SIZE_BITS;
-            pgdl::set_base(satp);
-            asm!("invtlb 0x0, $zero, $zero"); // 刷新所有 TLB 项
-        }
+        // 同时记录本核的页表，TLB 击落据此找到目标核
+        crate::mm::activate_by_token(self.page_table.token());
     }
    
    pub async  fn safe_translate(&mut self, vpn: VirtPageNum) -> Option<PageTableEntry> {
@@ -763,28 +960,31 @@ pub async  fn from_existed_user1(user_space: &mut Self) -> Self {
 }
     ///Remove all `MapArea`
     pub async  fn recycle_data_pages(&mut self) -> SyscallRet {
-        // 先检测是否需要munmap
+        // 有页缓存的文件，共享映射的页就是缓存页，脏页由页缓存负责写回；
+        // 其余文件的可写共享映射在这里逐页写回
         for (_,area) in self.areatree.iter_mut() {
-            if area.area_type == MapAreaType::Mmap {
-                if area.mmap_flags.contains(MmapFlags::MAP_SHARED)
-                    && area.map_perm.contains(MapPermission::W)
-                {
-                    let addr: VirtAddr = area.vpn_range.get_start().into();
-                    let mapped_len: usize = area
-                        .vpn_range
-                        .into_iter()
-                        .filter(|vpn| area.data_frames.contains_key(&vpn))
-                        .count()
-                        * PAGE_SIZE;
-                    let file = area.fd.clone().unwrap();
-                    file.file.write(UserBuffer {
-                        buffers: translated_byte_buffer(
-                            self.page_table.token(),
-                            addr.0 as *const u8,
-                            mapped_len,
-                        )
-                        ,
-                    }).await?;
+            if area.area_type == MapAreaType::Mmap
+                && area.mmap_flags.contains(MmapFlags::MAP_SHARED)
+                && area.map_perm.contains(MapPermission::W)
+            {
+                let Some(mmap_file) = area.fd.as_ref() else { continue };
+                let file = mmap_file.file.file()?;
+                if file.page_cache().is_some() {
+                    continue;
+                }
+                let size = file.fstat().st_size as usize;
+                for (vpn, frame) in area.data_frames.iter() {
+                    let off = mmap_file.offset
+                        + ((vpn.0 - area.vpn_range.get_start().0) << PAGE_SIZE_BITS);
+                    if off >= size {
+                        continue;
+                    }
+                    let len = (size - off).min(PAGE_SIZE);
+                    file.inner
+                        .lock()
+                        .inode
+                        .write_at(off as u64, &frame.ppn().get_bytes_array()[..len])
+                        .map_err(SysErrNo::from)?;
                 }
             }
         }
@@ -810,10 +1010,14 @@ pub async  fn safe_translate_va(&mut self, va: VirtAddr) -> Option<PhysAddr> {
      /// shrink the area to new_end
     #[allow(unused)]
     pub fn shrink_to(&mut self, start: VirtAddr, new_end: VirtAddr) -> bool {
+        self.adopt_swapped();
         if let Some(area) = self
             .areatree.get_mut(&start.floor())
         {
+            let end = area.end_vpn();
+            swap::discard(self.page_table.root_ppn().0, new_end.ceil(), end);
             area.shrink_to(&mut self.page_table, new_end.ceil());
+            self.munlock(new_end.ceil(), end);
             true
         } else {
             false
@@ -826,8 +1030,7 @@ pub async  fn safe_translate_va(&mut self, va: VirtAddr) -> Option<PhysAddr> {
         if let Some(area) = self
         .areatree.get_mut(&start.floor())
         {
-            area.append_to(&mut self.page_table, new_end.ceil());
-            true
+            area.append_to(&mut self.page_table, new_end.ceil()).is_ok()
         } else {
             false
         }
@@ -870,6 +1073,46 @@ pub async  fn safe_translate_va(&mut self, va: VirtAddr) -> Option<PhysAddr> {
 
     }
 
+    /// 检查 [addr, addr+len) 整段都落在可写的用户区域里，并提前分配好懒分配的页，
+    /// 用于在产生副作用之前确认之后的 `put_user` 不会失败
+    pub async fn check_user_writable(&mut self, addr: usize, len: usize) -> Result<(), SysErrNo> {
+        let end = addr.checked_add(len).ok_or(SysErrNo::EFAULT)?;
+        if len == 0 {
+            return Ok(());
+        }
+        let mut vpn = VirtAddr::from(addr).floor();
+        let end_vpn = VirtAddr::from(end).ceil();
+        while vpn < end_vpn {
+            let start = self.areatree.find_area(vpn).ok_or(SysErrNo::EFAULT)?;
+            if !self.areatree.get(&start).unwrap().map_perm.contains(MapPermission::W) {
+                return Err(SysErrNo::EFAULT);
+            }
+            vpn.step();
+        }
+        self.manual_alloc_range_for_lazy(addr.into(), end.into())
+            .await
+            .map_err(|_| SysErrNo::EFAULT)
+    }
+
+    /// 按地址写一个值到用户空间。不跨 await 持有裸指针，可在要求 `Send` 的 future（如 `File` 的方法）中使用
+    pub async fn put_user<T: Copy + Send + 'static>(&mut self, addr: usize, data: T) -> Result<(), SysErrNo> {
+        let start_va = VirtAddr::from(addr);
+        self.manual_alloc_range_for_lazy(start_va, (addr + core::mem::size_of::<T>()).into())
+            .await
+            .map_err(|_| SysErrNo::EFAULT)?;
+        super::page_table::put_data(self.token(), addr as *mut T, data)?;
+        Ok(())
+    }
+
+    /// 按地址从用户空间读一个值，用法同 `put_user`
+    pub async fn get_user<T: Copy + 'static>(&mut self, addr: usize) -> Result<T, SysErrNo> {
+        let start_va = VirtAddr::from(addr);
+        self.manual_alloc_range_for_lazy(start_va, (addr + core::mem::size_of::<T>()).into())
+            .await
+            .map_err(|_| SysErrNo::EFAULT)?;
+        Ok(unsafe { super::page_table::copy_from_user_exact(self.token(), addr as *const T) }?)
+    }
+
     pub  async  fn safe_put_data<T:Copy +'static>(&mut self, ptr: *mut T, data: T) -> PutDataRet {
         let data_size = core::mem::size_of::<T>();
         if data_size == 0 {
@@ -967,13 +1210,29 @@ pub async fn handle_page_fault(
         return Err(PageFaultError::AreaNotFound);
     };
 
-    
-    
+    self.adopt_swapped();
+    let stack_limit = self.limits.stack;
     let MemorySet { areatree, page_table, .. } = &mut *self;
     // areatree.debug_print();
     let area = areatree.get_mut(&start_vpn).unwrap();
 
-    
+    // 栈从高地址向下使用，缺页处离栈顶的距离超过 RLIMIT_STACK 时视为栈溢出
+    if area.area_type == MapAreaType::Stack
+        && !area.allocated(vpn)
+        && !stack_limit.allows((area.end_vpn().0 - vpn.0) << PAGE_SIZE_BITS)
+    {
+        return Err(PageFaultError::StackOverflow);
+    }
+
+    // 换出的页：读回并按换出时的权限重新映射
+    if !area.allocated(vpn) {
+        if let Some(frame) = swap::swap_in(page_table, vpn).map_err(|_| PageFaultError::OutOfMemory)? {
+            area.insert_frame(page_table, vpn, frame);
+            flush_all();
+            return Ok(true);
+        }
+    }
+
    let area_type = &area.area_type;
   if area_type != &MapAreaType::Mmap && area_type != &MapAreaType::Stack {
         // 2. 如果不是 mmap 区域 → NotMmapType
@@ -993,15 +1252,43 @@ pub async fn handle_page_fault(
     // 4. 如果 vpn 在范围内，则进行懒分配处理
 if area.vpn_range.contains(vpn) {
     trace!("[mmap_page_fault] lazy allocate page for vpn");
+        let start_addr: VirtAddr = start_vpn.into();
+        // 文件有页缓存时直接映射缓存页：共享映射共用该页，私有映射写时复制
+        let cached = area.fd.as_ref().and_then(|mmap_file| {
+            let cache = mmap_file.file.file().ok()?.page_cache()?;
+            Some((cache, (va.0 - start_addr.0 + mmap_file.offset) >> PAGE_SIZE_BITS))
+        });
+        if let Some((cache, idx)) = cached {
+            let frame = cache.get_page(idx).map_err(|_| PageFaultError::__)?;
+            if area.mmap_flags.contains(MmapFlags::MAP_SHARED) {
+                if area.map_perm.contains(MapPermission::W) {
+                    cache.mark_dirty(idx);
+                }
+                area.map_frame(page_table, vpn, frame, false);
+            } else {
+                area.map_frame(page_table, vpn, frame, true);
+            }
+            flush_all();
+            return Ok(true);
+        }
+
+        // 匿名映射中整块都还没用到的 2 MiB 区域一次性用大页映射
+        if let Some(block) = area.huge_block(vpn) {
+            let end = VirtPageNum(block.0 + HUGE_PAGE_PAGES);
+            if !swap::has_swapped(page_table.root_ppn().0, block, end) && area.map_huge(page_table, block) {
+                flush_all();
+                return Ok(true);
+            }
+        }
+
         // 映射一个页（lazy allocate）
-        area.map_one(page_table, vpn).expect("no memery ");
+        area.map_one(page_table, vpn).map_err(|_| PageFaultError::OutOfMemory)?;
 
         if let Some(mmap_file) = &area.fd {
             let file = mmap_file.file.file().expect("file mmap should be normal file");
             // 保存旧的文件偏移，以便读完后恢复
             let old_offset = file.lseek(0, SEEK_CUR).unwrap();
 
-            let start_addr: VirtAddr = start_vpn.into();
             let user_buff = UserBuffer {
                 buffers: translated_byte_buffer(
                     page_table.token(),
@@ -1041,6 +1328,8 @@ if area.vpn_range.contains(vpn) {
 
 
 }else {
+    // 大页上的写时复制只复制被写的 4K 页，先拆分
+    page_table.split_huge(vpn);
     if let Some(pte) =  page_table.find_pte(vpn){
       match pte.is_cow()||is_write{
             true=>{
@@ -1049,16 +1338,12 @@ if area.vpn_range.contains(vpn) {
    if ref_count > 1 {
     trace!("[mmap_page_fault] cow allocate page for vpn,pte:{:#? }",pte.flags());
 
-    let src = &mut page_table.translate(vpn).unwrap().ppn().get_bytes_array()[..PAGE_SIZE];
+    // 先分配新页再解除旧映射，分配失败时原映射保持不变
+    let frame = frame_alloc().ok_or(PageFaultError::OutOfMemory)?;
+    let src = &page_table.translate(vpn).unwrap().ppn().get_bytes_array()[..PAGE_SIZE];
+    frame.ppn().get_bytes_array().copy_from_slice(src);
     area.unmap_one(page_table, vpn);
-    area.map_one(page_table, vpn).unwrap();
-    let dst = &mut page_table.translate(vpn).unwrap().ppn().get_bytes_array()[..PAGE_SIZE];
-    dst.copy_from_slice(src);
-    {
-        let mut new_pte = page_table.translate(vpn)
-                             .expect("mapped just now");
-        new_pte.un_cow();    
-    }
+    area.map_frame(page_table, vpn, frame, false);
 
         flush_all();
         return Ok(true);
@@ -1207,6 +1492,8 @@ pub async fn mprotect(&mut self, start: VirtAddr, size: usize, flags: MapPermiss
      let end = start + size;
      let end_vpn = end.ceil();
      let start_vpn = start.floor();
+     self.adopt_swapped();
+     swap::set_perm(self.page_table.root_ppn().0, start_vpn, end_vpn, flags);
 
      let mut overlapped_area: Vec<(usize, MapArea)> = Vec::new();
      let mut prev_area: BTreeMap<VirtPageNum, MapArea> = BTreeMap::new();
@@ -1250,6 +1537,41 @@ pub async fn mprotect(&mut self, start: VirtAddr, size: usize, flags: MapPermiss
 
 
 
+}
+/// madvise(MADV_HUGEPAGE / MADV_NOHUGEPAGE)：设置 [start_vpn, end_vpn) 之后的缺页是否使用透明大页
+///
+/// 区域只有一部分在范围内时先拆分，但堆区域按起始页号查找、不能拆分，整个堆一起设置；
+/// 已经建立的大页保持不变。
+pub async fn madvise_hugepage(&mut self, start_vpn: VirtPageNum, end_vpn: VirtPageNum, enable: bool) {
+    let mut overlapped_area: Vec<MapArea> = Vec::new();
+    let mut prev_area: BTreeMap<VirtPageNum, MapArea> = BTreeMap::new();
+    while let Some((idx, area)) = self.areatree.pop_first() {
+        if area.overlap_with(start_vpn, end_vpn) {
+            overlapped_area.push(area);
+        } else {
+            prev_area.insert(idx, area);
+        }
+    }
+    self.areatree.areas = prev_area;
+    for mut area in overlapped_area {
+        if area.contained_in(start_vpn, end_vpn) || area.area_type == MapAreaType::Brk {
+            area.hugepage = enable;
+        } else if area.strict_contain(start_vpn, end_vpn) {
+            let (mut mid, right) = area.split3(start_vpn, end_vpn).await;
+            mid.hugepage = enable;
+            assert!(self.areatree.insert(mid.start_vpn(), mid).is_none());
+            assert!(self.areatree.insert(right.start_vpn(), right).is_none());
+        } else if start_vpn <= area.start_vpn() && area.start_vpn() < end_vpn {
+            let right = area.split(end_vpn).await;
+            area.hugepage = enable;
+            assert!(self.areatree.insert(right.start_vpn(), right).is_none());
+        } else {
+            let mut right = area.split(start_vpn).await;
+            right.hugepage = enable;
+            assert!(self.areatree.insert(right.start_vpn(), right).is_none());
+        }
+        assert!(self.areatree.insert(area.start_vpn(), area).is_none());
+    }
 }
  /// 手动分配 
  pub async fn manual_alloc_type_for_lazy<T: Sized>(&mut self, obj: *const T) -> GeneralRet {
@@ -1301,6 +1623,10 @@ pub async fn mremap(&mut self, old_start: VirtAddr, old_size: usize, new_size: u
 
             }
             else{
+               let data = self.areatree.get(&start).map_or(false, Self::counts_as_data);
+               if !self.may_grow(lacklen as usize, data) {
+                   return Err(SysErrNo::ENOMEM);
+               }
                let vpn= self.areatree.find_gap_from( VirtPageNum::from(new_end>>PAGE_SIZE_BITS), new_size>>PAGE_SIZE_BITS);
                let allocated=if let Some(vpn)=vpn{
                if vpn == old_start.floor(){
@@ -1385,8 +1711,12 @@ pub async fn mremap(&mut self, old_start: VirtAddr, old_size: usize, new_size: u
 /// access to this memory region will trigger a page fault, and the kernel
 /// can re-allocate a new, zeroed page on demand.
 pub fn madvise_dontneed(&mut self, start_vpn: VirtPageNum, end_vpn: VirtPageNum) {
+    self.adopt_swapped();
+    swap::discard(self.page_table.root_ppn().0, start_vpn, (end_vpn.0 + 1).into());
     // 遍历指定范围内的每一个虚拟页
     for vpn in VPNRange::new(start_vpn, (end_vpn.0+ 1).into()) {
+        // 大页中的页先拆分，只释放范围内的部分
+        self.page_table.split_huge(vpn);
         // 查找该虚拟页对应的页表项 (PTE)
         if let Some(pte) = self.page_table.find_pte(vpn) {
             // 检查页表项是否有效（即是否映射到了一个物理页）
@@ -1404,7 +1734,7 @@ pub fn madvise_dontneed(&mut self, start_vpn: VirtPageNum, end_vpn: VirtPageNum)
                 match self.areatree.find_area(vpn){
                     Some(area) => {
                         if let Some(area_mut) = self.areatree.get_mut(&area) {
-                            if let Some(_frame_tracker) = area_mut.data_frames.remove(&vpn) {
+                            if let Some(_frame_tracker) = area_mut.remove_frame(&mut self.page_table, vpn) {
                                 
                             } else {
                                 warn!("Frame tracker not found for vpn: {:?}", vpn);
@@ -6,6 +6,9 @@
 //!
 //! Every task or process has a memory_set to control its virtual memory.
 pub mod shm;
+pub mod swap;
+pub mod oom;
+pub mod rlimit;
 mod address;
 mod area;
 pub(crate) mod frame_allocator;
@@ -20,7 +23,7 @@ use lazy_init::LazyInit;
 pub use address::{KernelAddr, PhysAddr, PhysPageNum, StepByOne, VPNRange, VirtAddr, VirtPageNum};
 pub use area::{MapArea, MapAreaType, MapPermission, MapType, MmapFile, MmapFlags, VmAreaTree};
 pub use frame_allocator::{frame_alloc, frame_dealloc, FrameTracker};
-pub use memory_set::MemorySet;
+pub use memory_set::{MemorySet, PageFaultError};
 pub use page_table::put_data;
 
 pub use arch::{PTEFlags, PageTableEntry};
@@ -80,7 +83,8 @@ pub fn activate_by_token(satp: usize) {
         loongArch64::register::pgdl::set_base(satp << PAGE_SIZE_BITS);
         // pgdh::set_base(satp<<PAGE_SIZE_BITS);
    }
-        flush_all();
+        crate::smp::set_token(satp);
+        local_flush_tlb();
         
 }
 
@@ -89,7 +93,14 @@ pub fn kernel_token() -> usize {
     *KERNEL_PAGE_TABLE_TOKEN
 }
 
+/// 刷新本核 TLB，并通知其他使用同一页表的核
 pub fn flush_all() {
+    local_flush_tlb();
+    crate::smp::tlb_shootdown(crate::smp::token());
+}
+
+/// 只刷新本核 TLB
+pub fn local_flush_tlb() {
     #[cfg(target_arch = "riscv64")]
     unsafe {
         asm!("sfence.vma");
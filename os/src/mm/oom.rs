@@ -0,0 +1,129 @@
+//! 内存耗尽（OOM）处理
+//!
+//! 页帧分配失败、换出也腾不出内存时，按地址空间中的常驻页帧数给进程打分，
+//! 向占用最多的进程发送 SIGKILL，等它退出释放内存后由调用者重试分配，
+//! 避免一个失控的进程拖垮整个系统。
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use alloc::vec::Vec;
+
+use super::frame_allocator::{remaining_frames, total_frames};
+use crate::config::{OOM_WAIT_ROUNDS, PAGE_SIZE, SWAP_CLUSTER};
+use crate::signal::{send_signal, Signal};
+use crate::task::{current_process, yield_now, ProcessRef, IDLE_PID, INITPROC, PID2PC};
+
+/// 已发送 SIGKILL、尚未释放内存的进程，0 表示没有
+static OOM_VICTIM: AtomicUsize = AtomicUsize::new(0);
+
+/// 进程地址空间中常驻的页帧数，拿不到地址空间锁时返回 `None`
+fn resident_frames(proc: &ProcessRef) -> Option<usize> {
+    let ms = proc.memory_set.try_lock()?;
+    Some(ms.resident_pages())
+}
+
+/// 被杀的进程是否已经释放了内存
+fn released(victim: &ProcessRef) -> bool {
+    !PID2PC.lock().contains_key(&victim.get_pid()) || resident_frames(victim) == Some(0)
+}
+
+/// 打印内存使用报告；内核堆耗尽时也会调用，因此不能分配内存
+pub fn report() {
+    let (swap_total, swap_free) = super::swap::stats();
+    error!(
+        "[oom] out of memory: free {} kB / {} kB, swap free {} kB / {} kB",
+        remaining_frames() * PAGE_SIZE / 1024,
+        total_frames() * PAGE_SIZE / 1024,
+        swap_free * PAGE_SIZE / 1024,
+        swap_total * PAGE_SIZE / 1024
+    );
+    let Some(procs) = PID2PC.try_lock() else {
+        return;
+    };
+    for (pid, proc) in procs.iter() {
+        match resident_frames(proc) {
+            Some(rss) => error!("[oom]   pid {:>5}  rss {:>8} kB", pid, rss * PAGE_SIZE / 1024),
+            None => error!("[oom]   pid {:>5}  rss        ? kB", pid),
+        }
+    }
+}
+
+/// 选出常驻页帧最多的进程，不考虑 idle、init、`exclude` 中的进程和已经退出的进程
+async fn select_victim(exclude: &[usize]) -> Option<(ProcessRef, usize)> {
+    let procs: Vec<ProcessRef> = PID2PC.lock().values().cloned().collect();
+    let init_pid = INITPROC.get_pid();
+    let mut victim: Option<(ProcessRef, usize)> = None;
+    for proc in procs {
+        let pid = proc.get_pid();
+        if pid == IDLE_PID || pid == init_pid || exclude.contains(&pid) || proc.is_zombie().await {
+            continue;
+        }
+        let Some(rss) = resident_frames(&proc) else { continue };
+        if rss > 0 && victim.as_ref().map_or(true, |(_, max)| rss > *max) {
+            victim = Some((proc, rss));
+        }
+    }
+    victim
+}
+
+/// 等待被杀的进程释放内存，成功返回 `true`
+async fn wait_for(victim: &ProcessRef) -> bool {
+    for _ in 0..OOM_WAIT_ROUNDS {
+        if released(victim) {
+            return true;
+        }
+        yield_now().await;
+    }
+    released(victim)
+}
+
+/// 杀掉占用内存最多的进程并等它释放内存
+///
+/// 返回 `true` 表示已经腾出了内存，调用者可以重试分配；
+/// 当前进程已经被选中或没有可杀的进程时返回 `false`，调用者应按分配失败处理。
+pub async fn out_of_memory() -> bool {
+    let current = current_process();
+    // 当前进程已经被杀，正在退出，不要再选别的进程
+    let pending = OOM_VICTIM.load(Ordering::Acquire);
+    if pending != 0 && pending == current.get_pid() {
+        return false;
+    }
+    // 上一个被杀的进程还没退出时先等它，不要接连杀多个进程
+    if pending != 0 {
+        let victim = PID2PC.lock().get(&pending).cloned();
+        if let Some(victim) = victim {
+            if wait_for(&victim).await {
+                let _ = OOM_VICTIM.compare_exchange(pending, 0, Ordering::AcqRel, Ordering::Relaxed);
+                return true;
+            }
+            return false;
+        }
+    }
+
+    report();
+    let Some((victim, rss)) = select_victim(&[current.get_pid(), pending]).await else {
+        error!("[oom] no killable process");
+        return false;
+    };
+    let pid = victim.get_pid();
+    error!(
+        "[oom] killed process {} (rss {} kB), triggered by pid {}",
+        pid,
+        rss * PAGE_SIZE / 1024,
+        current.get_pid()
+    );
+    if send_signal(pid, None, Signal::SIGKILL).await.is_err() {
+        return false;
+    }
+    OOM_VICTIM.store(pid, Ordering::Release);
+    if wait_for(&victim).await {
+        let _ = OOM_VICTIM.compare_exchange(pid, 0, Ordering::AcqRel, Ordering::Relaxed);
+        return true;
+    }
+    false
+}
+
+/// 分配失败后的最后手段：先换出，不行再杀进程；返回 `true` 时调用者可以重试分配
+pub async fn reclaim_or_kill() -> bool {
+    super::swap::reclaim(SWAP_CLUSTER).await > 0 || out_of_memory().await
+}
@@ -3,7 +3,8 @@
 
 
 use super::{KernelAddr, MapPermission, KERNEL_PAGE_TABLE_PPN};
-use super::{frame_alloc, FrameTracker, PhysAddr, PhysPageNum, StepByOne, VirtAddr, VirtPageNum};
+use super::frame_allocator::frame_alloc_reserved;
+use super::{FrameTracker, PhysAddr, PhysPageNum, StepByOne, VirtAddr, VirtPageNum};
 use crate::config::{KERNEL_DIRECT_OFFSET, KERNEL_PGNUM_OFFSET};
 use crate::mm::arch::{PAGE_LEVEL, PTE_NUM_IN_PAGE};
 use crate::mm::{PageTableEntry, PTEFlags};
@@ -39,6 +40,10 @@ pub struct PageTable {
 
     root_ppn: PhysPageNum,
     frames: Vec<Arc<FrameTracker>>,
+    /// 本地址空间常驻的用户页数，随各区域 `data_frames` 的增减更新
+    rss: usize,
+    /// 常驻页数的峰值
+    max_rss: usize,
 }
 
 #[repr(usize)]
@@ -59,10 +64,10 @@ pub type PagingResult<T = ()> = Result<T, PagingError>;
 
 
 
-/// Assume that it won't oom when creating/mapping.
+/// 页表页从保留页帧中分配，普通页帧耗尽时仍能建立映射
 impl PageTable {
     fn alloc_frame(&mut self)->PhysPageNum{
-        let frame  =frame_alloc().unwrap();
+        let frame  =frame_alloc_reserved().expect("out of memory: page table frame");
         let ppn = frame.ppn;
 
         self.frames.push(frame);
@@ -78,6 +83,18 @@ impl PageTable {
     ) -> PagingResult {
         let end = vpn + size;
         while vpn < end {
+            // 整个大页都在区域内时直接修改大页，只覆盖一部分或处于写时复制时先拆分
+            if let Some(leaf) = self.huge_entry(vpn.floor()) {
+                if vpn.0 % PageSize::Size2M as usize == 0
+                    && vpn + PageSize::Size2M as usize <= end
+                    && !leaf.flags().contains(PTEFlags::COW)
+                {
+                    *leaf = PageTableEntry::new_huge(leaf.ppn(), flags.into());
+                    vpn.0 += PageSize::Size2M as usize;
+                    continue;
+                }
+                self.split_huge(vpn.floor());
+            }
             let page_size = self.update(vpn, None, Some(flags))?;
             vpn.0 += page_size as usize;
         }
@@ -96,13 +113,15 @@ impl PageTable {
         flags: Option<MapPermission>,
     ) -> PagingResult<PageSize> {
         let vpn= vpn.floor();
+        self.split_huge(vpn);
        let pte= match  self.find_pte(vpn){
             Some(f) => f,
             None => return Err(PagingError::NotMapped),
         };
-        // if pte.ppn() == 0.into() {
-        //     return Ok();
-        // }
+        // 懒分配尚未分配或已经换出的页没有映射，权限在映射时按区域设置
+        if !pte.is_valid() {
+            return Ok(PageSize::Size4K);
+        }
         if let Some(paddr) = paddr {
             pte.set_ppn(paddr);
         }
@@ -114,11 +133,36 @@ impl PageTable {
     }
     ///clear frame 
     pub fn clear(&mut self) {
+        if !self.frames.is_empty() {
+            super::swap::release_all(self.root_ppn.0);
+        }
         self.frames.clear();
+        self.rss = 0;
+    }
+    /// 常驻页数增加 `n`，同时更新峰值
+    pub fn rss_add(&mut self, n: usize) {
+        self.rss += n;
+        self.max_rss = self.max_rss.max(self.rss);
+    }
+    /// 常驻页数减少 `n`
+    pub fn rss_sub(&mut self, n: usize) {
+        self.rss = self.rss.saturating_sub(n);
+    }
+    /// 常驻页数
+    pub fn rss(&self) -> usize {
+        self.rss
+    }
+    /// 常驻页数的峰值
+    pub fn max_rss(&self) -> usize {
+        self.max_rss
+    }
+    /// exec 换入新地址空间时继承旧地址空间的峰值
+    pub fn inherit_max_rss(&mut self, peak: usize) {
+        self.max_rss = self.max_rss.max(peak);
     }
     ///Create new PageTable from global kernel space
     pub fn new_from_kernel() -> Self {
-        let frame = frame_alloc().unwrap();
+        let frame = frame_alloc_reserved().expect("out of memory: page table frame");
         let global_root_ppn = *KERNEL_PAGE_TABLE_PPN ;
 
         // Map kernel space
@@ -134,6 +178,8 @@ impl PageTable {
         PageTable {
             root_ppn: frame.ppn(),
             frames: vec![frame],
+            rss: 0,
+            max_rss: 0,
         }
     }
     pub fn root_ppn(&self)->PhysPageNum{
@@ -142,11 +188,13 @@ impl PageTable {
     /// Create a new page table
     pub fn new() -> Self {
 
-        let frame = frame_alloc().unwrap();
+        let frame = frame_alloc_reserved().expect("out of memory: page table frame");
         debug!("frame: {:#x}",frame.ppn().0);
         PageTable {
             root_ppn: frame.ppn(),
             frames: vec![frame],
+            rss: 0,
+            max_rss: 0,
         }
     }
     cfg_if::cfg_if! {
@@ -157,6 +205,8 @@ impl PageTable {
         Self {
             root_ppn: PhysPageNum::from(satp & ((1usize << 44) - 1)),
             frames: Vec::new(),
+            rss: 0,
+            max_rss: 0,
         }
     }
     
@@ -167,6 +217,8 @@ impl PageTable {
                 Self {
                     root_ppn: PhysPageNum::from(pgn & ((1usize << 36) - 1)),
                     frames: Vec::new(),
+                    rss: 0,
+                    max_rss: 0,
                 }
             } 
     
@@ -202,12 +254,15 @@ impl PageTable {
                         let pte = &mut pte_list[vpn.pn_index(1)];
                         if !pte.is_valid() {
                             *pte = PageTableEntry::new_table(self.alloc_frame().into());
+                        } else if pte.is_huge() {
+                            self.split_entry(pte);
                         }
                         pte_list = Self::get_pte_list(pte.address());
                     }
                     // level 1, map page
                    Some(&mut pte_list[vpn.pn_index(0)] )
                 }
+                /// 大页中的页没有 4K 页表项，返回 `None`；需要修改时先调用 [`Self::split_huge`]
                 pub fn find_pte(&self, vpn: VirtPageNum) -> Option<&mut PageTableEntry> {
                     let mut pte_list = Self::get_pte_list(self.root_ppn.into());
                     if PAGE_LEVEL == 4 {
@@ -229,7 +284,7 @@ impl PageTable {
                     // level 2
                     {
                         let pte = &mut pte_list[vpn.pn_index(1)];
-                        if !pte.is_valid() {
+                        if !pte.is_valid() || pte.is_huge() {
                            
                             return None;
                         }
@@ -268,6 +323,7 @@ impl PageTable {
     pub fn unmap(&mut self, vpn: VirtPageNum) {
         use crate::config::PAGE_SIZE_BITS;
 
+        self.split_huge(vpn);
         let pte = self.find_pte(vpn).unwrap();
         assert!(pte.is_valid(), "vpn {:?} is invalid before unmapping", vpn);
         *pte = PageTableEntry::empty();
@@ -278,6 +334,7 @@ impl PageTable {
     #[cfg(target_arch = "riscv64")]
     #[allow(unused)]
     pub fn unmap(&mut self, vpn: VirtPageNum) {
+        self.split_huge(vpn);
         let pte = self.find_pte(vpn).unwrap();
         assert!(pte.is_valid(), "vpn {:?} is invalid before unmapping", vpn);
         *pte = PageTableEntry::empty();
@@ -293,7 +350,110 @@ impl PageTable {
 
                 return Some( PageTableEntry{bits:PhysAddr::from( KernelAddr::from(va.0)).0});
          } 
-        self.find_pte(vpn).map(|pte| *pte)
+        self.find_present_pte(vpn)
+    }
+    /// fork 时让 `child` 共享本页表 [start, end) 范围内换出的页
+    pub fn share_swapped(&self, child: &PageTable, start: VirtPageNum, end: VirtPageNum) {
+        super::swap::dup_range(self.root_ppn.0, child.root_ppn.0, start, end);
+    }
+    /// 同 `find_pte`，但页已经换出时先同步换入；大页中的页返回对应的 4K 页表项
+    fn find_present_pte(&self, vpn: VirtPageNum) -> Option<PageTableEntry> {
+        if let Some(leaf) = self.find_huge(vpn) {
+            return Some(leaf.huge_subpage(vpn.0 % PTE_NUM_IN_PAGE));
+        }
+        let pte = self.find_pte(vpn)?;
+        if !pte.is_valid() {
+            if let Some(swapped) = super::swap::fault_in(self, vpn) {
+                return Some(swapped);
+            }
+        }
+        Some(*pte)
+    }
+    /// `vpn` 是否已经映射到物理页（包括大页中的页）
+    pub fn is_mapped(&self, vpn: VirtPageNum) -> bool {
+        self.find_huge(vpn).is_some() || self.find_pte(vpn).map_or(false, |pte| pte.is_valid())
+    }
+    /// 把 `vpn` 的映射设为写时复制；落在大页中时整个大页一起设置，写入时再拆分
+    pub fn set_cow(&self, vpn: VirtPageNum) {
+        if let Some(leaf) = self.find_huge(vpn) {
+            leaf.set_cow();
+        } else if let Some(pte) = self.find_pte(vpn) {
+            pte.set_cow();
+        }
+    }
+    /// 覆盖 `vpn` 的第二级页表项，不存在时返回 `None`
+    fn find_pmd(&self, vpn: VirtPageNum) -> Option<&'static mut PageTableEntry> {
+        let mut pte_list = Self::get_pte_list(self.root_ppn.into());
+        if PAGE_LEVEL == 4 {
+            let pte = &mut pte_list[vpn.pn_index(3)];
+            if !pte.is_valid() {
+                return None;
+            }
+            pte_list = Self::get_pte_list(pte.address());
+        }
+        let pte = &mut pte_list[vpn.pn_index(2)];
+        if !pte.is_valid() {
+            return None;
+        }
+        Some(&mut Self::get_pte_list(pte.address())[vpn.pn_index(1)])
+    }
+    /// 映射 `vpn` 的 2 MiB 大页页表项
+    pub fn find_huge(&self, vpn: VirtPageNum) -> Option<&mut PageTableEntry> {
+        self.huge_entry(vpn)
+    }
+    fn huge_entry(&self, vpn: VirtPageNum) -> Option<&'static mut PageTableEntry> {
+        self.find_pmd(vpn).filter(|pte| pte.is_valid() && pte.is_huge())
+    }
+    /// 把 `vpn` 所在的大页拆分为 512 个 4K 页表项，返回是否拆分
+    ///
+    /// 拆分前后映射的物理页和权限不变，不需要刷新 TLB
+    pub fn split_huge(&mut self, vpn: VirtPageNum) -> bool {
+        match self.huge_entry(vpn) {
+            Some(leaf) => {
+                self.split_entry(leaf);
+                true
+            }
+            None => false,
+        }
+    }
+    fn split_entry(&mut self, leaf: &mut PageTableEntry) {
+        let huge = *leaf;
+        let table = self.alloc_frame();
+        for (i, pte) in table.get_pte_array().iter_mut().enumerate() {
+            *pte = huge.huge_subpage(i);
+        }
+        *leaf = PageTableEntry::new_table(table.into());
+    }
+    /// 用一个 2 MiB 大页映射从 `vpn` 开始的 512 页，`vpn` 与 `ppn` 都要按大页对齐
+    ///
+    /// 这段地址中已经有 4K 映射时返回 `false`，调用者应改用 4K 页
+    pub fn map_huge(&mut self, vpn: VirtPageNum, ppn: PhysPageNum, flags: PTEFlags) -> bool {
+        debug_assert!(vpn.0 % PTE_NUM_IN_PAGE == 0 && ppn.0 % PTE_NUM_IN_PAGE == 0);
+        let mut pte_list = Self::get_pte_list(self.root_ppn.into());
+        if PAGE_LEVEL == 4 {
+            let pte = &mut pte_list[vpn.pn_index(3)];
+            if !pte.is_valid() {
+                *pte = PageTableEntry::new_table(self.alloc_frame().into());
+            }
+            pte_list = Self::get_pte_list(pte.address());
+        }
+        let pte = &mut pte_list[vpn.pn_index(2)];
+        if !pte.is_valid() {
+            *pte = PageTableEntry::new_table(self.alloc_frame().into());
+        }
+        let pmd = &mut Self::get_pte_list(pte.address())[vpn.pn_index(1)];
+        if pmd.is_valid() {
+            // 以前拆分出的页表：其中的页都已解除映射时才能换成大页
+            if pmd.is_huge() || Self::get_pte_list(pmd.address()).iter().any(|pte| pte.is_valid()) {
+                return false;
+            }
+            let table = pmd.address().floor();
+            self.frames.retain(|frame| frame.ppn != table);
+        }
+        *pmd = PageTableEntry::new_huge(ppn, flags);
+        #[cfg(target_arch = "loongarch64")]
+        crate::mm::flush_tlb(vpn.0 << crate::config::PAGE_SIZE_BITS);
+        true
     }
     /// get the physical address from the virtual address
     /// va to pa
@@ -302,7 +462,7 @@ impl PageTable {
               if va.0>=KERNEL_DIRECT_OFFSET{
                 return Some(PhysAddr::from( KernelAddr::from(va.0)));
          }
-        self.find_pte(va.clone().floor()).map(|pte| {
+        self.find_present_pte(va.clone().floor()).map(|pte| {
             let aligned_pa: PhysAddr = pte.ppn().into();
             let offset = va.page_offset();
             let aligned_pa_usize: usize = aligned_pa.into();
@@ -315,7 +475,7 @@ impl PageTable {
     }
 
     pub fn translate_va_with_perm(&self, va: VirtAddr, require_writable: bool) -> Result<PhysAddr, TranslateError> {
-        match self.find_pte(va.clone().floor())
+        match self.find_present_pte(va.clone().floor())
         {
             Some(pte) => {
               
@@ -346,6 +506,12 @@ impl PageTable {
 
         
     
+    /// 通知正在使用本页表的其他核刷新 TLB。`map`/`unmap` 只刷新本核，
+    /// 修改一段映射后调用一次；LoongArch 的一个 TLB 项对应奇偶两页，
+    /// 新建映射时其他核也可能缓存了这一页的无效项
+    pub fn flush_remote(&self) {
+        crate::smp::tlb_shootdown(self.token());
+    }
     pub fn token(&self) -> usize {
         cfg_if::cfg_if! {
     if #[cfg(target_arch = "riscv64")] {
@@ -360,6 +526,15 @@ impl PageTable {
     }
 }
 
+impl Drop for PageTable {
+    fn drop(&mut self) {
+        // `from_token` 得到的临时页表不拥有任何页
+        if !self.frames.is_empty() {
+            super::swap::release_all(self.root_ppn.0);
+        }
+    }
+}
+
 /// An abstraction over a buffer passed from user space to kernel space
 
 pub struct UserBuffer<'b> {
@@ -559,7 +734,6 @@ impl<'b> UserBuffer<'b> {
     }
     //在指定位置写入数据
     pub fn write_at(&mut self, offset: usize, buff: &[u8]) -> isize {
-        //未被使用，暂不做优化
         let len = buff.len();
         if offset + len > self.len() {
             return -1;
@@ -568,30 +742,19 @@ impl<'b> UserBuffer<'b> {
         let mut current = 0; // current offset of buff
 
         for sub_buff in self.buffers.iter_mut() {
+            if current == len {
+                break;
+            }
             let sblen = (*sub_buff).len();
-            if head + sblen < offset {
-                continue;
-            } else if head < offset {
-                for j in (offset - head)..sblen {
-                    (*sub_buff)[j] = buff[current];
-                    current += 1;
-                    if current == len {
-                        return len as isize;
-                    }
-                }
-            } else {
-                //head + sblen > offset and head > offset
-                for j in 0..sblen {
-                    (*sub_buff)[j] = buff[current];
-                    current += 1;
-                    if current == len {
-                        return len as isize;
-                    }
-                }
+            if head + sblen > offset + current {
+                let start = offset + current - head;
+                let n = (sblen - start).min(len - current);
+                sub_buff[start..start + n].copy_from_slice(&buff[current..current + n]);
+                current += n;
             }
             head += sblen;
         }
-        0
+        len as isize
     }
 }
 
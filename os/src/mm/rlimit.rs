@@ -0,0 +1,79 @@
+//! 地址空间的资源限制
+//!
+//! 和文件描述符上限存放在 `FdManage` 中一样，内存相关的限制存放在
+//! `MemorySet` 中：fork 时随地址空间复制，exec 时转移到新地址空间。
+
+use crate::config::USER_STACK_SIZE;
+use crate::utils::error::{GeneralRet, SysErrNo};
+
+pub const RLIMIT_DATA: u32 = 2;
+pub const RLIMIT_STACK: u32 = 3;
+pub const RLIMIT_MEMLOCK: u32 = 8;
+pub const RLIMIT_AS: u32 = 9;
+/// 不限制
+pub const RLIM_INFINITY: usize = usize::MAX;
+
+/// 一项限制的软限制和硬限制（字节）
+#[derive(Clone, Copy, Debug)]
+pub struct MemLimit {
+    pub cur: usize,
+    pub max: usize,
+}
+
+impl MemLimit {
+    const fn new(cur: usize, max: usize) -> Self {
+        Self { cur, max }
+    }
+    /// 用量 `size` 是否没有超过软限制
+    pub fn allows(&self, size: usize) -> bool {
+        self.cur == RLIM_INFINITY || size <= self.cur
+    }
+}
+
+/// RLIMIT_AS / RLIMIT_DATA / RLIMIT_STACK / RLIMIT_MEMLOCK
+#[derive(Clone, Copy, Debug)]
+pub struct MemLimits {
+    pub address_space: MemLimit,
+    pub data: MemLimit,
+    pub stack: MemLimit,
+    pub memlock: MemLimit,
+}
+
+impl MemLimits {
+    pub const fn new() -> Self {
+        Self {
+            address_space: MemLimit::new(RLIM_INFINITY, RLIM_INFINITY),
+            data: MemLimit::new(RLIM_INFINITY, RLIM_INFINITY),
+            // 用户栈区域固定为 USER_STACK_SIZE，默认软限制与之相同
+            stack: MemLimit::new(USER_STACK_SIZE, RLIM_INFINITY),
+            memlock: MemLimit::new(8 * 1024 * 1024, 8 * 1024 * 1024),
+        }
+    }
+
+    /// 按 `resource` 取出对应的限制，不是内存相关的资源返回 `None`
+    pub fn get(&self, resource: u32) -> Option<MemLimit> {
+        match resource {
+            RLIMIT_AS => Some(self.address_space),
+            RLIMIT_DATA => Some(self.data),
+            RLIMIT_STACK => Some(self.stack),
+            RLIMIT_MEMLOCK => Some(self.memlock),
+            _ => None,
+        }
+    }
+
+    /// 设置 `resource` 的限制，软限制不能超过硬限制
+    pub fn set(&mut self, resource: u32, cur: usize, max: usize) -> GeneralRet {
+        if cur > max {
+            return Err(SysErrNo::EINVAL);
+        }
+        let limit = match resource {
+            RLIMIT_AS => &mut self.address_space,
+            RLIMIT_DATA => &mut self.data,
+            RLIMIT_STACK => &mut self.stack,
+            RLIMIT_MEMLOCK => &mut self.memlock,
+            _ => return Err(SysErrNo::EINVAL),
+        };
+        *limit = MemLimit::new(cur, max);
+        Ok(())
+    }
+}
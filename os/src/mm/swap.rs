@@ -0,0 +1,556 @@
+//! 交换分区与页回收
+//!
+//! - `swapon` 启用一块用 mkswap 格式化（"SWAPSPACE2" 签名）的 virtio 块设备，
+//!   每页一个槽位，槽位带引用计数以便 fork 后父子进程共享同一份换出的页；
+//! - 空闲物理页低于 `SWAP_LOW_WATERMARK` 时，trap 返回路径按时钟算法轮流扫描
+//!   各地址空间，把匿名私有页（Mmap/Brk/Stack）写入空闲槽位并解除映射，
+//!   直到空闲页回到 `SWAP_HIGH_WATERMARK`；缺页时分配不到物理页也会就地回收；
+//! - 换出的页按 (根页表页号, 虚拟页号) 登记在这里，页表项保持为空。用户态访问
+//!   经缺页处理换入；内核经软件页表访问用户内存时由 `PageTable::translate*`
+//!   同步换入，但不持有地址空间的锁，不能改页表，换入的页先挂在这里，
+//!   等所属地址空间下次持锁操作时再映射。
+//!
+//! 读写设备时不持有 `SWAP` 锁：登记项标记为读/写中，其他要换入同一页的路径
+//! 等标记清除后再继续，不会读到尚未写完的槽位。没有可靠的访问位
+//! （LoongArch 没有 A 位），时钟指针只是依次轮转，近似 FIFO。
+
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::hint::spin_loop;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use spin::Mutex;
+
+use super::frame_allocator::remaining_frames;
+use super::{frame_alloc, local_flush_tlb, FrameTracker, MapPermission, MemorySet, PTEFlags, PageTable, PageTableEntry, VirtPageNum};
+use crate::config::{PAGE_SIZE, SWAP_HIGH_WATERMARK, SWAP_LOW_WATERMARK};
+use crate::devices::device::BlkDriver;
+use crate::devices::get_blk_device;
+use crate::drivers::parse_virtio_device_name;
+use crate::fs::mount::MNT_TABLE;
+use crate::sync::Mutex as AsyncMutex;
+use crate::task::{current_process, ProcessRef, PID2PC};
+use crate::utils::error::{SysErrNo, SyscallRet, TemplateRet};
+
+/// 扇区大小
+const SECTOR_SIZE: usize = 0x200;
+const SECTORS_PER_PAGE: usize = PAGE_SIZE / SECTOR_SIZE;
+/// 交换分区头部的签名，位于第 0 页末尾
+const SWAP_MAGIC: &[u8] = b"SWAPSPACE2";
+/// 头部（第 0 页）和坏页的引用计数
+const SLOT_RESERVED: u16 = u16::MAX;
+
+/// (根页表页号, 虚拟页号)
+type SwapKey = (usize, usize);
+
+/// 槽位上正在进行的读写
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum SlotIo {
+    Idle,
+    /// 正在换出，槽位内容尚未写完
+    Writing,
+    /// 正在换入
+    Reading,
+}
+
+/// 一个换出的页
+#[derive(Clone, Copy)]
+struct SwapEntry {
+    slot: usize,
+    /// 换入时使用的映射权限
+    perm: MapPermission,
+    io: SlotIo,
+}
+
+struct SwapDevice {
+    path: String,
+    dev: Arc<dyn BlkDriver>,
+    /// 每个槽位的引用计数，0 为空闲
+    counts: Vec<u16>,
+    /// 可用槽位总数（不含头部和坏页）
+    total: usize,
+    free: usize,
+    /// 下次从这里开始找空闲槽位
+    cursor: usize,
+}
+
+impl SwapDevice {
+    fn alloc_slot(&mut self) -> Option<usize> {
+        if self.free == 0 {
+            return None;
+        }
+        let n = self.counts.len();
+        for i in 0..n {
+            let slot = (self.cursor + i) % n;
+            if self.counts[slot] == 0 {
+                self.counts[slot] = 1;
+                self.free -= 1;
+                self.cursor = slot + 1;
+                return Some(slot);
+            }
+        }
+        None
+    }
+
+    fn get_slot(&mut self, slot: usize) {
+        self.counts[slot] += 1;
+    }
+
+    fn put_slot(&mut self, slot: usize) {
+        self.counts[slot] -= 1;
+        if self.counts[slot] == 0 {
+            self.free += 1;
+        }
+    }
+
+}
+
+fn read_slot(dev: &Arc<dyn BlkDriver>, slot: usize, buf: &mut [u8]) {
+    dev.read_blocks(slot * SECTORS_PER_PAGE, buf);
+}
+
+fn write_slot(dev: &Arc<dyn BlkDriver>, slot: usize, buf: &[u8]) {
+    dev.write_blocks(slot * SECTORS_PER_PAGE, buf);
+}
+
+struct SwapState {
+    device: Option<SwapDevice>,
+    /// 换出的页
+    entries: BTreeMap<SwapKey, SwapEntry>,
+    /// 内核同步换入、还没被所属地址空间映射的页
+    swapped_in: BTreeMap<SwapKey, (Arc<FrameTracker>, MapPermission)>,
+}
+
+impl SwapState {
+    /// 更新 `PENDING`，没有登记项时各钩子可以不取锁直接返回
+    fn publish(&self) {
+        PENDING.store(self.entries.len() + self.swapped_in.len(), Ordering::Release);
+    }
+
+    /// 释放 `root` 地址空间中 [start, end) 范围内换出的页
+    fn drop_entries(&mut self, root: usize, start: usize, end: usize) {
+        let keys: Vec<SwapKey> = self.entries.range((root, start)..(root, end)).map(|(k, _)| *k).collect();
+        for key in keys {
+            let entry = self.entries.remove(&key).unwrap();
+            if let Some(dev) = self.device.as_mut() {
+                dev.put_slot(entry.slot);
+            }
+        }
+    }
+
+}
+
+/// 换入 `key`：读出槽位内容到新分配的物理页并归还槽位，不是换出的页返回 None。
+///
+/// 槽位正在读写时等待；`park` 为真时换入的页挂到 `swapped_in` 等待接管，
+/// 否则由调用者映射。页已被其他路径换入时返回那一页。
+fn read_in(key: SwapKey, park: bool) -> TemplateRet<Option<(Arc<FrameTracker>, MapPermission)>> {
+    let mut frame = None;
+    let (dev, slot) = loop {
+        let mut state = SWAP.lock();
+        let Some(entry) = state.entries.get_mut(&key) else {
+            let done = if park {
+                state.swapped_in.get(&key).cloned()
+            } else {
+                state.swapped_in.remove(&key)
+            };
+            state.publish();
+            return Ok(done);
+        };
+        match entry.io {
+            SlotIo::Idle if frame.is_some() => {
+                entry.io = SlotIo::Reading;
+                let slot = entry.slot;
+                let dev = state.device.as_ref().expect("swap entry without swap device");
+                break (dev.dev.clone(), slot);
+            }
+            SlotIo::Idle => {
+                drop(state);
+                frame = Some(frame_alloc().ok_or(SysErrNo::ENOMEM)?);
+            }
+            _ => {
+                drop(state);
+                spin_loop();
+            }
+        }
+    };
+    let frame = frame.unwrap();
+    read_slot(&dev, slot, frame.ppn().get_bytes_array());
+
+    let mut state = SWAP.lock();
+    let SwapState { device, entries, swapped_in } = &mut *state;
+    // 读的过程中页被释放（munmap、进程退出）时丢弃读到的内容，槽位已经归还
+    let perm = match entries.get(&key) {
+        Some(entry) if entry.io == SlotIo::Reading && entry.slot == slot => entry.perm,
+        _ => return Ok(None),
+    };
+    entries.remove(&key);
+    if let Some(dev) = device.as_mut() {
+        dev.put_slot(slot);
+    }
+    if park {
+        swapped_in.insert(key, (frame.clone(), perm));
+    }
+    state.publish();
+    Ok(Some((frame, perm)))
+}
+
+static SWAP: Mutex<SwapState> = Mutex::new(SwapState {
+    device: None,
+    entries: BTreeMap::new(),
+    swapped_in: BTreeMap::new(),
+});
+
+/// 是否启用了交换分区
+static ENABLED: AtomicBool = AtomicBool::new(false);
+/// 换出的页和待接管的页的总数
+static PENDING: AtomicUsize = AtomicUsize::new(0);
+/// 回收时轮转地址空间的指针
+static RECLAIM_HAND: AtomicUsize = AtomicUsize::new(0);
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Acquire)
+}
+
+/// 交换分区的总页数和空闲页数
+pub fn stats() -> (usize, usize) {
+    match SWAP.lock().device.as_ref() {
+        Some(dev) => (dev.total, dev.free),
+        None => (0, 0),
+    }
+}
+
+/// 启用 `path` 指定的块设备作为交换分区
+pub fn swapon(path: &str) -> SyscallRet {
+    let id = parse_virtio_device_name(path).ok_or(SysErrNo::ENOTBLK)?;
+    let dev = get_blk_device(id).ok_or(SysErrNo::ENXIO)?;
+    if MNT_TABLE.lock().entries.iter().any(|e| e.special_device == path) {
+        return Err(SysErrNo::EBUSY);
+    }
+    if SWAP.lock().device.is_some() {
+        return Err(SysErrNo::EBUSY);
+    }
+
+    // 解析 mkswap 写入的头部：签名、版本、最后一页和坏页表
+    let mut header = vec![0u8; PAGE_SIZE];
+    dev.read_blocks(0, &mut header);
+    if &header[PAGE_SIZE - SWAP_MAGIC.len()..] != SWAP_MAGIC {
+        warn!("[swapon] {}: no swap signature", path);
+        return Err(SysErrNo::EINVAL);
+    }
+    let word = |off: usize| u32::from_le_bytes(header[off..off + 4].try_into().unwrap()) as usize;
+    if word(1024) != 1 {
+        return Err(SysErrNo::EINVAL);
+    }
+    let slots = (word(1028) + 1).min(dev.capacity() / PAGE_SIZE);
+    if slots <= 1 {
+        return Err(SysErrNo::EINVAL);
+    }
+    let mut counts = vec![0u16; slots];
+    counts[0] = SLOT_RESERVED;
+    let nr_bad = word(1032).min((PAGE_SIZE - 1536) / 4);
+    for i in 0..nr_bad {
+        let bad = word(1536 + i * 4);
+        if bad < slots {
+            counts[bad] = SLOT_RESERVED;
+        }
+    }
+    let total = counts.iter().filter(|&&c| c == 0).count();
+
+    let mut state = SWAP.lock();
+    if state.device.is_some() {
+        return Err(SysErrNo::EBUSY);
+    }
+    state.device = Some(SwapDevice {
+        path: String::from(path),
+        dev,
+        counts,
+        total,
+        free: total,
+        cursor: 1,
+    });
+    ENABLED.store(true, Ordering::Release);
+    info!("[swapon] {}: {} pages", path, total);
+    Ok(0)
+}
+
+/// 停用交换分区：先把换出的页全部换入，内存不足时返回 ENOMEM 并保持启用
+///
+/// 换入的页挂起等待各地址空间接管，这里不改其他地址空间的页表
+pub fn swapoff(path: &str) -> SyscallRet {
+    match SWAP.lock().device.as_ref() {
+        Some(dev) if dev.path == path => {}
+        _ => return Err(SysErrNo::EINVAL),
+    }
+    // 此后 swap_out 不再登记新的页，已登记的在下面的循环中换入
+    ENABLED.store(false, Ordering::Release);
+    loop {
+        let keys: Vec<SwapKey> = SWAP.lock().entries.keys().copied().collect();
+        if keys.is_empty() {
+            break;
+        }
+        for key in keys {
+            if let Err(e) = read_in(key, true) {
+                ENABLED.store(true, Ordering::Release);
+                return Err(e);
+            }
+        }
+    }
+    let mut state = SWAP.lock();
+    match state.device.as_ref() {
+        Some(dev) if dev.path == path && state.entries.is_empty() => state.device = None,
+        _ => return Err(SysErrNo::EBUSY),
+    }
+    state.publish();
+    drop(state);
+    info!("[swapoff] {}", path);
+    Ok(0)
+}
+
+/// 把 `data_frames` 中 `vpns` 对应的页换出，返回换出的页数。
+///
+/// 先解除映射并等其他核刷新完 TLB，再检查 `busy`：若此时有线程进入了系统调用，
+/// 它可能在解除映射前取得了指向这些页的切片，放弃本次换出并恢复映射。
+/// 写槽位时不持有 `SWAP` 锁，登记项标记为写入中。
+pub(super) fn swap_out(
+    page_table: &mut PageTable,
+    data_frames: &mut BTreeMap<VirtPageNum, Arc<FrameTracker>>,
+    vpns: &[VirtPageNum],
+    perm: MapPermission,
+    busy: &dyn Fn() -> bool,
+) -> usize {
+    let root = page_table.root_ppn().0;
+    let mut state = SWAP.lock();
+    if !enabled() {
+        return 0;
+    }
+    let SwapState { device, entries, .. } = &mut *state;
+    let Some(dev) = device.as_mut() else {
+        return 0;
+    };
+    let mut victims = Vec::new();
+    for &vpn in vpns {
+        let Some(slot) = dev.alloc_slot() else { break };
+        let frame = data_frames.remove(&vpn).unwrap();
+        // 大页按 4K 页换出，先拆分
+        page_table.split_huge(vpn);
+        let pte = page_table.find_pte(vpn).unwrap();
+        let flags = pte.flags();
+        *pte = PageTableEntry::empty();
+        entries.insert((root, vpn.0), SwapEntry { slot, perm, io: SlotIo::Writing });
+        victims.push((vpn, slot, frame, flags));
+    }
+    if victims.is_empty() {
+        return 0;
+    }
+    let blk = dev.dev.clone();
+    state.publish();
+    drop(state);
+    // 击落返回时其他核都已刷新，不会再经旧的 TLB 项改写这些页
+    local_flush_tlb();
+    crate::smp::tlb_shootdown(page_table.token());
+    if busy() {
+        for (vpn, _, frame, flags) in victims.iter() {
+            *page_table.find_pte(*vpn).unwrap() = PageTableEntry::new(frame.ppn(), *flags);
+        }
+        let mut state = SWAP.lock();
+        for (vpn, slot, frame, _) in victims {
+            state.entries.remove(&(root, vpn.0));
+            if let Some(dev) = state.device.as_mut() {
+                dev.put_slot(slot);
+            }
+            data_frames.insert(vpn, frame);
+        }
+        state.publish();
+        return 0;
+    }
+    let n = victims.len();
+    page_table.rss_sub(n);
+    for (_, slot, frame, _) in victims.iter() {
+        write_slot(&blk, *slot, frame.ppn().get_bytes_array());
+    }
+    let mut state = SWAP.lock();
+    for (vpn, _, _, _) in victims {
+        if let Some(entry) = state.entries.get_mut(&(root, vpn.0)) {
+            entry.io = SlotIo::Idle;
+        }
+    }
+    n
+}
+
+/// 缺页时换入 `vpn`：不是换出的页返回 None
+pub(super) fn swap_in(page_table: &mut PageTable, vpn: VirtPageNum) -> TemplateRet<Option<Arc<FrameTracker>>> {
+    if PENDING.load(Ordering::Acquire) == 0 {
+        return Ok(None);
+    }
+    let Some((frame, perm)) = read_in((page_table.root_ppn().0, vpn.0), false)? else {
+        return Ok(None);
+    };
+    page_table.map(vpn, frame.ppn(), PTEFlags::from(perm));
+    Ok(Some(frame))
+}
+
+/// 内核经软件页表访问到未映射的用户页时调用：是换出的页就同步换入，
+/// 返回指向换入的页的页表项。页表不变，换入的页挂在这里等待所属地址空间接管
+pub(super) fn fault_in(page_table: &PageTable, vpn: VirtPageNum) -> Option<PageTableEntry> {
+    if PENDING.load(Ordering::Acquire) == 0 {
+        return None;
+    }
+    match read_in((page_table.root_ppn().0, vpn.0), true) {
+        Ok(res) => res.map(|(frame, perm)| PageTableEntry::new(frame.ppn(), PTEFlags::from(perm))),
+        Err(_) => {
+            warn!("[swap] no memory to swap in vpn {:#x}", vpn.0);
+            None
+        }
+    }
+}
+
+/// 把 `root` 地址空间中由内核换入的页逐个交给 `adopt` 映射。
+///
+/// 映射完成前一直持锁，内核此时查找这些页会等待，而不是既找不到登记项也查不到页表项
+pub(super) fn adopt_swapped_in(root: usize, mut adopt: impl FnMut(VirtPageNum, Arc<FrameTracker>, MapPermission)) {
+    if PENDING.load(Ordering::Acquire) == 0 {
+        return;
+    }
+    let mut state = SWAP.lock();
+    let mut rest = state.swapped_in.split_off(&(root, 0));
+    let mut tail = rest.split_off(&(root + 1, 0));
+    state.swapped_in.append(&mut tail);
+    for ((_, vpn), (frame, perm)) in rest {
+        adopt(VirtPageNum(vpn), frame, perm);
+    }
+    state.publish();
+}
+
+/// 释放 [start, end) 范围内换出的页
+pub(super) fn discard(root: usize, start: VirtPageNum, end: VirtPageNum) {
+    if PENDING.load(Ordering::Acquire) == 0 {
+        return;
+    }
+    let mut state = SWAP.lock();
+    state.drop_entries(root, start.0, end.0);
+    state.publish();
+}
+
+/// [start, end) 范围内是否有换出或由内核换入、尚未接管的页
+pub(super) fn has_swapped(root: usize, start: VirtPageNum, end: VirtPageNum) -> bool {
+    if PENDING.load(Ordering::Acquire) == 0 {
+        return false;
+    }
+    let state = SWAP.lock();
+    state.entries.range((root, start.0)..(root, end.0)).next().is_some()
+        || state.swapped_in.range((root, start.0)..(root, end.0)).next().is_some()
+}
+
+/// fork 时让子地址空间共享父地址空间 [start, end) 范围内换出的页
+pub(super) fn dup_range(src: usize, dst: usize, start: VirtPageNum, end: VirtPageNum) {
+    if PENDING.load(Ordering::Acquire) == 0 {
+        return;
+    }
+    let mut state = SWAP.lock();
+    let SwapState { device, entries, .. } = &mut *state;
+    let shared: Vec<(usize, SwapEntry)> = entries
+        .range((src, start.0)..(src, end.0))
+        .map(|(&(_, vpn), &entry)| (vpn, entry))
+        .collect();
+    for (vpn, entry) in shared {
+        if let Some(dev) = device.as_mut() {
+            dev.get_slot(entry.slot);
+        }
+        // 父地址空间的页可能正被内核换入，子地址空间的登记项与之无关
+        entries.insert((dst, vpn), SwapEntry { io: SlotIo::Idle, ..entry });
+    }
+    state.publish();
+}
+
+/// mprotect 时更新 [start, end) 范围内换出的页换入后的权限
+pub(super) fn set_perm(root: usize, start: VirtPageNum, end: VirtPageNum, perm: MapPermission) {
+    if PENDING.load(Ordering::Acquire) == 0 {
+        return;
+    }
+    let mut state = SWAP.lock();
+    for (_, entry) in state.entries.range_mut((root, start.0)..(root, end.0)) {
+        entry.perm = perm;
+    }
+    for (_, (_, old)) in state.swapped_in.range_mut((root, start.0)..(root, end.0)) {
+        *old = perm;
+    }
+}
+
+/// 页表释放时丢弃它名下的全部登记项
+pub(super) fn release_all(root: usize) {
+    if PENDING.load(Ordering::Acquire) == 0 {
+        return;
+    }
+    let mut state = SWAP.lock();
+    state.drop_entries(root, 0, usize::MAX);
+    let mut rest = state.swapped_in.split_off(&(root, 0));
+    let mut tail = rest.split_off(&(root + 1, 0));
+    state.swapped_in.append(&mut tail);
+    state.publish();
+}
+
+/// 空闲物理页低于低水位时换出，直到回到高水位；由 trap 返回路径调用
+pub async fn balance() {
+    if !enabled() {
+        return;
+    }
+    let free = remaining_frames();
+    if free < SWAP_LOW_WATERMARK {
+        reclaim(SWAP_HIGH_WATERMARK - free).await;
+    }
+}
+
+/// 回收至多 `want` 页：先丢弃干净的页缓存页，不够再从各地址空间换出，返回释放的页数
+pub async fn reclaim(want: usize) -> usize {
+    let dropped = crate::fs::pagecache::shrink(want);
+    if dropped >= want || !enabled() {
+        return dropped;
+    }
+    let procs: Vec<ProcessRef> = PID2PC.lock().values().cloned().collect();
+    let mut spaces: Vec<Arc<AsyncMutex<MemorySet>>> = Vec::new();
+    for proc in procs.iter() {
+        if !spaces.iter().any(|ms| Arc::ptr_eq(ms, &proc.memory_set)) {
+            spaces.push(proc.memory_set.clone());
+        }
+    }
+    if spaces.is_empty() {
+        return dropped;
+    }
+    let current = current_process();
+    let first = RECLAIM_HAND.fetch_add(1, Ordering::Relaxed) % spaces.len();
+    spaces.rotate_left(first);
+
+    let mut freed = dropped;
+    for ms in spaces {
+        if freed >= want {
+            break;
+        }
+        // 共用该地址空间的进程里有线程在系统调用中时不能换出
+        let users: Vec<ProcessRef> = procs
+            .iter()
+            .filter(|p| Arc::ptr_eq(&p.memory_set, &ms))
+            .cloned()
+            .collect();
+        let busy = || users.iter().any(|p| p.in_syscall());
+        if busy() {
+            continue;
+        }
+        if Arc::ptr_eq(&ms, &current.memory_set) {
+            freed += ms.lock().await.swap_out_pages(want - freed, &busy);
+        } else if let Some(mut guard) = ms.try_lock() {
+            freed += guard.swap_out_pages(want - freed, &busy);
+        }
+    }
+    if freed > 0 {
+        debug!(
+            "[swap] reclaimed {} pages ({} from page cache), {} free",
+            freed,
+            dropped,
+            remaining_frames()
+        );
+    }
+    freed
+}
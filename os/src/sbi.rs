@@ -43,6 +43,28 @@ mod riscv_sbi {
         sbi_call(SBI_SHUTDOWN, 0, 0, 0);
         panic!("It should shutdown!");
     }
+
+    /// SBI IPI 扩展（"sPI"）
+    const SBI_EXT_IPI: usize = 0x735049;
+
+    /// 向 `hart` 发送核间中断，对方收到 supervisor 软件中断
+    pub fn send_ipi(hart: usize) {
+        unsafe {
+            asm!(
+                "ecall",
+                inlateout("x10") 1usize << (hart % 64) => _,
+                inlateout("x11") hart / 64 * 64 => _,
+                in("x16") 0,
+                in("x17") SBI_EXT_IPI,
+            );
+        }
+    }
+
+    /// 清除本核待处理的核间中断
+    pub fn clear_ipi() {
+        // 写 sip.SSIP 清除 supervisor 软件中断
+        unsafe { asm!("csrc sip, {}", in(reg) 1usize << 1) };
+    }
 }
 
 #[cfg(target_arch = "loongarch64")]
@@ -88,6 +110,33 @@ mod loongarch_platform {
         unreachable!()
     }
 
+    /// 向 `cpu` 发送核间中断
+    pub fn send_ipi(cpu: usize) {
+        loongArch64::ipi::send_ipi_single(cpu, IPI_ACTION_SCHED);
+    }
+
+    /// 清除本核待处理的核间中断
+    pub fn clear_ipi() {
+        use loongArch64::consts::{LOONGARCH_IOCSR_IPI_CLEAR, LOONGARCH_IOCSR_IPI_STATUS};
+        unsafe {
+            let status: u32;
+            asm!("iocsrrd.w {}, {}", out(reg) status, in(reg) LOONGARCH_IOCSR_IPI_STATUS);
+            asm!("iocsrwr.w {}, {}", in(reg) status, in(reg) LOONGARCH_IOCSR_IPI_CLEAR);
+        }
+    }
+
+    /// 打开本核所有核间中断向量，并清掉启动从核时留下的中断
+    pub fn init_ipi() {
+        use loongArch64::consts::LOONGARCH_IOCSR_IPI_EN;
+        unsafe {
+            asm!("iocsrwr.w {}, {}", in(reg) u32::MAX, in(reg) LOONGARCH_IOCSR_IPI_EN);
+        }
+        clear_ipi();
+    }
+
+    /// 内核使用的 IPI 向量（启动从核使用向量 0）
+    const IPI_ACTION_SCHED: u32 = 1 << 1;
+
     // QEMU LoongArch64 特定的关机实现
     unsafe fn qemu_loongarch64_shutdown() -> ! {
         // 方法1: 使用 QEMU 的调试退出接口
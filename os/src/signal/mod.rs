@@ -40,10 +40,11 @@ pub const SI_TKILL: i32 = -6; // tkill or tgkill
 use crate::config::{SS_DISABLE, USER_SIGNAL_PROTECT};
 use crate::mm::{get_target_ref, put_data, translated_refmut};
 use crate::task::{current_process, current_task, current_task_id, current_token, exit_proc};
-use crate::task::{ProcessRef, Task, TaskRef, PID2PC}; // 确保 Task 有 id()
+use crate::task::{ProcessRef, Task, TaskRef, TaskStatus, PID2PC}; // 确保 Task 有 id()
 use crate::trap::{disable_irqs, TrapContext, TrapStatus, UContext};
 use crate::utils::error::SysErrNo;
 use alloc::sync::Arc;
+use alloc::vec::Vec;
 pub use sigact::*;
 pub use signal::*; 
 
@@ -75,6 +76,7 @@ pub async fn send_signal(
 
     // TODO: 权限检查 (当前进程是否有权限向目标进程/线程发送信号) @Heliosly.
     // ...
+    prepare_signal(&pcb_arc, sig).await;
 
     if let Some(tid) = target_tid {
         // --- 发送给特定线程 (tkill / pthread_kill 语义) ---
@@ -133,6 +135,176 @@ pub async fn send_signal(
     Ok(())
 }
 
+/// 向进程组 `pgid` 中的每个进程发送信号（`kill(-pgid, sig)` 语义）
+pub async fn send_signal_to_pgrp(pgid: usize, sig: Signal) -> Result<(), SignalError> {
+    let members = pgrp_members(pgid);
+    if members.is_empty() {
+        return Err(SignalError::NoSuchProcess);
+    }
+    let mut delivered = false;
+    let mut last_err = SignalError::NoSuchProcess;
+    for pcb in members {
+        match send_signal(pcb.get_pid(), None, sig).await {
+            Ok(()) => delivered = true,
+            Err(e) => last_err = e,
+        }
+    }
+    if delivered {
+        Ok(())
+    } else {
+        Err(last_err)
+    }
+}
+
+/// 进程组 `pgid` 中的所有进程（含尚未回收的僵尸进程）
+pub fn pgrp_members(pgid: usize) -> Vec<ProcessRef> {
+    PID2PC
+        .lock()
+        .values()
+        .filter(|pcb| pcb.pgid() == pgid)
+        .cloned()
+        .collect()
+}
+
+/// 信号入队前对作业控制状态的处理，与是否被忽略无关
+///
+/// - SIGCONT 让停止的进程继续，并丢弃尚未处理的停止信号；
+/// - 停止信号丢弃尚未处理的 SIGCONT；
+/// - SIGKILL 把停止的进程唤醒，让它去处理 SIGKILL。
+async fn prepare_signal(pcb: &ProcessRef, sig: Signal) {
+    match sig {
+        Signal::SIGCONT => {
+            discard_pending(
+                pcb,
+                &[Signal::SIGSTOP, Signal::SIGTSTP, Signal::SIGTTIN, Signal::SIGTTOU],
+            )
+            .await;
+            if pcb.resume(true) {
+                notify_parent(pcb).await;
+            }
+        }
+        Signal::SIGSTOP | Signal::SIGTSTP | Signal::SIGTTIN | Signal::SIGTTOU => {
+            discard_pending(pcb, &[Signal::SIGCONT]).await;
+        }
+        Signal::SIGKILL => {
+            pcb.resume(false);
+        }
+        _ => {}
+    }
+}
+
+/// 从进程及其所有线程的挂起集合中去掉 `sigs`
+async fn discard_pending(pcb: &ProcessRef, sigs: &[Signal]) {
+    {
+        let mut shared = pcb.signal_shared_state.lock().await;
+        for &sig in sigs {
+            shared.shared_sigpending.remove(sig);
+        }
+    }
+    let tasks = pcb.tasks.lock().await.clone();
+    for task in tasks {
+        let mut state = task.signal_state.lock().await;
+        for &sig in sigs {
+            state.sigpending.remove(sig);
+        }
+    }
+}
+
+/// 子进程停止或继续时通知父进程：唤醒 wait，并按父进程的设置发送 SIGCHLD
+async fn notify_parent(pcb: &ProcessRef) {
+    let Some(parent) = PID2PC.lock().get(&pcb.parent()).cloned() else {
+        return;
+    };
+    parent.notify_child_event();
+    let action = parent.signal_shared_state.lock().await.sigactions[Signal::SIGCHLD as usize];
+    if action.flags.contains(SigActionFlags::SA_NOCLDSTOP) {
+        return;
+    }
+    // SIGCHLD 默认忽略：没有处理函数时只有阻塞了它的线程（sigwait/signalfd）需要它
+    let wanted = match action.handler {
+        SIG_IGN => false,
+        SIG_DFL => {
+            let tasks = parent.tasks.lock().await.clone();
+            let mut blocked = false;
+            for task in tasks {
+                if task.signal_state.lock().await.sigmask.contains(Signal::SIGCHLD) {
+                    blocked = true;
+                    break;
+                }
+            }
+            blocked
+        }
+        _ => true,
+    };
+    if !wanted {
+        return;
+    }
+    // 直接挂到父进程的共享挂起集合上（经 send_signal 会与 prepare_signal 互相递归）
+    parent.signal_shared_state.lock().await.shared_sigpending.add(Signal::SIGCHLD);
+    let task = parent.tasks.lock().await.first().cloned();
+    if let Some(task) = task {
+        unsafe { crate::task::waker::wakeup_task(Arc::as_ptr(&task)) };
+    }
+}
+
+/// 执行停止信号：进程进入停止状态，通知父进程，当前线程挂起直到继续
+///
+/// 孤儿进程组不响应来自终端的停止信号（SIGTSTP/SIGTTIN/SIGTTOU）。
+pub async fn job_stop(pcb: &ProcessRef, sig: Signal) {
+    if sig != Signal::SIGSTOP && is_orphaned_pgrp(pcb.pgid(), 0).await {
+        log::info!("Process {} ignores {:?} in orphaned process group", pcb.pid.0, sig);
+        return;
+    }
+    if pcb.stop(sig as usize) {
+        notify_parent(pcb).await;
+    }
+    pcb.wait_until_resumed().await;
+}
+
+/// 进程组是否是孤儿进程组：组内每个存活进程的父进程要么在同一组，要么在另一个会话
+///
+/// `exiting` 是正在退出、不再算作组成员的进程，0 表示没有。
+pub async fn is_orphaned_pgrp(pgid: usize, exiting: usize) -> bool {
+    for pcb in pgrp_members(pgid) {
+        if pcb.get_pid() == exiting || *pcb.state.lock().await == TaskStatus::Zombie {
+            continue;
+        }
+        let Some(parent) = PID2PC.lock().get(&pcb.parent()).cloned() else {
+            continue;
+        };
+        if parent.get_pid() == exiting || *parent.state.lock().await == TaskStatus::Zombie {
+            continue;
+        }
+        if parent.pgid() != pgid && parent.sid() == pcb.sid() {
+            return false;
+        }
+    }
+    true
+}
+
+/// 进程组变成孤儿时，若组内有停止的进程，先后发送 SIGHUP 和 SIGCONT
+pub async fn kill_orphaned_pgrp(pgid: usize, exiting: usize) {
+    let has_stopped = pgrp_members(pgid).iter().any(|pcb| pcb.is_stopped());
+    if has_stopped && is_orphaned_pgrp(pgid, exiting).await {
+        log::info!("[signal] process group {} orphaned with stopped jobs", pgid);
+        let _ = send_signal_to_pgrp(pgid, Signal::SIGHUP).await;
+        let _ = send_signal_to_pgrp(pgid, Signal::SIGCONT).await;
+    }
+}
+
+/// 当前线程是否忽略或阻塞了 `sig`
+pub async fn signal_ignored_or_blocked(sig: Signal) -> bool {
+    let task = current_task();
+    let Some(pcb) = task.get_process() else {
+        return true;
+    };
+    if task.signal_state.lock().await.sigmask.contains(sig) {
+        return true;
+    }
+    let handler = pcb.signal_shared_state.lock().await.sigactions[sig as usize].handler;
+    handler == SIG_IGN
+}
+
 #[derive(Debug)]
 pub enum SignalError {
     InvalidSignal,
@@ -166,6 +338,7 @@ pub async fn send_signal_to_task(task_arc: &Arc<Task>, sig: Signal) -> Result<()
     // 2. 获取进程共享的 sigactions（注册的处理方式）
     //    假设 Task 有方法 .get_pcb() 拿到它所属的进程控制块
     let pcb_arc = task_arc.get_process().unwrap();
+    prepare_signal(&pcb_arc, sig).await;
     let proc_sig_shared = pcb_arc.signal_shared_state.lock().await;
     let action = proc_sig_shared.sigactions[signum];
     // SIGKILL 和 SIGSTOP 永远不能被忽略
@@ -188,6 +361,36 @@ pub async fn send_signal_to_task(task_arc: &Arc<Task>, sig: Signal) -> Result<()
 
     Ok(())
 }
+/// 当前任务是否有未被屏蔽的挂起信号，可中断的阻塞操作据此提前返回
+pub async fn signal_pending() -> bool {
+    let task_arc = current_task();
+    let pcb_arc = match task_arc.get_process() {
+        Some(o) => o,
+        None => return false,
+    };
+    let task_state = task_arc.signal_state.lock().await;
+    let mut pending = pcb_arc.signal_shared_state.lock().await.shared_sigpending;
+    pending.union_with(&task_state.sigpending);
+    pending.bits & !task_state.sigmask.bits != 0
+}
+/// `signal_pending` 的非阻塞版本，供等待条件的闭包使用。
+/// 拿不到信号状态的锁时保守地返回 true，调用者应再用 `signal_pending` 确认
+pub fn signal_pending_nowait() -> bool {
+    let task_arc = current_task();
+    let pcb_arc = match task_arc.get_process() {
+        Some(o) => o,
+        None => return false,
+    };
+    let Some(task_state) = task_arc.signal_state.try_lock() else {
+        return true;
+    };
+    let Some(process_state) = pcb_arc.signal_shared_state.try_lock() else {
+        return true;
+    };
+    let mut pending = process_state.shared_sigpending;
+    pending.union_with(&task_state.sigpending);
+    pending.bits & !task_state.sigmask.bits != 0
+}
 pub async fn handle_pending_signals(res: Option<usize>) {
     let task_arc = current_task();
 
@@ -200,6 +403,10 @@ pub async fn handle_pending_signals(res: Option<usize>) {
     if pcb_arc.is_zombie().await || task_arc.is_exited() {
         return;
     }
+    // 进程被停止时，其余线程也在返回用户态前停下
+    if pcb_arc.is_stopped() {
+        pcb_arc.wait_until_resumed().await;
+    }
     // 1. 获取线程和进程的信号状态锁
     let mut task_state = task_arc.signal_state.lock().await;
     let mut process_state = pcb_arc.signal_shared_state.lock().await;
@@ -270,18 +477,14 @@ pub async fn handle_pending_signals(res: Option<usize>) {
                     pid,
                     task_arc.id()
                 );
-                unimplemented!();
+                drop(task_state);
+                drop(process_state);
+                job_stop(&pcb_arc, sig).await;
+                task_state = task_arc.signal_state.lock().await;
+                process_state = pcb_arc.signal_shared_state.lock().await;
                 continue;
             }
-            // 同样，SIGCONT 需要唤醒进程中的所有线程
-            if sig == Signal::SIGCONT {
-                unimplemented!();
-                // log::info!("Process {} (task {}) received SIGCONT, continuing all tasks.", pcb_arc.pid.0, task_arc.id());
-                // pcb_arc.continue_all_tasks(); // 假设有此方法
-                // SIGCONT 的默认动作是 Ignore (如果之前是Stop) 或 Continue。
-                // 如果有用户处理器，则执行用户处理器。
-                // 我们这里先按默认处理，如果用户有处理器，下面会走到。
-            }
+            // SIGCONT 在发送时（prepare_signal）就已让进程继续，这里只需按普通信号交付
 
             // 计算在信号处理函数执行期间需要阻塞的掩码
             let mut new_mask_during_handler = task_state.sigmask; // 基于线程当前掩码
@@ -530,19 +733,15 @@ pub async fn perform_default_action_for_process(
         SignalDefaultAction::Ignore => {}
         SignalDefaultAction::Stop => {
             log::info!("Process {} stopping due to signal {:?}", pcb_arc.pid.0, sig);
-            // pcb_arc.stop_all_tasks();
-            
-            unimplemented!();
+            job_stop(pcb_arc, sig).await;
         }
         SignalDefaultAction::Continue => {
+            // 继续已在发送时完成
             log::info!(
                 "Process {} continuing due to signal {:?}",
                 pcb_arc.pid.0,
                 sig
             );
-
-            unimplemented!();
-            // pcb_arc.continue_all_tasks();
         }
         SignalDefaultAction::ForceTerminateOrStop => {
             unreachable!("SIGKILL/SIGSTOP default actions should be handled earlier in handle_pending_signals");
@@ -0,0 +1,210 @@
+//! 多核支持
+//!
+//! - 从核由 polyhal-boot 按设备树中的 CPU 列表启动，等主核完成初始化后进入
+//!   [`secondary_main`](crate::secondary_main)，随后与主核运行同一个调度循环；
+//! - 核间中断（IPI）用于唤醒空闲核（远程唤醒）和 TLB 击落；
+//! - 每个核记录当前使用的页表，[`crate::mm::flush_all`] 只通知使用同一页表的其他核。
+//!
+//! TLB 击落是同步的：每个请求在目标核上有一个序号，发送方等到目标核确认了
+//! 不小于该序号的刷新，或者确认它不在用户态为止。内核访问用户内存都经过软件页表查询，不依赖 TLB，所以在内核态
+//! 的核只需在返回用户态前处理，见 [`enter_user`]。
+
+use core::hint::spin_loop;
+use core::sync::atomic::{fence, AtomicBool, AtomicUsize, Ordering};
+
+use crate::config::MAX_CPUS;
+
+/// 重新调度：空闲核醒来后从就绪队列取任务
+pub const IPI_RESCHEDULE: usize = 1 << 0;
+/// 刷新本核 TLB
+pub const IPI_TLB_FLUSH: usize = 1 << 1;
+
+/// 每个核的状态，其他核可以读写
+struct CpuState {
+    /// 待处理的 IPI 类型
+    ipi_pending: AtomicUsize,
+    /// 是否在等待中断（没有可运行的任务）
+    idle: AtomicBool,
+    /// 当前使用的页表 token
+    token: AtomicUsize,
+    /// 时钟中断计数，用于周期性负载均衡
+    ticks: AtomicUsize,
+    /// 最近一个 TLB 刷新请求的序号
+    tlb_req: AtomicUsize,
+    /// 已完成刷新的请求序号：取走请求后读到的 `tlb_req`
+    tlb_ack: AtomicUsize,
+    /// 是否正在（或即将）运行用户态代码
+    in_user: AtomicBool,
+}
+
+impl CpuState {
+    const fn new() -> Self {
+        Self {
+            ipi_pending: AtomicUsize::new(0),
+            idle: AtomicBool::new(false),
+            token: AtomicUsize::new(0),
+            ticks: AtomicUsize::new(0),
+            tlb_req: AtomicUsize::new(0),
+            tlb_ack: AtomicUsize::new(0),
+            in_user: AtomicBool::new(false),
+        }
+    }
+}
+
+static CPUS: [CpuState; MAX_CPUS] = [const { CpuState::new() }; MAX_CPUS];
+
+/// 在线 CPU 的掩码
+static ONLINE_MASK: AtomicUsize = AtomicUsize::new(0);
+
+/// 主核是否完成了全局初始化
+static BOOT_DONE: AtomicBool = AtomicBool::new(false);
+
+/// 当前核编号
+#[inline]
+pub fn cpu_id() -> usize {
+    polyhal::hart_id()
+}
+
+/// 在线 CPU 的掩码
+pub fn online_mask() -> usize {
+    ONLINE_MASK.load(Ordering::Acquire)
+}
+
+/// 在线 CPU 数
+pub fn online_cpus() -> usize {
+    online_mask().count_ones() as usize
+}
+
+/// 当前核完成初始化，开始参与调度
+pub fn set_online(cpu: usize) {
+    ONLINE_MASK.fetch_or(1 << cpu, Ordering::AcqRel);
+    info!("[smp] cpu {} online", cpu);
+}
+
+/// 主核完成全局初始化，放行等待中的从核
+pub fn finish_boot() {
+    BOOT_DONE.store(true, Ordering::Release);
+}
+
+/// 从核等待主核完成全局初始化
+pub fn wait_for_boot() {
+    while !BOOT_DONE.load(Ordering::Acquire) {
+        spin_loop();
+    }
+}
+
+/// 标记当前核进入/离开空闲
+pub fn set_idle(idle: bool) {
+    CPUS[cpu_id()].idle.store(idle, Ordering::Release);
+}
+
+pub fn is_idle(cpu: usize) -> bool {
+    CPUS[cpu].idle.load(Ordering::Acquire)
+}
+
+/// 记录当前核切换到的页表
+pub fn set_token(token: usize) {
+    CPUS[cpu_id()].token.store(token, Ordering::Release);
+}
+
+/// 当前核正在使用的页表
+pub fn token() -> usize {
+    CPUS[cpu_id()].token.load(Ordering::Acquire)
+}
+
+/// 当前核的时钟中断计数加一，返回是否到了负载均衡的时机
+pub fn tick() -> bool {
+    let ticks = CPUS[cpu_id()].ticks.fetch_add(1, Ordering::Relaxed) + 1;
+    ticks % crate::config::LOAD_BALANCE_TICKS == 0
+}
+
+/// 向 `cpu` 发送 `kind` 类型的核间中断
+pub fn send_ipi(cpu: usize, kind: usize) {
+    CPUS[cpu].ipi_pending.fetch_or(kind, Ordering::AcqRel);
+    crate::sbi::send_ipi(cpu);
+}
+
+/// 新任务进入了 `cpu` 的就绪队列，若它正空闲则将其唤醒
+pub fn kick(cpu: usize) {
+    if cpu != cpu_id() && is_idle(cpu) {
+        send_ipi(cpu, IPI_RESCHEDULE);
+    }
+}
+
+/// 通知其他正在使用 `token` 页表的核刷新 TLB，返回时它们都已刷新
+pub fn tlb_shootdown(token: usize) {
+    shootdown(|cpu| CPUS[cpu].token.load(Ordering::Acquire) == token);
+}
+
+/// 通知其他所有核刷新 TLB，用于同时修改了多个页表的情况
+pub fn tlb_shootdown_all() {
+    shootdown(|_| true);
+}
+
+/// 向满足 `filter` 的其他核发送刷新请求，并等待它们确认
+fn shootdown(filter: impl Fn(usize) -> bool) {
+    let mut seqs = [0usize; MAX_CPUS];
+    let mut targets = 0usize;
+    let mut mask = online_mask() & !(1 << cpu_id());
+    while mask != 0 {
+        let cpu = mask.trailing_zeros() as usize;
+        mask &= mask - 1;
+        if filter(cpu) {
+            // 序号先于请求位发布，对方取走请求位后读到的序号一定覆盖本请求
+            seqs[cpu] = CPUS[cpu].tlb_req.fetch_add(1, Ordering::SeqCst) + 1;
+            targets |= 1 << cpu;
+            send_ipi(cpu, IPI_TLB_FLUSH);
+        }
+    }
+    // 与 enter_user 配对：对方要么看到请求，要么被这里看到已在用户态
+    fence(Ordering::SeqCst);
+    while targets != 0 {
+        let cpu = targets.trailing_zeros() as usize;
+        let state = &CPUS[cpu];
+        // 不在用户态的核会在返回用户态前处理请求
+        if state.tlb_ack.load(Ordering::Acquire) >= seqs[cpu]
+            || !state.in_user.load(Ordering::SeqCst)
+        {
+            targets &= targets - 1;
+        } else {
+            // 对方也可能在等本核确认
+            handle_pending();
+            spin_loop();
+        }
+    }
+}
+
+/// 即将返回用户态：先公布状态，再处理已到达的请求，
+/// 保证此后发出的击落一定会等本核确认
+pub fn enter_user() {
+    CPUS[cpu_id()].in_user.store(true, Ordering::SeqCst);
+    fence(Ordering::SeqCst);
+    handle_pending();
+}
+
+/// 从用户态陷入内核
+pub fn leave_user() {
+    CPUS[cpu_id()].in_user.store(false, Ordering::Release);
+}
+
+/// 核间中断处理
+pub fn handle_ipi() {
+    crate::sbi::clear_ipi();
+    handle_pending();
+}
+
+/// 处理其他核发来的请求；关中断运行的调度循环也会主动调用
+pub fn handle_pending() {
+    let state = &CPUS[cpu_id()];
+    if state.ipi_pending.load(Ordering::Acquire) == 0 {
+        return;
+    }
+    let pending = state.ipi_pending.swap(0, Ordering::SeqCst);
+    if pending & IPI_TLB_FLUSH != 0 {
+        // 此时读到的序号对应的请求，其页表修改都发生在这次刷新之前
+        let seq = state.tlb_req.load(Ordering::SeqCst);
+        crate::mm::local_flush_tlb();
+        state.tlb_ack.fetch_max(seq, Ordering::AcqRel);
+    }
+    // IPI_RESCHEDULE 只需把核从等待中断中唤醒，调度循环会自行取任务
+}
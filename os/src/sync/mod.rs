@@ -5,6 +5,7 @@ mod up;
 
 pub use up::UPSafeCell;
 mod waitqueue;
+pub use waitqueue::WaitQueue;
 pub mod futex;
 mod mutex;
 pub use mutex::{Mutex,MutexGuard};
@@ -180,6 +180,11 @@ impl WaitQueue {
         self.queue.lock().notify_one()
     }
 
+    /// 唤醒队列中的所有等待者，返回唤醒的数量。
+    pub fn notify_all(&self) -> usize {
+        self.queue.lock().notify_n(usize::MAX)
+    }
+
     /// 创建一个 Future，该 Future 会一直等待直到提供的 `condition` 函数返回 `true`。
     ///
     /// # Arguments
@@ -212,7 +217,8 @@ impl WaitQueue {
 ///
 /// 这个 Future 在被轮询时：
 /// 1. 检查条件。如果满足，Future 完成。
-/// 2. 如果条件不满足且尚未注册，它会将当前任务的 waker 注册到 `WaitQueue` 中。
+/// 2. 如果条件不满足，它会将当前任务的 waker 注册到 `WaitQueue` 中，然后再检查一次条件，
+///    避免错过注册前到来的唤醒。
 /// 3. 返回 `Poll::Pending`，直到被 `WaitQueue` 唤醒。
 ///
 /// `#[must_use]` 属性提示用户这个 Future 必须被轮询（例如通过 `.await`）才能执行任何操作。
@@ -261,23 +267,28 @@ impl<'a, F: Fn() -> bool + Unpin> Future for WaitUntilFuture<'a, F> {
             return Poll::Ready(());
         }
 
-        // 2. 条件不满足。检查我们是否已经注册到等待队列。
-        if mut_self.registered_node_arc.is_none() {
-            // 尚未注册，需要将当前任务的 waker 添加到等待队列。
-            // 创建一个新的 waker 节点。
-            let task = cx.waker().data() as *const Task;
-            unsafe { &*task }.set_state(TaskStatus::Blocking);
-            info!("blocking in wait_until");
-            let node = GeneralWaitWakerNode::new(cx.waker().clone());
-            let node_arc = Arc::new(node);
-          
-            // 获取锁并将节点添加到队列。
-            mut_self.wq.queue.lock().add_waiter(node_arc.clone());
+        // 2. 条件不满足。已经注册过说明是被唤醒后条件又不成立了（被别的任务抢先，
+        //    或者被信号等其它原因唤醒），节点可能已被 notify 取走，需要重新注册并再次阻塞，
+        //    否则任务不会再被唤醒，只能被当作 yield 反复轮询。
+        if let Some(node_arc) = mut_self.registered_node_arc.take() {
+            mut_self.wq.queue.lock().remove_waiter(&node_arc);
+        }
+        // 将当前任务的 waker 添加到等待队列。
+        let task = unsafe { &*(cx.waker().data() as *const Task) };
+        task.set_state(TaskStatus::Blocking);
+        let node_arc = Arc::new(GeneralWaitWakerNode::new(cx.waker().clone()));
+        mut_self.wq.queue.lock().add_waiter(node_arc.clone());
 
-            // 保存对节点的 Arc 引用，以便在 Future 完成或被丢弃时可以移除它。
-            mut_self.registered_node_arc = Some(node_arc);
+        // 3. 注册后再检查一次：其他核可能在第一次检查之后、注册之前满足条件并 notify，
+        //    那次唤醒没有找到本任务，不再检查就会一直阻塞。
+        if (mut_self.condition)() {
+            mut_self.wq.queue.lock().remove_waiter(&node_arc);
+            task.set_state(TaskStatus::Running);
+            return Poll::Ready(());
         }
-       
+
+        // 保存对节点的 Arc 引用，以便在 Future 完成或被丢弃时可以移除它。
+        mut_self.registered_node_arc = Some(node_arc);
 
         trace!("WaitUntilFutex Pending in ?");
         Poll::Pending
@@ -76,6 +76,7 @@ pub const SYSCALL_UNAME:usize = 160;
 pub const SYSCALL_FSTATAT :usize =79;
 pub const SYSCALL_IOCTL :usize =29;
 pub const SYSCALL_FCNTL:usize =25;
+pub const SYSCALL_FLOCK: usize = 32;
 pub const SYSCALL_SIGNALRET:usize =139;
 pub const SYSCALL_GETEUID:usize=175;
 pub const SYSCALL_GETCWD:usize= 17;
@@ -84,6 +85,7 @@ pub const SYSCALL_CHDIR:usize = 49;
 pub const SYSCALL_GETDENTS64:usize=61;
 pub const SYSCALL_GETPGID :usize = 155;
 pub const SYSCALL_SETPGID :usize = 154;
+pub const SYSCALL_GETSID :usize = 156;
 pub const SYSCALL_CLOCK_GETTIME:usize = 113;
 pub const SYSCALL_CLOCK_SETTIME:usize = 112;
 pub const SYSCALL_CLOCK_GETRES:usize = 114; 
@@ -102,6 +104,8 @@ pub const SYSCALL_SYMLINKAT:usize =36;
 pub const SYSCALL_READLINKAT:usize =78;
 
 pub const SYSCALL_MPROTECT:usize =226; 
+pub const SYSCALL_SWAPON: usize = 224;
+pub const SYSCALL_SWAPOFF: usize = 225;
 
 pub const SYSCALL_PIPE2:usize =59; 
 pub const SYSCALL_SENDFILE: usize= 71;
@@ -126,6 +130,7 @@ pub const SYSCALL_SENDTO:usize=206;
 pub const SYSCALL_RECVFROM :usize =207;
 
 pub const SYSCALL_SENDMSG: usize = 211;
+pub const SYSCALL_RECVMSG: usize = 212;
 pub const SYSCALL_LISTEN: usize = 201;
 pub const SYSCALL_ACCEPT: usize = 202;
 
@@ -166,6 +171,7 @@ pub const SYSCALL_FSYNC: usize = 82;
 
 //todo();
 pub const SYSCALL_GETSOCKOPT: usize = 209;
+pub const SYSCALL_SHUTDOWN: usize = 210;
 pub const SYSCALL_SHMGET: usize= 194;
 pub const SYSCALL_SHMAT: usize= 196;
 pub const SYSCALL_SHMCTL: usize= 195;
@@ -173,3 +179,13 @@ pub const SYSCALL_SHMDT: usize= 197;
 pub const SYSCALL_GETITIMER: usize = 102;
 pub const SYSCALL_SETITIMER: usize = 103;
 pub const SYSCALL_UMASK:usize = 166;
+pub const SYSCALL_EPOLL_CREATE1: usize = 20;
+pub const SYSCALL_EPOLL_CTL: usize = 21;
+pub const SYSCALL_EPOLL_PWAIT: usize = 22;
+pub const SYSCALL_EPOLL_PWAIT2: usize = 441;
+pub const SYSCALL_EVENTFD2: usize = 19;
+pub const SYSCALL_SIGNALFD4: usize = 74;
+pub const SYSCALL_TIMERFD_CREATE: usize = 85;
+pub const SYSCALL_TIMERFD_SETTIME: usize = 86;
+pub const SYSCALL_TIMERFD_GETTIME: usize = 87;
+pub const SYSCALL_MSYNC: usize = 227;
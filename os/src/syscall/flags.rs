@@ -33,6 +33,16 @@ bitflags::bitflags! {
         const MCL_FUTURE  = 0x0002;
     }
 }
+bitflags::bitflags! {
+    pub struct MsyncFlags: u32 {
+        /// 安排写回后立即返回
+        const MS_ASYNC      = 0x0001;
+        /// 使同一文件的其他映射失效
+        const MS_INVALIDATE = 0x0002;
+        /// 写回完成后才返回
+        const MS_SYNC       = 0x0004;
+    }
+}
 
 pub const  AT_FDCWD :i32=  -100;
 
@@ -73,6 +83,12 @@ pub const F_GETFD: usize = 1;
 pub const F_SETFD: usize = 2;
 pub const F_GETFL: usize = 3;
 pub const F_SETFL: usize = 4;
+pub const F_GETLK: usize = 5;
+pub const F_SETLK: usize = 6;
+pub const F_SETLKW: usize = 7;
+pub const F_OFD_GETLK: usize = 36;
+pub const F_OFD_SETLK: usize = 37;
+pub const F_OFD_SETLKW: usize = 38;
 pub const FD_CLOEXEC: usize = 1;
 
 #[repr(C)] // 与 C iovec 兼容
@@ -242,4 +258,29 @@ pub const FUTEX_OP_SET: u32 = 0;   // oparg
 pub const FUTEX_OP_ADD: u32 = 1;   // oldval + oparg
 pub const FUTEX_OP_OR: u32 = 2;    // oldval | oparg
 pub const FUTEX_OP_ANDN: u32 = 3;  // oldval & ~oparg
-pub const FUTEX_OP_XOR: u32 = 4;   // oldval ^ oparg
\ No newline at end of file
+pub const FUTEX_OP_XOR: u32 = 4;   // oldval ^ oparg
+
+// ioctl 命令号（asm-generic）
+// 终端
+pub const TCGETS: usize = 0x5401;
+pub const TCSETS: usize = 0x5402;
+pub const TCSETSW: usize = 0x5403;
+pub const TCSETSF: usize = 0x5404;
+pub const TIOCSCTTY: usize = 0x540E;
+pub const TIOCGPGRP: usize = 0x540F;
+pub const TIOCSPGRP: usize = 0x5410;
+pub const TIOCGWINSZ: usize = 0x5413;
+pub const TIOCSWINSZ: usize = 0x5414;
+pub const TIOCNOTTY: usize = 0x5422;
+pub const TIOCGSID: usize = 0x5429;
+// 通用
+pub const FIONREAD: usize = 0x541B;
+pub const FIONBIO: usize = 0x5421;
+pub const FIONCLEX: usize = 0x5450;
+pub const FIOCLEX: usize = 0x5451;
+// 块设备
+pub const BLKSSZGET: usize = 0x1268;
+pub const BLKGETSIZE: usize = 0x1260;
+pub const BLKGETSIZE64: usize = 0x8008_1272;
+// RTC
+pub const RTC_RD_TIME: usize = 0x8024_7009;
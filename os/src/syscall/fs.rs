@@ -1,8 +1,18 @@
 //! File and filesystem-related syscalls
 
-use crate::fs::dev::open_device_file;
 use crate::fs::mount::MNT_TABLE;
+use crate::fs::epoll::{EpollEvent, EpollFile, EPOLL_CTL_DEL};
+use crate::fs::lock::{
+    flock, get_record_lock, set_record_lock, Flock, LockOwner, F_RDLCK, F_UNLCK,
+    F_WRLCK,
+};
+use crate::fs::eventfd::{EventFd, EFD_CLOEXEC, EFD_NONBLOCK, EFD_SEMAPHORE};
 use crate::fs::pipe::make_pipe;
+use crate::fs::signalfd::{SignalFd, SFD_CLOEXEC, SFD_NONBLOCK};
+use crate::fs::timerfd::{
+    ITimerSpec, TimerFd, CLOCK_BOOTTIME, CLOCK_MONOTONIC, CLOCK_REALTIME, TFD_CLOEXEC, TFD_NONBLOCK,
+    TFD_TIMER_ABSTIME, TFD_TIMER_CANCEL_ON_SET,
+};
 use crate::fs::stat::Statx;
 use crate::fs::vfs::VfsManager;
 use crate::signal::SigSet;
@@ -17,7 +27,7 @@ use alloc::vec::Vec;
 use lwext4_rust::bindings::{SEEK_CUR, SEEK_SET};
 const NONE_MODE: u32 = 0;
 use crate::config::{
-    FD_SETSIZE, MAX_FD_NUM, MAX_KERNEL_RW_BUFFER_SIZE, PATH_MAX, SENDFILE_KERNEL_BUFFER_SIZE,
+    FD_SETSIZE, MAX_FD_NUM, MAX_KERNEL_RW_BUFFER_SIZE, PAGE_SIZE, PATH_MAX, SENDFILE_KERNEL_BUFFER_SIZE,
     UIO_MAXIOV,
 };
 use crate::fs::{
@@ -32,8 +42,9 @@ use crate::utils::error::{SysErrNo, SyscallRet};
 use crate::utils::normalize_and_join_path;
 
 use super::flags::{
-    FstatatFlags, IoVec, AT_FDCWD, FD_CLOEXEC, F_DUPFD, F_DUPFD_CLOEXEC, F_GETFD, F_GETFL, F_SETFD,
-    F_SETFL,
+    FstatatFlags, IoVec, AT_FDCWD, FD_CLOEXEC, FIOCLEX, FIONBIO, FIONCLEX, F_DUPFD, F_DUPFD_CLOEXEC,
+    F_GETFD, F_GETFL, F_GETLK, F_OFD_GETLK, F_OFD_SETLK, F_OFD_SETLKW, F_SETFD, F_SETFL, F_SETLK,
+    F_SETLKW,
 };
 use super::process;
 
@@ -58,13 +69,13 @@ pub async fn sys_write(fd: usize, buf: *const u8, len: usize) -> SyscallRet {
             if !file.any().writable().map_err(|_| SysErrNo::EIO)? {
                 return Err(SysErrNo::EBADF);
             }
-            let file = file.clone();
+            let file = file.any();
+            // 阻塞的读写(socket、管道)不能持有 fd_table 的锁
+            drop(fd_table);
             // 3. 执行写操作
             let bytes = file
-                .any()
                 .write(UserBuffer::new(translated_byte_buffer(token, buf, len)))
-                .await
-                .map_err(|_| SysErrNo::EIO)?;
+                .await?;
             Ok(bytes)
         }
         None => Err(SysErrNo::EBADF),
@@ -92,13 +103,13 @@ pub async fn sys_read(fd: usize, buf: *const u8, len: usize) -> SyscallRet {
             if !file.any().readable().map_err(|_| SysErrNo::EIO)? {
                 return Err(SysErrNo::EBADF);
             }
-            let file = file.clone();
+            let file = file.any();
+            // 阻塞的读写(socket、管道)不能持有 fd_table 的锁
+            drop(fd_table);
             // 3. 执行读操作
             let bytes = file
-                .any()
                 .read(UserBuffer::new(translated_byte_buffer(token, buf, len)))
-                .await
-                .map_err(|_| SysErrNo::EIO)?;
+                .await?;
             Ok(bytes)
         }
         None => Err(SysErrNo::EBADF),
@@ -189,9 +200,10 @@ pub async fn sys_close(fd: i32) -> SyscallRet {
     // 2. 从表中移除 FileClass (通过 Option::take)
     // 当 Arc<FileClass> 的最后一个引用被移除时 (如果 FileClass 是 Arc'd),
     // 它的 drop 方法会被调用，触发 VFS 层的清理。
-    let _removed_file = fd_table.table[fd_usize].take(); // Option::take 将 Some(v) 变为 None 并返回 v
+    // 关闭文件的任意一个 fd 都会释放该进程在这个文件上的 POSIX 记录锁
+    let _removed_file = fd_table.close_fd(fd_usize, proc.get_pid());
 
-    // _removed_file (一个 Option<Arc<FileClass>>) 在这里超出作用域并被 drop。
+    // _removed_file (一个 Option<FileDescriptor>) 在这里超出作用域并被 drop。
     // 如果这是 Arc 的最后一个引用, FileClass 的 Drop trait (如果实现) 将被调用。
 
     Ok(0)
@@ -219,10 +231,36 @@ pub async fn sys_fstat(fd: usize, st: *mut Kstat) -> SyscallRet {
     Ok(0)
 }
 
-pub fn sys_ioctl(_fd: usize, _cmd: usize, _arg: usize) -> SyscallRet {
-    // 伪实现
-    warn!("[sys_ioctl]");
-    Ok(0)
+pub async fn sys_ioctl(fd: usize, cmd: usize, arg: usize) -> SyscallRet {
+    trace!("[sys_ioctl] fd:{},cmd:{:#x},arg:{:#x}", fd, cmd, arg);
+    let proc = current_process();
+    let mut fd_table = proc.fd_table.lock().await;
+    let desc = fd_table.table.get_mut(fd).and_then(|f| f.as_mut()).ok_or(SysErrNo::EBADF)?;
+    // 作用在描述符本身上的命令，对所有文件类型都有效
+    match cmd {
+        FIOCLEX => {
+            desc.set_cloexec();
+            return Ok(0);
+        }
+        FIONCLEX => {
+            desc.unset_cloexec();
+            return Ok(0);
+        }
+        FIONBIO => {
+            let on = proc.memory_set.lock().await.get_user::<i32>(arg).await? != 0;
+            if on {
+                desc.set_nonblock();
+            } else {
+                desc.unset_nonblock();
+            }
+            desc.any().set_nonblock(on);
+            return Ok(0);
+        }
+        _ => {}
+    }
+    let file = desc.any();
+    drop(fd_table);
+    file.ioctl(cmd, arg).await
 }
 
 pub async fn sys_fstatat(
@@ -360,15 +398,66 @@ pub async fn sys_fcntl(fd: usize, cmd: usize, arg: usize) -> Result<usize, SysEr
             let current = file.flags.bits();
             let new_bits = (current & !settable.bits()) | ((arg as u32) & settable.bits());
             file.flags = OpenFlags::from_bits_truncate(new_bits);
+            // read/write 走 File trait，拿不到 fd 上的标志，需要同步过去
+            file.any().set_nonblock(file.flags.contains(OpenFlags::O_NONBLOCK));
             Ok(0)
         }
 
+        // —— 记录锁 —— //
+        F_GETLK | F_SETLK | F_SETLKW | F_OFD_GETLK | F_OFD_SETLK | F_OFD_SETLKW => {
+            let file = file_table.get_file(fd)?;
+            // F_SETLKW 可能阻塞，不能持有 fd 表的锁
+            drop(file_table);
+            fcntl_record_lock(file, cmd, arg).await
+        }
+
         // —— 其他命令暂不支持 —— //
         _ => Err(SysErrNo::EINVAL),
     }
 }
 
-use crate::mm::{PageTable, TranslateError, VirtAddr};
+/// fcntl 的 POSIX 记录锁和 OFD 锁命令
+async fn fcntl_record_lock(file: FileDescriptor, cmd: usize, arg: usize) -> SyscallRet {
+    let proc = current_process();
+    let inode = file.file()?;
+    let mut fl = proc.memory_set.lock().await.get_user::<Flock>(arg).await?;
+    let ofd = matches!(cmd, F_OFD_GETLK | F_OFD_SETLK | F_OFD_SETLKW);
+    if ofd && fl.l_pid != 0 {
+        return Err(SysErrNo::EINVAL);
+    }
+    let owner = if ofd {
+        LockOwner::of_file(&inode)
+    } else {
+        LockOwner::Process(proc.get_pid())
+    };
+    match cmd {
+        F_GETLK | F_OFD_GETLK => {
+            match get_record_lock(&inode, owner, &fl)? {
+                Some(lock) => fl = lock.to_flock(),
+                None => fl.l_type = F_UNLCK,
+            }
+            proc.memory_set.lock().await.put_user(arg, fl).await?;
+            Ok(0)
+        }
+        _ => {
+            // 读锁要求 fd 可读，写锁要求 fd 可写
+            let (readable, writable) = file.flags.read_write();
+            if (fl.l_type == F_RDLCK && !readable) || (fl.l_type == F_WRLCK && !writable) {
+                return Err(SysErrNo::EBADF);
+            }
+            let wait = matches!(cmd, F_SETLKW | F_OFD_SETLKW);
+            set_record_lock(&inode, owner, &fl, wait).await
+        }
+    }
+}
+
+pub async fn sys_flock(fd: usize, operation: usize) -> SyscallRet {
+    trace!("[sys_flock] fd: {}, operation: {}", fd, operation);
+    let file = current_process().get_file(fd).await?;
+    flock(&*file.file()?, operation).await
+}
+
+use crate::mm::{PageTable, TranslateError, VirtAddr, VirtPageNum};
 
 // 导入我们新定义的内存复制函数 (假设它们在 mm 模块或一个新模块 user_mem)
 use crate::mm::page_table::{
@@ -1696,7 +1785,7 @@ pub async fn sys_dup3(oldfd: i32, newfd: i32, flags_u32: u32) -> SyscallRet {
     // 5. 如果 newfd 已经打开，则先关闭它
     if newfd_usize < fd_table_guard.len() && fd_table_guard.table[newfd_usize].is_some() {
         log::trace!("sys_dup3: Closing already open newfd({}).", newfd);
-        fd_table_guard.table[newfd_usize] = None;
+        fd_table_guard.close_fd(newfd_usize, pcb_arc.get_pid());
     }
 
     // 6. 确保 fd_table 足够大以容纳 newfd
@@ -1783,10 +1872,14 @@ pub async fn sys_mount(
     // --- 核心逻辑部分 (修改) ---
     // 将所有参数委托给 VfsManager::mount 处理。
     // VfsManager 会负责创建驱动实例、检查父目录、更新挂载表等所有工作。
+    let dir = pcb_arc.resolve_path_from_fd(AT_FDCWD, &dir, false).await?;
     let result = VfsManager::mount(&special, &dir, &fstype, flags, data_opt);
+    if let Err(e) = result {
+        warn!("[sys_mount] mount {} on {} failed: {:?}", special, dir, e);
+    }
 
     // 将 VfsManager 的返回结果 (GeneralRet, 即 Result<(), SysErrNo>) 转换为系统调用返回值
-    Ok(0) // 成功时，将 Ok(()) 映射为 0
+    result.map(|_| 0) // 成功时，将 Ok(()) 映射为 0
 }
 
 /// umount 系统调用实现
@@ -1817,12 +1910,35 @@ pub async fn sys_umount2(target_user_ptr: *const u8, flags: u32) -> SyscallRet {
     let pcb_arc = current_process();
     let token = pcb_arc.memory_set.lock().await.token();
     let target = translated_str(token, target_user_ptr);
+    let target = if target.starts_with("/dev/") {
+        target
+    } else {
+        pcb_arc.resolve_path_from_fd(AT_FDCWD, &target, false).await?
+    };
 
     let result = VfsManager::umount(&target);
 
     result.map(|_| 0)
 }
 
+/// swapon 系统调用实现：启用块设备上的交换区
+///
+/// `flags` 中的优先级与 discard 标志被忽略，只支持一个交换设备。
+pub async fn sys_swapon(path_ptr: *const u8, flags: u32) -> SyscallRet {
+    let token = current_process().memory_set.lock().await.token();
+    let path = translated_str(token, path_ptr);
+    trace!("[sys_swapon] path: {}, flags: {:#x}", path, flags);
+    crate::mm::swap::swapon(&path)
+}
+
+/// swapoff 系统调用实现：把交换区中的页全部换入后停用该设备
+pub async fn sys_swapoff(path_ptr: *const u8) -> SyscallRet {
+    let token = current_process().memory_set.lock().await.token();
+    let path = translated_str(token, path_ptr);
+    trace!("[sys_swapoff] path: {}", path);
+    crate::mm::swap::swapoff(&path)
+}
+
 pub async fn sys_unlinkat(dirfd: i32, path: *const u8, _flags: u32) -> SyscallRet {
     // assert!(flags != AT_REMOVEDIR, "not support yet");
     trace!(
@@ -1887,6 +2003,9 @@ pub async fn sys_renameat(
     if old_abs_path.len() > PATH_MAX || new_abs_path.len() > PATH_MAX {
         return Err(SysErrNo::ENAMETOOLONG);
     }
+    if !VfsManager::same_fs(&old_abs_path, &new_abs_path) {
+        return Err(SysErrNo::EXDEV);
+    }
 
     let old_inode = find_inode(&old_abs_path, OpenFlags::O_RDWR)?;
     //let new_inode = find_inode(&new_abs_path, OpenFlags::O_RDWR)?;
@@ -1935,6 +2054,9 @@ pub async fn sys_renameat2(
     if old_abs_path.len() > PATH_MAX || new_abs_path.len() > PATH_MAX {
         return Err(SysErrNo::ENAMETOOLONG);
     }
+    if !VfsManager::same_fs(&old_abs_path, &new_abs_path) {
+        return Err(SysErrNo::EXDEV);
+    }
 
     // 查找 old 和 new inode
     let old_inode = find_inode(&old_abs_path, OpenFlags::O_RDWR)?;
@@ -2633,7 +2755,7 @@ pub async fn sys_mlock(addr: usize, len: usize) -> SyscallRet {
 
     // 2. 获取进程的内存集合 (MemorySet)
     let proc = current_process();
-    let memory_set = proc.memory_set.lock().await;
+    let mut memory_set = proc.memory_set.lock().await;
 
     // 3. 计算需要操作的虚拟页范围
     let start_va = VirtAddr::from(addr);
@@ -2645,9 +2767,11 @@ pub async fn sys_mlock(addr: usize, len: usize) -> SyscallRet {
     if !memory_set.is_region_alloc(start_va, end_va) {
         return Err(SysErrNo::ENOMEM);
     }
+    // 5. 记录锁定的页段，锁定总量不能超过 RLIMIT_MEMLOCK
+    memory_set.mlock(start_va.floor(), VirtPageNum(end_va.floor().0 + 1))?;
 
     trace!(
-        "[sys_mlock] Region {:#x} - {:#x} locked (no-op).",
+        "[sys_mlock] Region {:#x} - {:#x} locked.",
         addr,
         addr + len
     );
@@ -2678,12 +2802,11 @@ pub async fn sys_munlock(addr: usize, len: usize) -> SyscallRet {
         return Err(SysErrNo::ENOMEM);
     }
 
-    // 5. (空操作) 解除锁定
-    //    在支持换页的系统中，这里会清除 PTE 的 LOCKED 标志。
-    //    在我们的实现中，我们什么都不做。
+    // 5. 解除锁定，这些页重新可以被换出
+    memory_set.munlock(start_va.floor(), VirtPageNum(end_va.floor().0 + 1));
 
     trace!(
-        "[sys_munlock] Region {:#x} - {:#x} unlocked (no-op).",
+        "[sys_munlock] Region {:#x} - {:#x} unlocked.",
         addr,
         addr + len
     );
@@ -2747,10 +2870,11 @@ pub async fn sys_munlockall() -> SyscallRet {
     // 4. 成功
     Ok(0)
 }
-pub fn sys_sync() -> SyscallRet {
-    trace!("[sys_sync] Syncing all filesystems (no-op).");
+pub async fn sys_sync() -> SyscallRet {
+    trace!("[sys_sync] Syncing all filesystems.");
 
     VfsManager::sync();
+    crate::drivers::bcache::flush_all_async().await?;
     Ok(0)
 }
 pub async fn sys_fsync(fd: usize) -> SyscallRet {
@@ -2762,7 +2886,8 @@ pub async fn sys_fsync(fd: usize) -> SyscallRet {
     }
 
     let file = file.unwrap().file()?;
-    file.inner.lock().inode.sync();
+    file.sync()?;
+    crate::drivers::bcache::flush_all_async().await?;
     Ok(0)
 }
 
@@ -2913,3 +3038,221 @@ pub async fn sys_statx(
         Err(SysErrNo::EFAULT) // 用户指针无效
     }
 }
+
+/// epoll_create1：创建 epoll 实例，flags 只支持 EPOLL_CLOEXEC（与 O_CLOEXEC 相同）
+pub async fn sys_epoll_create1(flags: usize) -> SyscallRet {
+    let flags = OpenFlags::from_bits(flags as u32).ok_or(SysErrNo::EINVAL)?;
+    if !(flags - OpenFlags::FD_CLOEXEC).is_empty() {
+        return Err(SysErrNo::EINVAL);
+    }
+    let epoll: Arc<dyn File> = Arc::new(EpollFile::new());
+    current_process()
+        .alloc_and_add_fd(FileDescriptor::new(flags | OpenFlags::O_RDWR, FileClass::Abs(epoll)))
+        .await
+}
+
+/// epoll_ctl：增删改兴趣列表
+pub async fn sys_epoll_ctl(epfd: usize, op: usize, fd: usize, event_ptr: usize) -> SyscallRet {
+    trace!("[sys_epoll_ctl] epfd:{} op:{} fd:{}", epfd, op, fd);
+    let proc = current_process();
+    let (epoll_file, target) = {
+        let fd_table = proc.fd_table.lock().await;
+        (fd_table.get_file(epfd)?.any(), fd_table.get_file(fd)?.any())
+    };
+    let epoll = epoll_file
+        .as_any()
+        .downcast_ref::<EpollFile>()
+        .ok_or(SysErrNo::EINVAL)?;
+    // 不能把 epoll 实例加到它自己里面
+    if Arc::ptr_eq(&epoll_file, &target) {
+        return Err(SysErrNo::EINVAL);
+    }
+    let event = if op == EPOLL_CTL_DEL {
+        None
+    } else {
+        Some(proc.memory_set.lock().await.get_user::<EpollEvent>(event_ptr).await?)
+    };
+    epoll.ctl(op, fd, target, event)
+}
+
+/// epoll_pwait / epoll_pwait2 的公共部分，deadline 为 None 表示无限等待
+async fn epoll_wait_common(
+    epfd: usize,
+    events_ptr: usize,
+    maxevents: i32,
+    deadline: Option<TimeVal>,
+    sigmask_ptr: *const SigSet,
+) -> SyscallRet {
+    if maxevents <= 0 {
+        return Err(SysErrNo::EINVAL);
+    }
+    let proc = current_process();
+    let epoll_file = proc.get_file(epfd).await?.any();
+    let epoll = epoll_file
+        .as_any()
+        .downcast_ref::<EpollFile>()
+        .ok_or(SysErrNo::EINVAL)?;
+
+    // 收集事件会消耗 ET/ONESHOT 状态，必须先确认整个输出数组可写
+    let size = core::mem::size_of::<EpollEvent>();
+    if maxevents as usize > i32::MAX as usize / size {
+        return Err(SysErrNo::EINVAL);
+    }
+    let token = {
+        let mut ms = proc.memory_set.lock().await;
+        ms.check_user_writable(events_ptr, maxevents as usize * size)
+            .await?;
+        ms.token()
+    };
+    let old_mask = set_temp_sigmask_from_user(token, sigmask_ptr).await?;
+    let result = epoll.wait(maxevents as usize, deadline).await;
+    if let Some(old_mask) = old_mask {
+        restore_sigmask_internal(old_mask).await;
+    }
+
+    let ready = result?;
+    let mut ms = proc.memory_set.lock().await;
+    for (i, event) in ready.iter().enumerate() {
+        ms.put_user(events_ptr + i * size, *event)
+            .await?;
+    }
+    Ok(ready.len())
+}
+
+/// epoll_pwait：timeout 以毫秒计，-1 表示无限等待
+pub async fn sys_epoll_pwait(
+    epfd: usize,
+    events_ptr: usize,
+    maxevents: i32,
+    timeout_ms: i32,
+    sigmask_ptr: *const SigSet,
+) -> SyscallRet {
+    trace!(
+        "[sys_epoll_pwait] epfd:{} maxevents:{} timeout:{}",
+        epfd,
+        maxevents,
+        timeout_ms
+    );
+    let deadline = if timeout_ms < 0 {
+        None
+    } else {
+        Some(current_time().add_milliseconds(timeout_ms as usize))
+    };
+    epoll_wait_common(epfd, events_ptr, maxevents, deadline, sigmask_ptr).await
+}
+
+/// epoll_pwait2：timeout 是 timespec，NULL 表示无限等待
+pub async fn sys_epoll_pwait2(
+    epfd: usize,
+    events_ptr: usize,
+    maxevents: i32,
+    timeout_ptr: usize,
+    sigmask_ptr: *const SigSet,
+) -> SyscallRet {
+    let deadline = if timeout_ptr == 0 {
+        None
+    } else {
+        let timeout = current_process()
+            .memory_set
+            .lock()
+            .await
+            .get_user::<UserTimeSpec>(timeout_ptr)
+            .await?;
+        if timeout.tv_nsec >= 1_000_000_000 {
+            return Err(SysErrNo::EINVAL);
+        }
+        Some(current_time().add_timespec(&timeout))
+    };
+    epoll_wait_common(epfd, events_ptr, maxevents, deadline, sigmask_ptr).await
+}
+
+/// eventfd2
+pub async fn sys_eventfd2(initval: u32, flags: u32) -> SyscallRet {
+    if flags & !(EFD_SEMAPHORE | EFD_CLOEXEC | EFD_NONBLOCK) != 0 {
+        return Err(SysErrNo::EINVAL);
+    }
+    let file: Arc<dyn File> = Arc::new(EventFd::new(initval, flags));
+    current_process()
+        .alloc_and_add_fd(FileDescriptor::new(
+            OpenFlags::from_bits_truncate(flags & (EFD_CLOEXEC | EFD_NONBLOCK)) | OpenFlags::O_RDWR,
+            FileClass::Abs(file),
+        ))
+        .await
+}
+
+/// timerfd_create
+pub async fn sys_timerfd_create(clockid: usize, flags: u32) -> SyscallRet {
+    if !matches!(clockid, CLOCK_REALTIME | CLOCK_MONOTONIC | CLOCK_BOOTTIME) {
+        return Err(SysErrNo::EINVAL);
+    }
+    if flags & !(TFD_CLOEXEC | TFD_NONBLOCK) != 0 {
+        return Err(SysErrNo::EINVAL);
+    }
+    let file: Arc<dyn File> = Arc::new(TimerFd::new(flags));
+    current_process()
+        .alloc_and_add_fd(FileDescriptor::new(
+            OpenFlags::from_bits_truncate(flags),
+            FileClass::Abs(file),
+        ))
+        .await
+}
+
+/// timerfd_settime
+pub async fn sys_timerfd_settime(fd: usize, flags: u32, new_ptr: usize, old_ptr: usize) -> SyscallRet {
+    if flags & !(TFD_TIMER_ABSTIME | TFD_TIMER_CANCEL_ON_SET) != 0 {
+        return Err(SysErrNo::EINVAL);
+    }
+    let proc = current_process();
+    let file = proc.get_file(fd).await?.any();
+    let timer = file
+        .as_any()
+        .downcast_ref::<TimerFd>()
+        .ok_or(SysErrNo::EINVAL)?;
+    let mut ms = proc.memory_set.lock().await;
+    let new = ms.get_user::<ITimerSpec>(new_ptr).await?;
+    let old = timer.set_time(flags, &new)?;
+    if old_ptr != 0 {
+        ms.put_user(old_ptr, old).await?;
+    }
+    Ok(0)
+}
+
+/// timerfd_gettime
+pub async fn sys_timerfd_gettime(fd: usize, cur_ptr: usize) -> SyscallRet {
+    let proc = current_process();
+    let file = proc.get_file(fd).await?.any();
+    let timer = file
+        .as_any()
+        .downcast_ref::<TimerFd>()
+        .ok_or(SysErrNo::EINVAL)?;
+    let cur = timer.get_time();
+    proc.memory_set.lock().await.put_user(cur_ptr, cur).await?;
+    Ok(0)
+}
+
+/// signalfd4：fd 为 -1 时新建，否则修改已有 signalfd 的掩码
+pub async fn sys_signalfd4(fd: isize, mask_ptr: usize, sizemask: usize, flags: u32) -> SyscallRet {
+    if sizemask != core::mem::size_of::<SigSet>() {
+        return Err(SysErrNo::EINVAL);
+    }
+    if flags & !(SFD_CLOEXEC | SFD_NONBLOCK) != 0 {
+        return Err(SysErrNo::EINVAL);
+    }
+    let proc = current_process();
+    let mask = proc.memory_set.lock().await.get_user::<SigSet>(mask_ptr).await?;
+    if fd >= 0 {
+        let file = proc.get_file(fd as usize).await?.any();
+        let signalfd = file
+            .as_any()
+            .downcast_ref::<SignalFd>()
+            .ok_or(SysErrNo::EINVAL)?;
+        signalfd.set_mask(mask);
+        return Ok(fd as usize);
+    }
+    let file: Arc<dyn File> = Arc::new(SignalFd::new(mask, flags));
+    proc.alloc_and_add_fd(FileDescriptor::new(
+        OpenFlags::from_bits_truncate(flags),
+        FileClass::Abs(file),
+    ))
+    .await
+}
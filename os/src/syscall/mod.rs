@@ -18,9 +18,11 @@ mod mm;
 pub mod arch;
 mod net;
 pub mod flags;
+pub mod trace;
+pub mod unimplemented;
 use mm::*;
 use flags::{IoVec, Utsname};
-use crate::{fs::select::FdSet, mm::shm::ShmIdDs, signal::SigInfo, syscall::net::{sys_accept, sys_accept4, sys_bind, sys_connect, sys_getpeername, sys_getsockname, sys_listen, sys_recvfrom, sys_sendmsg, sys_sendto, sys_setsockopt, sys_socket, sys_socketpair}, timer::{Tms, UserTimeSpec}};
+use crate::{fs::select::FdSet, mm::shm::ShmIdDs, signal::SigInfo, syscall::net::{sys_accept, sys_accept4, sys_bind, sys_connect, sys_getpeername, sys_getsockname, sys_getsockopt, sys_listen, sys_recvfrom, sys_recvmsg, sys_sendmsg, MsgHdr, sys_sendto, sys_setsockopt, sys_shutdown, sys_socket, sys_socketpair}, timer::{Tms, UserTimeSpec}};
 use fs::*;
 use process::*;
 use other::*;
@@ -31,7 +33,32 @@ use crate::{fs::{Kstat, PollFd}, signal::{SigAction, SigSet}, timer::TimeVal, ut
 
 use signal::*;
 /// handle syscall exception with `syscall_id` and other arguments
-pub async  fn syscall(syscall_id: usize, args: [usize; 6]) -> SyscallRet {
+pub async fn syscall(syscall_id: usize, args: [usize; 6]) -> SyscallRet {
+    if !trace::tracing() {
+        return dispatch(syscall_id, args).await;
+    }
+    let pid = crate::task::current_process().get_pid();
+    if !trace::is_traced(pid) {
+        return dispatch(syscall_id, args).await;
+    }
+    let tid = crate::task::current_task().id();
+    let token = crate::task::current_process().get_user_token().await;
+    // 参数里的字符串要在调用之前读取，execve 之后旧的地址空间就没了
+    let call = trace::format_call(token, syscall_id, &args);
+    if syscall_id == SYSCALL_EXIT || syscall_id == SYSCALL_EXITGROUP {
+        trace::record_noreturn(pid, tid, call);
+        if syscall_id == SYSCALL_EXITGROUP {
+            trace::untrace_pid(pid);
+        }
+        return dispatch(syscall_id, args).await;
+    }
+    let start = crate::timer::get_time_ns();
+    let ret = dispatch(syscall_id, args).await;
+    trace::record(pid, tid, syscall_id, &args, call, start, &ret);
+    ret
+}
+
+async fn dispatch(syscall_id: usize, args: [usize; 6]) -> SyscallRet {
   match syscall_id {
         SYSCALL_OPEN => sys_openat(args[0] as i32,args[1] as *const u8,args[2] as u32,args[3] as u32).await,
         SYSCALL_CLOSE => sys_close(args[0] as i32).await,
@@ -83,8 +110,9 @@ pub async  fn syscall(syscall_id: usize, args: [usize; 6]) -> SyscallRet {
             args[2] as *const UserTimeSpec,
         ),
         SYSCALL_MUNMAP => sys_munmap(args[0], args[1]).await,
+        SYSCALL_MSYNC => sys_msync(args[0], args[1], args[2] as u32).await,
         SYSCALL_UNAME => sys_uname(args[0] as  *mut Utsname).await,
-        SYSCALL_IOCTL =>sys_ioctl(args[0], args[1], args[2]),
+        SYSCALL_IOCTL =>sys_ioctl(args[0], args[1], args[2]).await,
         SYSCALL_FCNTL=>sys_fcntl(args[0], args[1], args[2]).await,
         SYSCALL_READ => sys_read(args[0], args[1] as *const u8, args[2]).await,
         SYSCALL_WRITE => sys_write(args[0], args[1] as *const u8, args[2]).await,
@@ -107,11 +135,22 @@ pub async  fn syscall(syscall_id: usize, args: [usize; 6]) -> SyscallRet {
         SYSCALL_GETEUID=> sys_geteuid() ,
         SYSCALL_GETCWD =>sys_getcwd(args[0] as *mut u8, args[1]).await,
         // SYSCALL_TGKILL => sys_tgkill(args[0], args[1], args[2]),
+        SYSCALL_EVENTFD2 => sys_eventfd2(args[0] as u32, args[1] as u32).await,
+        SYSCALL_TIMERFD_CREATE => sys_timerfd_create(args[0], args[1] as u32).await,
+        SYSCALL_TIMERFD_SETTIME => sys_timerfd_settime(args[0], args[1] as u32, args[2], args[3]).await,
+        SYSCALL_TIMERFD_GETTIME => sys_timerfd_gettime(args[0], args[1]).await,
+        SYSCALL_SIGNALFD4 => sys_signalfd4(args[0] as isize, args[1], args[2], args[3] as u32).await,
+        SYSCALL_FLOCK => sys_flock(args[0], args[1]).await,
+        SYSCALL_EPOLL_CREATE1 => sys_epoll_create1(args[0]).await,
+        SYSCALL_EPOLL_CTL => sys_epoll_ctl(args[0], args[1], args[2], args[3]).await,
+        SYSCALL_EPOLL_PWAIT => sys_epoll_pwait(args[0], args[1], args[2] as i32, args[3] as i32, args[4] as *const SigSet).await,
+        SYSCALL_EPOLL_PWAIT2 => sys_epoll_pwait2(args[0], args[1], args[2] as i32, args[3], args[4] as *const SigSet).await,
         SYSCALL_PPOLL => sys_ppoll(args[0] as *mut PollFd, args[1] , args[2] as *const UserTimeSpec, args[3] as *const SigSet).await,
         SYSCALL_CHDIR => sys_chdir(args[0] as *const u8).await,
         SYSCALL_GETDENTS64 => sys_getdents64(args[0], args[1] as *mut u8, args[2]).await,
-        SYSCALL_SETPGID => Ok(0),
-        SYSCALL_GETPGID => Ok(0),
+        SYSCALL_SETPGID => sys_setpgid(args[0], args[1] as isize).await,
+        SYSCALL_GETPGID => sys_getpgid(args[0]),
+        SYSCALL_GETSID => sys_getsid(args[0]),
         SYSCALL_CLOCK_GETTIME => sys_clock_gettime(args[0] , args[1]).await,
         SYSCALL_GETTID => sys_gettid(),
         SYSCALL_FACCESSAT=>sys_faccessat(args[0] as i32,args[1] as *const u8,args[2] as u32,args[3]).await,
@@ -134,6 +173,8 @@ pub async  fn syscall(syscall_id: usize, args: [usize; 6]) -> SyscallRet {
         SYSCALL_SYMLINKAT=>sys_symlinkat(args[0] as *const u8,args[2] as i32, args[2] as *const u8).await,
         SYSCALL_READLINKAT=>sys_readlinkat(args[0] as i32, args[1] as *const u8, args[2] as *mut u8, args[3]).await,
         SYSCALL_MPROTECT=>sys_mprotect(args[0], args[1],args[2] ).await,
+        SYSCALL_SWAPON => sys_swapon(args[0] as *const u8, args[1] as u32).await,
+        SYSCALL_SWAPOFF => sys_swapoff(args[0] as *const u8).await,
         SYSCALL_PIPE2=> sys_pipe2(args[0] as *mut i32 , args[1]as u32).await,
         SYSCALL_SENDFILE=>sys_sendfile(args[0]  as i32,args[1] as i32 , args[2] as *mut isize , args[3]).await,
         SYSCALL_STATFS=>sys_statfs(args[0] as *const u8, args[1] as *mut crate::fs::Statfs).await,
@@ -149,12 +190,12 @@ pub async  fn syscall(syscall_id: usize, args: [usize; 6]) -> SyscallRet {
             args[2] as u32,
             args[3] as *mut u32,
         ).await,
-        SYSCALL_BIND => sys_bind(args[0], args[1] as *const u8, args[2] as u32),
-        SYSCALL_LISTEN => sys_listen(args[0], args[1] as u32),
-        SYSCALL_ACCEPT => sys_accept(args[0], args[1] as *const u8, args[2] as u32),
-        SYSCALL_CONNECT => sys_connect(args[0], args[1] as *const u8, args[2] as u32),
-        SYSCALL_GETSOCKNAME => sys_getsockname(args[0], args[1] as *const u8, args[2] as u32),
-        SYSCALL_GETPEERNAME => sys_getpeername(args[0], args[1] as *const u8, args[2] as u32),
+        SYSCALL_BIND => sys_bind(args[0], args[1] as *const u8, args[2] as u32).await,
+        SYSCALL_LISTEN => sys_listen(args[0], args[1] as u32).await,
+        SYSCALL_ACCEPT => sys_accept(args[0], args[1] as *mut u8, args[2] as *mut u32).await,
+        SYSCALL_CONNECT => sys_connect(args[0], args[1] as *const u8, args[2] as u32).await,
+        SYSCALL_GETSOCKNAME => sys_getsockname(args[0], args[1] as *mut u8, args[2] as *mut u32).await,
+        SYSCALL_GETPEERNAME => sys_getpeername(args[0], args[1] as *mut u8, args[2] as *mut u32).await,
         SYSCALL_SENDTO => sys_sendto(
             args[0],
             args[1] as *const u8,
@@ -162,14 +203,14 @@ pub async  fn syscall(syscall_id: usize, args: [usize; 6]) -> SyscallRet {
             args[3] as u32,
             args[4] as *const u8,
             args[5] as u32,
-        ),
+        ).await,
         SYSCALL_RECVFROM => sys_recvfrom(
             args[0],
             args[1] as *mut u8,
             args[2],
             args[3] as u32,
-            args[4] as *const u8,
-            args[5] as u32,
+            args[4] as *mut u8,
+            args[5] as *mut u32,
         ).await,
         SYSCALL_SETSOCKOPT => sys_setsockopt(
             args[0],
@@ -177,14 +218,23 @@ pub async  fn syscall(syscall_id: usize, args: [usize; 6]) -> SyscallRet {
             args[2] as u32,
             args[3] as *const u8,
             args[4] as u32,
-        ),
-        SYSCALL_SENDMSG => sys_sendmsg(args[0], args[1] as *const u8, args[2] as u32),
-        SYSCALL_ACCEPT4 => sys_accept4(
-            args[0] as usize,
-            args[1] as *const u8,
+        ).await,
+        SYSCALL_GETSOCKOPT => sys_getsockopt(
+            args[0],
+            args[1] as u32,
             args[2] as u32,
+            args[3] as *mut u8,
+            args[4] as *mut u32,
+        ).await,
+        SYSCALL_SHUTDOWN => sys_shutdown(args[0], args[1] as u32).await,
+        SYSCALL_SENDMSG => sys_sendmsg(args[0], args[1] as *const MsgHdr, args[2] as u32).await,
+        SYSCALL_RECVMSG => sys_recvmsg(args[0], args[1] as *mut MsgHdr, args[2] as u32).await,
+        SYSCALL_ACCEPT4 => sys_accept4(
+            args[0],
+            args[1] as *mut u8,
+            args[2] as *mut u32,
             args[3] as u32,
-        ),
+        ).await,
         SYSCALL_MREMAP=>sys_mremap(args[0] as *mut u8, args[1], args[2], args[3] as u32, args[4] as  *mut u8).await,
         
         SYSCALL_SETSID=>sys_setsid(),
@@ -226,7 +276,7 @@ pub async  fn syscall(syscall_id: usize, args: [usize; 6]) -> SyscallRet {
 
         SYSCALL_GETRUSAGE=>sys_getrusage(args[0] as i32,args[1] as *mut Rusage ).await,
         SYSCALL_PSELECT6=>sys_pselect6(args[0] as i32, args[1] as *mut FdSet, args[2] as *mut FdSet,args[3] as *mut FdSet, args[4] as *const UserTimeSpec, args[5] as *const SigSet).await,
-        SYSCALL_SYNC=>sys_sync(),
+        SYSCALL_SYNC=>sys_sync().await,
         SYSCALL_FSYNC=>sys_fsync(args[0]).await,
         SYSCALL_SHMGET=>sys_shmget(
             args[0] as i32,
@@ -244,12 +294,6 @@ pub async  fn syscall(syscall_id: usize, args: [usize; 6]) -> SyscallRet {
             args[2] as *mut ShmIdDs,
         ).await,
         SYSCALL_SHMDT=>sys_shmdt(args[0] as usize).await,
-        // SYSCALL_GETSOCKOPT=>sys_getsockopt(
-        //     args[0] as i32,
-        //     args[1] as u32,
-        //     args[2] as u32,
-        //     args[3] as *mut u8, 
-        // ).await,
         SYSCALL_GETITIMER=>sys_getitimer(args[0] as i32, args[1] as *mut ITimerVal).await,
         SYSCALL_SETITIMER=>sys_setitimer(args[0] as i32, args[1] as *const ITimerVal, args[2] as *mut ITimerVal).await,
         SYSCALL_UMASK=>sys_umaske(),
@@ -263,9 +307,10 @@ pub async  fn syscall(syscall_id: usize, args: [usize; 6]) -> SyscallRet {
             )
             .await,
         // 291=> Err(crate::utils::error::SysErrNo::ENOSYS),
-        _ =>{
-             panic!("Unsupported syscall_id: {}", syscall_id);
-            }
+        _ => {
+            unimplemented::record_unimplemented(syscall_id, &args);
+            Err(crate::utils::error::SysErrNo::ENOSYS)
+        }
     }
     
 
@@ -1,174 +1,590 @@
-use alloc::{format, string::ToString};
+use alloc::{sync::Arc, vec, vec::Vec};
 
 use crate::{
-    fs::{ net::make_socket, FileClass, FileDescriptor, OpenFlags},
-    mm::{put_data, translated_refmut},
-    task::{current_process, current_task, current_token}, utils::error::{SysErrNo, SyscallRet},
+    config::UIO_MAXIOV,
+    fs::{
+        net::{
+            addr::{
+                read_sockaddr_family, read_sockaddr_in, read_sockaddr_un, write_sock_addr, SockAddr, UnixAddr,
+                AF_UNIX, AF_UNSPEC,
+            },
+            make_socket,
+            socket::{
+                MSG_CMSG_CLOEXEC, MSG_CTRUNC, MSG_DONTWAIT, MSG_TRUNC, OPTMEM_MAX, SCM_MAX_FD, SCM_RIGHTS,
+                SOCK_CLOEXEC, SOCK_NONBLOCK, SOL_SOCKET,
+            },
+            Socket,
+        },
+        File, FileClass, FileDescriptor, OpenFlags,
+    },
+    mm::{
+        page_table::{copy_from_user_array, copy_from_user_bytes, copy_from_user_exact, copy_to_user_bytes},
+        translated_refmut, VirtAddr,
+    },
+    task::current_process,
+    utils::error::{GeneralRet, SysErrNo, SyscallRet, TemplateRet},
 };
-use log::debug;
 
-pub async  fn sys_socket(_domain: u32, _type: u32, _protocol: u32) -> SyscallRet {
-    let proc = current_process();
-    let new_fd =proc.alloc_fd().await?;
-    let close_on_exec = (_type & 0o2000000) == 0o2000000;
-    let non_block = (_type & 0o4000) == 0o4000;
-    let mut flags = OpenFlags::empty();
-    if close_on_exec {
+use super::flags::{IoVec, AT_FDCWD};
+
+/// 用户态的 `struct msghdr`
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct MsgHdr {
+    pub name: *mut u8,
+    pub namelen: u32,
+    pub iov: *mut IoVec,
+    pub iovlen: usize,
+    pub control: *mut u8,
+    pub controllen: usize,
+    pub flags: i32,
+}
+
+/// 用户态的 `struct cmsghdr`，其后紧跟数据
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct CmsgHdr {
+    len: usize,
+    level: i32,
+    ty: i32,
+}
+
+const CMSG_HDR_LEN: usize = core::mem::size_of::<CmsgHdr>();
+
+fn cmsg_align(len: usize) -> usize {
+    (len + core::mem::size_of::<usize>() - 1) & !(core::mem::size_of::<usize>() - 1)
+}
+
+/// 取出 fd 对应的 socket 文件，同时返回 fd 上的 O_NONBLOCK
+async fn socket_file(fd: usize) -> TemplateRet<(Arc<dyn File>, bool)> {
+    let fdesc = current_process().get_file(fd).await?;
+    let file = fdesc.any();
+    if file.as_any().downcast_ref::<Socket>().is_none() {
+        return Err(SysErrNo::ENOTSOCK);
+    }
+    Ok((file, fdesc.non_block()))
+}
+
+fn as_socket(file: &Arc<dyn File>) -> &Socket {
+    file.as_any().downcast_ref::<Socket>().unwrap()
+}
+
+fn sock_fd_flags(stype: u32) -> OpenFlags {
+    let mut flags = OpenFlags::O_RDWR;
+    if stype & SOCK_CLOEXEC != 0 {
         flags |= OpenFlags::FD_CLOEXEC;
     }
-    if non_block {
+    if stype & SOCK_NONBLOCK != 0 {
         flags |= OpenFlags::O_NONBLOCK;
     }
-    
-    proc.fd_table.lock().await.add_fd(
-        FileDescriptor::new(flags, FileClass::Abs(make_socket())),
+    flags
+}
+
+/// 按 socket 的协议族读取用户传入的地址，AF_UNSPEC 返回 `None`
+///
+/// AF_UNIX 的相对路径相对于当前工作目录解析
+async fn read_sock_addr(sock: &Socket, token: usize, addr: *const u8, addrlen: u32) -> TemplateRet<Option<SockAddr>> {
+    let family = read_sockaddr_family(token, addr, addrlen)?;
+    if family == AF_UNSPEC {
+        return Ok(None);
+    }
+    if family != sock.domain() {
+        return Err(SysErrNo::EAFNOSUPPORT);
+    }
+    if family != AF_UNIX {
+        return Ok(Some(SockAddr::Inet(read_sockaddr_in(token, addr, addrlen)?)));
+    }
+    let unix = match read_sockaddr_un(token, addr, addrlen)? {
+        UnixAddr::Path(path) if path.is_empty() => return Err(SysErrNo::ENOENT),
+        UnixAddr::Path(path) => {
+            let abs = current_process().resolve_path_from_fd(AT_FDCWD, &path, false).await?;
+            UnixAddr::Path(abs)
+        }
+        other => other,
+    };
+    Ok(Some(SockAddr::Unix(unix)))
+}
+
+pub async fn sys_socket(domain: u32, stype: u32, protocol: u32) -> SyscallRet {
+    trace!("[sys_socket] domain: {}, type: {:#x}, protocol: {}", domain, stype, protocol);
+    let socket = make_socket(domain, stype, protocol)?;
+    current_process()
+        .alloc_and_add_fd(FileDescriptor::new(sock_fd_flags(stype), FileClass::Abs(socket)))
+        .await
+}
 
-        new_fd,
-    )?;
-    
-    Ok(new_fd)
+pub async fn sys_bind(sockfd: usize, addr: *const u8, addrlen: u32) -> SyscallRet {
+    trace!("[sys_bind] sockfd: {}, addr: {:p}, addrlen: {}", sockfd, addr, addrlen);
+    let (file, _) = socket_file(sockfd).await?;
+    let sock = as_socket(&file);
+    let token = current_process().get_user_token().await;
+    let addr = match read_sock_addr(sock, token, addr, addrlen).await? {
+        Some(addr) => addr,
+        // 兼容旧程序：AF_INET socket 用 AF_UNSPEC 绑定等同于 INADDR_ANY
+        None if sock.domain() != AF_UNIX => SockAddr::Inet(Default::default()),
+        None => return Err(SysErrNo::EINVAL),
+    };
+    sock.bind(addr)?;
+    Ok(0)
 }
 
-pub fn sys_bind(_sockfd: usize, _addr: *const u8, _addrlen: u32) -> SyscallRet {
+pub async fn sys_getsockname(sockfd: usize, addr: *mut u8, addrlen: *mut u32) -> SyscallRet {
+    let (file, _) = socket_file(sockfd).await?;
+    let proc = current_process();
+    proc.manual_alloc_type_for_lazy(addrlen).await?;
+    let token = proc.get_user_token().await;
+    write_sock_addr(token, addr, addrlen, &as_socket(&file).local_addr())?;
     Ok(0)
 }
 
-pub fn sys_getsockname(_sockfd: usize, _addr: *const u8, _addrlen: u32) -> SyscallRet {
+pub async fn sys_getpeername(sockfd: usize, addr: *mut u8, addrlen: *mut u32) -> SyscallRet {
+    let (file, _) = socket_file(sockfd).await?;
+    let peer = as_socket(&file).peer_addr()?;
+    let proc = current_process();
+    proc.manual_alloc_type_for_lazy(addrlen).await?;
+    let token = proc.get_user_token().await;
+    write_sock_addr(token, addr, addrlen, &peer)?;
     Ok(0)
 }
 
-pub fn sys_getpeername(_sockfd: usize, _addr: *const u8, _addrlen: u32) -> SyscallRet {
-    Err(SysErrNo::Default)
+pub async fn sys_setsockopt(
+    sockfd: usize,
+    level: u32,
+    optname: u32,
+    optval: *const u8,
+    optlen: u32,
+) -> SyscallRet {
+    trace!(
+        "[sys_setsockopt] sockfd: {}, level: {}, optname: {}, optlen: {}",
+        sockfd,
+        level,
+        optname,
+        optlen
+    );
+    let (file, _) = socket_file(sockfd).await?;
+    let token = current_process().get_user_token().await;
+    let mut val = vec![0u8; (optlen as usize).min(64)];
+    if !optval.is_null() && !val.is_empty() {
+        let len = val.len();
+        unsafe { copy_from_user_bytes(token, &mut val, VirtAddr::from(optval as usize), len) }?;
+    }
+    as_socket(&file).setsockopt(level, optname, &val)?;
+    Ok(0)
 }
 
-pub fn sys_setsockopt(
-    _sockfd: usize,
-    _level: u32,
-    _optname: u32,
-    _optcal: *const u8,
-    _optlen: u32,
+pub async fn sys_getsockopt(
+    sockfd: usize,
+    level: u32,
+    optname: u32,
+    optval: *mut u8,
+    optlen: *mut u32,
 ) -> SyscallRet {
+    trace!("[sys_getsockopt] sockfd: {}, level: {}, optname: {}", sockfd, level, optname);
+    let (file, _) = socket_file(sockfd).await?;
+    let val = as_socket(&file).getsockopt(level, optname)?;
+    if optval.is_null() || optlen.is_null() {
+        return Err(SysErrNo::EFAULT);
+    }
+    let proc = current_process();
+    proc.manual_alloc_type_for_lazy(optlen).await?;
+    let token = proc.get_user_token().await;
+    let len = translated_refmut(token, optlen)?;
+    let bytes = val.to_ne_bytes();
+    let n = (*len as usize).min(bytes.len());
+    unsafe { copy_to_user_bytes(token, VirtAddr::from(optval as usize), &bytes[..n]) }?;
+    *len = n as u32;
     Ok(0)
 }
 
-pub fn sys_sendto(
-    _sockfd: usize,
-    _buf: *const u8,
-    _len: usize,
-    _flags: u32,
-    _dest_addr: *const u8,
-    _addrlen: u32,
+pub async fn sys_sendto(
+    sockfd: usize,
+    buf: *const u8,
+    len: usize,
+    flags: u32,
+    dest_addr: *const u8,
+    addrlen: u32,
 ) -> SyscallRet {
-    Ok(1)
+    trace!(
+        "[sys_sendto] sockfd: {}, buf: {:p}, len: {}, flags: {:#x}, dest_addr: {:p}, addrlen: {}",
+        sockfd,
+        buf,
+        len,
+        flags,
+        dest_addr,
+        addrlen
+    );
+    let (file, nonblock) = socket_file(sockfd).await?;
+    let sock = as_socket(&file);
+    let token = current_process().get_user_token().await;
+    let dst = if dest_addr.is_null() {
+        None
+    } else {
+        read_sock_addr(sock, token, dest_addr, addrlen).await?
+    };
+    let iov = [IoVec { base: buf as *mut u8, len }];
+    let flags = if nonblock { flags | MSG_DONTWAIT } else { flags };
+    send_iovecs(sock, token, &iov, len, dst, Vec::new(), flags).await
 }
 
-pub async  fn sys_recvfrom(
-    _sockfd: usize,
+pub async fn sys_recvfrom(
+    sockfd: usize,
     buf: *mut u8,
-    _len: usize,
-    _flags: u32,
-    _src_addr: *const u8,
-    _addrlen: u32,
+    len: usize,
+    flags: u32,
+    src_addr: *mut u8,
+    addrlen: *mut u32,
 ) -> SyscallRet {
     trace!(
-        "[sys_recvfrom] sockfd: {}, buf: {:p}, len: {}, flags: {:#x}, src_addr: {:p}, addrlen: {}",
-        _sockfd,
+        "[sys_recvfrom] sockfd: {}, buf: {:p}, len: {}, flags: {:#x}, src_addr: {:p}, addrlen: {:p}",
+        sockfd,
         buf,
-        _len,
-        _flags,
-        _src_addr,
-        _addrlen
+        len,
+        flags,
+        src_addr,
+        addrlen
     );
+    let (file, nonblock) = socket_file(sockfd).await?;
+    let flags = if nonblock { flags | MSG_DONTWAIT } else { flags };
+    // 先换入用户缓冲区，地址无效时不能已经把数据报取走
     let proc = current_process();
-    proc.manual_alloc_type_for_lazy(buf).await?;
-    let token  = proc.get_user_token().await;
-    unsafe {
-        put_data(token, buf, b'x')?;
-        put_data(token, buf.add(1), b'0')?;
+    if len > 0 {
+        proc.manual_alloc_range_for_lazy(VirtAddr::from(buf as usize), VirtAddr::from(buf as usize + len))
+            .await?;
+    }
+    prepare_sock_addr(src_addr, addrlen).await?;
+    let (data, full_len, src) = as_socket(&file).recv_from(len, flags).await?;
+
+    let token = proc.get_user_token().await;
+    unsafe { copy_to_user_bytes(token, VirtAddr::from(buf as usize), &data) }?;
+    if let Some(src) = src {
+        write_sock_addr(token, src_addr, addrlen, &src)?;
+    }
+    if flags & MSG_TRUNC != 0 {
+        Ok(full_len)
+    } else {
+        Ok(data.len())
     }
-    Ok(1)
 }
 
-pub fn sys_listen(_sockfd: usize, _backlog: u32) -> SyscallRet {
-    trace!("[sys_listen] sockfd: {}, backlog: {}", _sockfd, _backlog);
+pub async fn sys_listen(sockfd: usize, backlog: u32) -> SyscallRet {
+    trace!("[sys_listen] sockfd: {}, backlog: {}", sockfd, backlog);
+    let (file, _) = socket_file(sockfd).await?;
+    as_socket(&file).listen(backlog as usize)?;
     Ok(0)
 }
 
-pub fn sys_connect(_sockfd: usize, _addr: *const u8, _addrlen: u32) -> SyscallRet {
+pub async fn sys_connect(sockfd: usize, addr: *const u8, addrlen: u32) -> SyscallRet {
     trace!(
         "[sys_connect] sockfd: {}, addr: {:p}, addrlen: {}",
-        _sockfd,
-        _addr,
-        _addrlen
+        sockfd,
+        addr,
+        addrlen
     );
+    let (file, nonblock) = socket_file(sockfd).await?;
+    let sock = as_socket(&file);
+    let token = current_process().get_user_token().await;
+    let remote = read_sock_addr(sock, token, addr, addrlen).await?;
+    sock.connect(remote, nonblock).await?;
     Ok(0)
 }
 
-pub fn sys_accept(_sockfd: usize, _addr: *const u8, _addrlen: u32) -> SyscallRet {
-    trace!(
-        "[sys_accept] sockfd: {}, addr: {:p}, addrlen: {}",
-        _sockfd,
-        _addr,
-        _addrlen
-    );
-    Ok(0)
+pub async fn sys_accept(sockfd: usize, addr: *mut u8, addrlen: *mut u32) -> SyscallRet {
+    sys_accept4(sockfd, addr, addrlen, 0).await
 }
 
-pub fn sys_accept4(_sockfd: usize, _addr: *const u8, _addrlen: u32, _flags: u32) -> SyscallRet {
+pub async fn sys_accept4(sockfd: usize, addr: *mut u8, addrlen: *mut u32, flags: u32) -> SyscallRet {
     trace!(
-        "[sys_accept4] sockfd: {}, addr: {:p}, addrlen: {}, flags: {:#x}",
-        _sockfd,
-        _addr,
-        _addrlen,
-        _flags
+        "[sys_accept4] sockfd: {}, addr: {:p}, addrlen: {:p}, flags: {:#x}",
+        sockfd,
+        addr,
+        addrlen,
+        flags
     );
-    Ok(0)
+    if flags & !(SOCK_CLOEXEC | SOCK_NONBLOCK) != 0 {
+        return Err(SysErrNo::EINVAL);
+    }
+    let (file, nonblock) = socket_file(sockfd).await?;
+    // 先换入地址缓冲区，地址无效时不能已经把连接取走
+    prepare_sock_addr(addr, addrlen).await?;
+    let (conn, peer) = as_socket(&file).accept(nonblock).await?;
+    conn.set_nonblock(flags & SOCK_NONBLOCK != 0);
+
+    let proc = current_process();
+    let token = proc.get_user_token().await;
+    write_sock_addr(token, addr, addrlen, &peer)?;
+    proc.alloc_and_add_fd(FileDescriptor::new(sock_fd_flags(flags), FileClass::Abs(Arc::new(conn))))
+        .await
 }
 
-pub fn sys_sendmsg(_sockfd: usize, _addr: *const u8, _flags: u32) -> SyscallRet {
-    trace!(
-        "[sys_sendmsg] sockfd: {}, addr: {:p}, flags: {:#x}",
-        _sockfd,
-        _addr,
-        _flags
-    );
+pub async fn sys_shutdown(sockfd: usize, how: u32) -> SyscallRet {
+    trace!("[sys_shutdown] sockfd: {}, how: {}", sockfd, how);
+    let (file, _) = socket_file(sockfd).await?;
+    as_socket(&file).shutdown(how)?;
     Ok(0)
 }
 
-pub async  fn sys_socketpair(domain: u32, stype: u32, protocol: u32, sv: *mut u32) -> SyscallRet {
-    info!(
-        "[sys_socketpair] domain is {}, type is {}, protocol is {}, sv is {}",
-        domain, stype, protocol, sv as usize
-    );
+/// 换入 `write_sock_addr` 要写的 `addrlen` 和 `addr` 缓冲区，任一无效时返回 EFAULT；
+/// 在取出数据报或连接之前调用，之后写回地址就不会因为缺页或地址无效而丢掉它们
+async fn prepare_sock_addr(addr: *mut u8, addrlen: *mut u32) -> GeneralRet {
+    if addr.is_null() || addrlen.is_null() {
+        return Ok(());
+    }
+    let proc = current_process();
+    proc.manual_alloc_type_for_lazy(addrlen).await?;
+    let token = proc.get_user_token().await;
+    let len = *translated_refmut(token, addrlen)? as usize;
+    if len > 0 {
+        let start = addr as usize;
+        let end = start.checked_add(len).ok_or(SysErrNo::EFAULT)?;
+        proc.manual_alloc_range_for_lazy(VirtAddr::from(start), VirtAddr::from(end)).await?;
+    }
+    Ok(())
+}
+
+/// 把 iovec 中的数据分块拷入内核后发送，每块不超过 socket 的发送上限，
+/// 不按用户给出的长度一次性分配内核缓冲区。数据报超过上限返回 EMSGSIZE；
+/// 流式 socket 在某块发送不完整或出错时返回已发送的字节数
+async fn send_iovecs(
+    sock: &Socket,
+    token: usize,
+    iovs: &[IoVec],
+    total: usize,
+    dst: Option<SockAddr>,
+    mut rights: Vec<FileDescriptor>,
+    flags: u32,
+) -> SyscallRet {
+    let (limit, stream) = sock.send_limit();
+    if !stream && total > limit {
+        return Err(SysErrNo::EMSGSIZE);
+    }
+    let mut sent = 0;
+    let (mut idx, mut off) = (0, 0);
+    loop {
+        let n = (total - sent).min(limit);
+        let mut chunk = vec![0u8; n];
+        let mut filled = 0;
+        while filled < n {
+            let v = &iovs[idx];
+            let m = (v.len - off).min(n - filled);
+            let res = unsafe {
+                copy_from_user_bytes(token, &mut chunk[filled..filled + m], VirtAddr::from(v.base as usize + off), m)
+            };
+            if let Err(e) = res {
+                return if sent > 0 { Ok(sent) } else { Err(e.into()) };
+            }
+            filled += m;
+            off += m;
+            if off == v.len {
+                idx += 1;
+                off = 0;
+            }
+        }
+        // 文件描述符只随第一块发送
+        match sock.send_msg(&chunk, dst.clone(), core::mem::take(&mut rights), flags).await {
+            Ok(w) => {
+                sent += w;
+                if w < n || sent == total {
+                    return Ok(sent);
+                }
+            }
+            Err(e) => return if sent > 0 { Ok(sent) } else { Err(e) },
+        }
+    }
+}
+
+/// 把 iovec 数组读入内核，返回数组和总长度
+async fn read_iovecs(token: usize, iov: *const IoVec, iovlen: usize) -> TemplateRet<(Vec<IoVec>, usize)> {
+    if iovlen > UIO_MAXIOV {
+        return Err(SysErrNo::EMSGSIZE);
+    }
+    if iovlen == 0 {
+        return Ok((Vec::new(), 0));
+    }
+    let iovs = unsafe { copy_from_user_array::<IoVec>(token, iov, iovlen) }.map_err(|_| SysErrNo::EFAULT)?;
+    let mut total = 0usize;
+    for v in &iovs {
+        if v.base.is_null() && v.len > 0 {
+            return Err(SysErrNo::EFAULT);
+        }
+        total = total.checked_add(v.len).ok_or(SysErrNo::EINVAL)?;
+    }
+    Ok((iovs, total))
+}
+
+/// 解析 sendmsg 的控制消息，取出 SCM_RIGHTS 携带的文件描述符
+async fn read_rights(token: usize, control: *const u8, controllen: usize) -> TemplateRet<Vec<FileDescriptor>> {
+    let mut rights = Vec::new();
+    if control.is_null() || controllen == 0 {
+        return Ok(rights);
+    }
+    if controllen > OPTMEM_MAX {
+        return Err(SysErrNo::ENOBUFS);
+    }
+    let mut buf = vec![0u8; controllen];
+    unsafe { copy_from_user_bytes(token, &mut buf, VirtAddr::from(control as usize), controllen) }?;
+    let proc = current_process();
+    let mut off = 0;
+    while off + CMSG_HDR_LEN <= controllen {
+        let hdr = unsafe { core::ptr::read_unaligned(buf[off..].as_ptr() as *const CmsgHdr) };
+        if hdr.len < CMSG_HDR_LEN || off + hdr.len > controllen {
+            return Err(SysErrNo::EINVAL);
+        }
+        if hdr.level as u32 == SOL_SOCKET && hdr.ty as u32 == SCM_RIGHTS {
+            for fd in buf[off + CMSG_HDR_LEN..off + hdr.len].chunks_exact(4) {
+                let fd = i32::from_ne_bytes([fd[0], fd[1], fd[2], fd[3]]);
+                if fd < 0 {
+                    return Err(SysErrNo::EBADF);
+                }
+                if rights.len() >= SCM_MAX_FD {
+                    return Err(SysErrNo::EINVAL);
+                }
+                rights.push(proc.get_file(fd as usize).await?);
+            }
+        } else {
+            warn!("[sendmsg] unsupported control message level {} type {}", hdr.level, hdr.ty);
+        }
+        off += cmsg_align(hdr.len);
+    }
+    Ok(rights)
+}
+
+pub async fn sys_sendmsg(sockfd: usize, msg: *const MsgHdr, flags: u32) -> SyscallRet {
+    trace!("[sys_sendmsg] sockfd: {}, msg: {:p}, flags: {:#x}", sockfd, msg, flags);
+    let (file, nonblock) = socket_file(sockfd).await?;
+    let sock = as_socket(&file);
+    let token = current_process().get_user_token().await;
+    let hdr = unsafe { copy_from_user_exact::<MsgHdr>(token, msg) }?;
 
-    let task = current_task();
+    let (iovs, total) = read_iovecs(token, hdr.iov, hdr.iovlen).await?;
+    let dst = if hdr.name.is_null() || hdr.namelen == 0 {
+        None
+    } else {
+        read_sock_addr(sock, token, hdr.name, hdr.namelen).await?
+    };
+    let rights = read_rights(token, hdr.control, hdr.controllen).await?;
+    let flags = if nonblock { flags | MSG_DONTWAIT } else { flags };
+    send_iovecs(sock, token, &iovs, total, dst, rights, flags).await
+}
+
+pub async fn sys_recvmsg(sockfd: usize, msg: *mut MsgHdr, flags: u32) -> SyscallRet {
+    trace!("[sys_recvmsg] sockfd: {}, msg: {:p}, flags: {:#x}", sockfd, msg, flags);
+    let (file, nonblock) = socket_file(sockfd).await?;
     let proc = current_process();
-    let token= proc.get_user_token().await;
+    proc.manual_alloc_type_for_lazy(msg).await?;
+    let token = proc.get_user_token().await;
+    let mut hdr = unsafe { copy_from_user_exact::<MsgHdr>(token, msg) }?;
+    let (iovs, total) = read_iovecs(token, hdr.iov, hdr.iovlen).await?;
+    // 先换入接收缓冲区和地址缓冲区，地址无效时不能已经把消息取走
+    for v in iovs.iter().filter(|v| v.len > 0) {
+        proc.manual_alloc_range_for_lazy(VirtAddr::from(v.base as usize), VirtAddr::from(v.base as usize + v.len))
+            .await?;
+    }
+    if !hdr.name.is_null() && hdr.namelen > 0 {
+        let name = hdr.name as usize;
+        proc.manual_alloc_range_for_lazy(VirtAddr::from(name), VirtAddr::from(name + hdr.namelen as usize))
+            .await?;
+    }
 
-    let (socket1, socket2) = crate::fs::net::make_socketpair( OpenFlags::O_NONBLOCK);
-    let close_on_exec = (stype & 0o2000000) == 0o2000000;
-    let non_block = (stype & 0o4000) == 0o4000;
-    let mut flags = OpenFlags::empty();
-    if close_on_exec {
-        flags |= OpenFlags::FD_CLOEXEC;
+    let recv_flags = if nonblock { flags | MSG_DONTWAIT } else { flags };
+    let (data, full_len, src, rights) = as_socket(&file).recv_msg(total, recv_flags).await?;
+
+    let mut off = 0;
+    for v in iovs.iter().filter(|v| v.len > 0) {
+        if off >= data.len() {
+            break;
+        }
+        let n = v.len.min(data.len() - off);
+        unsafe { copy_to_user_bytes(token, VirtAddr::from(v.base as usize), &data[off..off + n]) }?;
+        off += n;
     }
-    if non_block {
-        flags |= OpenFlags::O_NONBLOCK;
+
+    hdr.flags = 0;
+    if full_len > data.len() {
+        hdr.flags |= MSG_TRUNC as i32;
+    }
+    if hdr.name.is_null() {
+        hdr.namelen = 0;
+    } else if let Some(src) = src {
+        let bytes = src.to_bytes();
+        let n = (hdr.namelen as usize).min(bytes.len());
+        unsafe { copy_to_user_bytes(token, VirtAddr::from(hdr.name as usize), &bytes[..n]) }?;
+        hdr.namelen = bytes.len() as u32;
+    } else {
+        hdr.namelen = 0;
     }
-    proc.manual_alloc_type_for_lazy(sv).await?;
-    let new_fd1 = proc.fd_table.lock().await.alloc_fd()?;
-    proc
-        .fd_table.lock()
-        .await.add_fd(FileDescriptor::new(flags, FileClass::Abs(socket1)),new_fd1, )?;
-        let new_fd2 = proc.fd_table.lock().await.alloc_fd()?;
-    proc
-        .fd_table.lock()
 
-        .await.add_fd( FileDescriptor::new(flags, FileClass::Abs(socket2)),new_fd2)?;
- 
+    // 放不下的描述符直接关闭，并置 MSG_CTRUNC
+    let mut control = Vec::new();
+    if !rights.is_empty() {
+        let room = if hdr.control.is_null() { 0 } else { hdr.controllen.saturating_sub(CMSG_HDR_LEN) / 4 };
+        let fit = rights.len().min(room);
+        if fit < rights.len() {
+            hdr.flags |= MSG_CTRUNC as i32;
+        }
+        if fit > 0 {
+            let cmsg = CmsgHdr {
+                len: CMSG_HDR_LEN + fit * 4,
+                level: SOL_SOCKET as i32,
+                ty: SCM_RIGHTS as i32,
+            };
+            control.extend_from_slice(unsafe {
+                core::slice::from_raw_parts(&cmsg as *const CmsgHdr as *const u8, CMSG_HDR_LEN)
+            });
+            for mut fdesc in rights.into_iter().take(fit) {
+                if flags & MSG_CMSG_CLOEXEC != 0 {
+                    fdesc.set_cloexec();
+                } else {
+                    fdesc.unset_cloexec();
+                }
+                let fd = proc.alloc_and_add_fd(fdesc).await?;
+                control.extend_from_slice(&(fd as i32).to_ne_bytes());
+            }
+        }
+    }
+    if !control.is_empty() {
+        proc.manual_alloc_range_for_lazy(
+            VirtAddr::from(hdr.control as usize),
+            VirtAddr::from(hdr.control as usize + control.len()),
+        )
+        .await?;
+        unsafe { copy_to_user_bytes(token, VirtAddr::from(hdr.control as usize), &control) }?;
+    }
+    hdr.controllen = control.len();
 
-    *translated_refmut(token, sv)? = new_fd1 as u32;
-    *translated_refmut(token, unsafe { sv.add(1) })? = new_fd2 as u32;
+    let bytes = unsafe {
+        core::slice::from_raw_parts(&hdr as *const MsgHdr as *const u8, core::mem::size_of::<MsgHdr>())
+    };
+    unsafe { copy_to_user_bytes(token, VirtAddr::from(msg as usize), bytes) }?;
+    if flags & MSG_TRUNC != 0 {
+        Ok(full_len)
+    } else {
+        Ok(data.len())
+    }
+}
 
+pub async fn sys_socketpair(domain: u32, stype: u32, protocol: u32, sv: *mut u32) -> SyscallRet {
+    trace!(
+        "[sys_socketpair] domain: {}, type: {:#x}, protocol: {}, sv: {:p}",
+        domain,
+        stype,
+        protocol,
+        sv
+    );
+    if domain != AF_UNIX as u32 {
+        return Err(SysErrNo::EOPNOTSUPP);
+    }
+    let (s1, s2) = Socket::pair(domain, stype, protocol)?;
+    let proc = current_process();
+    proc.manual_alloc_type_for_lazy(sv).await?;
+    let token = proc.get_user_token().await;
+    let flags = sock_fd_flags(stype);
+    let fd1 = proc
+        .alloc_and_add_fd(FileDescriptor::new(flags, FileClass::Abs(Arc::new(s1))))
+        .await?;
+    let fd2 = proc
+        .alloc_and_add_fd(FileDescriptor::new(flags, FileClass::Abs(Arc::new(s2))))
+        .await?;
+    *translated_refmut(token, sv)? = fd1 as u32;
+    *translated_refmut(token, unsafe { sv.add(1) })? = fd2 as u32;
     Ok(0)
 }
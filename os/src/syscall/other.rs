@@ -2,7 +2,7 @@ use linux_raw_sys::general::{CLOCK_MONOTONIC, CLOCK_REALTIME, MAX_CLOCKS};
 use alloc::{string::String, vec};
 #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
 use riscv::register::time;
-use crate::{config::{MAX_KERNEL_RW_BUFFER_SIZE, TOTALMEM}, fs::{open_file, OpenFlags, NONE_MODE}, mm::{fill_str, get_target_ref, page_table::get_data, put_data, translated_byte_buffer, translated_refmut, translated_str, UserBuffer}, syscall::flags::{Sysinfo, Utsname}, task::{current_process, current_task, current_token, sleeplist::sleep_until, task_count, PID2PC}, timer::{self, current_time, get_time_ms, get_usertime, usertime2_timeval, TimeVal, Tms, UserTimeSpec}, utils::error::{SysErrNo,  SyscallRet}};
+use crate::{config::{MAX_KERNEL_RW_BUFFER_SIZE, TOTALMEM}, fs::{open_file, OpenFlags, NONE_MODE}, mm::{fill_str, get_target_ref, page_table::get_data, put_data, translated_byte_buffer, translated_refmut, translated_str, UserBuffer}, syscall::flags::{Sysinfo, Utsname}, task::{current_process, current_task, current_token, sleeplist::sleep_until, task_count, yield_now, TaskRef, PID2PC, TID2TC}, timer::{self, current_time, get_time_ms, get_usertime, usertime2_timeval, TimeVal, Tms, UserTimeSpec}, utils::error::{SysErrNo,  SyscallRet, TemplateRet}};
 
 pub async  fn sys_sysinfo(info: *const u8) -> SyscallRet {
 
@@ -317,6 +317,18 @@ target_inode
     .map(|_| 0) // 成功则返回 0
 }
 
+/// CPU 掩码的大小：按 long 对齐，内核最多支持 64 个核
+const CPU_MASK_SIZE: usize = core::mem::size_of::<usize>();
+
+/// sched_*affinity 的 pid 参数实际指线程号，0 表示调用者
+fn affinity_target(pid: i32) -> TemplateRet<TaskRef> {
+    match pid {
+        0 => Ok(current_task().clone()),
+        pid if pid < 0 => Err(SysErrNo::ESRCH),
+        pid => TID2TC.lock().get(&(pid as usize)).cloned().ok_or(SysErrNo::ESRCH),
+    }
+}
+
 pub async fn sys_sched_getaffinity(
     pid: i32,
     cpusetsize: usize,
@@ -328,35 +340,20 @@ pub async fn sys_sched_getaffinity(
         cpusetsize,
         user_mask
     );
-    
-    // --- 核心修正 ---
-    // 1. 定义内核实际支持的 CPU 数量和掩码大小
-    const KERNEL_NR_CPUS: usize = 1; // 我们的系统是单核
-    const KERNEL_CPU_MASK_SIZE: usize = (KERNEL_NR_CPUS + 7) / 8; // 计算所需字节数，这里是 1
-
-    // 2. 检查用户提供的 cpusetsize 是否足够
-    //    如果用户提供的缓冲区太小，我们什么都不做，直接返回我们需要的真实大小。
-    //    这是 man page 规定的行为。
-    if cpusetsize < KERNEL_CPU_MASK_SIZE {
+    // 用户缓冲区必须能放下整个掩码，且按 long 对齐
+    if cpusetsize < CPU_MASK_SIZE || cpusetsize % CPU_MASK_SIZE != 0 {
         return Err(SysErrNo::EINVAL);
     }
+    let task = affinity_target(pid)?;
+    let affinity_mask = task.affinity() & crate::smp::online_mask();
 
-    // 3. 获取目标任务的亲和性掩码
-    //    （在单核模型中，我们忽略 pid，因为所有任务都一样）
-    //    TODO: 如果需要支持多进程，这里需要根据 pid 查找任务
-    let current_pcb = current_process(); // 假设有 current_process()
-    let affinity_mask :usize= 1;
-    
-    // 4. 安全地将掩码写入用户空间
+    let current_pcb = current_process();
     let token = current_token().await;
-    // 确保用户指针有效
     current_pcb.manual_alloc_type_for_lazy(user_mask).await?;
+    *translated_refmut(token, user_mask)? = affinity_mask;
 
-    *translated_refmut(token, user_mask)?=affinity_mask;
-
-
-    // 7. 返回内核实际支持的掩码大小
-    Ok(KERNEL_CPU_MASK_SIZE) 
+    // 返回内核实际写入的掩码大小
+    Ok(CPU_MASK_SIZE)
 }
 pub async fn sys_sched_setaffinity(
     pid: i32,
@@ -369,10 +366,28 @@ pub async fn sys_sched_setaffinity(
         cpusetsize,
         user_mask_ptr
     );
+    if cpusetsize == 0 {
+        return Err(SysErrNo::EINVAL);
+    }
+    let task = affinity_target(pid)?;
 
-   //多核心todo
+    let current_pcb = current_process();
+    let token = current_token().await;
+    current_pcb.manual_alloc_type_for_lazy(user_mask_ptr).await?;
+    let mut mask = *translated_refmut(token, user_mask_ptr as *mut usize)?;
+    // 用户只给了不足一个 long 的掩码时，多读的部分不算数
+    if cpusetsize < CPU_MASK_SIZE {
+        mask &= (1usize << (cpusetsize * 8)) - 1;
+    }
+    if mask & crate::smp::online_mask() == 0 {
+        return Err(SysErrNo::EINVAL);
+    }
+    task.set_affinity(mask);
 
-    // 成功
+    // 调用者不再允许在当前核运行：让出 CPU，放回就绪队列时会迁移到允许的核
+    if current_task().ptr_eq(&task) && mask & (1 << crate::smp::cpu_id()) == 0 {
+        yield_now().await;
+    }
     Ok(0)
 }
 
@@ -3,7 +3,7 @@
 
 
 
-use core::{error, future::Future, pin::Pin};
+use core::error;
 
 use alloc::{
    boxed::Box, collections::{btree_map::BTreeMap, linked_list::LinkedList}, format, string::{String, ToString}, sync::Arc, vec::Vec
@@ -13,11 +13,11 @@ use spin::Lazy;
 
 use crate::{
     config::{FD_SETSIZE, MAX_SYSCALL_NUM, MEMORY_END, MMAP_BASE, MMAP_TOP, PAGE_SIZE, PAGE_SIZE_BITS}, fs::{open_file, select::{FdSet, PSelectFuture}, File, FileDescriptor, OpenFlags, NONE_MODE}, mm::{
-        flush_all,  frame_allocator::remaining_frames, get_target_ref, page_table::{copy_to_user_bytes}, put_data, translated_byte_buffer, translated_refmut, translated_str, FrameTracker, MapArea, MapAreaType, MapPermission, MapType, MmapFile, MmapFlags, TranslateError, UserBuffer, VirtAddr, VirtPageNum, MPOL_BIND, MPOL_DEFAULT, MPOL_PREFERRED
-    }, signal::{SigMaskHow, SigSet, Signal, NSIG}, sync::futex::{ FutexKey, FutexWaitInternalFuture, GLOBAL_FUTEX_SYSTEM}, syscall::{flags::{  MmapProt, MremapFlags, WaitFlags, FUTEX_CLOCK_REALTIME, FUTEX_CMP_REQUEUE, FUTEX_OP_ADD, FUTEX_OP_ANDN, FUTEX_OP_CMP_EQ, FUTEX_OP_CMP_GE, FUTEX_OP_CMP_GT, FUTEX_OP_CMP_LE, FUTEX_OP_CMP_LT, FUTEX_OP_CMP_NE, FUTEX_OP_OR, FUTEX_OP_SET, FUTEX_OP_XOR, FUTEX_PRIVATE_FLAG, FUTEX_REQUEUE, FUTEX_WAIT, FUTEX_WAIT_BITSET, FUTEX_WAKE, FUTEX_WAKE_BITSET, FUTEX_WAKE_OP}, process}, task::{
-        current_process, current_task, current_task_id, current_token, exit_current, exit_proc, future::{JoinFuture, WaitAnyFuture}, set_priority, yield_now, CloneFlags, ProcessControlBlock,  RobustList, TaskStatus, PID2PC, TID2TC
+        flush_all,  frame_allocator::remaining_frames, get_target_ref, page_table::{copy_to_user_bytes}, put_data, rlimit::{RLIMIT_AS, RLIMIT_DATA, RLIMIT_MEMLOCK, RLIMIT_STACK, RLIM_INFINITY}, translated_byte_buffer, translated_refmut, translated_str, FrameTracker, MapArea, MapAreaType, MapPermission, MapType, MmapFile, MmapFlags, TranslateError, UserBuffer, VirtAddr, VirtPageNum, MPOL_BIND, MPOL_DEFAULT, MPOL_PREFERRED
+    }, signal::{pgrp_members, SigMaskHow, SigSet, Signal, NSIG}, sync::futex::{ FutexKey, FutexWaitInternalFuture, GLOBAL_FUTEX_SYSTEM}, syscall::{flags::{  MmapProt, MremapFlags, MsyncFlags, WaitFlags, FUTEX_CLOCK_REALTIME, FUTEX_CMP_REQUEUE, FUTEX_OP_ADD, FUTEX_OP_ANDN, FUTEX_OP_CMP_EQ, FUTEX_OP_CMP_GE, FUTEX_OP_CMP_GT, FUTEX_OP_CMP_LE, FUTEX_OP_CMP_LT, FUTEX_OP_CMP_NE, FUTEX_OP_OR, FUTEX_OP_SET, FUTEX_OP_XOR, FUTEX_PRIVATE_FLAG, FUTEX_REQUEUE, FUTEX_WAIT, FUTEX_WAIT_BITSET, FUTEX_WAKE, FUTEX_WAKE_BITSET, FUTEX_WAKE_OP}, process}, task::{
+        current_process, current_task, current_task_id, current_token, exit_current, exit_proc, future::WaitAnyFuture, set_priority, yield_now, CloneFlags, ProcessControlBlock, ProcessRef, RobustList, TaskStatus, PID2PC, TID2TC
     }, timer::{ current_time, get_time_ns, get_time_us, get_usertime, usertime2_timeval, TimeVal, UserTimeSpec}, utils::{
-         error::{SysErrNo, SyscallRet}, page_round_up, string::get_abs_path
+         error::{SysErrNo, SyscallRet, TemplateRet}, page_round_up, string::get_abs_path
     }
 };
 pub const MADV_NORMAL: u32 = 0;
@@ -493,34 +493,39 @@ pub async fn sys_wait4(pid: isize, wstatus: *mut i32, options: u32) -> SyscallRe
     info!("[sys_wait4] pid:{}, wstatus:{:?}, options:{},pid:{}", pid, wstatus, options,proc.get_pid());
 
     // --- 0. 参数校验 ---
-    if pid == 0 || (pid as i32) == i32::MIN {
+    if (pid as i32) == i32::MIN {
         warn!("Unsupported pid value: {}", pid);
-        return Err(SysErrNo::ESRCH); // or EINVAL for pid==0 depending on spec
+        return Err(SysErrNo::ESRCH);
     }
     let wait_flags = match WaitFlags::from_bits(options) {
         Some(flags) => flags,
         None => return Err(SysErrNo::EINVAL),
     };
-
+    let want_stopped = wait_flags.contains(WaitFlags::WIMTRACED);
+    let want_continued = wait_flags.contains(WaitFlags::WCONTINUED);
+    // pid 为 0 时匹配调用时所在的进程组
+    let my_pgid = proc.pgid();
+    let matches = |child: &ProcessRef| match pid {
+        -1 => true,
+        0 => child.pgid() == my_pgid,
+        p if p > 0 => child.get_pid() == p as usize,
+        p => child.pgid() == (-p) as usize,
+    };
 
     loop { // 使用循环来处理查找和等待的逻辑
+        // 先记下子进程事件计数，之后发生的停止/继续都会让等待结束
+        let seq = proc.child_event_seq();
         // --- 1. 查找已存在的僵尸子进程 (持有锁的快速路径) ---
         let mut children_guard = proc.children.lock().await;
 
-        // 检查是否有任何子进程，如果没有，并且指定了pid，就ECHILD
-        if !children_guard.iter().any(|p| pid == -1 || pid as usize == p.get_pid()) {
-             if pid > 0 && children_guard.is_empty() {
-                debug!("No children for parent pid: {}", proc.get_pid());
-                return Err(SysErrNo::ECHILD);
-             }
-             if pid > 0 { // 如果指定了pid，但不在子进程列表中
-                debug!("Cannot find specific pid: {} in children of pid: {}", pid, proc.get_pid());
-                return Err(SysErrNo::ECHILD);
-             }
+        let candidates: Vec<ProcessRef> = children_guard.iter().filter(|c| matches(c)).cloned().collect();
+        if candidates.is_empty() {
+            debug!("No child matching pid {} for parent pid: {}", pid, proc.get_pid());
+            return Err(SysErrNo::ECHILD);
         }
-        
+
         for (idx, child_proc) in children_guard.iter().enumerate() {
-            if child_proc.is_zombie().await && (pid == -1 || (pid as usize) == child_proc.get_pid()) {
+            if matches(child_proc) && child_proc.is_zombie().await {
                 let child_to_reap = children_guard.remove(idx);
                 let found_pid = child_to_reap.get_pid();
                 let exit_code = child_to_reap.exit_code();
@@ -530,6 +535,7 @@ pub async fn sys_wait4(pid: isize, wstatus: *mut i32, options: u32) -> SyscallRe
         
                 // 释放锁
                 drop(children_guard);
+                proc.account_reaped_child(&child_to_reap).await;
         
                 debug!("[sys_wait4] Reaped zombie child pid: {}", found_pid);
         
@@ -537,68 +543,40 @@ pub async fn sys_wait4(pid: isize, wstatus: *mut i32, options: u32) -> SyscallRe
                     proc.memory_set.lock().await.safe_put_data(wstatus, exit_code << 8).await?;
                 }
         
-                // assert_eq!(
-                //     Arc::strong_count(&child_to_reap),
-                //     1,
-                //     "strong_count should be 1 after reaping, but is {}",
-                //     Arc::strong_count(&child_to_reap)
-                // );
-        
                 return Ok(found_pid);
             }
         }
+        drop(children_guard);
+
+        // --- 2. 报告停止/继续的子进程 ---
+        if want_stopped || want_continued {
+            for child in candidates.iter() {
+                if let Some(status) = child.take_job_event(want_stopped, want_continued) {
+                    debug!("[sys_wait4] child {} job event {:#x}", child.get_pid(), status);
+                    if !wstatus.is_null() {
+                        proc.memory_set.lock().await.safe_put_data(wstatus, status).await?;
+                    }
+                    return Ok(child.get_pid());
+                }
+            }
+        }
 
-        // --- 3. 如果没找到僵尸进程，处理 WNOHANG 或准备等待 ---
+        // --- 3. 如果没找到，处理 WNOHANG 或准备等待 ---
         // 如果是 WNOHANG 选项，立即返回 0
         if wait_flags.contains(WaitFlags::WNOHANG) {
             return Ok(0);
         }
-       // --- 准备等待 ---
-        // **关键点: 在 await 之前释放锁!**
-        
-        // 提取需要等待的子进程列表 (克隆Arc，不持有子进程内部的锁)
-        let children_to_watch: Vec<Arc<ProcessControlBlock>> = children_guard.iter().cloned().collect();
-        drop(children_guard); // **立即释放锁**
-
-        let future_to_await: Pin<Box<dyn Future<Output = usize> + Send + Sync>> = if pid == -1 {
-            if children_to_watch.is_empty() {
-                return Err(SysErrNo::ECHILD);
-            }
-            let futures_iter = children_to_watch.iter().map(|p| async move {
-                    p.main_task.lock().await.clone()
-                });
-                let tasks_to_wait = futures::future::join_all(futures_iter).await;
-            // 使用正确的异步处理方式来创建 WaitAnyFuture
-            Box::pin(async move {
-                // 1. 创建一个获取所有主线程 TCB 的 Future
-                
-
-                // 2. 并发地执行这些 Future 来获取 TCB 列表
-
-                // 3. 用获取到的 TCB 列表创建 WaitAnyFuture
-                WaitAnyFuture::new(tasks_to_wait).await
-            })
-
+        // --- 准备等待 (不持有 children 锁) ---
+        let futures_iter = candidates.iter().map(|p| async move {
+            p.main_task.lock().await.clone()
+        });
+        let tasks_to_wait = futures::future::join_all(futures_iter).await;
+        let exited = WaitAnyFuture::new(tasks_to_wait);
+        if want_stopped || want_continued {
+            futures::future::select(Box::pin(exited), proc.wait_child_event(seq)).await;
         } else {
-            // ... (等待特定 pid 的逻辑不变) ...
-            // 注意：这里也需要遵循同样的模式，先释放所有锁再 await
-            let child_proc_opt = PID2PC.lock().get(&(pid as usize)).cloned();
-            // 在这里已经没有 children_guard 锁了，是安全的
-
-            if let Some(child_proc) = child_proc_opt {
-
-            let task = child_proc.main_task.lock().await.clone();
-                Box::pin(async move {
-                    JoinFuture::new(task).await;
-                    pid as usize
-                })
-            } else {
-                return Err(SysErrNo::ECHILD);
-            }
-        };
-        
-        // --- 执行等待 (无锁状态) ---
-        future_to_await.await;
+            exited.await;
+        }
         
         // --- 返回循环开始处，重新查找并回收 ---
     }
@@ -848,6 +826,16 @@ pub async fn sys_mmap(
         start: (vpn),
         end: (end_vpn),
     };
+    // 超出 RLIMIT_AS，或私有可写映射超出 RLIMIT_DATA；MAP_FIXED 会替换掉范围内原有的映射
+    let data = map_perm.contains(MapPermission::W) && !flags.contains(MmapFlags::MAP_SHARED);
+    let allowed = if flags.contains(MmapFlags::MAP_FIXED) {
+        ms.may_replace(vpn, end_vpn, len, data)
+    } else {
+        ms.may_grow(len, data)
+    };
+    if !allowed {
+        return Err(SysErrNo::ENOMEM);
+    }
     // ——————————————————————————————————————————
     // 8. 按 MAP_FIXED / MAP_FIXED_NOREPLACE / hint / 自动选择地址
     let base = if flags.contains(MmapFlags::MAP_FIXED) {
@@ -904,11 +892,6 @@ pub async fn sys_mmap(
 
     info!("[sys_mmap]mmap ok,base:{:#x}", base.0);
 
-    // 10. 特殊 flags 的额外处理
-    if flags.contains(MmapFlags::MAP_POPULATE) {
-        // 立即为每页缺页、填充物理页
-        area.map(&mut ms.page_table)?;
-    }
    
     // if flags.contains(MmapFlags::MAP_LOCKED) {
     //     // mlock：锁定这些物理页
@@ -922,6 +905,13 @@ pub async fn sys_mmap(
     // ——————————————————————————————————————————
     // 11. 插入到 MemorySet 的 areatree / VMA 列表
     ms.areatree.push(area);
+
+    // 10. MAP_POPULATE：立即为每页走一遍缺页处理，文件映射也能填入内容
+    if flags.contains(MmapFlags::MAP_POPULATE) {
+        for i in 0..pages {
+            ms.handle_page_fault(base.0 + i * PAGE_SIZE, false).await?;
+        }
+    }
     flush_all();
 
     // 12. 返回映射基址
@@ -941,6 +931,53 @@ pub async  fn sys_munmap(start: usize, len: usize) -> SyscallRet {
     Ok(0)
 }
 
+/// 参考 https://man7.org/linux/man-pages/man2/msync.2.html
+/// 页缓存与映射共用物理页，MS_INVALIDATE 无需额外处理；MS_ASYNC 也同步写回
+pub async fn sys_msync(addr: usize, len: usize, flags: u32) -> SyscallRet {
+    trace!("[sys_msync] addr:{:#x},len:{:#x},flags:{:#x}", addr, len, flags);
+    let flags = MsyncFlags::from_bits(flags).ok_or(SysErrNo::EINVAL)?;
+    if addr % PAGE_SIZE != 0
+        || flags.contains(MsyncFlags::MS_ASYNC | MsyncFlags::MS_SYNC)
+    {
+        return Err(SysErrNo::EINVAL);
+    }
+    let start = VirtAddr::from(addr).floor();
+    let end = VirtAddr::from(addr + len).ceil();
+
+    let proc = current_process();
+    let ms = proc.memory_set.lock().await;
+    let mut ranges = Vec::new();
+    let mut next = start;
+    for (_, area) in ms.areatree.range(..end) {
+        if area.end_vpn() <= start {
+            continue;
+        }
+        // 范围内有未映射的空洞
+        if area.start_vpn() > next {
+            return Err(SysErrNo::ENOMEM);
+        }
+        next = area.end_vpn();
+        if !area.mmap_flags.contains(MmapFlags::MAP_SHARED) {
+            continue;
+        }
+        let Some(mmap_file) = area.fd.as_ref() else { continue };
+        let Some(cache) = mmap_file.file.file()?.page_cache() else { continue };
+        let base = mmap_file.offset >> PAGE_SIZE_BITS;
+        let first = area.start_vpn().0.max(start.0) - area.start_vpn().0 + base;
+        let last = area.end_vpn().0.min(end.0) - area.start_vpn().0 + base;
+        ranges.push((cache, first, last));
+    }
+    if next < end {
+        return Err(SysErrNo::ENOMEM);
+    }
+    drop(ms);
+
+    for (cache, first, last) in ranges {
+        cache.writeback_range(first, last)?;
+    }
+    Ok(0)
+}
+
 /// change data segment size
 pub async  fn sys_brk(new_brk:usize) -> SyscallRet {
     trace!(
@@ -1118,42 +1155,65 @@ pub async  fn sys_prlimit(
 
     trace!("[sys_prlimit]: pid:{},resource:{},new_limit:{:?},old_limit:{:?}",pid,resource,new_limit,old_limit);
     const RLIMIT_NOFILE: u32 = 7;
-        let proc = current_process();
-        if !old_limit.is_null() {
-        proc.manual_alloc_type_for_lazy(old_limit).await?;
-        }
-        if !new_limit.is_null() {
-        proc.manual_alloc_type_for_lazy(new_limit).await?;
-        }
-        let token  = proc.get_user_token().await;
-    if resource != RLIMIT_NOFILE {
-        // 说明是get
-        // let limit = translated_refmut(token, old_limit)?;
-        // limit.rlim_cur = 0xdeadbeff;
-        // limit.rlim_max =  0xdeadbeff;
-        return Ok(0)
+    let curr = current_process();
+    // pid 为 0 时作用于调用者自己
+    let proc = if pid == 0 || pid == curr.get_pid() {
+        curr.clone()
+    } else {
+        PID2PC.lock().get(&pid).cloned().ok_or(SysErrNo::ESRCH)?
+    };
+    if !old_limit.is_null() {
+        curr.manual_alloc_type_for_lazy(old_limit).await?;
     }
+    if !new_limit.is_null() {
+        curr.manual_alloc_type_for_lazy(new_limit).await?;
+    }
+    let token = curr.get_user_token().await;
+    let new = if new_limit.is_null() {
+        None
+    } else {
+        Some(*get_target_ref(token, new_limit)?)
+    };
 
-    if pid == 0 {
-       
-        let fd_table =  proc.fd_table.lock().await;
-        if !old_limit.is_null() {
-            // 说明是get
-            let limit = translated_refmut(token, old_limit)?;
-            limit.rlim_cur = fd_table.get_soft_limit();
-            limit.rlim_max = fd_table.get_hard_limit();
+    match resource {
+        RLIMIT_NOFILE => {
+            let fd_table = proc.fd_table.lock().await;
+            if !old_limit.is_null() {
+                // 说明是get
+                let limit = translated_refmut(token, old_limit)?;
+                limit.rlim_cur = fd_table.get_soft_limit();
+                limit.rlim_max = fd_table.get_hard_limit();
+            }
+            if let Some(limit) = new {
+                // 说明是set
+                fd_table.set_limit(limit.rlim_cur, limit.rlim_max);
+            }
+        }
+        RLIMIT_AS | RLIMIT_DATA | RLIMIT_STACK | RLIMIT_MEMLOCK => {
+            let mut ms = proc.memory_set.lock().await;
+            if !old_limit.is_null() {
+                let limit = ms.limits.get(resource).unwrap();
+                *translated_refmut(token, old_limit)? = RLimit {
+                    rlim_cur: limit.cur,
+                    rlim_max: limit.max,
+                };
+            }
+            if let Some(limit) = new {
+                ms.limits.set(resource, limit.rlim_cur, limit.rlim_max)?;
+            }
         }
-        if !new_limit.is_null() {
-            // 说明是set
-            let limit: &RLimit = get_target_ref(token, new_limit)?;
-            fd_table.set_limit(limit.rlim_cur, limit.rlim_max);
+        // 其余资源暂不限制，查询时报告为无限制
+        _ => {
+            if !old_limit.is_null() {
+                *translated_refmut(token, old_limit)? = RLimit {
+                    rlim_cur: RLIM_INFINITY,
+                    rlim_max: RLIM_INFINITY,
+                };
+            }
         }
-    } else {
-        unimplemented!("pid must equal zero");
     }
 
     Ok(0)
-//   Err(SysErrNo::ENOSYS )
 }
 
 #[repr(C)]
@@ -1566,13 +1626,68 @@ pub fn sys_gettid() -> SyscallRet {
     Ok(current_task().get_tid())
 }
 
-pub fn sys_setsid() -> SyscallRet {
-    //涉及到会话和进程组，暂时伪实现
+/// 按 pid 查找进程，0 表示调用者
+fn find_process(pid: usize) -> TemplateRet<ProcessRef> {
+    if pid == 0 {
+        return Ok(current_process());
+    }
+    PID2PC.lock().get(&pid).cloned().ok_or(SysErrNo::ESRCH)
+}
 
-    trace!("[sys_setsid] ");
+/// 设置进程组：只能设置自己或自己的子进程，且不能跨会话
+pub async fn sys_setpgid(pid: usize, pgid: isize) -> SyscallRet {
+    trace!("[sys_setpgid] pid: {}, pgid: {}", pid, pgid);
+    if pgid < 0 {
+        return Err(SysErrNo::EINVAL);
+    }
+    let proc = current_process();
+    let target = find_process(pid)?;
+    let target_pid = target.get_pid();
+    if target_pid != proc.get_pid() {
+        let is_child = proc.children.lock().await.iter().any(|c| c.get_pid() == target_pid);
+        if !is_child {
+            return Err(SysErrNo::ESRCH);
+        }
+        if target.sid() != proc.sid() {
+            return Err(SysErrNo::EPERM);
+        }
+    }
+    // 会话首进程不能换组
+    if target.sid() == target_pid {
+        return Err(SysErrNo::EPERM);
+    }
+    let pgid = if pgid == 0 { target_pid } else { pgid as usize };
+    // 加入已有进程组时，该组必须在同一会话中
+    if pgid != target_pid && !pgrp_members(pgid).iter().any(|p| p.sid() == proc.sid()) {
+        return Err(SysErrNo::EPERM);
+    }
+    target.set_pgid(pgid);
     Ok(0)
 }
 
+pub fn sys_getpgid(pid: usize) -> SyscallRet {
+    trace!("[sys_getpgid] pid: {}", pid);
+    Ok(find_process(pid)?.pgid())
+}
+
+pub fn sys_getsid(pid: usize) -> SyscallRet {
+    trace!("[sys_getsid] pid: {}", pid);
+    Ok(find_process(pid)?.sid())
+}
+
+/// 创建新会话，调用者成为会话首进程和新进程组的组长，没有控制终端
+pub fn sys_setsid() -> SyscallRet {
+    trace!("[sys_setsid] ");
+    let proc = current_process();
+    let pid = proc.get_pid();
+    // 进程组组长不能创建会话，否则原进程组会跨两个会话
+    if !pgrp_members(pid).is_empty() {
+        return Err(SysErrNo::EPERM);
+    }
+    proc.set_sid();
+    Ok(pid)
+}
+
 
 
 pub static CUR_UID: Lazy<spin::mutex::Mutex<u32>> = Lazy::new(|| spin::mutex::Mutex::new(0));
@@ -1676,6 +1791,15 @@ pub async  fn sys_madvise(
             Ok(0) // 成功
         }
 
+        // 透明大页：只记录在区域上，之后缺页时按此决定是否用 2 MiB 大页映射
+        MADV_HUGEPAGE | MADV_NOHUGEPAGE => {
+            let end_vpn = VirtAddr::from(end_addr).ceil();
+            memory_set
+                .madvise_hugepage(start_va.floor(), end_vpn, advice == MADV_HUGEPAGE)
+                .await;
+            Ok(0)
+        }
+
         // 对于不支持的 advice，返回 EINVAL
         _ => {
             warn!("sys_madvise: unsupported advice value {}.", advice);
@@ -1867,7 +1991,10 @@ pub async fn sys_getrusage(who: i32, usage_ptr: *mut Rusage) -> SyscallRet {
     let pcb = current_process();
 
     
-    let token =pcb.memory_set.lock().await.token();
+    let (token, peak_rss) = {
+        let ms = pcb.memory_set.lock().await;
+        (ms.token(), ms.peak_resident_pages())
+    };
     pcb.manual_alloc_type_for_lazy(usage_ptr).await?;
     let task = current_task();
     let tms = unsafe { *task.tms.get() };
@@ -1877,6 +2004,8 @@ pub async fn sys_getrusage(who: i32, usage_ptr: *mut Rusage) -> SyscallRet {
             Rusage {
                 ru_utime:TimeVal { sec:tms.utime as usize, usec: 0},
                 ru_stime:TimeVal { sec:tms.stime as usize, usec: 0},
+                // ru_maxrss 以 KB 为单位
+                ru_maxrss: (peak_rss * PAGE_SIZE / 1024) as isize,
                 // 其他字段暂时填充为0
                 ..Default::default()
             }
@@ -1885,6 +2014,7 @@ pub async fn sys_getrusage(who: i32, usage_ptr: *mut Rusage) -> SyscallRet {
             Rusage {
                 ru_utime:TimeVal { sec:tms.utime as usize, usec: 0},
                 ru_stime:TimeVal { sec:tms.stime as usize, usec: 0},
+                ru_maxrss: (pcb.children_maxrss() * PAGE_SIZE / 1024) as isize,
                 // 子进程的其他统计信息通常也需要累加，这里简化
                 ..Default::default()
             }
@@ -11,7 +11,7 @@
 
 use alloc::vec::Vec;
 
-use crate::{mm::{get_target_ref, translated_refmut}, signal::{load_trap_for_signal, send_signal_to_task, SigAction, SigInfo, SigMaskHow, SigSet, Signal, NSIG}, task::{current_process, current_task, PID2PC, TID2TC}, timer::UserTimeSpec, utils::error::{SysErrNo, SyscallRet}};
+use crate::{mm::{get_target_ref, translated_refmut}, signal::{load_trap_for_signal, pgrp_members, send_signal, send_signal_to_pgrp, send_signal_to_task, SigAction, SigInfo, SigMaskHow, SigSet, Signal, NSIG}, task::{current_process, current_task, PID2PC, TID2TC}, timer::UserTimeSpec, utils::error::{SysErrNo, SyscallRet}};
 
 // pub fn sys_rt_sigaction(
 //     signo: usize,
@@ -168,10 +168,23 @@ pub async fn sys_kill(target_pid: usize, signum_usize: usize) -> SyscallRet {
         Some(s) => s,
         None => return Err(SysErrNo::EINVAL), // 无效信号
     };
-    let mut pid= target_pid;
-    if target_pid==0{
-        pid=current_process().get_pid();
+    // 0 和负数发给进程组，-1 发给除 init 和自己以外的所有进程
+    match target_pid as isize {
+        -1 => return kill_all(signum_usize, sig).await,
+        p if p <= 0 => {
+            let pgid = if p == 0 { current_process().pgid() } else { (-p) as usize };
+            if pgrp_members(pgid).is_empty() {
+                return Err(SysErrNo::ESRCH);
+            }
+            if signum_usize == 0 || sig == Signal::SIGNONE {
+                return Ok(0);
+            }
+            send_signal_to_pgrp(pgid, sig).await?;
+            return Ok(0);
+        }
+        _ => {}
     }
+    let pid = target_pid;
     // 步骤2：处理信号0（检查进程是否存在）
     if signum_usize == 0 {
         let has_thread = PID2PC.lock().contains_key(&pid);
@@ -213,6 +226,28 @@ pub async fn sys_kill(target_pid: usize, signum_usize: usize) -> SyscallRet {
 
     Ok(0) // 信号已尝试发送（即使部分线程失败，仍返回成功）
 }
+/// kill(-1, sig)：发给除 init 和调用者以外的所有进程
+async fn kill_all(signum_usize: usize, sig: Signal) -> SyscallRet {
+    let self_pid = current_process().get_pid();
+    let targets: Vec<usize> = PID2PC
+        .lock()
+        .keys()
+        .copied()
+        .filter(|&pid| pid != 1 && pid != self_pid)
+        .collect();
+    if targets.is_empty() {
+        return Err(SysErrNo::ESRCH);
+    }
+    if signum_usize == 0 || sig == Signal::SIGNONE {
+        return Ok(0);
+    }
+    for pid in targets {
+        if let Err(e) = send_signal(pid, None, sig).await {
+            warn!("Failed to send signal to pid {}: {:?}", pid, e);
+        }
+    }
+    Ok(0)
+}
 pub async fn sys_tgkill(target_pid: usize, target_tid: usize, signum_usize: usize)->SyscallRet{
     trace!("[sys_tgkill] target_pid:{} target_tid: {}, signum: {}", target_pid,target_tid, signum_usize);
     let pcb = match PID2PC.lock().get(&target_pid){
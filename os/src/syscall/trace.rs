@@ -0,0 +1,395 @@
+//! 按进程选择的系统调用跟踪（类似 strace）
+//!
+//! 往 `/proc/lingos/trace_pids` 写入 pid 开始跟踪该进程（`-pid` 停止跟踪，
+//! `clear` 全部停止），被跟踪进程 fork 出的子进程自动加入跟踪。每次系统调用
+//! 结束后按 strace 的格式记录调用名、解码后的参数、返回值/错误码和耗时，
+//! 写进一个环形缓冲区，通过读 `/proc/lingos/trace` 取出，写它则清空缓冲区。
+//! 没有进程被跟踪时只多一次原子变量的读取。
+
+use alloc::collections::{BTreeSet, VecDeque};
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Write;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use spin::Mutex;
+
+use crate::mm::{KernelAddr, PageTable, VirtAddr};
+use crate::timer::get_time_ns;
+use crate::utils::error::{SysErrNo, SyscallRet};
+
+/// 环形缓冲区的容量（字节），超出后丢弃最旧的记录
+const TRACE_BUF_SIZE: usize = 64 * 1024;
+/// 字符串和缓冲区参数最多显示的字节数，和 strace 默认的 -s 32 一致
+const TRACE_STR_MAX: usize = 32;
+const AT_FDCWD: isize = -100;
+const CLONE_THREAD: usize = 0x10000;
+
+/// 有进程被跟踪时为 true，用于快速跳过
+static TRACING: AtomicBool = AtomicBool::new(false);
+static TRACED_PIDS: Mutex<BTreeSet<usize>> = Mutex::new(BTreeSet::new());
+
+struct TraceBuffer {
+    lines: VecDeque<String>,
+    size: usize,
+    /// 因缓冲区满被丢弃的记录数
+    dropped: usize,
+}
+
+static TRACE_BUF: Mutex<TraceBuffer> = Mutex::new(TraceBuffer {
+    lines: VecDeque::new(),
+    size: 0,
+    dropped: 0,
+});
+
+/// 系统调用名和参数格式。riscv64 和 loongarch64 都使用 asm-generic 的调用号。
+///
+/// 参数格式每个字符对应一个参数：
+/// `d` 有符号十进制，`u` 无符号十进制，`x` 十六进制，`o` 八进制，`p` 指针，
+/// `f` 文件描述符（识别 AT_FDCWD），`s` 用户态字符串，
+/// `b` 用户态缓冲区（长度取下一个参数）
+const SYSCALL_TABLE: &[(usize, &str, &str)] = &[
+    (17, "getcwd", "pu"),
+    (19, "eventfd2", "ux"),
+    (20, "epoll_create1", "x"),
+    (21, "epoll_ctl", "fdfp"),
+    (22, "epoll_pwait", "fpddp"),
+    (23, "dup", "f"),
+    (24, "dup3", "ffx"),
+    (25, "fcntl", "fdx"),
+    (29, "ioctl", "fxx"),
+    (32, "flock", "fd"),
+    (34, "mkdirat", "fso"),
+    (35, "unlinkat", "fsx"),
+    (36, "symlinkat", "sfs"),
+    (37, "linkat", "fsfsx"),
+    (38, "renameat", "fsfs"),
+    (39, "umount2", "sx"),
+    (40, "mount", "sssxp"),
+    (43, "statfs", "sp"),
+    (44, "fstatfs", "fp"),
+    (45, "truncate", "sd"),
+    (46, "ftruncate", "fd"),
+    (48, "faccessat", "fsox"),
+    (49, "chdir", "s"),
+    (50, "fchdir", "f"),
+    (52, "fchmod", "fo"),
+    (53, "fchmodat", "fsox"),
+    (54, "fchownat", "fsddx"),
+    (55, "fchown", "fdd"),
+    (56, "openat", "fsxo"),
+    (57, "close", "f"),
+    (59, "pipe2", "px"),
+    (61, "getdents64", "fpu"),
+    (62, "lseek", "fdd"),
+    (63, "read", "fpu"),
+    (64, "write", "fbu"),
+    (65, "readv", "fpd"),
+    (66, "writev", "fpd"),
+    (67, "pread64", "fpud"),
+    (68, "pwrite64", "fbud"),
+    (71, "sendfile", "ffpu"),
+    (72, "pselect6", "dppppp"),
+    (73, "ppoll", "pdppu"),
+    (74, "signalfd4", "fpux"),
+    (78, "readlinkat", "fspu"),
+    (79, "newfstatat", "fspx"),
+    (80, "fstat", "fp"),
+    (81, "sync", ""),
+    (82, "fsync", "f"),
+    (85, "timerfd_create", "dx"),
+    (86, "timerfd_settime", "fxpp"),
+    (87, "timerfd_gettime", "fp"),
+    (88, "utimensat", "fspx"),
+    (93, "exit", "d"),
+    (94, "exit_group", "d"),
+    (96, "set_tid_address", "p"),
+    (98, "futex", "pduppd"),
+    (99, "set_robust_list", "pu"),
+    (101, "nanosleep", "pp"),
+    (113, "clock_gettime", "dp"),
+    (115, "clock_nanosleep", "dxpp"),
+    (122, "sched_setaffinity", "dup"),
+    (123, "sched_getaffinity", "dup"),
+    (124, "sched_yield", ""),
+    (129, "kill", "dd"),
+    (130, "tkill", "dd"),
+    (131, "tgkill", "ddd"),
+    (134, "rt_sigaction", "dppu"),
+    (135, "rt_sigprocmask", "dppu"),
+    (137, "rt_sigtimedwait", "pppu"),
+    (139, "rt_sigreturn", ""),
+    (153, "times", "p"),
+    (154, "setpgid", "dd"),
+    (155, "getpgid", "d"),
+    (157, "setsid", ""),
+    (160, "uname", "p"),
+    (163, "getrlimit", "dp"),
+    (165, "getrusage", "dp"),
+    (166, "umask", "o"),
+    (167, "prctl", "dxxxx"),
+    (169, "gettimeofday", "pp"),
+    (172, "getpid", ""),
+    (173, "getppid", ""),
+    (174, "getuid", ""),
+    (175, "geteuid", ""),
+    (176, "getgid", ""),
+    (177, "getegid", ""),
+    (178, "gettid", ""),
+    (179, "sysinfo", "p"),
+    (194, "shmget", "dux"),
+    (195, "shmctl", "ddp"),
+    (196, "shmat", "dpx"),
+    (197, "shmdt", "p"),
+    (198, "socket", "ddd"),
+    (199, "socketpair", "dddp"),
+    (200, "bind", "fpu"),
+    (201, "listen", "fd"),
+    (202, "accept", "fpp"),
+    (203, "connect", "fpu"),
+    (204, "getsockname", "fpp"),
+    (205, "getpeername", "fpp"),
+    (206, "sendto", "fbuxpu"),
+    (207, "recvfrom", "fpuxpp"),
+    (208, "setsockopt", "fddpu"),
+    (209, "getsockopt", "fddpp"),
+    (210, "shutdown", "fd"),
+    (211, "sendmsg", "fpx"),
+    (212, "recvmsg", "fpx"),
+    (214, "brk", "p"),
+    (215, "munmap", "pu"),
+    (220, "clone", "xppxp"),
+    (221, "execve", "spp"),
+    (222, "mmap", "puxxfx"),
+    (226, "mprotect", "pux"),
+    (227, "msync", "pux"),
+    (233, "madvise", "pud"),
+    (242, "accept4", "fppx"),
+    (260, "wait4", "dpxp"),
+    (261, "prlimit64", "ddpp"),
+    (278, "getrandom", "pux"),
+    (279, "memfd_create", "sx"),
+    (291, "statx", "fsxxp"),
+    (435, "clone3", "pu"),
+    (439, "faccessat2", "fsox"),
+    (441, "epoll_pwait2", "fpdpp"),
+];
+
+fn lookup(syscall_id: usize) -> Option<(&'static str, &'static str)> {
+    SYSCALL_TABLE
+        .binary_search_by_key(&syscall_id, |(id, _, _)| *id)
+        .ok()
+        .map(|i| (SYSCALL_TABLE[i].1, SYSCALL_TABLE[i].2))
+}
+
+/// 是否有进程被跟踪
+pub fn tracing() -> bool {
+    TRACING.load(Ordering::Relaxed)
+}
+
+pub fn is_traced(pid: usize) -> bool {
+    tracing() && TRACED_PIDS.lock().contains(&pid)
+}
+
+pub fn trace_pid(pid: usize) {
+    let mut pids = TRACED_PIDS.lock();
+    pids.insert(pid);
+    TRACING.store(true, Ordering::Relaxed);
+}
+
+pub fn untrace_pid(pid: usize) {
+    let mut pids = TRACED_PIDS.lock();
+    pids.remove(&pid);
+    TRACING.store(!pids.is_empty(), Ordering::Relaxed);
+}
+
+/// 处理写入 `/proc/lingos/trace_pids` 的内容
+pub fn trace_control(cmd: &str) -> Result<(), SysErrNo> {
+    for token in cmd.split_whitespace() {
+        if token == "clear" {
+            TRACED_PIDS.lock().clear();
+            TRACING.store(false, Ordering::Relaxed);
+        } else if let Some(pid) = token.strip_prefix('-') {
+            untrace_pid(pid.parse().map_err(|_| SysErrNo::EINVAL)?);
+        } else {
+            trace_pid(token.trim_start_matches('+').parse().map_err(|_| SysErrNo::EINVAL)?);
+        }
+    }
+    Ok(())
+}
+
+/// `/proc/lingos/trace_pids` 的内容
+pub fn traced_pids() -> String {
+    let mut s = String::new();
+    for pid in TRACED_PIDS.lock().iter() {
+        let _ = writeln!(s, "{}", pid);
+    }
+    s
+}
+
+/// `/proc/lingos/trace` 的内容
+pub fn trace_log() -> String {
+    let buf = TRACE_BUF.lock();
+    let mut s = String::with_capacity(buf.size + 64);
+    if buf.dropped > 0 {
+        let _ = writeln!(s, "--- {} earlier records dropped ---", buf.dropped);
+    }
+    for line in buf.lines.iter() {
+        s.push_str(line);
+    }
+    s
+}
+
+pub fn clear_trace_log() {
+    let mut buf = TRACE_BUF.lock();
+    buf.lines.clear();
+    buf.size = 0;
+    buf.dropped = 0;
+}
+
+fn push_line(line: String) {
+    let mut buf = TRACE_BUF.lock();
+    buf.size += line.len();
+    buf.lines.push_back(line);
+    while buf.size > TRACE_BUF_SIZE {
+        let Some(old) = buf.lines.pop_front() else {
+            break;
+        };
+        buf.size -= old.len();
+        buf.dropped += 1;
+    }
+}
+
+/// 按页表读用户态内存，遇到未映射的页返回 None；`nul` 为 true 时读到 `\0` 为止。
+/// 返回读到的字节和是否被截断
+fn read_user(token: usize, addr: usize, len: usize, nul: bool) -> Option<(Vec<u8>, bool)> {
+    if addr == 0 {
+        return None;
+    }
+    let page_table = PageTable::from_token(token);
+    let mut bytes = Vec::new();
+    for va in addr..addr.saturating_add(len.min(TRACE_STR_MAX)) {
+        let pa = page_table.translate_va(VirtAddr::from(va))?;
+        let ch: u8 = *KernelAddr::from(pa).get_ref();
+        if nul && ch == 0 {
+            return Some((bytes, false));
+        }
+        bytes.push(ch);
+    }
+    Some((bytes, len > TRACE_STR_MAX || nul))
+}
+
+fn write_escaped(s: &mut String, bytes: &[u8], truncated: bool) {
+    s.push('"');
+    for &b in bytes {
+        match b {
+            b'\n' => s.push_str("\\n"),
+            b'\t' => s.push_str("\\t"),
+            b'\r' => s.push_str("\\r"),
+            b'"' => s.push_str("\\\""),
+            b'\\' => s.push_str("\\\\"),
+            0x20..=0x7e => s.push(b as char),
+            _ => {
+                let _ = write!(s, "\\x{:02x}", b);
+            }
+        }
+    }
+    s.push('"');
+    if truncated {
+        s.push_str("...");
+    }
+}
+
+/// 系统调用开始前解码参数，字符串和缓冲区要在调用之前读取
+pub fn format_call(token: usize, syscall_id: usize, args: &[usize; 6]) -> String {
+    let mut s = String::new();
+    let Some((name, spec)) = lookup(syscall_id) else {
+        let _ = write!(
+            s,
+            "syscall_{}({:#x}, {:#x}, {:#x}, {:#x}, {:#x}, {:#x})",
+            syscall_id, args[0], args[1], args[2], args[3], args[4], args[5]
+        );
+        return s;
+    };
+    s.push_str(name);
+    s.push('(');
+    for (i, kind) in spec.bytes().enumerate() {
+        if i > 0 {
+            s.push_str(", ");
+        }
+        let arg = args[i];
+        let _ = match kind {
+            b'd' => write!(s, "{}", arg as isize),
+            b'u' => write!(s, "{}", arg),
+            b'o' => write!(s, "{:#o}", arg),
+            b'f' if arg as i32 as isize == AT_FDCWD => write!(s, "AT_FDCWD"),
+            b'f' => write!(s, "{}", arg as i32),
+            b's' | b'b' => {
+                let (len, nul) = if kind == b's' { (usize::MAX, true) } else { (args[i + 1], false) };
+                match read_user(token, arg, len, nul) {
+                    Some((bytes, truncated)) => write_escaped(&mut s, &bytes, truncated),
+                    None => {
+                        let _ = write!(s, "{:#x}", arg);
+                    }
+                }
+                Ok(())
+            }
+            b'p' if arg == 0 => write!(s, "NULL"),
+            _ => write!(s, "{:#x}", arg),
+        };
+    }
+    s.push(')');
+    s
+}
+
+/// 记录一次完成的系统调用。被跟踪进程 fork 出的子进程也加入跟踪
+pub fn record(pid: usize, tid: usize, syscall_id: usize, args: &[usize; 6], call: String, start_ns: usize, ret: &SyscallRet) {
+    let now = get_time_ns();
+    let mut line = String::new();
+    let _ = write!(
+        line,
+        "{}.{:06} [{}:{}] {} = ",
+        start_ns / 1_000_000_000,
+        start_ns % 1_000_000_000 / 1000,
+        pid,
+        tid,
+        call
+    );
+    let name = lookup(syscall_id).map_or("", |(name, _)| name);
+    let _ = match ret {
+        Ok(v) if matches!(name, "brk" | "mmap") => write!(line, "{:#x}", v),
+        Ok(v) => write!(line, "{}", *v as isize),
+        Err(e) => write!(line, "-1 {:?} ({})", e, e.str()),
+    };
+    let dur = now.saturating_sub(start_ns);
+    let _ = writeln!(line, " <{}.{:06}>", dur / 1_000_000_000, dur % 1_000_000_000 / 1000);
+    push_line(line);
+
+    if let Ok(child) = ret {
+        let fork = match name {
+            "clone" => args[0] & CLONE_THREAD == 0,
+            // clone3 的标志在用户态结构体里，这里不解析，新线程的 tid 多跟踪一个也无害
+            "clone3" => true,
+            _ => false,
+        };
+        if fork && *child != 0 {
+            trace_pid(*child);
+        }
+    }
+}
+
+/// 不会返回的系统调用（exit/exit_group/execve 成功时）在执行前记录
+pub fn record_noreturn(pid: usize, tid: usize, call: String) {
+    let now = get_time_ns();
+    let mut line = String::new();
+    let _ = writeln!(
+        line,
+        "{}.{:06} [{}:{}] {} = ?",
+        now / 1_000_000_000,
+        now % 1_000_000_000 / 1000,
+        pid,
+        tid,
+        call
+    );
+    push_line(line);
+}
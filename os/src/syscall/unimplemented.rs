@@ -0,0 +1,55 @@
+//! 未实现系统调用的统计
+//!
+//! 未知的系统调用号返回 ENOSYS，不再让内核 panic。每个 (pid, 调用号) 只打印一次
+//! 日志，调用次数按调用号和进程累计，通过 `/proc/lingos/unimplemented_syscalls`
+//! 导出，方便根据真实负载决定先实现哪些系统调用。
+
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use core::fmt::Write;
+
+use spin::Mutex;
+
+use crate::task::current_process;
+
+/// 某个调用号的统计
+#[derive(Default)]
+struct UnimplementedEntry {
+    total: usize,
+    /// pid -> 该进程的调用次数
+    per_process: BTreeMap<usize, usize>,
+}
+
+/// 调用号 -> 统计
+static UNIMPLEMENTED: Mutex<BTreeMap<usize, UnimplementedEntry>> = Mutex::new(BTreeMap::new());
+
+/// 记录一次未实现的系统调用，(pid, 调用号) 第一次出现时打印警告
+pub fn record_unimplemented(syscall_id: usize, args: &[usize; 6]) {
+    let pid = current_process().get_pid();
+    let mut table = UNIMPLEMENTED.lock();
+    let entry = table.entry(syscall_id).or_default();
+    entry.total += 1;
+    let count = entry.per_process.entry(pid).or_insert(0);
+    *count += 1;
+    if *count == 1 {
+        warn!(
+            "[syscall] unimplemented syscall {} from pid {}, args: {:x?}",
+            syscall_id, pid, args
+        );
+    }
+}
+
+/// `/proc/lingos/unimplemented_syscalls` 的内容：每行一个调用号，
+/// 依次为调用号、总次数和各进程的 `pid:次数`
+pub fn unimplemented_report() -> String {
+    let mut s = String::from("syscall\tcount\tpids\n");
+    for (id, entry) in UNIMPLEMENTED.lock().iter() {
+        let _ = write!(s, "{}\t{}\t", id, entry.total);
+        for (i, (pid, count)) in entry.per_process.iter().enumerate() {
+            let sep = if i == 0 { "" } else { " " };
+            let _ = write!(s, "{}{}:{}", sep, pid, count);
+        }
+        s.push('\n');
+    }
+    s
+}
@@ -56,154 +56,9 @@ fn local_irq_restore(flags: usize) {
     }
 }
 
-#[link_section = ".percpu"]
-static mut __PERCPU_CURRENT_TASK_PTR: usize = 0;
-
-#[allow(non_camel_case_types)]
-/// Wrapper struct for the per-CPU data [stringify! (CURRENT_TASK_PTR)]
-struct CURRENT_TASK_PTR_WRAPPER {}
-
-static CURRENT_TASK_PTR: CURRENT_TASK_PTR_WRAPPER = CURRENT_TASK_PTR_WRAPPER {};
-
-impl CURRENT_TASK_PTR_WRAPPER {
-    /// Returns the offset relative to the per-CPU data area base on the current CPU.
-    fn offset(&self) -> usize {
-        let value: usize;
-        unsafe {
-            #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
-            core::arch::asm!(
-                "lui {0}, %hi({VAR})",
-                "addi {0}, {0}, %lo({VAR})",
-                out(reg) value,
-                VAR = sym __PERCPU_CURRENT_TASK_PTR,
-            );
-
-            #[cfg(target_arch = "loongarch64")]
-            core::arch::asm!(
-                "la.global {0}, {VAR}",
-                out(reg) value,
-                VAR = sym __PERCPU_CURRENT_TASK_PTR,
-            );
-        }
-        value
-    }
-
-    #[inline]
-    /// Returns the raw pointer of this per-CPU data on the current CPU.
-    ///
-    /// # Safety
-    ///
-    /// Caller must ensure that preemption is disabled on the current CPU.
-    pub unsafe fn current_ptr(&self) -> *const usize {
-        let base: usize;
-        #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
-        core::arch::asm!("mv {}, gp", out(reg) base);
-
-        #[cfg(target_arch = "loongarch64")]
-        core::arch::asm!("or {}, $r21, $zero", out(reg) base); // $r21 是 LoongArch64 的 GP 寄存器
-
-        (base + self.offset()) as *const usize
-    }
-
-    #[inline]
-    /// Returns the reference of the per-CPU data on the current CPU.
-    ///
-    /// # Safety
-    ///
-    /// Caller must ensure that preemption is disabled on the current CPU.
-    pub unsafe fn current_ref_raw(&self) -> &usize {
-        &*self.current_ptr()
-    }
-
-    #[inline]
-    /// Returns the mutable reference of the per-CPU data on the current CPU.
-    ///
-    /// # Safety
-    ///
-    /// Caller must ensure that preemption is disabled on the current CPU.
-    #[allow(clippy::mut_from_ref)]
-    pub unsafe fn current_ref_mut_raw(&self) -> &mut usize {
-        &mut *(self.current_ptr() as *mut usize)
-    }
-
-    /// Manipulate the per-CPU data on the current CPU in the given closure.
-    ///
-    /// Preemption will be disabled during the call.
-    pub fn with_current<F, T>(&self, f: F) -> T
-    where
-        F: FnOnce(&mut usize) -> T,
-    {
-        f(unsafe { self.current_ref_mut_raw() })
-    }
-
-    #[inline]
-    /// Returns the value of the per-CPU data on the current CPU.
-    ///
-    /// # Safety
-    ///
-    /// Caller must ensure that preemption is disabled on the current CPU.
-    pub unsafe fn read_current_raw(&self) -> usize {
-        let ret: usize;
-        
-        #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
-        core::arch::asm!(
-            "la   {tmp}, {sym}",
-            "ld   {ret}, 0({tmp})",
-            tmp = out(reg) _,
-            ret = out(reg) ret,
-            sym = sym __PERCPU_CURRENT_TASK_PTR,
-        );
-        
-        #[cfg(target_arch = "loongarch64")]
-        core::arch::asm!(
-            "la.global {tmp}, {sym}",
-            "ld.d {ret}, {tmp}, 0",      // LoongArch64 语法：ld.d dst, base, offset
-            tmp = out(reg) _,
-            ret = out(reg) ret,
-            sym = sym __PERCPU_CURRENT_TASK_PTR,
-        );
-        
-        ret
-    }
-
-    #[inline]
-    /// Set the value of the per-CPU data on the current CPU.
-    ///
-    /// # Safety
-    ///
-    /// Caller must ensure that preemption is disabled on the current CPU.
-    pub unsafe fn write_current_raw(&self, val: usize) {
-        #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
-        core::arch::asm!(
-            "la   {tmp}, {sym}",       // 用占位符 {tmp}
-            "sd   {val}, 0({tmp})",    // 存 val 到 *tmp
-            tmp = out(reg) _,
-            val = in(reg) val,
-            sym = sym __PERCPU_CURRENT_TASK_PTR,
-        );
-        
-        #[cfg(target_arch = "loongarch64")]
-        core::arch::asm!(
-            "la.global {tmp}, {sym}",
-            "st.d {val}, {tmp}, 0",    // LoongArch64 语法：st.d src, base, offset
-            tmp = out(reg) _,
-            val = in(reg) val,
-            sym = sym __PERCPU_CURRENT_TASK_PTR,
-        );
-    }
-
-    /// Returns the value of the per-CPU data on the current CPU. Preemption will
-    /// be disabled during the call.
-    pub fn read_current(&self) -> usize {
-        unsafe { self.read_current_raw() }
-    }
-
-    /// Set the value of the per-CPU data on the current CPU.
-    /// Preemption will be disabled during the call.
-    pub fn write_current(&self, val: usize) {
-        unsafe { self.write_current_raw(val) }
-    }
-}
+/// 每个核上当前运行的任务
+#[polyhal::percpu]
+static CURRENT_TASK_PTR: usize = 0;
 
 /// Gets the pointer to the current task with preemption-safety.
 ///
@@ -214,7 +69,7 @@ pub fn current_task_ptr<T>() -> *const T {
     unsafe {
         // on RISC-V, reading `CURRENT_TASK_PTR` requires multiple instruction, so we disable local IRQs.
         let flags = local_irq_save_and_disable();
-        let ans = CURRENT_TASK_PTR.read_current_raw();
+        let ans = *CURRENT_TASK_PTR;
         local_irq_restore(flags);
         ans as _
     }
@@ -230,7 +85,7 @@ pub fn current_task_ptr<T>() -> *const T {
 #[inline]
 pub unsafe fn set_current_task_ptr<T>(ptr: *const T) {
     let flags = local_irq_save_and_disable();
-    CURRENT_TASK_PTR.write_current_raw(ptr as usize);
+    CURRENT_TASK_PTR.write(ptr as usize);
     local_irq_restore(flags)
 }
 
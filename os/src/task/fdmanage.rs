@@ -3,6 +3,7 @@ use core::sync::atomic::AtomicUsize;
 use alloc::{sync::Arc, vec};
 use alloc::vec:: Vec;
 
+use crate::fs::lock::release_posix_locks;
 use crate::fs::{Stdin, Stdout};
 use crate::utils::error::{SyscallRet, TemplateRet};
 use crate::{config::MAX_FD_NUM, fs::{FileClass, FileDescriptor, OpenFlags}, utils::error::SysErrNo};
@@ -200,13 +201,20 @@ impl FdManage {
         Err(SysErrNo::EMFILE)
     }
  
-    pub fn close_on_exec(&mut self) {
-        let fd_table = &mut self.table;
-        for idx in 0..fd_table.len() {
-            if let Some(fd) = &fd_table[idx] {
-                if fd.cloexec() {
-                    fd_table[idx] = None;
-                }
+    /// 关闭 `fd` 并返回原来的文件句柄。POSIX 记录锁属于进程，
+    /// 关闭文件的任意一个 fd 都会释放进程 `pid` 在这个文件上的锁
+    pub fn close_fd(&mut self, fd: usize, pid: usize) -> Option<FileDescriptor> {
+        let removed = self.take_file(fd)?;
+        if let FileClass::File(file) = &removed.file {
+            release_posix_locks(file, pid);
+        }
+        Some(removed)
+    }
+
+    pub fn close_on_exec(&mut self, pid: usize) {
+        for idx in 0..self.table.len() {
+            if self.table[idx].as_ref().is_some_and(|fd| fd.cloexec()) {
+                self.close_fd(idx, pid);
             }
         }
     }
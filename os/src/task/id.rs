@@ -3,7 +3,7 @@
 //! Assign PID to the process here. At the same time, the position of the application KernelStack
 //! is determined according to the PID.
 
-use crate::sync::UPSafeCell;
+use spin::Mutex;
 use alloc::vec::Vec;
 use lazy_static::*;
 
@@ -39,13 +39,14 @@ impl RecycleAllocator {
 }
 
 lazy_static! {
-    static ref PID_ALLOCATOR: UPSafeCell<RecycleAllocator> =
-        unsafe { UPSafeCell::new(RecycleAllocator{
+    // 多核下可能被并发访问，不能再用 UPSafeCell
+    static ref PID_ALLOCATOR: Mutex<RecycleAllocator> =
+        Mutex::new(RecycleAllocator{
             current:1,
             recycled:Vec::new(),
-        }) };
-    static ref KSTACK_ALLOCATOR: UPSafeCell<RecycleAllocator> =
-        unsafe { UPSafeCell::new(RecycleAllocator::new()) };
+        });
+    static ref KSTACK_ALLOCATOR: Mutex<RecycleAllocator> =
+        Mutex::new(RecycleAllocator::new());
 }
 /// Abstract structure of PID
 pub struct PidHandle(pub usize);
@@ -53,13 +54,13 @@ pub struct PidHandle(pub usize);
 impl Drop for PidHandle {
     fn drop(&mut self) {
         // println!("drop pid {}", self.0);
-        PID_ALLOCATOR.exclusive_access().dealloc(self.0);
+        PID_ALLOCATOR.lock().dealloc(self.0);
     }
 }
 
 /// Allocate a new PID
 pub fn pid_alloc() -> PidHandle {
-    PidHandle(PID_ALLOCATOR.exclusive_access().alloc())
+    PidHandle(PID_ALLOCATOR.lock().alloc())
 }
 
 // /// Return (bottom, top) of a kernel stack in kernel space.
@@ -6,6 +6,7 @@ use spin::mutex::SpinMutex;
 use crate::{
     config::{self, PAGE_SIZE},
     mm::VirtAddr,
+    smp::cpu_id,
 };
 
 pub struct TaskStack {
@@ -70,6 +71,10 @@ pub fn init() {
     stack_pool.init();
     STACK_POOL.init_by(SpinMutex::new(stack_pool));
 }
+/// 为从核分配调度循环使用的内核栈
+pub fn init_secondary() {
+    STACK_POOL.lock().init();
+}
 #[allow(dead_code)]
 pub fn pick_current_stack() -> TaskStack {
     let mut stack_pool = STACK_POOL.lock();
@@ -103,7 +108,8 @@ pub fn _alloc_current_stack() {
 #[allow(dead_code)]
 pub(crate) struct StackPool {
     free_stacks: Vec<TaskStack>,
-    current: Option<TaskStack>,
+    /// 每个核当前使用的栈
+    current: [Option<TaskStack>; config::MAX_CPUS],
 }
 
 impl StackPool {
@@ -111,12 +117,12 @@ impl StackPool {
     pub const fn new() -> Self {
         Self {
             free_stacks: Vec::new(),
-            current: None,
+            current: [const { None }; config::MAX_CPUS],
         }
     }
 
     pub fn init(&mut self) {
-        self.current = Some(TaskStack::alloc(config::TASK_STACK_SIZE));
+        self.current[cpu_id()] = Some(TaskStack::alloc(config::TASK_STACK_SIZE));
     }
 
     /// Alloc a free stack from the pool.
@@ -129,17 +135,15 @@ impl StackPool {
 
     pub fn pick_current_stack(&mut self) -> TaskStack {
         let new_stack = self.alloc();
-        self.current.replace(new_stack).unwrap()
+        self.current[cpu_id()].replace(new_stack).unwrap()
     }
 
     pub fn current_stack(&self) -> &TaskStack {
-        assert!(self.current.is_some());
-        self.current.as_ref().unwrap()
+        self.current[cpu_id()].as_ref().expect("kernel stack of this cpu is not initialized")
     }
 
     pub fn put_prev_stack(&mut self, kstack: TaskStack) {
-        assert!(self.current.is_some());
-        let curr_stack = self.current.replace(kstack).unwrap();
+        let curr_stack = self.current[cpu_id()].replace(kstack).unwrap();
         self.free_stacks.push(curr_stack);
     }
 }
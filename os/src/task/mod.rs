@@ -49,8 +49,8 @@ pub use flags::{CloneFlags,TaskStatus};
 
 pub use id::{pid_alloc, PidHandle, RecycleAllocator};
 pub use kstack::{TaskStack,current_stack_top};
-pub use processor::{init, run_task2};
-pub use schedule::{add_task, pick_next_task, put_prev_task, set_priority, task_tick,Task,TaskRef};
+pub use processor::{init, init_secondary, run_task2};
+pub use schedule::{add_task, load_balance, local_queue_empty, pick_next_task, put_prev_task, set_priority, task_tick,Task,TaskRef};
 pub use task::ProcessControlBlock;
 pub use future::yield_now;
 pub use waker::custom_noop_waker;
@@ -59,6 +59,7 @@ pub use task::RobustList;
 // pub use manager::get_task_count;
 use crate::fs::{open_file, OpenFlags};
 use crate::mm::{get_target_ref, put_data};
+use crate::signal::kill_orphaned_pgrp;
 use crate::sync::futex::GLOBAL_FUTEX_SYSTEM;
 use crate::syscall::flags::{FUTEX_OWNER_DIED, FUTEX_TID_MASK, FUTEX_WAITERS};
 use crate::task::task::TaskControlBlock;
@@ -121,18 +122,40 @@ pub async fn exit_proc(exit_code: i32) {
     
     // --- 第 2 步：为子进程重新指定父进程 (reparenting) ---
     // 只有非 init 进程需要 reparent
+    let mut orphans = Vec::new();
     if pid != INITPROC.get_pid() {
         let mut children = process.children.lock().await;
         let mut init_children = INITPROC.children.lock().await;
         for child in children.drain(..) { // 使用 drain 高效移动元素
             child.set_parent(INITPROC.pid.0);
+            orphans.push(child.clone());
             init_children.push(child);
         }
     }
 
+    // --- 第 2.5 步：作业控制 ---
+    // 本进程退出可能让自己所在的进程组或子进程所在的进程组成为孤儿进程组
+    let (pgid, sid) = (process.pgid(), process.sid());
+    let parent = PID2PC.lock().get(&process.parent()).cloned();
+    if let Some(parent) = parent {
+        if parent.pgid() != pgid && parent.sid() == sid {
+            kill_orphaned_pgrp(pgid, pid).await;
+        }
+    }
+    for child in orphans {
+        if child.pgid() != pgid && child.sid() == sid {
+            kill_orphaned_pgrp(child.pgid(), pid).await;
+        }
+    }
+    // 会话首进程退出时挂断控制终端
+    if sid == pid {
+        crate::fs::tty::hangup_session(sid).await;
+    }
+
     // --- 第 3 步：回收进程级资源 ---
     process.memory_set.lock().await.recycle_data_pages().await.unwrap();
     process.fd_table.lock().await.table.clear();
+    crate::fs::lock::release_owner(crate::fs::lock::LockOwner::Process(pid));
 
     // --- 第 4 步：从全局数据结构中移除所有线程 ---
     {
@@ -7,7 +7,7 @@
 use super::current::CurrentTask;
 use super::task::TaskControlBlock;
 use super::{schedule, TaskStatus};
-use crate::config::PAGE_SIZE_BITS;
+use crate::config::{MAX_CPUS, PAGE_SIZE_BITS};
 use crate::mm::activate_by_token;
 use crate::sbi::shutdown;
 use crate::sync::futex::init_futex_system;
@@ -18,6 +18,7 @@ use crate::trap::{disable_irqs, enable_irqs, user_return, TrapContext, TrapStatu
 use alloc::boxed::Box;
 use alloc::string::ToString;
 use alloc::sync::Arc;
+use alloc::vec::Vec;
 use core::future::Future;
 use core::panic;
 use core::pin::Pin;
@@ -27,7 +28,8 @@ use schedule::CFScheduler;
 use spin::mutex::Mutex as Spin;
 /// Processor management structure
 
-pub static KERNEL_SCHEDULER: LazyInit<Arc<Spin<CFScheduler<TaskControlBlock>>>> = LazyInit::new();
+/// 每个核一个就绪队列，按核编号索引
+pub static RUN_QUEUES: LazyInit<Vec<Spin<CFScheduler<TaskControlBlock>>>> = LazyInit::new();
 pub static UTRAP_HANDLER: LazyInit<fn() -> Pin<Box<dyn Future<Output = i32> + 'static>>> =
     LazyInit::new();
 
@@ -58,6 +60,8 @@ pub fn run_task2(mut curr: CurrentTask) {
                     "count {}",
                     Arc::strong_count(curr.as_task_ref())
                 );
+                // 关机前把块缓存中的脏块写回磁盘
+                crate::drivers::bcache::flush_all();
                 shutdown();
             }
             CurrentTask::clean_current();
@@ -92,6 +96,7 @@ pub fn run_task2(mut curr: CurrentTask) {
                             //                             );
                             enable_irqs();
                     trace!("[user_return]  result:{:#x} sepc:{:#x}", tf.regs.a0,tf.sepc);
+                            crate::smp::enter_user();
                             user_return(tf);
                         }
                     }
@@ -144,8 +149,8 @@ pub fn init(utrap_handler: fn() -> Pin<Box<dyn Future<Output = i32> + 'static>>)
     // kstack::alloc_current_stack();
     
     UTRAP_HANDLER.init_by(utrap_handler);
-    let scheduler = CFScheduler::new();
-    KERNEL_SCHEDULER.init_by(Arc::new(Spin::new(scheduler)));
+    RUN_QUEUES.init_by((0..MAX_CPUS).map(|_| Spin::new(CFScheduler::new())).collect());
+    crate::smp::set_online(crate::smp::cpu_id());
     // let task = Arc::new(CFSTask::new(TaskControlBlock::new(
     //     false,
     //     1,
@@ -169,3 +174,9 @@ pub fn init(utrap_handler: fn() -> Pin<Box<dyn Future<Output = i32> + 'static>>)
     init_sleeper_queue();
     init_futex_system();
 }
+
+/// 从核的初始化：分配本核调度循环使用的内核栈并开始参与调度
+pub fn init_secondary() {
+    kstack::init_secondary();
+    crate::smp::set_online(crate::smp::cpu_id());
+}
@@ -1,9 +1,10 @@
 use alloc::{collections::BTreeMap, sync::Arc};
 use core::ops::Deref;
-use core::sync::atomic::{AtomicIsize, Ordering};
+use core::sync::atomic::{AtomicIsize, AtomicUsize, Ordering};
 
-use super::processor::KERNEL_SCHEDULER;
+use super::processor::RUN_QUEUES;
 use super::task::TaskControlBlock;
+use crate::smp::{self, cpu_id};
 
 pub type TaskRef  = Arc<Task>;
 pub type Task = CFSTask<TaskControlBlock>;
@@ -14,6 +15,10 @@ pub struct CFSTask<T> {
     delta: AtomicIsize,
     nice: AtomicIsize,
     id: AtomicIsize,
+    /// 上一次运行所在的核
+    cpu: AtomicUsize,
+    /// 允许运行的核（sched_setaffinity）
+    affinity: AtomicUsize,
 }
 
 // https://elixir.bootlin.com/linux/latest/source/include/linux/sched/prio.h
@@ -47,9 +52,29 @@ impl<T> CFSTask<T> {
             delta: AtomicIsize::new(0_isize),
             nice: AtomicIsize::new(0_isize),
             id: AtomicIsize::new(0_isize),
+            cpu: AtomicUsize::new(0),
+            affinity: AtomicUsize::new(usize::MAX),
         }
     }
 
+    pub fn cpu(&self) -> usize {
+        self.cpu.load(Ordering::Acquire)
+    }
+
+    fn set_cpu(&self, cpu: usize) {
+        self.cpu.store(cpu, Ordering::Release);
+    }
+
+    /// 允许运行的核的掩码
+    pub fn affinity(&self) -> usize {
+        self.affinity.load(Ordering::Acquire)
+    }
+
+    /// 修改亲和性，已在其他核就绪队列中的任务在被取出时迁移
+    pub fn set_affinity(&self, mask: usize) {
+        self.affinity.store(mask, Ordering::Release);
+    }
+
     fn get_weight(&self) -> isize {
         let nice = self.nice.load(Ordering::Acquire);
         if nice >= 0 {
@@ -148,6 +173,22 @@ impl<T> CFScheduler<T> {
         }
     }
 
+    fn len(&self) -> usize {
+        self.ready_queue.len()
+    }
+
+    /// 取出 vruntime 最小的满足 `pred` 的任务，用于任务窃取和迁移
+    fn take_first(&mut self, pred: impl Fn(&SchedItem<T>) -> bool) -> Option<SchedItem<T>> {
+        let key = *self.ready_queue.iter().find(|(_, task)| pred(task))?.0;
+        let task = self.ready_queue.remove(&key);
+        if let Some(((min_vruntime, _), _)) = self.ready_queue.first_key_value() {
+            self.min_vruntime = Some(AtomicIsize::new(*min_vruntime));
+        } else {
+            self.min_vruntime = None;
+        }
+        task
+    }
+
     fn remove_task(&mut self, task: &SchedItem<T>) -> Option<SchedItem<T>> {
         if let Some((_, tmp)) = self
             .ready_queue
@@ -187,7 +228,12 @@ impl<T> CFScheduler<T> {
 
     fn set_priority(&mut self, task: &SchedItem<T>, prio: isize) -> bool {
         if (-20..=19).contains(&prio) {
+            // 就绪队列以 vruntime 为键，在队列中的任务要先取出再按新的 vruntime 放回
+            let queued = self.remove_task(task);
             task.set_priority(prio);
+            if let Some(task) = queued {
+                self.put_prev_task(task, false);
+            }
             true
         } else {
             false
@@ -195,26 +241,148 @@ impl<T> CFScheduler<T> {
     }
 }
 
+/// 任务可以运行的核：亲和性与在线核的交集，为空时退回所有在线核
+fn allowed_cpus(task: &SchedItem<TaskControlBlock>) -> usize {
+    let online = smp::online_mask() | (1 << cpu_id());
+    match task.affinity() & online {
+        0 => online,
+        mask => mask,
+    }
+}
+
+fn allowed_on(task: &SchedItem<TaskControlBlock>, cpu: usize) -> bool {
+    allowed_cpus(task) & (1 << cpu) != 0
+}
+
+/// 核的负载：就绪任务数加上正在运行的任务
+fn cpu_load(cpu: usize) -> usize {
+    RUN_QUEUES[cpu].lock().len() + !smp::is_idle(cpu) as usize
+}
+
+/// 为就绪的任务选择核：在允许的核中取负载最小者，相同时优先上次运行的核
+fn select_cpu(task: &SchedItem<TaskControlBlock>) -> usize {
+    let allowed = allowed_cpus(task);
+    let prev = task.cpu();
+    let mut best = if allowed & (1 << prev) != 0 {
+        prev
+    } else {
+        allowed.trailing_zeros() as usize
+    };
+    let mut best_load = cpu_load(best);
+    let mut mask = allowed & !(1 << best);
+    while mask != 0 && best_load > 0 {
+        let cpu = mask.trailing_zeros() as usize;
+        mask &= mask - 1;
+        let load = cpu_load(cpu);
+        if load < best_load {
+            best = cpu;
+            best_load = load;
+        }
+    }
+    best
+}
+
+/// 就绪队列最长的其他核
+fn busiest_cpu(this: usize) -> Option<(usize, usize)> {
+    let mut mask = smp::online_mask() & !(1 << this);
+    let mut busiest = None;
+    while mask != 0 {
+        let cpu = mask.trailing_zeros() as usize;
+        mask &= mask - 1;
+        let len = RUN_QUEUES[cpu].lock().len();
+        if len > 0 && busiest.map_or(true, |(_, max)| len > max) {
+            busiest = Some((cpu, len));
+        }
+    }
+    busiest
+}
+
+/// 本核无事可做时，从最忙的核窃取一个可以在本核运行的任务
+fn steal_task(this: usize) -> Option<SchedItem<TaskControlBlock>> {
+    let (victim, _) = busiest_cpu(this)?;
+    let task = RUN_QUEUES[victim]
+        .lock()
+        .take_first(|task| allowed_on(task, this))?;
+    task.set_cpu(this);
+    Some(task)
+}
+
+/// 周期性负载均衡：最忙的核比本核多出两个以上就绪任务时，拉取一个过来
+pub fn load_balance() {
+    let this = cpu_id();
+    let Some((victim, len)) = busiest_cpu(this) else {
+        return;
+    };
+    if len <= RUN_QUEUES[this].lock().len() + 1 {
+        return;
+    }
+    let task = RUN_QUEUES[victim]
+        .lock()
+        .take_first(|task| allowed_on(task, this));
+    if let Some(task) = task {
+        task.set_cpu(this);
+        RUN_QUEUES[this].lock().add_task(task);
+    }
+}
+
+/// 让出 CPU 的任务：仍允许在本核运行时放回本核队列，否则迁移到其他核
 pub fn put_prev_task(prev: SchedItem<TaskControlBlock>) {
-    KERNEL_SCHEDULER.lock().put_prev_task(prev, false);
+    let this = cpu_id();
+    if allowed_on(&prev, this) {
+        RUN_QUEUES[this].lock().put_prev_task(prev, false);
+    } else {
+        add_task(prev);
+    }
 }
 
+/// 新建或被唤醒的任务
 pub fn add_task(task: SchedItem<TaskControlBlock>) {
-    KERNEL_SCHEDULER.lock().add_task(task);
+    let cpu = select_cpu(&task);
+    task.set_cpu(cpu);
+    RUN_QUEUES[cpu].lock().add_task(task);
+    smp::kick(cpu);
 }
 
 pub fn remove_task(task: &SchedItem<TaskControlBlock>) -> Option<SchedItem<TaskControlBlock>> {
-    KERNEL_SCHEDULER.lock().remove_task(task)
+    if let Some(task) = RUN_QUEUES[task.cpu()].lock().remove_task(task) {
+        return Some(task);
+    }
+    RUN_QUEUES.iter().find_map(|queue| queue.lock().remove_task(task))
 }
 
 pub fn pick_next_task() -> Option<SchedItem<TaskControlBlock>> {
-    KERNEL_SCHEDULER.lock().pick_next_task()
+    let this = cpu_id();
+    loop {
+        let task = RUN_QUEUES[this].lock().pick_next_task();
+        match task {
+            // 亲和性在排队期间被修改，转到允许的核上
+            Some(task) if !allowed_on(&task, this) => add_task(task),
+            Some(task) => {
+                task.set_cpu(this);
+                return Some(task);
+            }
+            None => return steal_task(this),
+        }
+    }
+}
+
+/// 本核就绪队列是否为空
+pub fn local_queue_empty() -> bool {
+    RUN_QUEUES[cpu_id()].lock().len() == 0
 }
 
 pub fn task_tick(current: &SchedItem<TaskControlBlock>) -> bool {
-    KERNEL_SCHEDULER.lock().task_tick(current)
+    RUN_QUEUES[cpu_id()].lock().task_tick(current)
 }
 
+/// 任务可能在其他核的就绪队列中，持有它所在核的队列锁修改
 pub fn set_priority(task: &SchedItem<TaskControlBlock>, prio: isize) -> bool {
-    KERNEL_SCHEDULER.lock().set_priority(task, prio)
+    loop {
+        let cpu = task.cpu();
+        let mut queue = RUN_QUEUES[cpu].lock();
+        // 取锁期间任务可能被迁移到了别的核
+        if task.cpu() == cpu {
+            return queue.set_priority(task, prio);
+        }
+    }
 }
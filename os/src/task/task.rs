@@ -9,7 +9,7 @@ use crate::config::{PAGE_SIZE, PAGE_SIZE_BITS, USER_STACK_SIZE, USER_STACK_TOP};
 use crate::fs::inode::NONE_MODE;
 use crate::fs::{find_inode, open_file, FileClass, FileDescriptor, OpenFlags, Stdin, Stdout};
 use crate::mm::{
-    activate_by_token, flush_all, get_target_ref, put_data, translated_refmut, MapArea, MapAreaType, MapPermission, MemorySet, VirtAddr, VirtPageNum, KERNEL_PAGE_TABLE_TOKEN
+    activate_by_token, flush_all, get_target_ref, put_data, translated_refmut, MapArea, MapAreaType, MapPermission, MemorySet, PageFaultError, VirtAddr, VirtPageNum, KERNEL_PAGE_TABLE_TOKEN
 };
 use crate::signal::{ProcessSignalSharedState, TaskSignalState};
 use crate::sync::futex::GLOBAL_FUTEX_SYSTEM;
@@ -20,7 +20,7 @@ use crate::task::processor::UTRAP_HANDLER;
 use crate::task::schedule::CFSTask;
 use crate::task::waker::waker_from_task;
 use crate::task::{add_task, current_task, exit_robust_list_cleanup, Task, PID2PC, TID2TC};
-use crate::timer::{KernelTimer, TimeData, Tms, UserTimeSpec};
+use crate::timer::{get_time_ms, KernelTimer, TimeData, Tms, UserTimeSpec};
 use crate::trap::{TrapContext, TrapStatus};
 use crate::utils::error::{GeneralRet, SysErrNo, SyscallRet, TemplateRet};
 use crate::utils::string::{get_parent_path_and_filename, normalize_absolute_path};
@@ -37,7 +37,7 @@ use core::mem::ManuallyDrop;
 use core::ops::Deref;
 use core::pin::Pin;
 use core::sync::atomic::{AtomicBool, AtomicI32, AtomicIsize, AtomicUsize, Ordering};
-use core::task::Waker;
+use core::task::{Context, Poll, Waker};
 // use spin::mutex::Mutex;
 
 use crate::sync::Mutex;
@@ -73,9 +73,32 @@ pub struct ProcessControlBlock {
     /// current work p
     pub cwd: Mutex<String>,
     pub exe: Mutex<String>,
+    /// exec 时的参数，供 /proc/<pid>/cmdline 使用
+    pub cmdline: Spin<Vec<String>>,
+    /// 进程创建时间（毫秒）
+    pub start_time: usize,
     /// Parent process of the current process.
     /// Weak will not affect the reference count of the parent
     parent: AtomicUsize,
+    /// 进程组号
+    pgid: AtomicUsize,
+    /// 会话号
+    sid: AtomicUsize,
+    /// 是否被作业控制信号停止
+    stopped: AtomicBool,
+    /// 尚未被 wait 取走的停止/继续事件，按 wstatus 编码，0 表示没有
+    job_event: AtomicUsize,
+    /// 停止期间等待 SIGCONT 的线程
+    stop_wakers: Spin<VecDeque<Waker>>,
+    /// 子进程停止/继续的次数，wait 用它判断等待期间是否有新事件
+    child_event_seq: AtomicUsize,
+    /// 等待子进程停止/继续的父进程线程
+    child_wakers: Spin<VecDeque<Waker>>,
+    /// 正在执行系统调用的线程数。系统调用可能持有指向用户页的内核切片，
+    /// 此时不能换出该进程的页
+    in_syscall: AtomicUsize,
+    /// 已回收子进程常驻页数峰值中的最大值，供 getrusage(RUSAGE_CHILDREN) 使用
+    children_maxrss: AtomicUsize,
     /// It is set when active exit or execution error occurs
     exit_code: AtomicI32,
     /// Heap bottom
@@ -184,6 +207,131 @@ impl ProcessControlBlock {
         self.parent.store(p, Ordering::Release)
     }
 
+    /// 获取进程组号。
+    pub fn pgid(&self) -> usize {
+        self.pgid.load(Ordering::Acquire)
+    }
+
+    /// 设置进程组号。
+    pub fn set_pgid(&self, pgid: usize) {
+        self.pgid.store(pgid, Ordering::Release)
+    }
+
+    /// 获取会话号。
+    pub fn sid(&self) -> usize {
+        self.sid.load(Ordering::Acquire)
+    }
+
+    /// 成为新会话和新进程组的首进程。
+    pub fn set_sid(&self) {
+        let pid = self.pid.0;
+        self.sid.store(pid, Ordering::Release);
+        self.pgid.store(pid, Ordering::Release);
+    }
+
+    /// 线程进入/离开系统调用
+    pub fn enter_syscall(&self) {
+        self.in_syscall.fetch_add(1, Ordering::SeqCst);
+    }
+
+    pub fn leave_syscall(&self) {
+        self.in_syscall.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    /// 是否有线程正在执行系统调用
+    pub fn in_syscall(&self) -> bool {
+        self.in_syscall.load(Ordering::SeqCst) != 0
+    }
+    /// 已回收子进程（及其后代）常驻页数的峰值
+    pub fn children_maxrss(&self) -> usize {
+        self.children_maxrss.load(Ordering::Relaxed)
+    }
+    /// 回收子进程时记录它的常驻页数峰值
+    pub async fn account_reaped_child(&self, child: &ProcessControlBlock) {
+        let peak = child
+            .memory_set
+            .lock()
+            .await
+            .peak_resident_pages()
+            .max(child.children_maxrss());
+        self.children_maxrss.fetch_max(peak, Ordering::Relaxed);
+    }
+
+    /// 是否处于作业控制的停止状态。
+    pub fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::Acquire)
+    }
+
+    /// 进入停止状态，记录供 wait 报告的停止事件。已经停止时返回 false。
+    pub fn stop(&self, sig: usize) -> bool {
+        if self.stopped.swap(true, Ordering::AcqRel) {
+            return false;
+        }
+        self.job_event.store((sig << 8) | 0x7f, Ordering::Release);
+        true
+    }
+
+    /// 离开停止状态并唤醒停止的线程；`report` 为 true 时记录供 wait 报告的继续事件。
+    /// 原本没有停止时返回 false。
+    pub fn resume(&self, report: bool) -> bool {
+        if !self.stopped.swap(false, Ordering::AcqRel) {
+            return false;
+        }
+        if report {
+            self.job_event.store(0xffff, Ordering::Release);
+        }
+        let wakers = core::mem::take(&mut *self.stop_wakers.lock());
+        for waker in wakers {
+            waker.wake();
+        }
+        true
+    }
+
+    /// 取走 wait 可以报告的停止/继续事件
+    ///
+    /// `stopped`/`continued` 对应 WUNTRACED 和 WCONTINUED。
+    pub fn take_job_event(&self, stopped: bool, continued: bool) -> Option<i32> {
+        let event = self.job_event.load(Ordering::Acquire);
+        let wanted = match event {
+            0 => false,
+            0xffff => continued,
+            _ => stopped,
+        };
+        if wanted && self
+            .job_event
+            .compare_exchange(event, 0, Ordering::AcqRel, Ordering::Acquire)
+            .is_ok()
+        {
+            Some(event as i32)
+        } else {
+            None
+        }
+    }
+
+    /// 停止期间挂起当前线程，直到收到 SIGCONT 或 SIGKILL
+    pub async fn wait_until_resumed(&self) {
+        StopFuture { pcb: self }.await
+    }
+
+    /// 子进程事件计数，与 [`Self::wait_child_event`] 配合使用
+    pub fn child_event_seq(&self) -> usize {
+        self.child_event_seq.load(Ordering::Acquire)
+    }
+
+    /// 子进程停止或继续，唤醒在 wait 中等待的线程
+    pub fn notify_child_event(&self) {
+        self.child_event_seq.fetch_add(1, Ordering::AcqRel);
+        let wakers = core::mem::take(&mut *self.child_wakers.lock());
+        for waker in wakers {
+            waker.wake();
+        }
+    }
+
+    /// 等待子进程事件计数离开 `seq`
+    pub fn wait_child_event(&self, seq: usize) -> ChildEventFuture<'_> {
+        ChildEventFuture { pcb: self, seq }
+    }
+
     /// 获取退出码。
     pub fn exit_code(&self) -> i32 {
         self.exit_code.load(Ordering::Acquire)
@@ -338,16 +486,19 @@ impl ProcessControlBlock {
             MapAreaType::Stack) ;
         self.set_user_stack_top(end_va.0);
     
-        let old_memory=another.memory_set.lock().await;
+        let mut old_memory=another.memory_set.lock().await;
+        old_memory.adopt_swapped();
         let old_area=old_memory.areatree.get(&VirtPageNum::from(
             (another.user_stack_top() - USER_STACK_SIZE)>>PAGE_SIZE_BITS
     
     )).unwrap();
-        self.memory_set.lock().await.push_with_given_frames(new_area, &old_area.data_frames,true);
+        let mut new_memory = self.memory_set.lock().await;
+        new_memory.push_with_given_frames(new_area, &old_area.data_frames,true);
+        old_memory.page_table.share_swapped(&new_memory.page_table, old_area.start_vpn(), old_area.end_vpn());
+        drop(new_memory);
         // 对每对 (vpn, frame) 做映射并记录
         for (vpn, _) in old_area.data_frames.iter(){
-        let   pte= old_memory.page_table.find_pte(*vpn).unwrap();
-          pte.set_cow();
+          old_memory.page_table.set_cow(*vpn);
       }
         
     }
@@ -357,8 +508,9 @@ impl ProcessControlBlock {
     /// # Arguments
     ///
     /// * `new_ms`: 新的 `MemorySet`。
-    pub async fn replace_memory_set(&self, new_ms: MemorySet) {
+    pub async fn replace_memory_set(&self, mut new_ms: MemorySet) {
         let mut guard = self.memory_set.lock().await;
+        new_ms.inherit_from(&guard);
         let old_ms = core::mem::replace(&mut *guard, new_ms);
         drop(old_ms);
         let new_token = guard.token();
@@ -490,6 +642,15 @@ impl ProcessControlBlock {
             base_size: AtomicUsize::new(user_sp),
             cwd: Mutex::new(cwd),
             parent: AtomicUsize::new(1),
+            pgid: AtomicUsize::new(process_id),
+            sid: AtomicUsize::new(process_id),
+            stopped: AtomicBool::new(false),
+            job_event: AtomicUsize::new(0),
+            stop_wakers: Spin::new(VecDeque::new()),
+            child_event_seq: AtomicUsize::new(0),
+            child_wakers: Spin::new(VecDeque::new()),
+            in_syscall: AtomicUsize::new(0),
+            children_maxrss: AtomicUsize::new(0),
             children: Mutex::new(Vec::new()),
             exit_code: AtomicI32::new(1),
             heap_bottom: AtomicUsize::new(user_sp),
@@ -499,6 +660,8 @@ impl ProcessControlBlock {
             tasks: Mutex::new(vec![new_task.clone()]),
             fd_table: Arc::new(Mutex::new(FdManage::new_with_stdio())),
             exe: Mutex::new(exe),
+            cmdline: Spin::new(argv.clone()),
+            start_time: get_time_ms(),
             signal_shared_state: Arc::new(Mutex::new(ProcessSignalSharedState::default())),
             state: Mutex::new(TaskStatus::Runnable),
             wakers: Mutex::new({
@@ -719,7 +882,8 @@ impl ProcessControlBlock {
         //将设置了O_CLOEXEC位的文件描述符关闭 todo(heliosly)
         // update trap_cx ppn
         info!("exec entry_point:{:#x} sp:{:#x}", entry_point, user_sp);
-        self.fd_table.lock().await.close_on_exec();
+        self.fd_table.lock().await.close_on_exec(self.get_pid());
+        *self.cmdline.lock() = argv.clone();
         let binding = self.main_task.lock().await;
         let trap_cx: &mut TrapContext = binding.get_trap_cx().unwrap();
         *trap_cx = TrapContext::app_init_context(entry_point, user_sp);
@@ -811,6 +975,8 @@ impl ProcessControlBlock {
             child_tid,
             need_clear_tid,
         )));
+        // 子任务继承 CPU 亲和性
+        tcb.set_affinity(current_task().affinity());
         if flags.contains(CloneFlags::CLONE_PARENT_SETTID) {
             self.manual_alloc_type_for_lazy(ptid as *const u32).await?;
             let parent_token = self.memory_set.lock().await.token();
@@ -847,6 +1013,15 @@ impl ProcessControlBlock {
                 base_size: AtomicUsize::new(self.base_size()),
                 memory_set: memory_set,
                 parent: AtomicUsize::new(parent),
+                pgid: AtomicUsize::new(self.pgid()),
+                sid: AtomicUsize::new(self.sid()),
+                stopped: AtomicBool::new(false),
+                job_event: AtomicUsize::new(0),
+                stop_wakers: Spin::new(VecDeque::new()),
+                child_event_seq: AtomicUsize::new(0),
+                child_wakers: Spin::new(VecDeque::new()),
+                in_syscall: AtomicUsize::new(0),
+                children_maxrss: AtomicUsize::new(0),
                 children: Mutex::new(Vec::new()),
                 exit_code: AtomicI32::new(0),
                 fd_table: Arc::new(Mutex::new(FdManage::from_another(
@@ -859,6 +1034,8 @@ impl ProcessControlBlock {
                 signal_shared_state: new_proc_sig_state,
                 tasks: Mutex::new(Vec::new()),
                 exe: Mutex::new(self.exe.lock().await.clone()),
+                cmdline: Spin::new(self.cmdline.lock().clone()),
+                start_time: get_time_ms(),
                 state: Mutex::new(TaskStatus::Runnable),
                 
                 wakers: Mutex::new({
@@ -948,10 +1125,10 @@ impl ProcessControlBlock {
                 .await
                 .shrink_to(VirtAddr(heap_bottom), VirtAddr(new_brk))
         } else {
-            self.memory_set
-                .lock()
-                .await
-                .append_to(VirtAddr(heap_bottom), VirtAddr(new_brk))
+            let mut ms = self.memory_set.lock().await;
+            // 堆的增长受 RLIMIT_AS 和 RLIMIT_DATA 限制
+            ms.may_grow(size as usize, true)
+                && ms.append_to(VirtAddr(heap_bottom), VirtAddr(new_brk))
         };
         if result {
             self.set_program_brk(new_brk);
@@ -1051,23 +1228,29 @@ impl ProcessControlBlock {
     }
     /// alloc range physical memory for lazy allocation manually
     pub async fn manual_alloc_range_for_lazy(&self, start: VirtAddr, end: VirtAddr) -> GeneralRet {
-        self.memory_set
-            .lock()
-            .await
-            .manual_alloc_range_for_lazy(start, end)
-            .await?;
-        Ok(())
+        loop {
+            let res = self
+                .memory_set
+                .lock()
+                .await
+                .manual_alloc_range_for_lazy(start, end)
+                .await;
+            match res {
+                // 内存不足时换出或杀掉其他进程腾出内存后重试
+                Err(PageFaultError::OutOfMemory) if crate::mm::oom::reclaim_or_kill().await => {}
+                res => return res.map_err(SysErrNo::from),
+            }
+        }
     }
 
     /// alloc physical memory with the given type size for lazy allocation manually
     /// 需要保证传入的不是空指针哦
     pub async fn manual_alloc_type_for_lazy<T: Sized>(&self, obj: *const T) -> GeneralRet {
-        self.memory_set
-            .lock()
-            .await
-            .manual_alloc_type_for_lazy(obj)
-            .await?;
-        Ok(())
+        let start = obj as usize;
+        let end = start
+            .checked_add(core::mem::size_of::<T>() - 1)
+            .ok_or(SysErrNo::EINVAL)?;
+        self.manual_alloc_range_for_lazy(start.into(), end.into()).await
     }
 
     pub async fn join_proc(&self,waker:Waker){
@@ -1082,6 +1265,46 @@ impl ProcessControlBlock {
     }
 }
 
+/// 停止状态下等待 SIGCONT
+struct StopFuture<'a> {
+    pcb: &'a ProcessControlBlock,
+}
+
+impl Future for StopFuture<'_> {
+    type Output = ();
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut wakers = self.pcb.stop_wakers.lock();
+        // 持锁检查：resume 先清标志再取走等待者
+        if !self.pcb.is_stopped() {
+            return Poll::Ready(());
+        }
+        wakers.push_back(cx.waker().clone());
+        let task = cx.waker().data() as *const Task;
+        unsafe { &*task }.set_state(TaskStatus::Blocking);
+        Poll::Pending
+    }
+}
+
+/// 等待子进程停止或继续
+pub struct ChildEventFuture<'a> {
+    pcb: &'a ProcessControlBlock,
+    seq: usize,
+}
+
+impl Future for ChildEventFuture<'_> {
+    type Output = ();
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut wakers = self.pcb.child_wakers.lock();
+        if self.pcb.child_event_seq() != self.seq {
+            return Poll::Ready(());
+        }
+        wakers.push_back(cx.waker().clone());
+        let task = cx.waker().data() as *const Task;
+        unsafe { &*task }.set_state(TaskStatus::Blocking);
+        Poll::Pending
+    }
+}
+
 /// A unique identifier for a thread.
 pub struct TaskId(usize);
 
@@ -1113,7 +1336,7 @@ pub struct TaskControlBlock {
 
     pub tms:UnsafeCell<TimeData>,
     // executor: SpinNoIrq<Arc<Executor>>,
-    pub wait_wakers: UnsafeCell<VecDeque<Waker>>,
+    pub wait_wakers: Spin<VecDeque<Waker>>,
     // pub scheduler: SpinNoIrq<Arc<SpinNoIrq<Scheduler>>>,
     pub id: TaskId,
     /// Whether the task is the initial task
@@ -1172,7 +1395,7 @@ impl TaskControlBlock {
             is_init,
             exit_code: AtomicIsize::new(0),
             fut: UnsafeCell::new(fut),
-            wait_wakers: UnsafeCell::new(VecDeque::new()),
+            wait_wakers: Spin::new(VecDeque::new()),
             need_resched: AtomicBool::new(false),
             preempt_disable_count: AtomicUsize::new(0),
             is_leader: AtomicBool::new(is_leader),
@@ -1220,8 +1443,8 @@ impl TaskControlBlock {
         *task_status = state;
     }
     pub fn wake_all_waiters(&self) {
-        let wait_wakers = unsafe { &mut *self.wait_wakers.get() };
-        while let Some(waker) = wait_wakers.pop_front() {
+        let wakers = core::mem::take(&mut *self.wait_wakers.lock());
+        for waker in wakers {
             waker.wake();
         }
     }
@@ -1335,7 +1558,13 @@ pub fn get_noma_policy(&self) -> usize {
 pub fn join(&self, waker: Waker) {
     let task = waker.data() as *const Task;
     unsafe { &*task }.set_state(TaskStatus::Blocking);
-    let wait_wakers = unsafe { &mut *self.wait_wakers.get() };
+    let mut wait_wakers = self.wait_wakers.lock();
+    // 持锁再检查一次：目标可能已在其他核上退出并唤醒过等待者
+    if *self.state.lock() == TaskStatus::Zombie {
+        drop(wait_wakers);
+        waker.wake();
+        return;
+    }
     wait_wakers.push_back(waker);
 }
 pub async fn clear_child_tid(&self) -> Result<(), SysErrNo> {
@@ -65,7 +65,8 @@ pub fn wakeup_task(task_ptr: *const Task) {
              add_task(task_ref);
 
         }
-        TaskStatus::Waked => panic!("cannot wakeup Waked "),
+        // 多个核同时唤醒同一个任务，已经有人把它标记为 Waked
+        TaskStatus::Waked => (),
         // 无法唤醒已经退出的任务
         TaskStatus::Zombie=> panic!("cannot wakeup Exited "),
     };
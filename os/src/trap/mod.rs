@@ -7,6 +7,8 @@ use crate::syscall::syscall;
 use crate::task::{
      current_task, current_task_may_uninit, exit_current, exit_proc, pick_next_task, run_task2,  task_tick, yield_now, CurrentTask, TaskStatus
 };
+use crate::mm::PageFaultError;
+use crate::signal::{send_signal, Signal};
 use crate::timer::set_next_trigger;
 use crate::utils::error::SysErrNo;
 pub use context::user_return;
@@ -60,6 +62,12 @@ pub fn trap_from_kernel() {
     let scause = scause::read();
     match scause.cause() {
         Trap::Interrupt(Interrupt::SupervisorTimer) => {}
+        Trap::Interrupt(Interrupt::SupervisorExternal) => {
+            crate::devices::handle_irq();
+        }
+        Trap::Interrupt(Interrupt::SupervisorSoft) => {
+            crate::smp::handle_ipi();
+        }
         _ => {
             panic!(
                 "stval = {:#x}, sepc = {:#x},
@@ -102,7 +110,11 @@ pub fn trampoline(_tc: *mut TrapContext, has_trap: bool, from_user: bool) {
             trap_from_kernel();
             return;
         } else {
+            crate::smp::leave_user();
+            crate::smp::handle_pending();
             crate::task::sleeplist::process_timed_events();
+            crate::fs::tty::poll_input();
+            crate::devices::poll_blk_completions();
 
             // debug!("into trampoline from taskcount:{},task",task_count());
             // 用户态发生了 Trap 或者需要调度
@@ -127,10 +139,19 @@ pub fn trampoline(_tc: *mut TrapContext, has_trap: bool, from_user: bool) {
                 // trace!("run task tid = {}", curr.id());
                 run_task2(CurrentTask::from(curr));
             } else {
-                enable_irqs();
-                // error!("no tasks available in run_tasks");
+                crate::fs::net::stack::poll_deferred();
+                // 先标记空闲再检查队列，其他核在此之后加入的任务一定会发来 IPI
+                crate::smp::set_idle(true);
+                if crate::task::local_queue_empty() {
+                    enable_irqs();
+                    // error!("no tasks available in run_tasks");
 
-                wait_for_irqs();
+                    wait_for_irqs();
+                    crate::smp::handle_ipi();
+                    // 内核态不响应中断，唤醒空闲核的外设中断在这里处理
+                    crate::devices::handle_irq();
+                }
+                crate::smp::set_idle(false);
             }
         }
     }
@@ -183,7 +204,10 @@ pub async fn user_task_top() -> i32 {
 
                     tf.sepc += 4;
 
+                    let process = curr.get_process().unwrap();
+                    process.enter_syscall();
                     let result = syscall(syscall_id, args).await;
+                    process.leave_syscall();
 
                     curr.update_stime();
                     let result = match result {
@@ -254,7 +278,19 @@ pub async fn user_task_top() -> i32 {
                         .memory_set
                         .lock().await
                         .handle_page_fault(stval,is_write).await;
+                    let handleres = match handleres {
+                        // 内存不足时先换出一些页，不行再杀掉占用内存最多的进程，
+                        // 腾出内存后回到用户态重新执行触发缺页的指令
+                        Err(PageFaultError::OutOfMemory)
+                            if crate::mm::oom::reclaim_or_kill().await => Ok(true),
+                        res => res,
+                    };
                     match handleres {
+                        // 栈超出 RLIMIT_STACK：和 Linux 一样发送 SIGSEGV
+                        Err(PageFaultError::StackOverflow) => {
+                            let pid = curr.get_process().unwrap().get_pid();
+                            let _ = send_signal(pid, Some(curr.id()), Signal::SIGSEGV).await;
+                        }
                         Ok(value) if value == false => {
                             exit_proc(-2).await;
                             log_page_fault_error(scause, stval, sepc);
@@ -299,6 +335,8 @@ pub async fn user_task_top() -> i32 {
 
                         /// Timer IRQ of loongarch64
                        const TIMER_IRQ: usize = 11;
+                       /// IPI of loongarch64
+                       const IPI_IRQ: usize = 12;
                     let irq_num: usize = loongArch64::register::estat::read().is().trailing_zeros() as usize;
                     match irq_num {
                         // TIMER_IRQ
@@ -329,6 +367,16 @@ pub async fn user_task_top() -> i32 {
                     }
 
                         }
+                        // HWI0-HWI7，经中断控制器分发给设备驱动
+                        2..=9 => {
+                            tf.trap_status = TrapStatus::Done;
+                            crate::devices::handle_irq();
+                        }
+                        // 核间中断
+                        IPI_IRQ => {
+                            tf.trap_status = TrapStatus::Done;
+                            crate::smp::handle_ipi();
+                        }
                         _ => panic!("unknown interrupt: {}", irq_num),
                     }
                 } 
@@ -343,9 +391,14 @@ pub async fn user_task_top() -> i32 {
 
             {
                 //处理完系统调用过后，对应的信号处理和时钟更新
+                crate::fs::tty::poll_input();
+                crate::fs::tty::deliver_signals().await;
                 crate::signal::handle_pending_signals(syscall_ret).await;
                 crate::task::sleeplist::process_timed_events();
+                crate::drivers::bcache::run_deferred_io().await;
+                crate::mm::swap::balance().await;
                 crate::timer::handle_timer_tick().await;
+                crate::fs::net::stack::poll_deferred();
             }
 
             // trace!("sys_call end3");
@@ -415,7 +468,10 @@ pub async fn user_task_top() -> i32 {
 
                     tf.sepc += 4;
 
+                    let process = curr.get_process().unwrap();
+                    process.enter_syscall();
                     let result = syscall(syscall_id, args).await;
+                    process.leave_syscall();
 
                     curr.update_stime();
                     let result = match result {
@@ -487,7 +543,19 @@ pub async fn user_task_top() -> i32 {
                         .memory_set
                         .lock().await
                         .handle_page_fault(stval,is_write).await;
+                    let handleres = match handleres {
+                        // 内存不足时先换出一些页，不行再杀掉占用内存最多的进程，
+                        // 腾出内存后回到用户态重新执行触发缺页的指令
+                        Err(PageFaultError::OutOfMemory)
+                            if crate::mm::oom::reclaim_or_kill().await => Ok(true),
+                        res => res,
+                    };
                     match handleres {
+                        // 栈超出 RLIMIT_STACK：和 Linux 一样发送 SIGSEGV
+                        Err(PageFaultError::StackOverflow) => {
+                            let pid = curr.get_process().unwrap().get_pid();
+                            let _ = send_signal(pid, Some(curr.id()), Signal::SIGSEGV).await;
+                        }
                         Ok(value) if value == false => {
                             exit_proc(-2).await;
                             log_page_fault_error(scause, stval, sepc);
@@ -527,6 +595,14 @@ Trap::Exception(Exception::IllegalInstruction)  =>{
                 }
                 
 
+                Trap::Interrupt(Interrupt::SupervisorExternal) => {
+                    tf.trap_status = TrapStatus::Done;
+                    crate::devices::handle_irq();
+                }
+                Trap::Interrupt(Interrupt::SupervisorSoft) => {
+                    tf.trap_status = TrapStatus::Done;
+                    crate::smp::handle_ipi();
+                }
                 Trap::Interrupt(Interrupt::SupervisorTimer) => {
                     set_next_trigger();
 
@@ -563,9 +639,14 @@ Trap::Exception(Exception::IllegalInstruction)  =>{
 
             {
                 //处理完系统调用过后，对应的信号处理和时钟更新
+                crate::fs::tty::poll_input();
+                crate::fs::tty::deliver_signals().await;
                 crate::signal::handle_pending_signals(syscall_ret).await;
                 crate::task::sleeplist::process_timed_events();
+                crate::drivers::bcache::run_deferred_io().await;
+                crate::mm::swap::balance().await;
                 crate::timer::handle_timer_tick().await;
+                crate::fs::net::stack::poll_deferred();
             }
 
             // trace!("sys_call end3");
@@ -608,5 +689,8 @@ pub fn on_timer_tick() {
             curr.set_need_resched(true);
         }
     }
+    if crate::smp::tick() {
+        crate::task::load_balance();
+    }
 }
 